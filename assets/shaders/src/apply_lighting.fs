@@ -0,0 +1,14 @@
+#import "version_directive.glsl"
+
+in vec2 io_uv;
+
+out vec4 o_frag_color;
+
+uniform sampler2D source;
+uniform sampler2D light;
+
+void main()
+{
+  vec4 color = texture(source, io_uv);
+  o_frag_color = vec4(color.rgb * texture(light, io_uv).rgb, color.a);
+}
@@ -0,0 +1,17 @@
+#import "version_directive.glsl"
+
+in vec2 io_uv;
+
+out vec4 o_frag_color;
+
+uniform sampler2D source;
+uniform float threshold;
+
+void main()
+{
+  vec3 color = texture(source, io_uv).rgb;
+  float brightness = max(color.r, max(color.g, color.b));
+  // a soft knee, so bright areas fade into the bloom instead of popping in
+  float amount = clamp((brightness - threshold) / max(1.0 - threshold, 0.0001), 0.0, 1.0);
+  o_frag_color = vec4(color * amount, 1.0);
+}
@@ -0,0 +1,23 @@
+#import "version_directive.glsl"
+
+in vec2 io_uv;
+
+out vec4 o_frag_color;
+
+uniform sampler2D source;
+uniform vec2 u_resolution;
+uniform vec2 direction;
+
+const float WEIGHTS[5] = float[](0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+
+void main()
+{
+  vec2 step = direction / u_resolution;
+  vec3 color = texture(source, io_uv).rgb * WEIGHTS[0];
+  for (int i = 1; i < 5; ++i)
+  {
+    color += texture(source, io_uv + step * float(i)).rgb * WEIGHTS[i];
+    color += texture(source, io_uv - step * float(i)).rgb * WEIGHTS[i];
+  }
+  o_frag_color = vec4(color, 1.0);
+}
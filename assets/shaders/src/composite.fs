@@ -0,0 +1,46 @@
+#import "version_directive.glsl"
+
+in vec2 io_uv;
+
+out vec4 o_frag_color;
+
+uniform sampler2D scene;
+uniform sampler2D bloom;
+uniform sampler2D lut;
+uniform float bloom_intensity;
+uniform float vignette_strength;
+uniform float vignette_radius;
+uniform float lut_strength;
+
+const float LUT_SIZE = 16.0;
+
+// the table is LUT_SIZE squares side by side, one per blue level, with red across
+// each square and green down it
+vec3 grade(vec3 color)
+{
+  float blue = color.b * (LUT_SIZE - 1.0);
+  float first = floor(blue);
+  float second = min(first + 1.0, LUT_SIZE - 1.0);
+  float width = LUT_SIZE * LUT_SIZE;
+
+  float red = color.r * (LUT_SIZE - 1.0) + 0.5;
+  // textures are uploaded bottom row first, so the first row is at the top
+  float v = 1.0 - (color.g * (LUT_SIZE - 1.0) + 0.5) / LUT_SIZE;
+
+  vec3 low = texture(lut, vec2((first * LUT_SIZE + red) / width, v)).rgb;
+  vec3 high = texture(lut, vec2((second * LUT_SIZE + red) / width, v)).rgb;
+  return mix(low, high, blue - first);
+}
+
+void main()
+{
+  vec3 color = texture(scene, io_uv).rgb + texture(bloom, io_uv).rgb * bloom_intensity;
+  color = clamp(color, 0.0, 1.0);
+
+  color = mix(color, grade(color), lut_strength);
+
+  float distance = length(io_uv - 0.5) * 1.41421356;
+  color *= 1.0 - vignette_strength * smoothstep(vignette_radius, 1.0, distance);
+
+  o_frag_color = vec4(color, 1.0);
+}
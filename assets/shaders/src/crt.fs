@@ -0,0 +1,30 @@
+#import "version_directive.glsl"
+
+in vec2 io_uv;
+
+out vec4 o_frag_color;
+
+uniform sampler2D source;
+uniform vec2 u_resolution;
+uniform float curvature;
+uniform float scanline_strength;
+
+void main()
+{
+  // bulge the picture out like the glass of a tube
+  vec2 centered = io_uv * 2.0 - 1.0;
+  centered *= 1.0 + curvature * dot(centered.yx, centered.yx);
+  vec2 uv = centered * 0.5 + 0.5;
+
+  if (uv.x < 0.0 || uv.x > 1.0 || uv.y < 0.0 || uv.y > 1.0)
+  {
+    o_frag_color = vec4(0.0, 0.0, 0.0, 1.0);
+    return;
+  }
+
+  vec3 color = texture(source, uv).rgb;
+  float scanline = 0.5 + 0.5 * sin(uv.y * u_resolution.y * 3.14159265);
+  color *= 1.0 - scanline_strength * (1.0 - scanline);
+
+  o_frag_color = vec4(color, 1.0);
+}
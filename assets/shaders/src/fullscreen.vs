@@ -0,0 +1,11 @@
+#import "version_directive.glsl"
+
+out vec2 io_uv;
+
+void main()
+{
+  // one triangle twice the size of the screen, so the screen is covered by its corner
+  vec2 position = vec2((gl_VertexID << 1) & 2, gl_VertexID & 2);
+  io_uv         = position;
+  gl_Position   = vec4(position * 2.0 - 1.0, 0.0, 1.0);
+}
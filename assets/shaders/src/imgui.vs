@@ -0,0 +1,17 @@
+#import "version_directive.glsl"
+
+in vec2 i_pos;
+in vec2 i_uv;
+in vec4 i_color;
+
+out vec2 io_uv;
+out vec4 io_color;
+
+uniform mat4 u_projection;
+
+void main()
+{
+  io_uv       = i_uv;
+  io_color    = i_color;
+  gl_Position = u_projection * vec4(i_pos, 0.0, 1.0);
+}
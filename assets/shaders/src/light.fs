@@ -0,0 +1,41 @@
+#import "version_directive.glsl"
+
+in vec2 io_world;
+
+out vec4 o_frag_color;
+
+uniform vec2 u_resolution;
+uniform sampler2D normals;
+uniform sampler2D shadow;
+uniform float u_shadowed;
+uniform vec2 u_position;
+uniform vec3 u_color;
+uniform float u_radius;
+uniform float u_height;
+uniform vec2 u_direction;
+uniform float u_cos_outer;
+uniform float u_cos_inner;
+
+void main()
+{
+  vec2 to_light = u_position - io_world;
+  float falloff = clamp(1.0 - length(to_light) / u_radius, 0.0, 1.0);
+  falloff *= falloff;
+
+  // point lights pass a cone of -1, which takes in every direction
+  float cone = 1.0;
+  if (u_cos_outer > -1.0)
+  {
+    cone = smoothstep(u_cos_outer, u_cos_inner, dot(normalize(-to_light), u_direction));
+  }
+
+  vec2 screen = gl_FragCoord.xy / u_resolution;
+  vec3 normal = normalize(texture(normals, screen).xyz * 2.0 - 1.0);
+  // normal maps point green up the screen while the world runs down it
+  vec3 direction = normalize(vec3(to_light.x, -to_light.y, u_height));
+  float diffuse = max(dot(normal, direction), 0.0);
+
+  float lit = 1.0 - u_shadowed * min(texture(shadow, screen).r, 1.0);
+
+  o_frag_color = vec4(u_color * falloff * cone * diffuse * lit, 1.0);
+}
@@ -0,0 +1,14 @@
+#import "version_directive.glsl"
+
+in vec2 i_pos;
+
+out vec2 io_world;
+
+uniform mat4 u_view;
+uniform mat4 u_projection;
+
+void main()
+{
+  io_world    = i_pos;
+  gl_Position = u_projection * u_view * vec4(i_pos, 0.0, 1.0);
+}
@@ -0,0 +1,21 @@
+#import "version_directive.glsl"
+
+in vec2 i_corner;
+in vec2 i_center;
+in float i_size;
+in vec4 i_color;
+
+out vec2 io_uv;
+out vec4 io_color;
+
+uniform mat4 u_view;
+uniform mat4 u_projection;
+// u and v at the top left of the region, then at its bottom right
+uniform vec4 u_uv_rect;
+
+void main()
+{
+  io_uv       = mix(u_uv_rect.xy, u_uv_rect.zw, i_corner + 0.5);
+  io_color    = i_color;
+  gl_Position = u_projection * u_view * vec4(i_center + i_corner * i_size, 0.0, 1.0);
+}
@@ -0,0 +1,57 @@
+#import "version_directive.glsl"
+
+in vec2 io_uv;
+in vec4 io_color;
+
+out vec4 o_frag_color;
+
+uniform sampler2D tex;
+uniform float u_spread;
+uniform float outline_width;
+uniform vec4 outline_color;
+uniform vec2 shadow_offset;
+uniform float shadow_softness;
+uniform vec4 shadow_color;
+uniform float glow_radius;
+uniform vec4 glow_color;
+
+// texels from the outline, positive inside the glyph
+float distance_at(vec2 uv)
+{
+  return (texture(tex, uv).a - 0.5) * 2.0 * u_spread;
+}
+
+// puts `top` over `bottom`, both with straight alpha
+vec4 over(vec4 top, vec4 bottom)
+{
+  float alpha = top.a + bottom.a * (1.0 - top.a);
+  if (alpha <= 0.0)
+  {
+    return vec4(0.0);
+  }
+  return vec4((top.rgb * top.a + bottom.rgb * bottom.a * (1.0 - top.a)) / alpha, alpha);
+}
+
+void main()
+{
+  float distance = distance_at(io_uv);
+  // about half a screen pixel measured in texels, so edges stay sharp at any scale
+  float edge = max(length(vec2(dFdx(distance), dFdy(distance))) * 0.5, 0.0001);
+
+  float fill = smoothstep(-edge, edge, distance);
+  float outer = smoothstep(-edge, edge, distance + outline_width);
+  vec4 body = over(vec4(io_color.rgb, io_color.a * fill), vec4(outline_color.rgb, outline_color.a * outer));
+
+  vec2 shadow_uv = io_uv - shadow_offset * vec2(1.0, -1.0) / vec2(textureSize(tex, 0));
+  float shadow_distance = distance_at(shadow_uv) + outline_width;
+  float shadow = smoothstep(-shadow_softness - edge, shadow_softness + edge, shadow_distance);
+
+  float glow = 0.0;
+  if (glow_radius > 0.0)
+  {
+    glow = 1.0 - smoothstep(0.0, glow_radius, -(distance + outline_width));
+  }
+
+  vec4 behind = over(vec4(glow_color.rgb, glow_color.a * glow), vec4(shadow_color.rgb, shadow_color.a * shadow));
+  o_frag_color = over(body, behind);
+}
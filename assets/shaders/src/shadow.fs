@@ -0,0 +1,10 @@
+#import "version_directive.glsl"
+
+out vec4 o_frag_color;
+
+uniform float u_strength;
+
+void main()
+{
+  o_frag_color = vec4(vec3(u_strength), 1.0);
+}
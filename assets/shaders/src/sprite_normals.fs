@@ -0,0 +1,23 @@
+#import "version_directive.glsl"
+
+in vec2 io_uv;
+in float io_alpha;
+in vec2 io_turn;
+
+out vec4 o_frag_color;
+
+uniform sampler2D tex;
+uniform sampler2D normal_map;
+
+void main()
+{
+  vec3 normal = texture(normal_map, io_uv).xyz * 2.0 - 1.0;
+  // a sprite turned clockwise on screen turns its normals clockwise too, and the
+  // normals have green pointing up
+  normal.xy = vec2(
+    normal.x * io_turn.x + normal.y * io_turn.y,
+    normal.y * io_turn.x - normal.x * io_turn.y
+  );
+
+  o_frag_color = vec4(normal * 0.5 + 0.5, texture(tex, io_uv).a * io_alpha);
+}
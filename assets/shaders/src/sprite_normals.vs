@@ -0,0 +1,21 @@
+#import "version_directive.glsl"
+
+in vec2 i_pos;
+in vec2 i_uv;
+in vec4 i_color;
+in float i_rotation;
+
+out vec2 io_uv;
+out float io_alpha;
+out vec2 io_turn;
+
+uniform mat4 u_view;
+uniform mat4 u_projection;
+
+void main()
+{
+  io_uv       = i_uv;
+  io_alpha    = i_color.a;
+  io_turn     = vec2(cos(i_rotation), sin(i_rotation));
+  gl_Position = u_projection * u_view * vec4(i_pos, 0.0, 1.0);
+}
@@ -0,0 +1,13 @@
+#import "version_directive.glsl"
+#import "vertex_layout.glsl"
+
+out vec2 io_uv;
+
+uniform mat4 u_view;
+uniform mat4 u_projection;
+
+void main()
+{
+  io_uv       = i_uv;
+  gl_Position = u_projection * u_view * vec4(i_pos, 1.0);
+}
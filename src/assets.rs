@@ -0,0 +1,192 @@
+mod id;
+mod server;
+mod validation;
+
+use crate::util;
+pub use id::{AssetId, Namespace};
+use lazy_static::lazy_static;
+pub use server::{AssetKey, AssetLoader, AssetServer, Handle, LoadContext};
+use std::{
+  fs,
+  path::{Path, PathBuf},
+};
+pub use validation::validate_references;
+
+const CONFIG_EXTENSIONS: [&str; 2] = ["json", "toml"];
+
+lazy_static! {
+  static ref EXP_DIR: PathBuf = PathBuf::new().join("assets");
+  static ref ENGINE_DIR: PathBuf = PathBuf::new().join("assets").join("engine");
+  static ref MODS_DIR: PathBuf = PathBuf::new().join("mods");
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq, Ord)]
+pub enum AssetKind {
+  Shader,
+  Model,
+  Animation,
+  Texture,
+  GameObject,
+  Map,
+  Pipeline,
+  Font,
+  Particles,
+}
+
+impl AssetKind {
+  pub const CONFIGS: [AssetKind; 8] = [
+    AssetKind::Shader,
+    AssetKind::Model,
+    AssetKind::Animation,
+    AssetKind::Texture,
+    AssetKind::GameObject,
+    AssetKind::Pipeline,
+    AssetKind::Font,
+    AssetKind::Particles,
+  ];
+
+  pub fn config_dir(&self) -> &'static str {
+    match self {
+      AssetKind::Shader => "shaders",
+      AssetKind::Model => "models",
+      AssetKind::Animation => "animations",
+      AssetKind::Texture => "textures",
+      AssetKind::GameObject => "game",
+      AssetKind::Map => "maps",
+      AssetKind::Pipeline => "pipelines",
+      AssetKind::Font => "fonts",
+      AssetKind::Particles => "particles",
+    }
+  }
+}
+
+/// The root directory of every namespace that is present on disk,
+/// `exp` first, then `engine`, then each mod in `mods/<name>`.
+pub fn namespace_dirs() -> Vec<(Namespace, PathBuf)> {
+  let mut dirs = vec![
+    (Namespace::Exp, EXP_DIR.clone()),
+    (Namespace::Engine, ENGINE_DIR.clone()),
+  ];
+
+  if let Ok(entries) = fs::read_dir(MODS_DIR.as_path()) {
+    let mut mods: Vec<PathBuf> = entries
+      .flatten()
+      .map(|entry| entry.path())
+      .filter(|path| path.is_dir())
+      .collect();
+    mods.sort();
+
+    for path in mods {
+      if let Some(name) = path.file_name().and_then(|name| name.to_str()) {
+        if let Ok(namespace) = name.parse::<Namespace>() {
+          dirs.push((namespace, path.clone()));
+        }
+      }
+    }
+  }
+
+  dirs
+}
+
+pub fn namespace_dir(namespace: &Namespace) -> PathBuf {
+  match namespace {
+    Namespace::Exp => EXP_DIR.clone(),
+    Namespace::Engine => ENGINE_DIR.clone(),
+    Namespace::Mod(name) => MODS_DIR.join(name),
+  }
+}
+
+pub fn iterate_configs<F: FnMut(&Path, AssetId)>(kind: AssetKind, mut f: F) {
+  for (namespace, dir) in namespace_dirs() {
+    let config = dir.join("cfg").join(kind.config_dir());
+    util::iterate_dir_with_id(&config, &AssetId::new(namespace), &mut f);
+  }
+}
+
+/// Finds the config file that defines `id` along with the key of its entry, so
+/// `exp.test.random.player` is the `player` entry of `assets/cfg/<kind>/test/random.*`.
+pub fn locate_config(kind: AssetKind, id: &AssetId) -> Result<(PathBuf, &str), String> {
+  let (local_id, dirs) = id
+    .path()
+    .split_last()
+    .ok_or_else(|| format!("{} does not name an asset", id))?;
+
+  let mut file = namespace_dir(id.namespace())
+    .join("cfg")
+    .join(kind.config_dir());
+  for dir in dirs {
+    file.push(dir);
+  }
+
+  for ext in CONFIG_EXTENSIONS {
+    let candidate = file.with_extension(ext);
+    if candidate.is_file() {
+      return Ok((candidate, local_id));
+    }
+  }
+
+  Err(format!("no {} config defines {}", kind.config_dir(), id))
+}
+
+/// Finds an asset that lives in a file of its own, so `exp.test` with the `maps`
+/// kind is `assets/maps/test.<ext>`, trying each extension in order.
+pub fn locate_file(kind: AssetKind, id: &AssetId, extensions: &[&str]) -> Result<PathBuf, String> {
+  let mut file = namespace_dir(id.namespace()).join(kind.config_dir());
+  for part in id.path() {
+    file.push(part);
+  }
+
+  extensions
+    .iter()
+    .map(|ext| file.with_extension(ext))
+    .find(|candidate| candidate.is_file())
+    .ok_or_else(|| format!("no {} file for {}", kind.config_dir(), id))
+}
+
+pub fn read_json_entry(kind: AssetKind, id: &AssetId) -> Result<serde_json::Value, String> {
+  let (path, local_id) = locate_config(kind, id)?;
+  let data =
+    fs::read_to_string(&path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+  let mut root = serde_json::from_str::<serde_json::Value>(&data)
+    .map_err(|e| format!("cannot parse {}: {}", path.display(), e))?;
+
+  root
+    .as_object_mut()
+    .and_then(|entries| entries.remove(local_id))
+    .ok_or_else(|| format!("{} has no entry '{}'", path.display(), local_id))
+}
+
+pub fn read_toml_entry(kind: AssetKind, id: &AssetId) -> Result<toml::Value, String> {
+  let (path, local_id) = locate_config(kind, id)?;
+  let data =
+    fs::read_to_string(&path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+  let mut root = data
+    .parse::<toml::Value>()
+    .map_err(|e| format!("cannot parse {}: {}", path.display(), e))?;
+
+  root
+    .as_table_mut()
+    .and_then(|entries| entries.remove(local_id))
+    .ok_or_else(|| format!("{} has no entry '{}'", path.display(), local_id))
+}
+
+/// The ids of the entries a config file defines, relative to the file.
+pub fn config_keys(path: &Path) -> Vec<String> {
+  let data = match fs::read_to_string(path) {
+    Ok(data) => data,
+    Err(_) => return Vec::new(),
+  };
+
+  if path.extension().is_some_and(|ext| ext == "toml") {
+    data
+      .parse::<toml::Value>()
+      .ok()
+      .and_then(|root| root.as_table().map(|t| t.keys().cloned().collect()))
+      .unwrap_or_default()
+  } else {
+    serde_json::from_str::<serde_json::Value>(&data)
+      .ok()
+      .and_then(|root| root.as_object().map(|o| o.keys().cloned().collect()))
+      .unwrap_or_default()
+  }
+}
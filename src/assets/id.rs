@@ -0,0 +1,182 @@
+use std::{
+  fmt::{self, Display, Formatter},
+  str::FromStr,
+};
+
+const SEPARATOR: char = '.';
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub enum Namespace {
+  Exp,
+  Engine,
+  Mod(String),
+}
+
+impl Display for Namespace {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    match self {
+      Namespace::Exp => write!(f, "exp"),
+      Namespace::Engine => write!(f, "engine"),
+      Namespace::Mod(name) => write!(f, "{}", name),
+    }
+  }
+}
+
+impl FromStr for Namespace {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "exp" => Ok(Namespace::Exp),
+      "engine" => Ok(Namespace::Engine),
+      name => {
+        AssetId::validate_segment(name)?;
+        Ok(Namespace::Mod(name.to_string()))
+      }
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub struct AssetId {
+  namespace: Namespace,
+  path: Vec<String>,
+}
+
+impl AssetId {
+  pub fn new(namespace: Namespace) -> Self {
+    Self {
+      namespace,
+      path: Vec::default(),
+    }
+  }
+
+  pub fn namespace(&self) -> &Namespace {
+    &self.namespace
+  }
+
+  pub fn path(&self) -> &[String] {
+    &self.path
+  }
+
+  pub fn extend<T: AsRef<str>>(&self, s: T) -> Self {
+    let mut copy = self.clone();
+    copy.path.push(s.as_ref().to_string());
+    copy
+  }
+
+  fn validate_segment(segment: &str) -> Result<(), String> {
+    if segment.is_empty() {
+      return Err(String::from("empty id segment"));
+    }
+
+    match segment
+      .chars()
+      .find(|c| !(c.is_ascii_alphanumeric() || *c == '_' || *c == '-'))
+    {
+      Some(invalid) => Err(format!(
+        "invalid character '{}' in id segment '{}'",
+        invalid, segment
+      )),
+      None => Ok(()),
+    }
+  }
+}
+
+impl Display for AssetId {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.namespace)?;
+    for part in &self.path {
+      write!(f, "{}{}", SEPARATOR, part)?;
+    }
+    Ok(())
+  }
+}
+
+impl FromStr for AssetId {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let mut parts = s.split(SEPARATOR);
+
+    let namespace = parts
+      .next()
+      .ok_or_else(|| format!("'{}' is missing a namespace", s))?
+      .parse::<Namespace>()
+      .map_err(|e| format!("'{}': {}", s, e))?;
+
+    let mut id = AssetId::new(namespace);
+
+    for part in parts {
+      Self::validate_segment(part).map_err(|e| format!("'{}': {}", s, e))?;
+      id.path.push(part.to_string());
+    }
+
+    if id.path.is_empty() {
+      return Err(format!("'{}' only names a namespace", s));
+    }
+
+    Ok(id)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn known_namespaces_are_parsed() {
+    let id = "exp.test.random.player".parse::<AssetId>().unwrap();
+    assert_eq!(id.namespace(), &Namespace::Exp);
+    assert_eq!(id.path(), ["test", "random", "player"]);
+
+    let id = "engine.shaders.basic".parse::<AssetId>().unwrap();
+    assert_eq!(id.namespace(), &Namespace::Engine);
+    assert_eq!(id.path(), ["shaders", "basic"]);
+  }
+
+  #[test]
+  fn other_namespaces_name_a_mod() {
+    let id = "my_mod.items.sword-2".parse::<AssetId>().unwrap();
+    assert_eq!(id.namespace(), &Namespace::Mod(String::from("my_mod")));
+    assert_eq!(id.path(), ["items", "sword-2"]);
+  }
+
+  #[test]
+  fn ids_print_the_way_they_are_parsed() {
+    for source in ["exp.test.random.player", "engine.a", "my_mod.b.c"] {
+      assert_eq!(source.parse::<AssetId>().unwrap().to_string(), source);
+    }
+    assert_eq!(
+      AssetId::new(Namespace::Exp).extend("maps").extend("test"),
+      "exp.maps.test".parse().unwrap()
+    );
+  }
+
+  #[test]
+  fn namespaces_must_be_valid_segments() {
+    assert!("bad!.test".parse::<AssetId>().is_err());
+    assert!("my mod.test".parse::<AssetId>().is_err());
+    assert!(".test".parse::<AssetId>().is_err());
+  }
+
+  #[test]
+  fn empty_segments_are_rejected() {
+    assert!("".parse::<AssetId>().is_err());
+    assert!("exp..player".parse::<AssetId>().is_err());
+    assert!("exp.test.".parse::<AssetId>().is_err());
+  }
+
+  #[test]
+  fn a_namespace_alone_is_not_an_id() {
+    assert!("exp".parse::<AssetId>().is_err());
+    assert!("engine".parse::<AssetId>().is_err());
+  }
+
+  #[test]
+  fn segments_only_hold_letters_digits_underscores_and_dashes() {
+    assert!("exp.test/random".parse::<AssetId>().is_err());
+    assert!("exp.test.ran dom".parse::<AssetId>().is_err());
+    assert!("exp.Test_1.a-b".parse::<AssetId>().is_ok());
+  }
+}
@@ -0,0 +1,630 @@
+use super::{AssetId, AssetKind};
+use log::{error, info};
+use std::{
+  any::{Any, TypeId},
+  cell::RefCell,
+  collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+  fmt::{self, Display, Formatter},
+  rc::Rc,
+};
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord)]
+pub struct AssetKey {
+  pub kind: AssetKind,
+  pub id: AssetId,
+}
+
+impl Display for AssetKey {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    write!(f, "{}:{}", self.kind.config_dir(), self.id)
+  }
+}
+
+pub trait AssetLoader: 'static {
+  type Asset: 'static;
+
+  fn kind(&self) -> AssetKind;
+
+  /// May load other assets through `ctx`, including ones of its own type.
+  fn load(&self, id: &AssetId, ctx: &mut LoadContext) -> Result<Self::Asset, String>;
+}
+
+/// Handed to loaders so that assets they depend on are loaded through the
+/// server and recorded as dependencies of the asset being loaded.
+pub struct LoadContext<'a> {
+  server: &'a mut AssetServer,
+  dependencies: BTreeSet<AssetKey>,
+}
+
+impl<'a> LoadContext<'a> {
+  #[cfg(test)]
+  pub fn new(server: &'a mut AssetServer) -> Self {
+    Self {
+      server,
+      dependencies: BTreeSet::default(),
+    }
+  }
+
+  pub fn load<T: 'static>(&mut self, id: &AssetId) -> Result<Handle<T>, String> {
+    let handle = self.server.load::<T>(id)?;
+    self.dependencies.insert(AssetKey {
+      kind: self.server.kind_of::<T>()?,
+      id: id.clone(),
+    });
+    Ok(handle)
+  }
+}
+
+struct Slot<T> {
+  id: AssetId,
+  asset: RefCell<Option<Rc<T>>>,
+  /// Whether the server holds on to the slot as well.
+  managed: bool,
+}
+
+pub struct Handle<T> {
+  slot: Rc<Slot<T>>,
+}
+
+impl<T> Clone for Handle<T> {
+  fn clone(&self) -> Self {
+    Self {
+      slot: self.slot.clone(),
+    }
+  }
+}
+
+impl<T> Handle<T> {
+  /// A handle to an asset made at runtime instead of loaded, like a glyph atlas.
+  /// The server never sees it, so it is never reloaded or unloaded.
+  pub fn detached(id: AssetId, asset: T) -> Self {
+    Self {
+      slot: Rc::new(Slot {
+        id,
+        asset: RefCell::new(Some(Rc::new(asset))),
+        managed: false,
+      }),
+    }
+  }
+
+  pub fn id(&self) -> &AssetId {
+    &self.slot.id
+  }
+
+  /// The current asset, `None` while it is invalidated by a failed reload.
+  pub fn get(&self) -> Option<Rc<T>> {
+    self.slot.asset.borrow().clone()
+  }
+
+  /// Changes the asset in place, copying it first while something still holds it from
+  /// `get`. Returns `None` without calling `change` when the asset is invalidated. A
+  /// reload throws the changes away.
+  pub fn modify<R>(&self, change: impl FnOnce(&mut T) -> R) -> Option<R>
+  where
+    T: Clone,
+  {
+    self
+      .slot
+      .asset
+      .borrow_mut()
+      .as_mut()
+      .map(|asset| change(Rc::make_mut(asset)))
+  }
+
+  pub fn is_loaded(&self) -> bool {
+    self.slot.asset.borrow().is_some()
+  }
+
+  /// The number of handles in use outside of the server.
+  pub fn ref_count(&self) -> usize {
+    Rc::strong_count(&self.slot) - usize::from(self.slot.managed)
+  }
+}
+
+trait AnyStorage {
+  fn kind(&self) -> AssetKind;
+
+  fn as_any_mut(&mut self) -> &mut dyn Any;
+
+  fn invalidate(&mut self, id: &AssetId);
+
+  /// Reloads an asset of the storage, a function so that the storage is not borrowed
+  /// while its loader runs.
+  fn reloader(&self) -> fn(&mut AssetServer, &AssetId) -> Result<(), String>;
+
+  fn unload_unused(&mut self) -> Vec<AssetId>;
+}
+
+struct Storage<T> {
+  loader: Rc<dyn AssetLoader<Asset = T>>,
+  slots: BTreeMap<AssetId, Rc<Slot<T>>>,
+}
+
+impl<T: 'static> AnyStorage for Storage<T> {
+  fn kind(&self) -> AssetKind {
+    self.loader.kind()
+  }
+
+  fn as_any_mut(&mut self) -> &mut dyn Any {
+    self
+  }
+
+  fn invalidate(&mut self, id: &AssetId) {
+    if let Some(slot) = self.slots.get(id) {
+      *slot.asset.borrow_mut() = None;
+    }
+  }
+
+  fn reloader(&self) -> fn(&mut AssetServer, &AssetId) -> Result<(), String> {
+    AssetServer::reload_asset::<T>
+  }
+
+  fn unload_unused(&mut self) -> Vec<AssetId> {
+    let unused: Vec<AssetId> = self
+      .slots
+      .iter()
+      .filter(|(_, slot)| Rc::strong_count(slot) == 1)
+      .map(|(id, _)| id.clone())
+      .collect();
+
+    for id in &unused {
+      self.slots.remove(id);
+    }
+
+    unused
+  }
+}
+
+#[derive(Default)]
+pub struct AssetServer {
+  storages: HashMap<TypeId, Box<dyn AnyStorage>>,
+  kinds: BTreeMap<AssetKind, TypeId>,
+  dependencies: BTreeMap<AssetKey, BTreeSet<AssetKey>>,
+  dependents: BTreeMap<AssetKey, BTreeSet<AssetKey>>,
+  /// The assets whose loaders are running, to catch assets that depend on themselves.
+  loading: BTreeSet<AssetKey>,
+}
+
+impl AssetServer {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn register<L: AssetLoader>(&mut self, loader: L) {
+    let type_id = TypeId::of::<L::Asset>();
+    self.kinds.insert(loader.kind(), type_id);
+    self.storages.insert(
+      type_id,
+      Box::new(Storage::<L::Asset> {
+        loader: Rc::new(loader),
+        slots: BTreeMap::default(),
+      }),
+    );
+  }
+
+  pub fn load<T: 'static>(&mut self, id: &AssetId) -> Result<Handle<T>, String> {
+    let storage = self
+      .storage_for::<T>()
+      .ok_or_else(|| format!("no loader registered for {}", id))?;
+    if let Some(slot) = storage.slots.get(id) {
+      return Ok(Handle { slot: slot.clone() });
+    }
+
+    let loader = storage.loader.clone();
+    let asset = self.run_loader(loader.as_ref(), id)?;
+
+    let slot = Rc::new(Slot {
+      id: id.clone(),
+      asset: RefCell::new(Some(Rc::new(asset))),
+      managed: true,
+    });
+    if let Some(storage) = self.storage_for::<T>() {
+      storage.slots.insert(id.clone(), slot.clone());
+    }
+
+    Ok(Handle { slot })
+  }
+
+  pub fn load_all<T: 'static>(&mut self) {
+    let kind = match self.kind_of::<T>() {
+      Ok(kind) => kind,
+      Err(msg) => {
+        error!("{}", msg);
+        return;
+      }
+    };
+
+    for id in config_ids(kind) {
+      if let Err(msg) = self.load::<T>(&id) {
+        error!("cannot load {}: {}", id, msg);
+      }
+    }
+  }
+
+  /// Reloads every loaded asset of a type and what depends on it, so edits to their
+  /// files show up without a restart.
+  pub fn reload_all<T: 'static>(&mut self) {
+    let kind = match self.kind_of::<T>() {
+      Ok(kind) => kind,
+      Err(msg) => {
+        error!("{}", msg);
+        return;
+      }
+    };
+
+    for id in config_ids(kind) {
+      if self.get::<T>(&id).is_some() {
+        // failures are logged by reload, the rest still get reloaded
+        let _ = self.reload(&AssetKey { kind, id });
+      }
+    }
+  }
+
+  pub fn get<T: 'static>(&mut self, id: &AssetId) -> Option<Handle<T>> {
+    self
+      .storage_for::<T>()?
+      .slots
+      .get(id)
+      .map(|slot| Handle { slot: slot.clone() })
+  }
+
+  /// Reloads an asset and everything that transitively depends on it. Dependents
+  /// are invalidated first so nothing observes a mix of old and new assets.
+  pub fn reload(&mut self, key: &AssetKey) -> Result<(), String> {
+    let mut order = vec![key.clone()];
+    let mut queue = VecDeque::from(vec![key.clone()]);
+    let mut seen = BTreeSet::from([key.clone()]);
+
+    while let Some(next) = queue.pop_front() {
+      for dependent in self.dependents(&next) {
+        if seen.insert(dependent.clone()) {
+          order.push(dependent.clone());
+          queue.push_back(dependent.clone());
+        }
+      }
+    }
+
+    for key in &order {
+      if let Some(storage) = self.storage_of(key.kind) {
+        storage.invalidate(&key.id);
+      }
+    }
+
+    let mut result = Ok(());
+
+    for key in &order {
+      let reload = match self.storage_of(key.kind) {
+        Some(storage) => storage.reloader(),
+        None => continue,
+      };
+
+      if let Err(msg) = reload(self, &key.id) {
+        error!("cannot reload {}: {}", key, msg);
+        if result.is_ok() {
+          result = Err(msg);
+        }
+      }
+    }
+
+    result
+  }
+
+  /// Drops every asset that no handle refers to anymore, returning how many were unloaded.
+  pub fn unload_unused(&mut self) -> usize {
+    let mut total = 0;
+
+    // unloading a dependent releases its handles, which may leave its dependencies unused
+    loop {
+      let mut unloaded = Vec::new();
+      for storage in self.storages.values_mut() {
+        let kind = storage.kind();
+        for id in storage.unload_unused() {
+          unloaded.push(AssetKey { kind, id });
+        }
+      }
+
+      if unloaded.is_empty() {
+        break;
+      }
+
+      for key in unloaded {
+        info!("unloaded {}", key);
+        self.forget(&key);
+        total += 1;
+      }
+    }
+
+    total
+  }
+
+  pub fn dependencies(&self, key: &AssetKey) -> impl Iterator<Item = &AssetKey> {
+    self.dependencies.get(key).into_iter().flatten()
+  }
+
+  pub fn dependents(&self, key: &AssetKey) -> impl Iterator<Item = &AssetKey> {
+    self.dependents.get(key).into_iter().flatten()
+  }
+
+  /// Runs `loader` for `id`, recording what the asset loaded as its dependencies.
+  fn run_loader<T: 'static>(
+    &mut self,
+    loader: &dyn AssetLoader<Asset = T>,
+    id: &AssetId,
+  ) -> Result<T, String> {
+    let key = AssetKey {
+      kind: loader.kind(),
+      id: id.clone(),
+    };
+    if !self.loading.insert(key.clone()) {
+      return Err(format!("{} depends on itself", key));
+    }
+
+    let mut ctx = LoadContext {
+      server: self,
+      dependencies: BTreeSet::default(),
+    };
+    let asset = loader.load(id, &mut ctx);
+    let dependencies = ctx.dependencies;
+
+    self.loading.remove(&key);
+    let asset = asset?;
+    self.record_dependencies(key, dependencies);
+
+    Ok(asset)
+  }
+
+  fn reload_asset<T: 'static>(&mut self, id: &AssetId) -> Result<(), String> {
+    let loader = match self.storage_for::<T>() {
+      Some(storage) => storage.loader.clone(),
+      None => return Ok(()),
+    };
+    let asset = self.run_loader(loader.as_ref(), id)?;
+    if let Some(slot) = self
+      .storage_for::<T>()
+      .and_then(|storage| storage.slots.get(id))
+    {
+      *slot.asset.borrow_mut() = Some(Rc::new(asset));
+    }
+    Ok(())
+  }
+
+  fn kind_of<T: 'static>(&self) -> Result<AssetKind, String> {
+    let type_id = TypeId::of::<T>();
+    self
+      .kinds
+      .iter()
+      .find(|(_, t)| **t == type_id)
+      .map(|(kind, _)| *kind)
+      .ok_or_else(|| String::from("no loader registered for asset type"))
+  }
+
+  fn storage_of(&mut self, kind: AssetKind) -> Option<&mut Box<dyn AnyStorage>> {
+    let type_id = self.kinds.get(&kind)?;
+    self.storages.get_mut(type_id)
+  }
+
+  fn storage_for<T: 'static>(&mut self) -> Option<&mut Storage<T>> {
+    self
+      .storages
+      .get_mut(&TypeId::of::<T>())?
+      .as_any_mut()
+      .downcast_mut::<Storage<T>>()
+  }
+
+  fn record_dependencies(&mut self, key: AssetKey, dependencies: BTreeSet<AssetKey>) {
+    self.forget_dependencies(&key);
+
+    for dependency in &dependencies {
+      self
+        .dependents
+        .entry(dependency.clone())
+        .or_default()
+        .insert(key.clone());
+    }
+
+    self.dependencies.insert(key, dependencies);
+  }
+
+  fn forget_dependencies(&mut self, key: &AssetKey) {
+    if let Some(old) = self.dependencies.remove(key) {
+      for dependency in old {
+        if let Some(dependents) = self.dependents.get_mut(&dependency) {
+          dependents.remove(key);
+        }
+      }
+    }
+  }
+
+  fn forget(&mut self, key: &AssetKey) {
+    self.forget_dependencies(key);
+    self.dependents.remove(key);
+  }
+}
+
+/// The ids of every entry in the configs of `kind`.
+fn config_ids(kind: AssetKind) -> Vec<AssetId> {
+  let mut ids = Vec::new();
+  super::iterate_configs(kind, |path, file_id| {
+    for local_id in super::config_keys(path) {
+      ids.push(file_id.extend(local_id));
+    }
+  });
+  ids
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// What each asset is loaded from: its value and the ids of the other parts it is
+  /// made of.
+  type Sources = Rc<RefCell<BTreeMap<String, (u32, Vec<String>)>>>;
+
+  struct Part {
+    value: u32,
+    parts: Vec<Handle<Part>>,
+  }
+
+  struct PartLoader {
+    sources: Sources,
+  }
+
+  impl AssetLoader for PartLoader {
+    type Asset = Part;
+
+    fn kind(&self) -> AssetKind {
+      AssetKind::GameObject
+    }
+
+    fn load(&self, id: &AssetId, ctx: &mut LoadContext) -> Result<Part, String> {
+      let (value, parts) = self
+        .sources
+        .borrow()
+        .get(&id.to_string())
+        .cloned()
+        .ok_or_else(|| format!("no source for {}", id))?;
+      let parts = parts
+        .iter()
+        .map(|part| ctx.load(&part.parse()?))
+        .collect::<Result<_, String>>()?;
+      Ok(Part { value, parts })
+    }
+  }
+
+  fn server(parts: &[(&str, u32, &[&str])]) -> (AssetServer, Sources) {
+    let sources: Sources = Rc::default();
+    for (id, value, parts) in parts {
+      let parts = parts.iter().map(|part| part.to_string()).collect();
+      sources.borrow_mut().insert(id.to_string(), (*value, parts));
+    }
+
+    let mut server = AssetServer::new();
+    server.register(PartLoader {
+      sources: sources.clone(),
+    });
+    (server, sources)
+  }
+
+  fn id(s: &str) -> AssetId {
+    s.parse().unwrap()
+  }
+
+  fn key(s: &str) -> AssetKey {
+    AssetKey {
+      kind: AssetKind::GameObject,
+      id: id(s),
+    }
+  }
+
+  #[test]
+  fn handles_share_one_asset() {
+    let (mut server, _) = server(&[("exp.a", 1, &[])]);
+    let first = server.load::<Part>(&id("exp.a")).unwrap();
+    let second = server.load::<Part>(&id("exp.a")).unwrap();
+
+    assert!(Rc::ptr_eq(&first.get().unwrap(), &second.get().unwrap()));
+    assert_eq!(first.ref_count(), 2);
+
+    drop(second);
+    assert_eq!(first.ref_count(), 1);
+  }
+
+  #[test]
+  fn detached_handles_count_only_their_users() {
+    let handle = Handle::detached(id("exp.atlas"), 5);
+    assert_eq!(handle.ref_count(), 1);
+
+    let clone = handle.clone();
+    assert_eq!(clone.ref_count(), 2);
+  }
+
+  #[test]
+  fn loaders_load_assets_of_their_own_type() {
+    let (mut server, _) = server(&[("exp.a", 1, &["exp.b"]), ("exp.b", 2, &[])]);
+    let a = server.load::<Part>(&id("exp.a")).unwrap().get().unwrap();
+
+    assert_eq!(a.parts[0].get().unwrap().value, 2);
+    assert_eq!(
+      server.dependencies(&key("exp.a")).collect::<Vec<_>>(),
+      [&key("exp.b")]
+    );
+    assert_eq!(
+      server.dependents(&key("exp.b")).collect::<Vec<_>>(),
+      [&key("exp.a")]
+    );
+  }
+
+  #[test]
+  fn assets_cannot_depend_on_themselves() {
+    let (mut server, _) = server(&[
+      ("exp.a", 1, &["exp.b"]),
+      ("exp.b", 2, &["exp.a"]),
+      ("exp.c", 3, &[]),
+    ]);
+
+    assert!(server.load::<Part>(&id("exp.a")).is_err());
+    assert!(server.load::<Part>(&id("exp.a")).is_err());
+    assert_eq!(
+      server
+        .load::<Part>(&id("exp.c"))
+        .unwrap()
+        .get()
+        .unwrap()
+        .value,
+      3
+    );
+  }
+
+  #[test]
+  fn reloading_updates_the_asset_and_its_dependents() {
+    let (mut server, sources) = server(&[("exp.a", 1, &["exp.b"]), ("exp.b", 2, &[])]);
+    let a = server.load::<Part>(&id("exp.a")).unwrap();
+    let b = server.load::<Part>(&id("exp.b")).unwrap();
+    let old_a = a.get().unwrap();
+
+    sources.borrow_mut().get_mut("exp.b").unwrap().0 = 20;
+    server.reload(&key("exp.b")).unwrap();
+
+    assert_eq!(b.get().unwrap().value, 20);
+    assert!(!Rc::ptr_eq(&old_a, &a.get().unwrap()));
+    assert_eq!(a.get().unwrap().parts[0].get().unwrap().value, 20);
+  }
+
+  #[test]
+  fn failed_reloads_invalidate_the_asset() {
+    let (mut server, sources) = server(&[("exp.a", 1, &[])]);
+    let a = server.load::<Part>(&id("exp.a")).unwrap();
+
+    sources.borrow_mut().remove("exp.a");
+    assert!(server.reload(&key("exp.a")).is_err());
+    assert!(!a.is_loaded());
+
+    sources
+      .borrow_mut()
+      .insert(String::from("exp.a"), (7, Vec::new()));
+    server.reload(&key("exp.a")).unwrap();
+    assert_eq!(a.get().unwrap().value, 7);
+  }
+
+  #[test]
+  fn unloading_drops_assets_without_handles() {
+    let (mut server, _) = server(&[
+      ("exp.a", 1, &["exp.b"]),
+      ("exp.b", 2, &[]),
+      ("exp.c", 3, &[]),
+    ]);
+    let a = server.load::<Part>(&id("exp.a")).unwrap();
+    let c = server.load::<Part>(&id("exp.c")).unwrap();
+    assert_eq!(server.unload_unused(), 0);
+
+    // b goes with a, which held the last handle to it
+    drop(a);
+    assert_eq!(server.unload_unused(), 2);
+    assert!(server.get::<Part>(&id("exp.a")).is_none());
+    assert!(server.get::<Part>(&id("exp.b")).is_none());
+    assert_eq!(server.dependents(&key("exp.b")).count(), 0);
+
+    assert_eq!(c.ref_count(), 1);
+    assert!(server.get::<Part>(&id("exp.c")).is_some());
+  }
+}
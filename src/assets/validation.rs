@@ -0,0 +1,314 @@
+use super::{AssetId, AssetKind};
+use std::{
+  collections::{BTreeMap, BTreeSet},
+  fmt::{self, Display, Formatter},
+  fs,
+  path::{Path, PathBuf},
+};
+
+mod keys {
+  pub const SHADER: &str = "shader";
+  pub const MODEL: &str = "model";
+  pub const ANIMATION: &str = "animation";
+  pub const TEXTURE: &str = "texture";
+  pub const FILE: &str = "file";
+  pub const PARTICLES: &str = "particles";
+}
+
+#[derive(Debug)]
+pub struct DanglingReference {
+  pub file: PathBuf,
+  pub owner: AssetId,
+  pub field: String,
+  pub target: String,
+  pub reason: String,
+}
+
+impl Display for DanglingReference {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "{}: {} '{}' -> '{}' ({})",
+      self.file.display(),
+      self.owner,
+      self.field,
+      self.target,
+      self.reason
+    )
+  }
+}
+
+type Fields = BTreeMap<String, String>;
+
+struct ConfigEntry {
+  file: PathBuf,
+  id: AssetId,
+  fields: Fields,
+}
+
+#[derive(Default)]
+struct AssetIndex {
+  defined: BTreeMap<AssetKind, BTreeSet<AssetId>>,
+  entries: BTreeMap<AssetKind, Vec<ConfigEntry>>,
+  dangling: Vec<DanglingReference>,
+}
+
+impl AssetIndex {
+  fn build() -> Self {
+    let mut index = Self::default();
+
+    for kind in AssetKind::CONFIGS {
+      super::iterate_configs(kind, |path, id| index.index_file(kind, path, id));
+    }
+
+    index
+  }
+
+  fn index_file(&mut self, kind: AssetKind, path: &Path, id: AssetId) {
+    let entries = match Self::read_entries(path) {
+      Ok(entries) => entries,
+      Err(reason) => {
+        self.dangling.push(DanglingReference {
+          file: path.to_path_buf(),
+          owner: id.clone(),
+          field: String::new(),
+          target: String::new(),
+          reason,
+        });
+        return;
+      }
+    };
+
+    for (local_id, fields) in entries {
+      let id = id.extend(local_id);
+      self.defined.entry(kind).or_default().insert(id.clone());
+      self.entries.entry(kind).or_default().push(ConfigEntry {
+        file: path.to_path_buf(),
+        id,
+        fields,
+      });
+    }
+  }
+
+  // flattens each top level entry down to its string fields, which is where every reference lives
+  fn read_entries(path: &Path) -> Result<Vec<(String, Fields)>, String> {
+    let data = fs::read_to_string(path).map_err(|e| format!("cannot read file: {}", e))?;
+
+    let mut entries = Vec::new();
+
+    if path.extension().is_some_and(|ext| ext == "toml") {
+      let root = data
+        .parse::<toml::Value>()
+        .map_err(|e| format!("cannot parse file: {}", e))?;
+
+      for (local_id, entry) in root.as_table().into_iter().flatten() {
+        let fields = entry
+          .as_table()
+          .into_iter()
+          .flatten()
+          .filter_map(|(k, v)| v.as_str().map(|v| (k.clone(), v.to_string())))
+          .collect();
+        entries.push((local_id.clone(), fields));
+      }
+    } else {
+      let root = serde_json::from_str::<serde_json::Value>(&data)
+        .map_err(|e| format!("cannot parse file: {}", e))?;
+
+      for (local_id, entry) in root.as_object().into_iter().flatten() {
+        let fields = entry
+          .as_object()
+          .into_iter()
+          .flatten()
+          .filter_map(|(k, v)| v.as_str().map(|v| (k.clone(), v.to_string())))
+          .collect();
+        entries.push((local_id.clone(), fields));
+      }
+    }
+
+    Ok(entries)
+  }
+
+  fn check_id(&mut self, kind: AssetKind, field: &str, target_kind: AssetKind) {
+    let mut dangling = Vec::new();
+
+    for entry in self.entries.get(&kind).into_iter().flatten() {
+      if let Some(target) = entry.fields.get(field) {
+        let reason = match target.parse::<AssetId>() {
+          Ok(target_id) => {
+            let defined = self
+              .defined
+              .get(&target_kind)
+              .is_some_and(|ids| ids.contains(&target_id));
+            if defined {
+              continue;
+            }
+            format!("no {} with this id", target_kind.config_dir())
+          }
+          Err(e) => e,
+        };
+
+        dangling.push(DanglingReference {
+          file: entry.file.clone(),
+          owner: entry.id.clone(),
+          field: field.to_string(),
+          target: target.clone(),
+          reason,
+        });
+      }
+    }
+
+    self.dangling.append(&mut dangling);
+  }
+
+  fn check_files(&mut self, kind: AssetKind, fields: &[&str], dir: &[&str]) {
+    let mut dangling = Vec::new();
+
+    for entry in self.entries.get(&kind).into_iter().flatten() {
+      let mut base = super::namespace_dir(entry.id.namespace());
+      for part in dir {
+        base.push(part);
+      }
+
+      for (field, target) in &entry.fields {
+        if !fields.is_empty() && !fields.contains(&field.as_str()) {
+          continue;
+        }
+
+        let file = base.join(target);
+        if !file.is_file() {
+          dangling.push(DanglingReference {
+            file: entry.file.clone(),
+            owner: entry.id.clone(),
+            field: field.clone(),
+            target: target.clone(),
+            reason: format!("missing file {}", file.display()),
+          });
+        }
+      }
+    }
+
+    self.dangling.append(&mut dangling);
+  }
+}
+
+/// Checks that every id and file referenced from the game, animation, texture,
+/// shader, font and particle configs exists, returning the references that do not
+/// resolve.
+pub fn validate_references() -> Vec<DanglingReference> {
+  let mut index = AssetIndex::build();
+
+  index.check_id(AssetKind::GameObject, keys::SHADER, AssetKind::Shader);
+  index.check_id(AssetKind::GameObject, keys::MODEL, AssetKind::Model);
+  index.check_id(AssetKind::GameObject, keys::TEXTURE, AssetKind::Texture);
+  index.check_id(AssetKind::GameObject, keys::ANIMATION, AssetKind::Animation);
+  index.check_id(AssetKind::GameObject, keys::PARTICLES, AssetKind::Particles);
+  index.check_id(AssetKind::Animation, keys::TEXTURE, AssetKind::Texture);
+  index.check_files(AssetKind::Texture, &[keys::FILE], &["textures"]);
+  index.check_files(AssetKind::Shader, &[], &["shaders", "src"]);
+  index.check_id(AssetKind::Font, keys::SHADER, AssetKind::Shader);
+  index.check_files(AssetKind::Font, &[keys::FILE], &["fonts"]);
+  index.check_id(AssetKind::Particles, keys::TEXTURE, AssetKind::Texture);
+
+  index.dangling
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn id(s: &str) -> AssetId {
+    s.parse().unwrap()
+  }
+
+  /// Adds an entry to `index` as if a config file defined it.
+  fn define(index: &mut AssetIndex, kind: AssetKind, owner: &str, fields: &[(&str, &str)]) {
+    index.defined.entry(kind).or_default().insert(id(owner));
+    index.entries.entry(kind).or_default().push(ConfigEntry {
+      file: PathBuf::from("test.json"),
+      id: id(owner),
+      fields: fields
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect(),
+    });
+  }
+
+  #[test]
+  fn defined_references_resolve() {
+    let mut index = AssetIndex::default();
+    define(&mut index, AssetKind::Shader, "exp.shaders.basic", &[]);
+    define(
+      &mut index,
+      AssetKind::GameObject,
+      "exp.game.square",
+      &[(keys::SHADER, "exp.shaders.basic")],
+    );
+
+    index.check_id(AssetKind::GameObject, keys::SHADER, AssetKind::Shader);
+    assert!(index.dangling.is_empty());
+  }
+
+  #[test]
+  fn undefined_references_dangle() {
+    let mut index = AssetIndex::default();
+    // defined, but as another kind
+    define(&mut index, AssetKind::Model, "exp.shaders.basic", &[]);
+    define(
+      &mut index,
+      AssetKind::GameObject,
+      "exp.game.square",
+      &[(keys::SHADER, "exp.shaders.basic")],
+    );
+
+    index.check_id(AssetKind::GameObject, keys::SHADER, AssetKind::Shader);
+    assert_eq!(index.dangling.len(), 1);
+    let dangling = &index.dangling[0];
+    assert_eq!(dangling.owner, id("exp.game.square"));
+    assert_eq!(dangling.field, keys::SHADER);
+    assert_eq!(dangling.target, "exp.shaders.basic");
+    assert_eq!(dangling.reason, "no shaders with this id");
+    assert_eq!(
+      dangling.to_string(),
+      "test.json: exp.game.square 'shader' -> 'exp.shaders.basic' (no shaders with this id)"
+    );
+  }
+
+  #[test]
+  fn malformed_references_dangle() {
+    let mut index = AssetIndex::default();
+    define(
+      &mut index,
+      AssetKind::GameObject,
+      "exp.game.square",
+      &[(keys::MODEL, "exp..square"), (keys::SHADER, "exp")],
+    );
+
+    index.check_id(AssetKind::GameObject, keys::MODEL, AssetKind::Model);
+    index.check_id(AssetKind::GameObject, keys::SHADER, AssetKind::Shader);
+    assert_eq!(index.dangling.len(), 2);
+    assert!(index.dangling[0].reason.contains("empty id segment"));
+    assert!(index.dangling[1].reason.contains("only names a namespace"));
+  }
+
+  #[test]
+  fn missing_files_dangle() {
+    let mut index = AssetIndex::default();
+    define(
+      &mut index,
+      AssetKind::Texture,
+      "exp.render.particle",
+      &[(keys::FILE, "particle.png")],
+    );
+    define(
+      &mut index,
+      AssetKind::Texture,
+      "exp.render.missing",
+      &[(keys::FILE, "missing.png")],
+    );
+
+    index.check_files(AssetKind::Texture, &[keys::FILE], &["textures"]);
+    assert_eq!(index.dangling.len(), 1);
+    assert_eq!(index.dangling[0].owner, id("exp.render.missing"));
+    assert_eq!(index.dangling[0].target, "missing.png");
+  }
+}
@@ -0,0 +1,6 @@
+pub mod components;
+mod prototypes;
+mod world;
+
+pub use prototypes::{Prototype, PrototypeLoader};
+pub use world::{Entity, World};
@@ -0,0 +1,51 @@
+use super::Prototype;
+use crate::assets::{AssetId, Handle};
+use crate::gfx::RenderState;
+use crate::map;
+use crate::math::glm::{self, Mat4, Vec2};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transform {
+  pub position: Vec2,
+  pub rotation: f32,
+  pub scale: Vec2,
+}
+
+impl Default for Transform {
+  fn default() -> Self {
+    Self {
+      position: glm::vec2(0.0, 0.0),
+      rotation: 0.0,
+      scale: glm::vec2(1.0, 1.0),
+    }
+  }
+}
+
+impl Transform {
+  pub fn matrix(&self) -> Mat4 {
+    let translated = glm::translate(
+      &Mat4::identity(),
+      &glm::vec3(self.position.x, self.position.y, 0.0),
+    );
+    let rotated = glm::rotate_z(&translated, self.rotation.to_radians());
+    glm::scale(&rotated, &glm::vec3(self.scale.x, self.scale.y, 1.0))
+  }
+}
+
+pub struct Renderable {
+  pub prototype: Handle<Prototype>,
+  pub render_state: RenderState,
+  pub animation: Option<AssetId>,
+  pub layer: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Name(pub String);
+
+/// The script driving an entity, from its prototype unless the map placed it with
+/// another.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Script(pub AssetId);
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Properties(pub map::Properties);
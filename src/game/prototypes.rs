@@ -0,0 +1,153 @@
+use crate::assets::{self, AssetId, AssetKind, AssetLoader, Handle, LoadContext};
+use crate::gfx::{Light, Model, ParticleEffect, RenderState, Shader, Texture};
+use crate::physics::{Collider, RigidBody};
+use glium::{uniforms::Uniforms, Surface};
+use serde_json::Value;
+
+mod keys {
+  pub const SHADER: &str = "shader";
+  pub const MODEL: &str = "model";
+  pub const TEXTURE: &str = "texture";
+  pub const ANIMATION: &str = "animation";
+  pub const SCRIPT: &str = "script";
+  pub const DRAW_DESCRIPTION: &str = "draw_description";
+  pub const COLLIDER: &str = "collider";
+  pub const BODY: &str = "body";
+  pub const LIGHT: &str = "light";
+  pub const OCCLUDER: &str = "occluder";
+  pub const PARTICLES: &str = "particles";
+}
+
+pub struct Prototype {
+  pub shader: Handle<Shader>,
+  pub model: Handle<Model>,
+  /// Drawn as a sprite of this texture instead of with the model and shader.
+  pub texture: Option<Handle<Texture>>,
+  pub animation: Option<AssetId>,
+  pub script: Option<AssetId>,
+  pub render_state: RenderState,
+  pub collider: Option<Collider>,
+  pub body: Option<RigidBody>,
+  pub light: Option<Light>,
+  /// Whether the collider casts shadows.
+  pub occluder: bool,
+  /// Played from the entity for as long as it exists.
+  pub particles: Option<Handle<ParticleEffect>>,
+}
+
+impl Prototype {
+  /// Draws the model with the shader, for prototypes without a texture. Entities placed
+  /// with their own render state pass it over the one of the prototype.
+  pub fn draw<S: Surface, U: Uniforms>(
+    &self,
+    surface: &mut S,
+    uniforms: &U,
+    render_state: &RenderState,
+  ) -> Result<(), String> {
+    let shader = self
+      .shader
+      .get()
+      .ok_or_else(|| format!("shader {} is not loaded", self.shader.id()))?;
+    let model = self
+      .model
+      .get()
+      .ok_or_else(|| format!("model {} is not loaded", self.model.id()))?;
+
+    surface
+      .draw(
+        model.vertices(),
+        model.indices(),
+        shader.program(),
+        uniforms,
+        &render_state.draw_parameters(),
+      )
+      .map_err(|e| e.to_string())
+  }
+
+  fn parse(value: &Value, ctx: &mut LoadContext) -> Result<Self, String> {
+    let string = |key: &str| -> Result<Option<&str>, String> {
+      match value.get(key) {
+        Some(Value::String(s)) => Ok(Some(s.as_str())),
+        Some(_) => Err(format!("'{}' must be a string", key)),
+        None => Ok(None),
+      }
+    };
+
+    let id = |key: &str| -> Result<Option<AssetId>, String> {
+      string(key)?
+        .map(|s| s.parse::<AssetId>().map_err(|e| format!("'{}' {}", key, e)))
+        .transpose()
+    };
+
+    let required = |key: &str| -> Result<AssetId, String> {
+      id(key)?.ok_or_else(|| format!("missing '{}'", key))
+    };
+
+    let render_state = match value.get(keys::DRAW_DESCRIPTION) {
+      Some(description) => RenderState::try_from(description)?,
+      None => RenderState::default(),
+    };
+
+    let collider = value
+      .get(keys::COLLIDER)
+      .map(Collider::try_from)
+      .transpose()?;
+
+    let body = value.get(keys::BODY).map(RigidBody::try_from).transpose()?;
+
+    let light = value.get(keys::LIGHT).map(Light::try_from).transpose()?;
+
+    let occluder = match value.get(keys::OCCLUDER) {
+      Some(occluder) => occluder
+        .as_bool()
+        .ok_or_else(|| format!("'{}' must be a boolean", keys::OCCLUDER))?,
+      None => false,
+    };
+    if occluder && collider.is_none() {
+      return Err(format!(
+        "'{}' needs a '{}' to cast shadows with",
+        keys::OCCLUDER,
+        keys::COLLIDER
+      ));
+    }
+
+    Ok(Self {
+      shader: ctx.load(&required(keys::SHADER)?)?,
+      model: ctx.load(&required(keys::MODEL)?)?,
+      texture: id(keys::TEXTURE)?
+        .map(|texture| ctx.load(&texture))
+        .transpose()?,
+      animation: id(keys::ANIMATION)?,
+      script: id(keys::SCRIPT)?,
+      render_state,
+      collider,
+      body,
+      light,
+      occluder,
+      particles: id(keys::PARTICLES)?
+        .map(|particles| ctx.load(&particles))
+        .transpose()?,
+    })
+  }
+}
+
+pub struct PrototypeLoader;
+
+impl PrototypeLoader {
+  pub fn new() -> Self {
+    Self
+  }
+}
+
+impl AssetLoader for PrototypeLoader {
+  type Asset = Prototype;
+
+  fn kind(&self) -> AssetKind {
+    AssetKind::GameObject
+  }
+
+  fn load(&self, id: &AssetId, ctx: &mut LoadContext) -> Result<Prototype, String> {
+    let entry = assets::read_json_entry(AssetKind::GameObject, id)?;
+    Prototype::parse(&entry, ctx).map_err(|msg| format!("rejecting game object {}: {}", id, msg))
+  }
+}
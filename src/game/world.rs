@@ -0,0 +1,345 @@
+use super::components::{Name, Properties, Renderable, Script, Transform};
+use super::Prototype;
+use crate::assets::{AssetId, AssetServer, Handle};
+use crate::gfx::{Occluder, ParticleEmitter};
+use crate::map::Map;
+use log::{error, info};
+use std::{
+  any::{Any, TypeId},
+  collections::{BTreeMap, BTreeSet, HashMap},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub struct Entity(u32);
+
+impl Entity {
+  pub fn index(&self) -> u32 {
+    self.0
+  }
+}
+
+trait AnyComponents {
+  fn remove(&mut self, entity: Entity);
+
+  fn clear(&mut self);
+
+  fn as_any(&self) -> &dyn Any;
+
+  fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: 'static> AnyComponents for BTreeMap<Entity, T> {
+  fn remove(&mut self, entity: Entity) {
+    BTreeMap::remove(self, &entity);
+  }
+
+  fn clear(&mut self) {
+    BTreeMap::clear(self);
+  }
+
+  fn as_any(&self) -> &dyn Any {
+    self
+  }
+
+  fn as_any_mut(&mut self) -> &mut dyn Any {
+    self
+  }
+}
+
+#[derive(Default)]
+pub struct World {
+  next_entity: u32,
+  entities: BTreeSet<Entity>,
+  components: HashMap<TypeId, Box<dyn AnyComponents>>,
+  map: Option<Handle<Map>>,
+}
+
+impl World {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn spawn(&mut self) -> Entity {
+    let entity = Entity(self.next_entity);
+    self.next_entity += 1;
+    self.entities.insert(entity);
+    entity
+  }
+
+  pub fn despawn(&mut self, entity: Entity) {
+    if self.entities.remove(&entity) {
+      for storage in self.components.values_mut() {
+        storage.remove(entity);
+      }
+    }
+  }
+
+  pub fn contains(&self, entity: Entity) -> bool {
+    self.entities.contains(&entity)
+  }
+
+  pub fn entities(&self) -> impl Iterator<Item = Entity> + '_ {
+    self.entities.iter().copied()
+  }
+
+  pub fn insert<T: 'static>(&mut self, entity: Entity, component: T) {
+    if self.contains(entity) {
+      self.storage_mut::<T>().insert(entity, component);
+    }
+  }
+
+  pub fn get<T: 'static>(&self, entity: Entity) -> Option<&T> {
+    self.storage::<T>()?.get(&entity)
+  }
+
+  pub fn get_mut<T: 'static>(&mut self, entity: Entity) -> Option<&mut T> {
+    self.storage_mut::<T>().get_mut(&entity)
+  }
+
+  pub fn query<T: 'static>(&self) -> impl Iterator<Item = (Entity, &T)> {
+    self
+      .storage::<T>()
+      .into_iter()
+      .flat_map(|storage| storage.iter().map(|(entity, c)| (*entity, c)))
+  }
+
+  pub fn query_mut<T: 'static>(&mut self) -> impl Iterator<Item = (Entity, &mut T)> {
+    self
+      .storage_mut::<T>()
+      .iter_mut()
+      .map(|(entity, c)| (*entity, c))
+  }
+
+  pub fn clear(&mut self) {
+    self.entities.clear();
+    for storage in self.components.values_mut() {
+      storage.clear();
+    }
+    self.map = None;
+  }
+
+  pub fn map(&self) -> Option<&Handle<Map>> {
+    self.map.as_ref()
+  }
+
+  /// Replaces everything in the world with the contents of a map, spawning an
+  /// entity for every object placed on its object layers.
+  pub fn enter_map(&mut self, server: &mut AssetServer, id: &AssetId) -> Result<(), String> {
+    let handle = server.load::<Map>(id)?;
+    let map = handle
+      .get()
+      .ok_or_else(|| format!("map {} is not loaded", id))?;
+
+    self.clear();
+
+    for layer in map.object_layers() {
+      for object in &layer.objects {
+        let prototype = match server.load::<Prototype>(&object.prototype) {
+          Ok(prototype) => prototype,
+          Err(msg) => {
+            error!("cannot place {} on {}: {}", object.prototype, id, msg);
+            continue;
+          }
+        };
+
+        let render_state = match (&object.overrides.render_state, prototype.get()) {
+          (Some(render_state), _) => *render_state,
+          (None, Some(prototype)) => prototype.render_state,
+          (None, None) => Default::default(),
+        };
+
+        let (collider, body, light, occluder, particles, script) = match prototype.get() {
+          Some(prototype) => (
+            prototype.collider.clone(),
+            prototype.body.clone(),
+            prototype.light.clone(),
+            prototype.occluder,
+            prototype.particles.clone(),
+            prototype.script.clone(),
+          ),
+          None => (None, None, None, false, None, None),
+        };
+        let script = object.overrides.script.clone().or(script);
+
+        let entity = self.spawn();
+        self.insert(
+          entity,
+          Transform {
+            position: object.position,
+            rotation: object.rotation,
+            scale: object.scale,
+          },
+        );
+        self.insert(
+          entity,
+          Renderable {
+            prototype,
+            render_state,
+            animation: object.overrides.animation.clone(),
+            layer: layer.name.clone(),
+          },
+        );
+        if let Some(collider) = collider {
+          self.insert(entity, collider);
+        }
+        if let Some(body) = body {
+          self.insert(entity, body);
+        }
+        if let Some(light) = light {
+          self.insert(entity, light);
+        }
+        if occluder {
+          self.insert(entity, Occluder);
+        }
+        if let Some(particles) = particles {
+          self.insert(entity, ParticleEmitter::new(particles, entity.index() as u64));
+        }
+        if let Some(script) = script {
+          self.insert(entity, Script(script));
+        }
+        self.insert(entity, Properties(object.properties.clone()));
+        if let Some(name) = &object.name {
+          self.insert(entity, Name(name.clone()));
+        }
+      }
+    }
+
+    info!("entered map {} with {} entities", id, self.entities.len());
+
+    self.map = Some(handle);
+
+    Ok(())
+  }
+
+  fn storage<T: 'static>(&self) -> Option<&BTreeMap<Entity, T>> {
+    self
+      .components
+      .get(&TypeId::of::<T>())
+      .and_then(|storage| storage.as_any().downcast_ref())
+  }
+
+  fn storage_mut<T: 'static>(&mut self) -> &mut BTreeMap<Entity, T> {
+    self
+      .components
+      .entry(TypeId::of::<T>())
+      .or_insert_with(|| Box::new(BTreeMap::<Entity, T>::new()))
+      .as_any_mut()
+      .downcast_mut()
+      .unwrap()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::map::PropertyValue;
+  use crate::math::glm;
+  use crate::physics::{Collider, RigidBody};
+  use crate::view::headless::HeadlessBackend;
+
+  /// A server loading from the assets directory, drawing into a headless context.
+  fn server() -> AssetServer {
+    let backend = HeadlessBackend::any((16, 16)).unwrap();
+    let ctx = unsafe { glium::backend::Context::new(backend, true, Default::default()) }.unwrap();
+    let mut server = AssetServer::new();
+    crate::register_loaders(&mut server, &ctx);
+    server
+  }
+
+  fn named(world: &World, name: &str) -> Entity {
+    world
+      .query::<Name>()
+      .find(|(_, Name(n))| n == name)
+      .map(|(entity, _)| entity)
+      .unwrap()
+  }
+
+  #[test]
+  fn entering_a_map_spawns_its_objects() {
+    let mut server = server();
+    let mut world = World::new();
+    world
+      .enter_map(&mut server, &"exp.test".parse().unwrap())
+      .unwrap();
+
+    assert_eq!(world.entities().count(), 2);
+    assert_eq!(
+      world.map().map(|map| map.id().to_string()).as_deref(),
+      Some("exp.test")
+    );
+
+    let player = named(&world, "player");
+    assert_eq!(
+      world.get::<Transform>(player).unwrap().position,
+      glm::vec2(32.0, 32.0)
+    );
+    assert_eq!(world.get::<Renderable>(player).unwrap().layer, "entities");
+    assert!(world.get::<Collider>(player).is_some());
+    assert!(world.get::<RigidBody>(player).is_some());
+    assert!(world.get::<ParticleEmitter>(player).is_some());
+    assert_eq!(
+      world.get::<Script>(player),
+      Some(&Script("exp.game.player".parse().unwrap()))
+    );
+  }
+
+  #[test]
+  fn objects_apply_their_overrides_and_properties() {
+    let mut server = server();
+    let mut world = World::new();
+    world
+      .enter_map(&mut server, &"exp.test".parse().unwrap())
+      .unwrap();
+
+    let square = world
+      .entities()
+      .find(|entity| world.get::<Name>(*entity).is_none())
+      .unwrap();
+    let transform = world.get::<Transform>(square).unwrap();
+    assert_eq!(transform.position, glm::vec2(80.0, 48.0));
+    assert_eq!(transform.scale, glm::vec2(0.5, 0.5));
+    // the map draws it as a wireframe although its prototype does not
+    assert!(
+      world
+        .get::<Renderable>(square)
+        .unwrap()
+        .render_state
+        .wireframe
+    );
+    assert_eq!(
+      world.get::<Properties>(square).unwrap().0.get("spin"),
+      Some(&PropertyValue::Float(1.5))
+    );
+  }
+
+  #[test]
+  fn entering_a_map_replaces_what_was_there() {
+    let mut server = server();
+    let mut world = World::new();
+    let stray = world.spawn();
+    world.insert(stray, Name(String::from("stray")));
+
+    world
+      .enter_map(&mut server, &"exp.test".parse().unwrap())
+      .unwrap();
+    world
+      .enter_map(&mut server, &"exp.test".parse().unwrap())
+      .unwrap();
+
+    assert_eq!(world.entities().count(), 2);
+    assert!(world.query::<Name>().all(|(_, Name(name))| name != "stray"));
+  }
+
+  #[test]
+  fn missing_maps_leave_the_world_alone() {
+    let mut server = server();
+    let mut world = World::new();
+    let stray = world.spawn();
+
+    assert!(world
+      .enter_map(&mut server, &"exp.missing".parse().unwrap())
+      .is_err());
+    assert!(world.contains(stray));
+    assert!(world.map().is_none());
+  }
+}
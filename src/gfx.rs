@@ -1,4 +1,30 @@
+mod font;
 mod image;
+mod imgui_renderer;
+mod lighting;
+mod model;
+mod particles;
+mod pipeline;
+mod render_graph;
+mod render_state;
 mod shaders;
+mod sprite_batch;
+mod tilemap;
 
-pub use shaders::{Shader, ShaderRepository, ShaderSources};
+pub use font::{
+  Align, Font, FontLoader, Icons, RichLayout, RichText, TextEffects, TextLayout, Typewriter,
+};
+pub use image::{Filter, Texture, TextureLoader};
+pub use imgui_renderer::ImguiRenderer;
+pub use lighting::{Light, LightKind, LightRenderer, Occluder, Shadows};
+pub use model::{Model, ModelLoader, Vertex};
+pub use particles::{
+  spawn_effect, update_emitters, ParticleEffect, ParticleEffectLoader, ParticleEmitter,
+  ParticleRenderer,
+};
+pub use pipeline::{PipelineLoader, UniformParam};
+pub use render_graph::{PassInputs, PassRenderer, RenderGraph};
+pub use render_state::{BlendMode, RenderState, Scissor};
+pub use shaders::{Shader, ShaderLoader};
+pub use sprite_batch::{BatchStats, Sprite, SpriteBatch};
+pub use tilemap::TilemapRenderer;
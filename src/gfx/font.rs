@@ -0,0 +1,473 @@
+mod atlas;
+mod bmfont;
+mod effects;
+mod layout;
+mod markup;
+mod rich;
+mod sdf;
+
+use super::{image, Filter, Shader, Texture};
+use crate::assets::{self, AssetId, AssetKind, AssetLoader, Handle, LoadContext};
+use crate::math::glm::{self, Vec2};
+use atlas::Atlas;
+use bmfont::BmFont;
+use geo::Rect;
+use log::error;
+use rusttype::Scale;
+use std::{cell::RefCell, collections::HashMap, fs, rc::Rc};
+
+pub use effects::TextEffects;
+pub use layout::{Align, TextLayout};
+pub use markup::{RichText, Style, Typewriter};
+pub use rich::{Icons, RichLayout};
+
+mod keys {
+  pub const TYPE: &str = "type";
+  pub const FILE: &str = "file";
+  pub const SIZE: &str = "size";
+  pub const FILTER: &str = "filter";
+  pub const MODE: &str = "mode";
+  pub const SPREAD: &str = "spread";
+  pub const SHADER: &str = "shader";
+
+  pub const TYPE_TTF: &str = "ttf";
+  pub const TYPE_BMFONT: &str = "bmfont";
+
+  pub const MODE_BITMAP: &str = "bitmap";
+  pub const MODE_SDF: &str = "sdf";
+}
+
+/// How far a distance field reaches past the outline, in atlas texels, unless the
+/// config says otherwise.
+const DEFAULT_SPREAD: f32 = 4.0;
+
+/// Drawn in place of characters a bitmap font does not have.
+const REPLACEMENT: char = '?';
+
+/// Where a character is drawn from and how far it moves the pen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Glyph {
+  pub page: usize,
+  /// The part of the page to draw in pixels from its top left, `None` for characters
+  /// with nothing to draw like spaces.
+  pub region: Option<Rect<f32>>,
+  /// From the pen position at the top of the line to the top left of the region.
+  pub offset: Vec2,
+  pub advance: f32,
+}
+
+enum Source {
+  /// TrueType and OpenType outlines, rasterized into the atlas the first time each
+  /// character is asked for.
+  Outline {
+    font: rusttype::Font<'static>,
+    scale: Scale,
+    /// Glyphs are turned into distance fields this far across when set.
+    spread: Option<f32>,
+    glyphs: RefCell<HashMap<char, Glyph>>,
+    atlas: RefCell<Atlas>,
+  },
+  /// Glyphs drawn ahead of time into page images.
+  Bitmap {
+    glyphs: HashMap<char, Glyph>,
+    kerning: HashMap<(char, char), f32>,
+    pages: Vec<Handle<Texture>>,
+  },
+}
+
+/// Glyph pages holding distance to the outline instead of coverage, drawn with a
+/// shader that finds the edge again at whatever size the text ends up on screen.
+#[derive(Clone)]
+pub struct DistanceField {
+  /// How far past the outline the field reaches, in texels of the pages.
+  pub spread: f32,
+  pub shader: Handle<Shader>,
+}
+
+pub struct Font {
+  source: Source,
+  /// The size glyphs are drawn at without scaling, in pixels.
+  size: f32,
+  line_height: f32,
+  ascent: f32,
+  distance_field: Option<DistanceField>,
+}
+
+impl Font {
+  pub fn size(&self) -> f32 {
+    self.size
+  }
+
+  /// Set for fonts that can be scaled without blurring and can have effects.
+  pub fn distance_field(&self) -> Option<&DistanceField> {
+    self.distance_field.as_ref()
+  }
+
+  /// The distance from the top of one line to the top of the next.
+  pub fn line_height(&self) -> f32 {
+    self.line_height
+  }
+
+  /// The distance from the top of a line to its baseline.
+  pub fn ascent(&self) -> f32 {
+    self.ascent
+  }
+
+  pub fn glyph(&self, character: char) -> Option<Glyph> {
+    match &self.source {
+      Source::Outline {
+        font,
+        scale,
+        spread,
+        glyphs,
+        atlas,
+      } => {
+        if let Some(glyph) = glyphs.borrow().get(&character) {
+          return Some(*glyph);
+        }
+
+        let mut atlas = atlas.borrow_mut();
+        let glyph = match spread {
+          Some(spread) => self.rasterize_field(font, *scale, *spread, &mut atlas, character),
+          None => self.rasterize(font, *scale, &mut atlas, character),
+        }
+        .map_err(|msg| error!("cannot rasterize '{}': {}", character, msg))
+        .ok()?;
+        glyphs.borrow_mut().insert(character, glyph);
+        Some(glyph)
+      }
+      Source::Bitmap { glyphs, .. } => glyphs
+        .get(&character)
+        .or_else(|| glyphs.get(&REPLACEMENT))
+        .copied(),
+    }
+  }
+
+  /// How much closer the second character is drawn to the first than their advance.
+  pub fn kerning(&self, first: char, second: char) -> f32 {
+    match &self.source {
+      Source::Outline { font, scale, .. } => font.pair_kerning(*scale, first, second),
+      Source::Bitmap { kerning, .. } => kerning.get(&(first, second)).copied().unwrap_or(0.0),
+    }
+  }
+
+  /// The texture glyphs on a page are drawn from.
+  pub fn page(&self, page: usize) -> Option<Handle<Texture>> {
+    match &self.source {
+      Source::Outline { atlas, .. } => atlas.borrow().page(page),
+      Source::Bitmap { pages, .. } => pages.get(page).cloned(),
+    }
+  }
+
+  fn rasterize(
+    &self,
+    font: &rusttype::Font<'static>,
+    scale: Scale,
+    atlas: &mut Atlas,
+    character: char,
+  ) -> Result<Glyph, String> {
+    let glyph = font.glyph(character).scaled(scale);
+    let advance = glyph.h_metrics().advance_width;
+    let glyph = glyph.positioned(rusttype::point(0.0, 0.0));
+
+    let bounds = match glyph.pixel_bounding_box() {
+      Some(bounds) => bounds,
+      None => {
+        return Ok(Glyph {
+          page: 0,
+          region: None,
+          offset: glm::vec2(0.0, 0.0),
+          advance,
+        })
+      }
+    };
+
+    // white everywhere, the outline only shows through the alpha
+    let (width, height) = (bounds.width() as u32, bounds.height() as u32);
+    let mut pixels = vec![255u8; (width * height * 4) as usize];
+    glyph.draw(|x, y, coverage| {
+      pixels[((y * width + x) * 4 + 3) as usize] = (coverage * 255.0).round() as u8;
+    });
+    let (page, region) = atlas.insert((width, height), &pixels)?;
+
+    Ok(Glyph {
+      page,
+      region: Some(region),
+      // the bounds are from the baseline, which is `ascent` below the top of the line
+      offset: glm::vec2(bounds.min.x as f32, self.ascent + bounds.min.y as f32),
+      advance,
+    })
+  }
+
+  /// Like `rasterize`, but keeps the distance to the outline, with `spread` texels
+  /// around the glyph for the field to fade out in.
+  fn rasterize_field(
+    &self,
+    font: &rusttype::Font<'static>,
+    scale: Scale,
+    spread: f32,
+    atlas: &mut Atlas,
+    character: char,
+  ) -> Result<Glyph, String> {
+    let advance = font
+      .glyph(character)
+      .scaled(scale)
+      .h_metrics()
+      .advance_width;
+    let oversample = sdf::OVERSAMPLE as i32;
+    let large = Scale {
+      x: scale.x * oversample as f32,
+      y: scale.y * oversample as f32,
+    };
+    let glyph = font
+      .glyph(character)
+      .scaled(large)
+      .positioned(rusttype::point(0.0, 0.0));
+
+    let bounds = match glyph.pixel_bounding_box() {
+      Some(bounds) => bounds,
+      None => {
+        return Ok(Glyph {
+          page: 0,
+          region: None,
+          offset: glm::vec2(0.0, 0.0),
+          advance,
+        })
+      }
+    };
+
+    // padded out to whole atlas texels, so the large bitmap shrinks evenly
+    let padding = spread.ceil() as i32 * oversample;
+    let snap_down = |value: i32| value.div_euclid(oversample) * oversample;
+    let snap_up = |value: i32| snap_down(value + oversample - 1);
+    let (left, top) = (
+      snap_down(bounds.min.x - padding),
+      snap_down(bounds.min.y - padding),
+    );
+    let (right, bottom) = (
+      snap_up(bounds.max.x + padding),
+      snap_up(bounds.max.y + padding),
+    );
+
+    let (width, height) = ((right - left) as u32, (bottom - top) as u32);
+    let (shift_x, shift_y) = ((bounds.min.x - left) as u32, (bounds.min.y - top) as u32);
+    let mut coverage = vec![0.0; (width * height) as usize];
+    glyph.draw(|x, y, value| {
+      coverage[((y + shift_y) * width + x + shift_x) as usize] = value;
+    });
+
+    let pixels: Vec<u8> = sdf::distance_field(&coverage, width, height, spread)
+      .into_iter()
+      .flat_map(|distance| [255, 255, 255, distance])
+      .collect();
+    let size = (width / sdf::OVERSAMPLE, height / sdf::OVERSAMPLE);
+    let (page, region) = atlas.insert(size, &pixels)?;
+
+    Ok(Glyph {
+      page,
+      region: Some(region),
+      offset: glm::vec2(
+        (left / oversample) as f32,
+        self.ascent + (top / oversample) as f32,
+      ),
+      advance,
+    })
+  }
+}
+
+pub struct FontLoader {
+  ctx: Rc<glium::backend::Context>,
+}
+
+impl FontLoader {
+  pub fn new(ctx: Rc<glium::backend::Context>) -> Self {
+    Self { ctx }
+  }
+
+  fn outline(
+    &self,
+    id: &AssetId,
+    data: Vec<u8>,
+    size: f32,
+    filter: Filter,
+    distance_field: Option<DistanceField>,
+  ) -> Result<Font, String> {
+    let font = rusttype::Font::try_from_vec(data)
+      .ok_or_else(|| String::from("not a TrueType or OpenType font"))?;
+    let scale = Scale::uniform(size);
+    let metrics = font.v_metrics(scale);
+
+    Ok(Font {
+      size,
+      line_height: metrics.ascent - metrics.descent + metrics.line_gap,
+      ascent: metrics.ascent,
+      source: Source::Outline {
+        font,
+        scale,
+        spread: distance_field.as_ref().map(|field| field.spread),
+        glyphs: RefCell::new(HashMap::new()),
+        atlas: RefCell::new(Atlas::new(self.ctx.clone(), id.clone(), filter)),
+      },
+      distance_field,
+    })
+  }
+
+  /// Distance field BMFonts were made that way by the tool that drew their pages.
+  fn bitmap(
+    &self,
+    id: &AssetId,
+    text: &str,
+    filter: Filter,
+    distance_field: Option<DistanceField>,
+  ) -> Result<Font, String> {
+    let font = BmFont::parse(text)?;
+
+    let dir = assets::namespace_dir(id.namespace()).join("fonts");
+    let pages = font
+      .pages
+      .iter()
+      .enumerate()
+      .map(|(page, file)| {
+        let texture = Texture::from(
+          self.ctx.clone(),
+          image::read_image(&dir.join(file))?,
+          filter,
+        )?;
+        Ok(Handle::detached(
+          id.extend(format!("page{}", page)),
+          texture,
+        ))
+      })
+      .collect::<Result<Vec<_>, String>>()?;
+
+    Ok(Font::from_bmfont(font, pages, distance_field))
+  }
+}
+
+impl Font {
+  /// A bitmap font drawing its glyphs from `pages`.
+  fn from_bmfont(
+    font: BmFont,
+    pages: Vec<Handle<Texture>>,
+    distance_field: Option<DistanceField>,
+  ) -> Self {
+    let glyphs = font
+      .chars
+      .iter()
+      .map(|(character, bm_char)| {
+        let region = (bm_char.width > 0.0 && bm_char.height > 0.0).then(|| {
+          Rect::new(
+            (bm_char.x, bm_char.y),
+            (bm_char.x + bm_char.width, bm_char.y + bm_char.height),
+          )
+        });
+        let glyph = Glyph {
+          page: bm_char.page,
+          region,
+          offset: glm::vec2(bm_char.x_offset, bm_char.y_offset),
+          advance: bm_char.advance,
+        };
+        (*character, glyph)
+      })
+      .collect();
+
+    Font {
+      size: font.size,
+      line_height: font.line_height,
+      ascent: font.base,
+      distance_field,
+      source: Source::Bitmap {
+        glyphs,
+        kerning: font.kerning,
+        pages,
+      },
+    }
+  }
+}
+
+impl AssetLoader for FontLoader {
+  type Asset = Font;
+
+  fn kind(&self) -> AssetKind {
+    AssetKind::Font
+  }
+
+  fn load(&self, id: &AssetId, ctx: &mut LoadContext) -> Result<Font, String> {
+    let entry = assets::read_json_entry(AssetKind::Font, id)?;
+    let table = entry
+      .as_object()
+      .ok_or_else(|| format!("font {} must be an object", id))?;
+
+    let allowed = [
+      keys::TYPE,
+      keys::FILE,
+      keys::SIZE,
+      keys::FILTER,
+      keys::MODE,
+      keys::SPREAD,
+      keys::SHADER,
+    ];
+    if let Some(key) = table.keys().find(|key| !allowed.contains(&key.as_str())) {
+      return Err(format!("unknown font key '{}'", key));
+    }
+
+    let string = |key: &str| {
+      entry
+        .get(key)
+        .and_then(|value| value.as_str())
+        .ok_or_else(|| format!("font is missing '{}'", key))
+    };
+    // glyphs are smooth edged, so unlike textures fonts are filtered unless asked not to be
+    let filter = match entry.get(keys::FILTER).and_then(|filter| filter.as_str()) {
+      Some(filter) => Filter::try_from(filter)?,
+      None => Filter::Linear,
+    };
+
+    let mode = entry.get(keys::MODE).and_then(|mode| mode.as_str());
+    let distance_field = match mode.unwrap_or(keys::MODE_BITMAP) {
+      keys::MODE_BITMAP => None,
+      keys::MODE_SDF => {
+        let spread = match entry.get(keys::SPREAD) {
+          Some(spread) => spread
+            .as_f64()
+            .filter(|spread| *spread > 0.0)
+            .ok_or_else(|| format!("font '{}' must be a positive number", keys::SPREAD))?
+            as f32,
+          None => DEFAULT_SPREAD,
+        };
+        let shader = string(keys::SHADER)?
+          .parse::<AssetId>()
+          .map_err(|e| format!("'{}' {}", keys::SHADER, e))?;
+        Some(DistanceField {
+          spread,
+          shader: ctx.load(&shader)?,
+        })
+      }
+      invalid => return Err(format!("unknown font mode '{}'", invalid)),
+    };
+
+    let path = assets::namespace_dir(id.namespace())
+      .join("fonts")
+      .join(string(keys::FILE)?);
+    let data = fs::read(&path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+
+    let font = match string(keys::TYPE)? {
+      keys::TYPE_TTF => {
+        let size = entry
+          .get(keys::SIZE)
+          .and_then(|size| size.as_f64())
+          .filter(|size| *size > 0.0)
+          .ok_or_else(|| format!("font '{}' must be a positive number", keys::SIZE))?;
+        self.outline(id, data, size as f32, filter, distance_field)
+      }
+      keys::TYPE_BMFONT => {
+        let text = String::from_utf8(data)
+          .map_err(|_| format!("{} is not a text BMFont", path.display()))?;
+        self.bitmap(id, &text, filter, distance_field)
+      }
+      invalid => Err(format!("unknown font type '{}'", invalid)),
+    };
+
+    font.map_err(|msg| format!("rejecting font {}: {}", id, msg))
+  }
+}
@@ -0,0 +1,155 @@
+use crate::assets::{AssetId, Handle};
+use crate::gfx::{Filter, Texture};
+use geo::Rect;
+use glium::texture::RawImage2d;
+use std::rc::Rc;
+
+/// The width and height of every page.
+const PAGE_SIZE: u32 = 512;
+/// Empty pixels kept around each glyph so filtering does not bleed in its neighbours.
+const PADDING: u32 = 1;
+
+/// Packs glyph bitmaps into pages row by row, each row as tall as the tallest glyph on
+/// it, and opens a new page once one is full. Pages never move what they hold, so
+/// glyphs placed earlier stay valid.
+pub(super) struct Atlas {
+  ctx: Rc<glium::backend::Context>,
+  id: AssetId,
+  filter: Filter,
+  pages: Vec<Handle<Texture>>,
+  packer: Packer,
+}
+
+/// Decides where glyphs go, apart from the pages themselves.
+#[derive(Debug, Default)]
+struct Packer {
+  pages: usize,
+  /// Where the next glyph goes on the last page.
+  cursor: (u32, u32),
+  row_height: u32,
+}
+
+impl Packer {
+  /// The page a glyph goes on and its top left there, in pixels. The page is one past
+  /// the last when a new one has to be opened.
+  fn place(&mut self, (width, height): (u32, u32)) -> Result<(usize, (u32, u32)), String> {
+    if width + PADDING * 2 > PAGE_SIZE || height + PADDING * 2 > PAGE_SIZE {
+      return Err(format!(
+        "a {}x{} glyph does not fit on a {} pixel atlas page",
+        width, height, PAGE_SIZE
+      ));
+    }
+
+    if self.cursor.0 + width + PADDING * 2 > PAGE_SIZE {
+      self.cursor = (0, self.cursor.1 + self.row_height);
+      self.row_height = 0;
+    }
+    if self.pages == 0 || self.cursor.1 + height + PADDING * 2 > PAGE_SIZE {
+      self.pages += 1;
+      self.cursor = (0, 0);
+      self.row_height = 0;
+    }
+
+    let top_left = (self.cursor.0 + PADDING, self.cursor.1 + PADDING);
+    self.cursor.0 += width + PADDING * 2;
+    self.row_height = self.row_height.max(height + PADDING * 2);
+
+    Ok((self.pages - 1, top_left))
+  }
+}
+
+impl Atlas {
+  pub fn new(ctx: Rc<glium::backend::Context>, id: AssetId, filter: Filter) -> Self {
+    Self {
+      ctx,
+      id,
+      filter,
+      pages: Vec::new(),
+      packer: Packer::default(),
+    }
+  }
+
+  pub fn page(&self, index: usize) -> Option<Handle<Texture>> {
+    self.pages.get(index).cloned()
+  }
+
+  /// Copies rgba pixels, top row first, into the atlas and returns the page they went
+  /// on and where, in pixels from the top left of the page.
+  pub fn insert(
+    &mut self,
+    (width, height): (u32, u32),
+    pixels: &[u8],
+  ) -> Result<(usize, Rect<f32>), String> {
+    let (page, (left, top)) = self.packer.place((width, height))?;
+    if page == self.pages.len() {
+      self.add_page()?;
+    }
+
+    let texture = self.pages[page]
+      .get()
+      .ok_or_else(|| format!("atlas page {} is gone", page))?;
+    texture.write(left, top, (width, height), pixels);
+
+    Ok((
+      page,
+      Rect::new(
+        (left as f32, top as f32),
+        ((left + width) as f32, (top + height) as f32),
+      ),
+    ))
+  }
+
+  fn add_page(&mut self) -> Result<(), String> {
+    let pixels = vec![0u8; (PAGE_SIZE * PAGE_SIZE * 4) as usize];
+    let texture = Texture::from(
+      self.ctx.clone(),
+      RawImage2d::from_raw_rgba(pixels, (PAGE_SIZE, PAGE_SIZE)),
+      self.filter,
+    )?;
+
+    let id = self.id.extend(format!("page{}", self.pages.len()));
+    self.pages.push(Handle::detached(id, texture));
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn glyphs_fill_a_row_left_to_right() {
+    let mut packer = Packer::default();
+    assert_eq!(packer.place((10, 20)), Ok((0, (1, 1))));
+    assert_eq!(packer.place((30, 5)), Ok((0, (13, 1))));
+  }
+
+  #[test]
+  fn full_rows_continue_below_the_tallest_glyph() {
+    let mut packer = Packer::default();
+    packer.place((PAGE_SIZE - 20, 20)).unwrap();
+    packer.place((10, 30)).unwrap();
+
+    assert_eq!(packer.place((10, 10)), Ok((0, (1, 33))));
+  }
+
+  #[test]
+  fn full_pages_open_a_new_one() {
+    let mut packer = Packer::default();
+    let tall = PAGE_SIZE / 2 - 2;
+    for _ in 0..2 {
+      packer.place((PAGE_SIZE - 2, tall)).unwrap();
+    }
+
+    assert_eq!(packer.place((10, 10)), Ok((1, (1, 1))));
+    assert_eq!(packer.place((10, 10)), Ok((1, (13, 1))));
+  }
+
+  #[test]
+  fn glyphs_larger_than_a_page_are_rejected() {
+    let mut packer = Packer::default();
+    assert!(packer.place((PAGE_SIZE, 10)).is_err());
+    assert!(packer.place((10, PAGE_SIZE - 1)).is_err());
+    assert_eq!(packer.pages, 0);
+  }
+}
@@ -0,0 +1,243 @@
+use std::collections::HashMap;
+
+/// A character of a BMFont, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) struct BmChar {
+  pub x: f32,
+  pub y: f32,
+  pub width: f32,
+  pub height: f32,
+  /// From the pen position at the top of the line to the top left of the character.
+  pub x_offset: f32,
+  pub y_offset: f32,
+  pub advance: f32,
+  pub page: usize,
+}
+
+/// The parts of a BMFont description the renderer uses. Only the text format is read.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct BmFont {
+  /// The size the font was drawn at, in pixels.
+  pub size: f32,
+  pub line_height: f32,
+  /// From the top of a line to its baseline.
+  pub base: f32,
+  /// Image files by page id.
+  pub pages: Vec<String>,
+  pub chars: HashMap<char, BmChar>,
+  pub kerning: HashMap<(char, char), f32>,
+}
+
+/// Splits `key=value` pairs, values in quotes may hold spaces.
+fn pairs(line: &str) -> Result<HashMap<&str, &str>, String> {
+  let mut pairs = HashMap::new();
+  let mut rest = line.trim_start();
+
+  while !rest.is_empty() {
+    let (key, after) = rest
+      .split_once('=')
+      .ok_or_else(|| format!("expected key=value at '{}'", rest))?;
+    let (value, after) = match after.strip_prefix('"') {
+      Some(quoted) => {
+        let end = quoted
+          .find('"')
+          .ok_or_else(|| format!("unterminated quote after '{}='", key))?;
+        (&quoted[..end], &quoted[end + 1..])
+      }
+      None => after.split_at(after.find(' ').unwrap_or(after.len())),
+    };
+    pairs.insert(key.trim(), value);
+    rest = after.trim_start();
+  }
+
+  Ok(pairs)
+}
+
+impl BmFont {
+  pub fn parse(text: &str) -> Result<Self, String> {
+    let mut font = BmFont {
+      size: 0.0,
+      line_height: 0.0,
+      base: 0.0,
+      pages: Vec::new(),
+      chars: HashMap::new(),
+      kerning: HashMap::new(),
+    };
+    let mut has_common = false;
+
+    for (number, line) in text.lines().enumerate() {
+      let line = line.trim();
+      let (tag, rest) = line.split_once(' ').unwrap_or((line, ""));
+      let fields = pairs(rest).map_err(|msg| format!("line {}: {}", number + 1, msg))?;
+
+      let number_of = |key: &str| -> Result<f32, String> {
+        fields
+          .get(key)
+          .ok_or_else(|| format!("line {}: '{}' is missing '{}'", number + 1, tag, key))?
+          .parse::<f32>()
+          .map_err(|e| format!("line {}: '{}' {}", number + 1, key, e))
+      };
+      let char_of = |key: &str| -> Result<char, String> {
+        let code = number_of(key)?;
+        char::from_u32(code as u32)
+          .ok_or_else(|| format!("line {}: {} is not a character", number + 1, code))
+      };
+
+      match tag {
+        // negative sizes ask for the character height rather than the cell height
+        "info" => font.size = number_of("size")?.abs(),
+        "common" => {
+          font.line_height = number_of("lineHeight")?;
+          font.base = number_of("base")?;
+          has_common = true;
+        }
+        "page" => {
+          let id = number_of("id")? as usize;
+          let file = fields
+            .get("file")
+            .ok_or_else(|| format!("line {}: page is missing 'file'", number + 1))?;
+          if font.pages.len() <= id {
+            font.pages.resize(id + 1, String::new());
+          }
+          font.pages[id] = file.to_string();
+        }
+        "char" => {
+          font.chars.insert(
+            char_of("id")?,
+            BmChar {
+              x: number_of("x")?,
+              y: number_of("y")?,
+              width: number_of("width")?,
+              height: number_of("height")?,
+              x_offset: number_of("xoffset")?,
+              y_offset: number_of("yoffset")?,
+              advance: number_of("xadvance")?,
+              page: number_of("page")? as usize,
+            },
+          );
+        }
+        "kerning" => {
+          font.kerning.insert(
+            (char_of("first")?, char_of("second")?),
+            number_of("amount")?,
+          );
+        }
+        // counts are only there to size buffers ahead of time
+        "chars" | "kernings" | "" => {}
+        unknown => {
+          return Err(format!(
+            "line {}: unknown BMFont tag '{}', only the text format is supported",
+            number + 1,
+            unknown
+          ))
+        }
+      }
+    }
+
+    if !has_common {
+      return Err(String::from("BMFont is missing its 'common' line"));
+    }
+    if font.size == 0.0 {
+      font.size = font.line_height;
+    }
+    if let Some(page) = font.pages.iter().position(String::is_empty) {
+      return Err(format!("BMFont page {} has no file", page));
+    }
+    if let Some((c, _)) = font
+      .chars
+      .iter()
+      .find(|(_, bm_char)| bm_char.page >= font.pages.len())
+    {
+      return Err(format!(
+        "character '{}' is on a page that does not exist",
+        c
+      ));
+    }
+
+    Ok(font)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const FONT: &str = r#"info face="Test Sans" size=-20 bold=0
+common lineHeight=24 base=18 scaleW=256 scaleH=256 pages=2
+page id=0 file="test sans_0.png"
+page id=1 file="test sans_1.png"
+chars count=2
+char id=65   x=2 y=3 width=10 height=14 xoffset=-1 yoffset=4 xadvance=11 page=0 chnl=15
+char id=32   x=0 y=0 width=0 height=0 xoffset=0 yoffset=0 xadvance=5 page=1 chnl=15
+kernings count=1
+kerning first=65 second=32 amount=-2
+"#;
+
+  #[test]
+  fn the_text_format_is_parsed() {
+    let font = BmFont::parse(FONT).unwrap();
+
+    assert_eq!(font.size, 20.0);
+    assert_eq!(font.line_height, 24.0);
+    assert_eq!(font.base, 18.0);
+    assert_eq!(font.pages, ["test sans_0.png", "test sans_1.png"]);
+    assert_eq!(
+      font.chars[&'A'],
+      BmChar {
+        x: 2.0,
+        y: 3.0,
+        width: 10.0,
+        height: 14.0,
+        x_offset: -1.0,
+        y_offset: 4.0,
+        advance: 11.0,
+        page: 0,
+      }
+    );
+    assert_eq!(font.chars[&' '].page, 1);
+    assert_eq!(font.kerning, HashMap::from([(('A', ' '), -2.0)]));
+  }
+
+  #[test]
+  fn the_size_defaults_to_the_line_height() {
+    let font = BmFont::parse("common lineHeight=16 base=12\npage id=0 file=a.png").unwrap();
+    assert_eq!(font.size, 16.0);
+  }
+
+  #[test]
+  fn fonts_need_a_common_line() {
+    assert!(BmFont::parse("info size=12\npage id=0 file=a.png").is_err());
+  }
+
+  #[test]
+  fn binary_fonts_are_rejected_with_the_line() {
+    let msg = BmFont::parse("common lineHeight=16 base=12\nBMF3 x=1").unwrap_err();
+    assert!(msg.starts_with("line 2"), "{}", msg);
+  }
+
+  #[test]
+  fn malformed_fields_are_rejected() {
+    for line in [
+      "char id=65 x=0",
+      "char id=65 x=zero y=0 width=0 height=0 xoffset=0 yoffset=0 xadvance=0 page=0",
+      "page id=0 file=\"a.png",
+      "kerning first=65 amount",
+    ] {
+      let text = format!(
+        "common lineHeight=16 base=12\npage id=0 file=a.png\n{}",
+        line
+      );
+      assert!(BmFont::parse(&text).is_err(), "{}", line);
+    }
+  }
+
+  #[test]
+  fn characters_must_be_on_a_page_with_a_file() {
+    let skipped = "common lineHeight=16 base=12\npage id=1 file=b.png";
+    assert!(BmFont::parse(skipped).is_err());
+
+    let off_the_pages = "common lineHeight=16 base=12\npage id=0 file=a.png\n\
+      char id=65 x=0 y=0 width=1 height=1 xoffset=0 yoffset=0 xadvance=1 page=1";
+    assert!(BmFont::parse(off_the_pages).is_err());
+  }
+}
@@ -0,0 +1,89 @@
+use crate::gfx::UniformParam;
+use crate::math::glm::{Vec2, Vec4};
+
+/// A line around the outside of the glyphs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Outline {
+  pub width: f32,
+  pub color: Vec4,
+}
+
+/// A copy of the glyphs drawn behind them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DropShadow {
+  pub offset: Vec2,
+  /// How far the edge of the shadow is blurred.
+  pub softness: f32,
+  pub color: Vec4,
+}
+
+/// Light fading out around the glyphs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Glow {
+  pub radius: f32,
+  pub color: Vec4,
+}
+
+/// Extras drawn with text, sized in pixels on screen. Only fonts with a distance field
+/// can draw them, and none of them reach further than the spread of the field.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TextEffects {
+  pub outline: Option<Outline>,
+  pub shadow: Option<DropShadow>,
+  pub glow: Option<Glow>,
+}
+
+impl TextEffects {
+  /// The uniforms of the distance field shader, with text drawn `scale` times the
+  /// size of its font.
+  pub(super) fn params(&self, spread: f32, scale: f32) -> Vec<(String, UniformParam)> {
+    let texels = |pixels: f32| pixels / scale;
+    let none = [0.0; 4];
+
+    let (outline_width, outline_color) = match self.outline {
+      Some(outline) => (texels(outline.width), outline.color.into()),
+      None => (0.0, none),
+    };
+    let (shadow_offset, shadow_softness, shadow_color) = match self.shadow {
+      Some(shadow) => (
+        [texels(shadow.offset.x), texels(shadow.offset.y)],
+        texels(shadow.softness),
+        shadow.color.into(),
+      ),
+      None => ([0.0, 0.0], 0.0, none),
+    };
+    let (glow_radius, glow_color) = match self.glow {
+      Some(glow) => (texels(glow.radius), glow.color.into()),
+      None => (0.0, none),
+    };
+
+    vec![
+      (String::from("u_spread"), UniformParam::Float(spread)),
+      (
+        String::from("outline_width"),
+        UniformParam::Float(outline_width),
+      ),
+      (
+        String::from("outline_color"),
+        UniformParam::Vec4(outline_color),
+      ),
+      (
+        String::from("shadow_offset"),
+        UniformParam::Vec2(shadow_offset),
+      ),
+      (
+        String::from("shadow_softness"),
+        UniformParam::Float(shadow_softness),
+      ),
+      (
+        String::from("shadow_color"),
+        UniformParam::Vec4(shadow_color),
+      ),
+      (
+        String::from("glow_radius"),
+        UniformParam::Float(glow_radius),
+      ),
+      (String::from("glow_color"), UniformParam::Vec4(glow_color)),
+    ]
+  }
+}
@@ -0,0 +1,357 @@
+use super::{Font, Glyph, TextEffects};
+use crate::assets::Handle;
+use crate::game::components::Transform;
+use crate::gfx::{Shader, Sprite, UniformParam};
+use crate::math::glm::{self, Vec2, Vec4};
+use std::rc::Rc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+  Left,
+  Center,
+  Right,
+}
+
+/// A character placed by a layout.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LaidOutGlyph {
+  /// Which character of the text this is, counting characters rather than bytes.
+  pub index: usize,
+  pub character: char,
+  /// As the font has it, before the layout's scale.
+  pub glyph: Glyph,
+  /// The top left of the glyph's region from the top left of the text.
+  pub position: Vec2,
+  pub line: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Placed {
+  index: usize,
+  character: char,
+  glyph: Glyph,
+  /// The pen position along the line.
+  x: f32,
+}
+
+/// Text broken into lines and placed glyph by glyph. Lines break at newlines, and
+/// at the last space before a word that would run past the maximum width. Words too
+/// long for a line of their own are broken between characters.
+#[derive(Debug, Clone, Default)]
+pub struct TextLayout {
+  pub glyphs: Vec<LaidOutGlyph>,
+  pub size: Vec2,
+  pub lines: usize,
+  /// How many times the size of the font the text is drawn.
+  pub scale: f32,
+}
+
+impl TextLayout {
+  /// Lays text out at the size of the font.
+  pub fn new(font: &Font, text: &str, max_width: Option<f32>, align: Align) -> Self {
+    Self::sized(font, text, font.size(), max_width, align)
+  }
+
+  /// Lays text out `size` pixels high. Only distance field fonts stay sharp away
+  /// from their own size.
+  pub fn sized(font: &Font, text: &str, size: f32, max_width: Option<f32>, align: Align) -> Self {
+    Self::place(font, text, size, max_width, align, |_, character| {
+      font.glyph(character)
+    })
+  }
+
+  /// Lays text out with glyphs from `glyph_of`, given each character and its index.
+  pub(super) fn place(
+    font: &Font,
+    text: &str,
+    size: f32,
+    max_width: Option<f32>,
+    align: Align,
+    mut glyph_of: impl FnMut(usize, char) -> Option<Glyph>,
+  ) -> Self {
+    let scale = size / font.size();
+    let mut lines: Vec<Vec<Placed>> = vec![Vec::new()];
+    let mut pen = 0.0;
+    let mut previous: Option<char> = None;
+    // where the next line would start if the current one has to break
+    let mut break_at: Option<usize> = None;
+
+    for (index, character) in text.chars().enumerate() {
+      if character == '\n' {
+        lines.push(Vec::new());
+        pen = 0.0;
+        previous = None;
+        break_at = None;
+        continue;
+      }
+      let glyph = match glyph_of(index, character) {
+        Some(glyph) => glyph,
+        None => continue,
+      };
+      if let Some(previous) = previous {
+        pen += font.kerning(previous, character) * scale;
+      }
+      previous = Some(character);
+      let advance = glyph.advance * scale;
+
+      let line = lines.last_mut().unwrap();
+      // spaces may hang past the edge, they are never drawn
+      let overflows = max_width.is_some_and(|max| pen + advance > max);
+      if overflows
+        && !character.is_whitespace()
+        && line.iter().any(|p| !p.character.is_whitespace())
+      {
+        let carried = match break_at {
+          Some(at) => line.split_off(at),
+          None => Vec::new(),
+        };
+        let start = carried.first().map_or(pen, |placed| placed.x);
+        pen -= start;
+        lines.push(
+          carried
+            .into_iter()
+            .map(|placed| Placed {
+              x: placed.x - start,
+              ..placed
+            })
+            .collect(),
+        );
+        break_at = None;
+      }
+
+      let line = lines.last_mut().unwrap();
+      line.push(Placed {
+        index,
+        character,
+        glyph,
+        x: pen,
+      });
+      pen += advance;
+      if character.is_whitespace() {
+        break_at = Some(line.len());
+      }
+    }
+
+    let widths: Vec<f32> = lines
+      .iter()
+      .map(|line| {
+        line
+          .iter()
+          .filter(|placed| !placed.character.is_whitespace())
+          .map(|placed| placed.x + placed.glyph.advance * scale)
+          .fold(0.0, f32::max)
+      })
+      .collect();
+    let width = widths.iter().copied().fold(0.0, f32::max);
+
+    let mut glyphs = Vec::new();
+    for (number, (line, line_width)) in lines.into_iter().zip(&widths).enumerate() {
+      let shift = match align {
+        Align::Left => 0.0,
+        Align::Center => (width - line_width) / 2.0,
+        Align::Right => width - line_width,
+      };
+      let top = number as f32 * font.line_height() * scale;
+
+      for placed in line {
+        // whole pixels keep glyphs from blurring across texels
+        let position = glm::vec2(placed.x + shift, top) + placed.glyph.offset * scale;
+        glyphs.push(LaidOutGlyph {
+          index: placed.index,
+          character: placed.character,
+          glyph: placed.glyph,
+          position: glm::round(&position),
+          line: number,
+        });
+      }
+    }
+
+    Self {
+      glyphs,
+      size: glm::vec2(width, widths.len() as f32 * font.line_height() * scale),
+      lines: widths.len(),
+      scale,
+    }
+  }
+
+  /// Sprites for the glyphs with something to draw, with the top left of the text at
+  /// `position`.
+  pub fn sprites<'a>(
+    &'a self,
+    font: &'a Font,
+    position: Vec2,
+    color: Vec4,
+  ) -> impl Iterator<Item = Sprite> + 'a {
+    self.sprites_with_effects(font, position, color, &TextEffects::default())
+  }
+
+  /// Like `sprites`, drawing distance field fonts with their shader and `effects`.
+  /// Other fonts ignore the effects.
+  pub fn sprites_with_effects<'a>(
+    &'a self,
+    font: &'a Font,
+    position: Vec2,
+    color: Vec4,
+    effects: &TextEffects,
+  ) -> impl Iterator<Item = Sprite> + 'a {
+    let field = self.field_shader(font, effects);
+    self.glyphs.iter().filter_map(move |laid_out| {
+      self.glyph_sprite(font, laid_out, position, color, field.as_ref())
+    })
+  }
+
+  /// How the glyphs of `font` are drawn with `effects`, `None` unless it has a
+  /// distance field.
+  pub(super) fn field_shader(&self, font: &Font, effects: &TextEffects) -> Option<FieldShader> {
+    font.distance_field().map(|field| FieldShader {
+      shader: field.shader.clone(),
+      params: Rc::new(effects.params(field.spread, self.scale)),
+    })
+  }
+
+  /// A sprite for a glyph of the layout, with the top left of the text at `position`.
+  pub(super) fn glyph_sprite(
+    &self,
+    font: &Font,
+    laid_out: &LaidOutGlyph,
+    position: Vec2,
+    color: Vec4,
+    field: Option<&FieldShader>,
+  ) -> Option<Sprite> {
+    let region = laid_out.glyph.region?;
+    let page = font.page(laid_out.glyph.page)?;
+    let transform = Transform {
+      position: position + laid_out.position,
+      scale: glm::vec2(self.scale, self.scale),
+      ..Transform::default()
+    };
+
+    let sprite = Sprite::new(page, transform)
+      .with_region(region)
+      .with_origin(glm::vec2(0.0, 0.0))
+      .with_color(color);
+    Some(match field {
+      Some(field) => sprite
+        .with_shader(field.shader.clone())
+        .with_params(field.params.clone()),
+      None => sprite,
+    })
+  }
+}
+
+/// The shader glyphs of a distance field font are drawn with and its uniforms.
+pub(super) struct FieldShader {
+  shader: Handle<Shader>,
+  params: Rc<Vec<(String, UniformParam)>>,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::bmfont::BmFont;
+  use super::*;
+
+  /// Letters 10 wide with a pixel of bearing, 5 wide spaces and lines 20 high. `V`
+  /// tucks 2 under a leading `A`.
+  fn font() -> Font {
+    let mut text = String::from("common lineHeight=20 base=16\npage id=0 file=test.png\n");
+    for character in ('a'..='z').chain(['A', 'V', '?']) {
+      text += &format!(
+        "char id={} x=0 y=0 width=8 height=12 xoffset=1 yoffset=4 xadvance=10 page=0\n",
+        character as u32
+      );
+    }
+    text += "char id=32 x=0 y=0 width=0 height=0 xoffset=0 yoffset=0 xadvance=5 page=0\n";
+    text += "kerning first=65 second=86 amount=-2\n";
+
+    Font::from_bmfont(BmFont::parse(&text).unwrap(), Vec::new(), None)
+  }
+
+  /// The lines of a layout as text.
+  fn lines(layout: &TextLayout) -> Vec<String> {
+    let mut lines = vec![String::new(); layout.lines];
+    for glyph in &layout.glyphs {
+      lines[glyph.line].push(glyph.character);
+    }
+    lines
+  }
+
+  fn position(layout: &TextLayout, index: usize) -> Vec2 {
+    layout.glyphs[index].position
+  }
+
+  #[test]
+  fn lines_break_at_newlines() {
+    let layout = TextLayout::new(&font(), "ab\ncd", None, Align::Left);
+
+    assert_eq!(lines(&layout), ["ab", "cd"]);
+    assert_eq!(position(&layout, 2), glm::vec2(1.0, 24.0));
+    assert_eq!(layout.size, glm::vec2(20.0, 40.0));
+  }
+
+  #[test]
+  fn words_wrap_at_the_last_space() {
+    let layout = TextLayout::new(&font(), "ab cd ef", Some(55.0), Align::Left);
+
+    assert_eq!(lines(&layout), ["ab cd ", "ef"]);
+    assert_eq!(position(&layout, 6), glm::vec2(1.0, 24.0));
+    assert_eq!(layout.size, glm::vec2(45.0, 40.0));
+  }
+
+  #[test]
+  fn words_longer_than_a_line_break_between_characters() {
+    let layout = TextLayout::new(&font(), "abcdef", Some(25.0), Align::Left);
+    assert_eq!(lines(&layout), ["ab", "cd", "ef"]);
+  }
+
+  #[test]
+  fn spaces_hang_past_the_edge() {
+    let layout = TextLayout::new(&font(), "ab cd", Some(20.0), Align::Left);
+
+    assert_eq!(lines(&layout), ["ab ", "cd"]);
+    assert_eq!(layout.size.x, 20.0);
+  }
+
+  #[test]
+  fn kerned_pairs_are_drawn_closer() {
+    let font = font();
+
+    let kerned = TextLayout::new(&font, "AV", None, Align::Left);
+    assert_eq!(position(&kerned, 1).x, 9.0);
+    let plain = TextLayout::new(&font, "VA", None, Align::Left);
+    assert_eq!(position(&plain, 1).x, 11.0);
+  }
+
+  #[test]
+  fn lines_are_aligned_within_the_widest() {
+    let font = font();
+    let shifts = [
+      (Align::Left, 0.0),
+      (Align::Center, 10.0),
+      (Align::Right, 20.0),
+    ];
+
+    for (align, shift) in shifts {
+      let layout = TextLayout::new(&font, "abc\na", None, align);
+      assert_eq!(position(&layout, 0).x, 1.0, "{:?}", align);
+      assert_eq!(position(&layout, 3).x, 1.0 + shift, "{:?}", align);
+    }
+  }
+
+  #[test]
+  fn sized_text_scales_the_whole_layout() {
+    let layout = TextLayout::sized(&font(), "ab", 40.0, Some(30.0), Align::Left);
+
+    assert_eq!(layout.scale, 2.0);
+    assert_eq!(lines(&layout), ["a", "b"]);
+    assert_eq!(position(&layout, 1), glm::vec2(2.0, 48.0));
+  }
+
+  #[test]
+  fn missing_characters_are_drawn_as_a_question_mark() {
+    let layout = TextLayout::new(&font(), "a\u{e9}", None, Align::Left);
+
+    assert_eq!(layout.glyphs[1].character, '\u{e9}');
+    assert_eq!(layout.glyphs[1].glyph, font().glyph('?').unwrap());
+    assert_eq!(layout.size.x, 20.0);
+  }
+}
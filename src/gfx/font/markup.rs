@@ -0,0 +1,447 @@
+use crate::math::glm::{self, Vec2, Vec4};
+use crate::util::Rng;
+use std::fmt::{self, Display, Formatter};
+
+/// Stands in for an inline icon in the text markup is parsed into.
+pub const ICON: char = '\u{fffc}';
+
+mod tags {
+  pub const COLOR: &str = "color";
+  pub const BOLD: &str = "b";
+  pub const WAVE: &str = "wave";
+  pub const SHAKE: &str = "shake";
+  pub const SPEED: &str = "speed";
+  pub const ICON: &str = "icon";
+  pub const PAUSE: &str = "pause";
+}
+
+/// How far `[wave]` and `[shake]` move characters without a value, in pixels at the
+/// size of the font.
+const DEFAULT_WAVE: f32 = 2.0;
+const DEFAULT_SHAKE: f32 = 1.0;
+
+/// How quickly a wave goes up and down, in radians per second, and how far behind
+/// each character is from the one before it.
+const WAVE_SPEED: f32 = 6.0;
+const WAVE_PHASE: f32 = 0.6;
+
+/// How many times a second shaking characters jump somewhere new.
+const SHAKE_RATE: f32 = 20.0;
+
+/// Where markup could not be read, counting characters rather than bytes from the
+/// start of the markup.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarkupError {
+  pub position: usize,
+  pub message: String,
+}
+
+impl Display for MarkupError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    write!(f, "character {}: {}", self.position, self.message)
+  }
+}
+
+/// How a character is drawn and revealed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Style {
+  /// Drawn instead of the color the text is drawn with.
+  pub color: Option<Vec4>,
+  pub bold: bool,
+  /// How far the character bobs up and down, in pixels at the size of the font.
+  pub wave: Option<f32>,
+  /// How far the character jitters around its place.
+  pub shake: Option<f32>,
+  /// How many times faster than the rest of the text the character is revealed.
+  pub speed: f32,
+}
+
+impl Default for Style {
+  fn default() -> Self {
+    Self {
+      color: None,
+      bold: false,
+      wave: None,
+      shake: None,
+      speed: 1.0,
+    }
+  }
+}
+
+impl Style {
+  /// How far the character at `index` is moved from its place `time` seconds in, in
+  /// pixels at the size of the font.
+  pub fn offset(&self, index: usize, time: f32) -> Vec2 {
+    let mut offset = glm::vec2(0.0, 0.0);
+    if let Some(amplitude) = self.wave {
+      offset.y -= amplitude * (time * WAVE_SPEED - index as f32 * WAVE_PHASE).sin();
+    }
+    if let Some(amplitude) = self.shake {
+      // the same character lands in the same place until the next jump
+      let step = (time * SHAKE_RATE).floor() as u64;
+      let mut rng = Rng::new(((index as u64) << 32) ^ step);
+      offset.x += amplitude * rng.range_f32(-1.0, 1.0);
+      offset.y += amplitude * rng.range_f32(-1.0, 1.0);
+    }
+    offset
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RichChar {
+  pub style: Style,
+  /// The icon drawn in place of the character, which is `ICON`.
+  pub icon: Option<String>,
+  /// Seconds a typewriter waits before revealing the character.
+  pub pause: f32,
+}
+
+/// Text with the markup taken out and what it said about each character.
+///
+/// `[color=red]`, `[b]`, `[wave]`, `[shake]` and `[speed=2]` style the text up to
+/// their closing tag, like `[/color]`. Colors are names or `#rgb`, `#rrggbb` and
+/// `#rrggbbaa`, and `[wave=4]` and `[shake=4]` say how far characters move.
+/// `[icon=name]` places an icon and `[pause=0.5]` holds a typewriter for that many
+/// seconds, neither is closed. `[[` is a bracket.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RichText {
+  pub text: String,
+  /// One for every character of `text`.
+  pub chars: Vec<RichChar>,
+  /// Seconds a typewriter waits after the last character.
+  pub trailing_pause: f32,
+}
+
+impl RichText {
+  pub fn parse(markup: &str) -> Result<Self, MarkupError> {
+    let source: Vec<char> = markup.chars().collect();
+    let mut rich = RichText::default();
+    let mut style = Style::default();
+    // tags waiting to be closed, where they are and the style before them
+    let mut open: Vec<(&str, usize, Style)> = Vec::new();
+    let mut pause = 0.0;
+    let mut i = 0;
+
+    while i < source.len() {
+      if source[i] != '[' || source.get(i + 1) == Some(&'[') {
+        rich.push(source[i], style, None, pause);
+        pause = 0.0;
+        i += if source[i] == '[' { 2 } else { 1 };
+        continue;
+      }
+
+      let start = i;
+      let error = |message: String| MarkupError {
+        position: start,
+        message,
+      };
+      let end = source[start..]
+        .iter()
+        .position(|&c| c == ']')
+        .map(|length| start + length)
+        .ok_or_else(|| {
+          error(String::from(
+            "tag is never closed with ']', '[[' is a bracket",
+          ))
+        })?;
+      let tag: String = source[start + 1..end].iter().collect();
+      i = end + 1;
+
+      if let Some(name) = tag.strip_prefix('/') {
+        let name = name.trim();
+        match open.pop() {
+          Some((opened, _, previous)) if opened == name => style = previous,
+          Some((opened, position, _)) => {
+            return Err(error(format!(
+              "[/{}] cannot close [{}] from character {}",
+              name, opened, position
+            )))
+          }
+          None => return Err(error(format!("[/{}] closes nothing", name))),
+        }
+        continue;
+      }
+
+      let (name, value) = match tag.split_once('=') {
+        Some((name, value)) => (name.trim(), Some(value.trim())),
+        None => (tag.trim(), None),
+      };
+      let required = || value.ok_or_else(|| error(format!("[{}] needs a value", name)));
+      let number = |value: &str| {
+        value
+          .parse::<f32>()
+          .ok()
+          .filter(|number| number.is_finite() && *number >= 0.0)
+          .ok_or_else(|| error(format!("'{}' is not a positive number", value)))
+      };
+
+      let previous = style;
+      let name = match name {
+        tags::ICON => {
+          rich.push(ICON, style, Some(required()?.to_string()), pause);
+          pause = 0.0;
+          continue;
+        }
+        tags::PAUSE => {
+          pause += number(required()?)?;
+          continue;
+        }
+        tags::COLOR => {
+          style.color = Some(color(required()?).map_err(error)?);
+          tags::COLOR
+        }
+        tags::BOLD => {
+          if value.is_some() {
+            return Err(error(format!("[{}] takes no value", tags::BOLD)));
+          }
+          style.bold = true;
+          tags::BOLD
+        }
+        tags::WAVE => {
+          style.wave = Some(value.map_or(Ok(DEFAULT_WAVE), number)?);
+          tags::WAVE
+        }
+        tags::SHAKE => {
+          style.shake = Some(value.map_or(Ok(DEFAULT_SHAKE), number)?);
+          tags::SHAKE
+        }
+        tags::SPEED => {
+          let speed = number(required()?)?;
+          if speed == 0.0 {
+            return Err(error(String::from("text cannot be revealed at speed 0")));
+          }
+          style.speed *= speed;
+          tags::SPEED
+        }
+        unknown => return Err(error(format!("unknown tag [{}]", unknown))),
+      };
+      open.push((name, start, previous));
+    }
+
+    if let Some((name, position, _)) = open.pop() {
+      return Err(MarkupError {
+        position,
+        message: format!("[{}] is never closed with [/{}]", name, name),
+      });
+    }
+    rich.trailing_pause = pause;
+    Ok(rich)
+  }
+
+  fn push(&mut self, character: char, style: Style, icon: Option<String>, pause: f32) {
+    self.text.push(character);
+    self.chars.push(RichChar { style, icon, pause });
+  }
+}
+
+/// Reads a color name or a `#` and 3, 6 or 8 hex digits.
+fn color(value: &str) -> Result<Vec4, String> {
+  let named = match value {
+    "white" => Some(glm::vec4(1.0, 1.0, 1.0, 1.0)),
+    "black" => Some(glm::vec4(0.0, 0.0, 0.0, 1.0)),
+    "gray" | "grey" => Some(glm::vec4(0.5, 0.5, 0.5, 1.0)),
+    "red" => Some(glm::vec4(1.0, 0.0, 0.0, 1.0)),
+    "green" => Some(glm::vec4(0.0, 1.0, 0.0, 1.0)),
+    "blue" => Some(glm::vec4(0.0, 0.0, 1.0, 1.0)),
+    "yellow" => Some(glm::vec4(1.0, 1.0, 0.0, 1.0)),
+    "orange" => Some(glm::vec4(1.0, 0.5, 0.0, 1.0)),
+    "cyan" => Some(glm::vec4(0.0, 1.0, 1.0, 1.0)),
+    "magenta" => Some(glm::vec4(1.0, 0.0, 1.0, 1.0)),
+    _ => None,
+  };
+  if let Some(named) = named {
+    return Ok(named);
+  }
+
+  let invalid = || format!("'{}' is not a color name or #rrggbb", value);
+  let hex = value.strip_prefix('#').ok_or_else(invalid)?;
+  if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+    return Err(invalid());
+  }
+  let digits: Vec<f32> = match hex.len() {
+    // each digit of the short form stands for two
+    3 => hex
+      .chars()
+      .map(|c| c.to_digit(16).unwrap() as f32 / 15.0)
+      .chain([1.0])
+      .collect(),
+    6 | 8 => (0..hex.len())
+      .step_by(2)
+      .map(|at| u8::from_str_radix(&hex[at..at + 2], 16).unwrap() as f32 / 255.0)
+      .chain([1.0])
+      .collect(),
+    _ => return Err(invalid()),
+  };
+  Ok(glm::vec4(digits[0], digits[1], digits[2], digits[3]))
+}
+
+/// Reveals rich text a character at a time.
+#[derive(Debug, Clone)]
+pub struct Typewriter {
+  /// Seconds from the start until each character shows.
+  times: Vec<f32>,
+  duration: f32,
+  elapsed: f32,
+}
+
+impl Typewriter {
+  /// Reveals `text` at `chars_per_second`, faster or slower where it says `[speed]`
+  /// and holding where it says `[pause]`.
+  pub fn new(text: &RichText, chars_per_second: f32) -> Self {
+    let mut times = Vec::with_capacity(text.chars.len());
+    let mut time = 0.0;
+    let mut last = 0.0;
+    for rich_char in &text.chars {
+      time += rich_char.pause;
+      times.push(time);
+      last = time;
+      time += 1.0 / (chars_per_second * rich_char.style.speed);
+    }
+
+    Self {
+      times,
+      duration: last + text.trailing_pause,
+      elapsed: 0.0,
+    }
+  }
+
+  pub fn update(&mut self, dt: f32) {
+    self.elapsed = (self.elapsed + dt).min(self.duration);
+  }
+
+  /// How many characters from the start of the text are revealed.
+  pub fn visible(&self) -> usize {
+    self.times.partition_point(|&time| time <= self.elapsed)
+  }
+
+  pub fn is_finished(&self) -> bool {
+    self.elapsed >= self.duration
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn plain_text_is_unstyled() {
+    let rich = RichText::parse("hello").unwrap();
+
+    assert_eq!(rich.text, "hello");
+    assert!(rich.chars.iter().all(|c| c.style == Style::default()));
+  }
+
+  #[test]
+  fn tags_style_text_until_closed() {
+    let rich = RichText::parse("a[color=red]b[b]c[/b][/color]d").unwrap();
+
+    assert_eq!(rich.text, "abcd");
+    let red = Some(glm::vec4(1.0, 0.0, 0.0, 1.0));
+    assert_eq!(rich.chars[0].style.color, None);
+    assert_eq!(rich.chars[1].style.color, red);
+    assert!(!rich.chars[1].style.bold);
+    assert_eq!(rich.chars[2].style.color, red);
+    assert!(rich.chars[2].style.bold);
+    assert_eq!(rich.chars[3].style, Style::default());
+  }
+
+  #[test]
+  fn colors_are_read_from_hex() {
+    assert_eq!(color("#f80"), Ok(glm::vec4(1.0, 8.0 / 15.0, 0.0, 1.0)));
+    assert_eq!(color("#ff0000"), Ok(glm::vec4(1.0, 0.0, 0.0, 1.0)));
+    assert_eq!(color("#00000000"), Ok(glm::vec4(0.0, 0.0, 0.0, 0.0)));
+    assert!(color("#12345").is_err());
+    assert!(color("#gggggg").is_err());
+    assert!(color("teal").is_err());
+  }
+
+  #[test]
+  fn motion_tags_take_optional_amplitudes() {
+    let rich = RichText::parse("[wave]a[/wave][shake=3]b[/shake]").unwrap();
+
+    assert_eq!(rich.chars[0].style.wave, Some(DEFAULT_WAVE));
+    assert_eq!(rich.chars[1].style.shake, Some(3.0));
+  }
+
+  #[test]
+  fn icons_and_brackets_become_characters() {
+    let rich = RichText::parse("[[x] [icon=coin]").unwrap();
+
+    assert_eq!(rich.text, format!("[x] {}", ICON));
+    assert_eq!(rich.chars[4].icon.as_deref(), Some("coin"));
+    assert_eq!(rich.chars[0].icon, None);
+  }
+
+  #[test]
+  fn errors_point_at_the_tag() {
+    let error = |markup: &str| RichText::parse(markup).unwrap_err();
+
+    assert_eq!(error("ab[nope]").position, 2);
+    assert_eq!(error("ab[b").position, 2);
+    assert_eq!(error("ab[/b]").position, 2);
+    assert_eq!(error("é[color=nope]").position, 1);
+    assert_eq!(error("[icon]").position, 0);
+    assert_eq!(error("[b=1]").position, 0);
+    assert_eq!(error("[pause=-1]").position, 0);
+    assert_eq!(error("[speed=0]").position, 0);
+
+    let unclosed = error("a[b]b[wave]c[/wave]");
+    assert_eq!(unclosed.position, 1);
+    assert_eq!(
+      unclosed.to_string(),
+      "character 1: [b] is never closed with [/b]"
+    );
+
+    let crossed = error("[b][wave]a[/b][/wave]");
+    assert_eq!(crossed.position, 10);
+    assert!(crossed.message.contains("[wave] from character 3"));
+  }
+
+  #[test]
+  fn characters_are_revealed_over_time() {
+    let rich = RichText::parse("abcd").unwrap();
+    let mut typewriter = Typewriter::new(&rich, 10.0);
+
+    assert_eq!(typewriter.visible(), 1);
+    typewriter.update(0.15);
+    assert_eq!(typewriter.visible(), 2);
+    typewriter.update(0.2);
+    assert_eq!(typewriter.visible(), 4);
+    assert!(typewriter.is_finished());
+  }
+
+  #[test]
+  fn pauses_and_speed_change_the_timing() {
+    let rich = RichText::parse("a[pause=1]b[speed=2]cd[/speed]e[pause=0.5]").unwrap();
+    let mut typewriter = Typewriter::new(&rich, 10.0);
+
+    // a at 0, b after a tenth and a pause, then c and d twice as fast
+    let expected = [0.0, 1.1, 1.2, 1.25, 1.3];
+    assert!(typewriter
+      .times
+      .iter()
+      .zip(expected)
+      .all(|(time, expected)| (time - expected).abs() < 1e-5));
+    typewriter.update(1.29);
+    assert_eq!(typewriter.visible(), 4);
+    typewriter.update(0.1);
+    assert_eq!(typewriter.visible(), 5);
+    assert!(!typewriter.is_finished());
+    typewriter.update(0.5);
+    assert!(typewriter.is_finished());
+  }
+
+  #[test]
+  fn motion_moves_only_styled_characters() {
+    let still = Style::default();
+    let shaking = Style {
+      shake: Some(2.0),
+      ..Style::default()
+    };
+
+    assert_eq!(still.offset(3, 1.7), glm::vec2(0.0, 0.0));
+    let offset = shaking.offset(3, 1.72);
+    assert!(offset.x.abs() <= 2.0 && offset.y.abs() <= 2.0);
+    assert_eq!(shaking.offset(3, 1.74), offset);
+  }
+}
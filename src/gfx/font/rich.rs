@@ -0,0 +1,169 @@
+use super::{Align, Font, Glyph, RichText, Style, TextEffects, TextLayout};
+use crate::assets::Handle;
+use crate::game::components::Transform;
+use crate::gfx::{Sprite, Texture};
+use crate::math::glm::{self, Vec2, Vec4};
+use geo::Rect;
+use std::collections::HashMap;
+
+/// Space left after an inline icon, in pixels at the size of the font.
+const ICON_GAP: f32 = 1.0;
+
+/// Named parts of textures that rich text places with `[icon=name]`.
+#[derive(Clone, Default)]
+pub struct Icons {
+  icons: HashMap<String, (Handle<Texture>, Rect<f32>)>,
+}
+
+impl Icons {
+  /// Adds the part of `texture` in pixels from its top left as `name`.
+  pub fn insert(&mut self, name: &str, texture: Handle<Texture>, region: Rect<f32>) {
+    self.icons.insert(name.to_string(), (texture, region));
+  }
+
+  /// Adds an atlas of icons `width` by `height` pixels, named left to right and top to
+  /// bottom.
+  pub fn insert_grid(
+    &mut self,
+    texture: Handle<Texture>,
+    (width, height): (f32, f32),
+    names: &[&str],
+  ) -> Result<(), String> {
+    let columns = {
+      let atlas = texture
+        .get()
+        .ok_or_else(|| format!("icon atlas {} is not loaded", texture.id()))?;
+      (atlas.width() as f32 / width).floor() as usize
+    };
+    if columns == 0 {
+      return Err(format!(
+        "icon atlas {} is narrower than an icon",
+        texture.id()
+      ));
+    }
+
+    for (index, name) in names.iter().enumerate() {
+      let (x, y) = ((index % columns) as f32, (index / columns) as f32);
+      let region = Rect::new(
+        (x * width, y * height),
+        ((x + 1.0) * width, (y + 1.0) * height),
+      );
+      self.insert(name, texture.clone(), region);
+    }
+    Ok(())
+  }
+}
+
+/// Rich text laid out, ready to be drawn a frame at a time as it moves and is
+/// revealed.
+pub struct RichLayout {
+  pub layout: TextLayout,
+  styles: Vec<Style>,
+  /// Icons by the index of the character they stand in for.
+  icons: HashMap<usize, (Handle<Texture>, Rect<f32>)>,
+}
+
+impl RichLayout {
+  /// Lays text out `size` pixels high. Icons are as tall as the ascent of the font.
+  pub fn new(
+    font: &Font,
+    text: &RichText,
+    size: f32,
+    max_width: Option<f32>,
+    align: Align,
+    icons: &Icons,
+  ) -> Result<Self, String> {
+    let mut placed = HashMap::new();
+    for (index, rich_char) in text.chars.iter().enumerate() {
+      if let Some(name) = &rich_char.icon {
+        let icon = icons
+          .icons
+          .get(name)
+          .ok_or_else(|| format!("unknown icon '{}'", name))?;
+        placed.insert(index, icon.clone());
+      }
+    }
+
+    let layout = TextLayout::place(
+      font,
+      &text.text,
+      size,
+      max_width,
+      align,
+      |index, character| match placed.get(&index) {
+        Some((_, region)) => Some(Glyph {
+          page: 0,
+          region: Some(*region),
+          offset: glm::vec2(0.0, 0.0),
+          advance: region.width() * font.ascent() / region.height() + ICON_GAP,
+        }),
+        None => font.glyph(character),
+      },
+    );
+
+    Ok(Self {
+      layout,
+      styles: text.chars.iter().map(|rich_char| rich_char.style).collect(),
+      icons: placed,
+    })
+  }
+
+  /// Sprites for the first `visible` characters `time` seconds into their motion,
+  /// with the top left of the text at `position`. Icons keep their own colors.
+  pub fn sprites<'a>(
+    &'a self,
+    font: &'a Font,
+    position: Vec2,
+    color: Vec4,
+    effects: &TextEffects,
+    time: f32,
+    visible: usize,
+  ) -> impl Iterator<Item = Sprite> + 'a {
+    let field = self.layout.field_shader(font, effects);
+    let scale = self.layout.scale;
+
+    self
+      .layout
+      .glyphs
+      .iter()
+      .filter(move |laid_out| laid_out.index < visible)
+      .flat_map(move |laid_out| {
+        let style = self.styles[laid_out.index];
+        let position = position + style.offset(laid_out.index, time) * scale;
+
+        let sprite = match self.icons.get(&laid_out.index) {
+          Some((texture, region)) => {
+            let icon_scale = scale * font.ascent() / region.height();
+            let transform = Transform {
+              position: position + laid_out.position,
+              scale: glm::vec2(icon_scale, icon_scale),
+              ..Transform::default()
+            };
+            Some(
+              Sprite::new(texture.clone(), transform)
+                .with_region(*region)
+                .with_origin(glm::vec2(0.0, 0.0))
+                .with_color(glm::vec4(1.0, 1.0, 1.0, color.w)),
+            )
+          }
+          None => self.layout.glyph_sprite(
+            font,
+            laid_out,
+            position,
+            style.color.unwrap_or(color),
+            field.as_ref(),
+          ),
+        };
+
+        // bold is the glyph again a pixel to the right
+        let bold = sprite
+          .clone()
+          .filter(|_| style.bold && !self.icons.contains_key(&laid_out.index))
+          .map(|mut sprite| {
+            sprite.transform.position.x += scale.max(1.0);
+            sprite
+          });
+        sprite.into_iter().chain(bold)
+      })
+  }
+}
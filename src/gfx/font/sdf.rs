@@ -0,0 +1,202 @@
+/// How many times finer than the atlas glyphs are rasterized before their distance
+/// field is taken, so edges land between texels instead of on them.
+pub(super) const OVERSAMPLE: u32 = 4;
+
+/// The squared distance from every cell to the nearest cell where `f` is zero, along
+/// one row or column. Felzenszwalb and Huttenlocher's lower envelope of parabolas.
+fn transform_1d(f: &[f32], out: &mut [f32], sites: &mut [usize], bounds: &mut [f32]) {
+  let n = f.len();
+  let mut k = 0;
+  sites[0] = 0;
+  bounds[0] = f32::NEG_INFINITY;
+  bounds[1] = f32::INFINITY;
+
+  for q in 1..n {
+    let intersect =
+      |p: usize| ((f[q] + (q * q) as f32) - (f[p] + (p * p) as f32)) / (2 * q - 2 * p) as f32;
+    let mut s = intersect(sites[k]);
+    while s <= bounds[k] {
+      k -= 1;
+      s = intersect(sites[k]);
+    }
+    k += 1;
+    sites[k] = q;
+    bounds[k] = s;
+    bounds[k + 1] = f32::INFINITY;
+  }
+
+  k = 0;
+  for (q, out) in out.iter_mut().enumerate() {
+    while bounds[k + 1] < q as f32 {
+      k += 1;
+    }
+    let d = q as f32 - sites[k] as f32;
+    *out = d * d + f[sites[k]];
+  }
+}
+
+/// The squared distance from every cell to the nearest cell in `features`.
+fn transform_2d(features: &[bool], width: usize, height: usize) -> Vec<f32> {
+  // large rather than infinite so the parabola intersections stay finite
+  let far = ((width * width + height * height) * 4) as f32;
+  let mut grid: Vec<f32> = features
+    .iter()
+    .map(|&feature| if feature { 0.0 } else { far })
+    .collect();
+
+  let longest = width.max(height);
+  let (mut f, mut out) = (vec![0.0; longest], vec![0.0; longest]);
+  let (mut sites, mut bounds) = (vec![0; longest], vec![0.0; longest + 1]);
+
+  for x in 0..width {
+    for y in 0..height {
+      f[y] = grid[y * width + x];
+    }
+    transform_1d(&f[..height], &mut out[..height], &mut sites, &mut bounds);
+    for y in 0..height {
+      grid[y * width + x] = out[y];
+    }
+  }
+  for y in 0..height {
+    let row = &mut grid[y * width..(y + 1) * width];
+    f[..width].copy_from_slice(row);
+    transform_1d(&f[..width], &mut out[..width], &mut sites, &mut bounds);
+    row.copy_from_slice(&out[..width]);
+  }
+
+  grid
+}
+
+/// Turns a coverage bitmap rasterized `OVERSAMPLE` times too large into a signed
+/// distance field at the size of the atlas, top row first. Texels hold 0.5 on the
+/// outline, rising inside, and reach 0 or 1 `spread` texels away from it.
+pub(super) fn distance_field(coverage: &[f32], width: u32, height: u32, spread: f32) -> Vec<u8> {
+  let (width, height) = (width as usize, height as usize);
+  let inside: Vec<bool> = coverage.iter().map(|&c| c >= 0.5).collect();
+  let outside: Vec<bool> = inside.iter().map(|inside| !inside).collect();
+  let to_inside = transform_2d(&inside, width, height);
+  let to_outside = transform_2d(&outside, width, height);
+
+  // the outline runs between cells, half a cell from the centre of both
+  let signed: Vec<f32> = inside
+    .iter()
+    .enumerate()
+    .map(|(i, &inside)| {
+      if inside {
+        to_outside[i].sqrt() - 0.5
+      } else {
+        0.5 - to_inside[i].sqrt()
+      }
+    })
+    .collect();
+
+  let scale = OVERSAMPLE as usize;
+  let (small_width, small_height) = (width / scale, height / scale);
+  let mut field = Vec::with_capacity(small_width * small_height);
+  for y in 0..small_height {
+    for x in 0..small_width {
+      let mut sum = 0.0;
+      for row in y * scale..(y + 1) * scale {
+        sum += signed[row * width + x * scale..row * width + (x + 1) * scale]
+          .iter()
+          .sum::<f32>();
+      }
+      let distance = sum / (scale * scale) as f32 / OVERSAMPLE as f32;
+      let value = (0.5 + distance / (2.0 * spread)).clamp(0.0, 1.0);
+      field.push((value * 255.0).round() as u8);
+    }
+  }
+  field
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// The squared distance from `(x, y)` to the nearest cell of the box from `min` to
+  /// `max` inclusive, worked out directly.
+  fn to_box(x: usize, y: usize, min: usize, max: usize) -> f32 {
+    let axis = |v: usize| (min.saturating_sub(v) + v.saturating_sub(max)) as f32;
+    axis(x).powi(2) + axis(y).powi(2)
+  }
+
+  #[test]
+  fn a_single_pixel_gives_the_squared_distance_to_it() {
+    let (width, height) = (7, 5);
+    let mut features = vec![false; width * height];
+    features[2 * width + 3] = true;
+
+    let grid = transform_2d(&features, width, height);
+    for y in 0..height {
+      for x in 0..width {
+        let expected = (x as f32 - 3.0).powi(2) + (y as f32 - 2.0).powi(2);
+        assert_eq!(grid[y * width + x], expected, "at {}, {}", x, y);
+      }
+    }
+  }
+
+  #[test]
+  fn a_filled_square_gives_the_squared_distance_to_its_edge() {
+    let size = 9;
+    let features: Vec<bool> = (0..size * size)
+      .map(|i| (3..=5).contains(&(i % size)) && (3..=5).contains(&(i / size)))
+      .collect();
+
+    let grid = transform_2d(&features, size, size);
+    for y in 0..size {
+      for x in 0..size {
+        assert_eq!(grid[y * size + x], to_box(x, y, 3, 5), "at {}, {}", x, y);
+      }
+    }
+  }
+
+  #[test]
+  fn empty_and_full_bitmaps_are_all_outside_or_inside() {
+    let size = 4 * OVERSAMPLE;
+    let empty = distance_field(&vec![0.0; (size * size) as usize], size, size, 2.0);
+    assert_eq!(empty, [0; 16]);
+    let full = distance_field(&vec![1.0; (size * size) as usize], size, size, 2.0);
+    assert_eq!(full, [255; 16]);
+  }
+
+  #[test]
+  fn texels_hold_the_average_distance_of_their_cells() {
+    // an atlas of 6 by 6 texels with the middle 2 by 2 covered
+    let size = 6 * OVERSAMPLE as usize;
+    let (min, max) = (2 * OVERSAMPLE as usize, 4 * OVERSAMPLE as usize - 1);
+    let coverage: Vec<f32> = (0..size * size)
+      .map(|i| {
+        let (x, y) = (i % size, i / size);
+        if (min..=max).contains(&x) && (min..=max).contains(&y) {
+          1.0
+        } else {
+          0.0
+        }
+      })
+      .collect();
+
+    let field = distance_field(&coverage, size as u32, size as u32, 3.0);
+    let at = |x: usize, y: usize| field[y * 6 + x];
+
+    // the covered texels are inside and the rest outside, the same on every side
+    for y in 0..6 {
+      for x in 0..6 {
+        let inside = (2..4).contains(&x) && (2..4).contains(&y);
+        assert_eq!(at(x, y) > 128, inside, "at {}, {}", x, y);
+        assert_eq!(at(x, y), at(5 - x, y));
+        assert_eq!(at(x, y), at(y, x));
+      }
+    }
+    // beside the square every cell is straight across from it, so the texels hold how
+    // far their cells are on average, 1.5 and 0.5 texels out
+    assert_eq!(at(0, 2), (255.0f32 * (0.5 - 1.5 / 6.0)).round() as u8);
+    assert_eq!(at(1, 2), (255.0f32 * (0.5 - 0.5 / 6.0)).round() as u8);
+    // inside, each cell is as far from the outline as from the nearer of two edges
+    let inside = (1..=4)
+      .flat_map(|a| (1..=4).map(move |b| a.min(b) as f32 - 0.5))
+      .sum::<f32>()
+      / 16.0
+      / OVERSAMPLE as f32;
+    assert_eq!(at(2, 2), (255.0 * (0.5 + inside / 6.0)).round() as u8);
+  }
+}
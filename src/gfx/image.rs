@@ -1,7 +1,168 @@
-pub trait ImageLoader {}
+use crate::assets::{self, AssetId, AssetKind, AssetLoader, LoadContext};
+use glium::{
+  texture::{RawImage2d, Texture2d},
+  uniforms::{MagnifySamplerFilter, MinifySamplerFilter, Sampler},
+};
+use std::{path::Path, rc::Rc};
 
-pub struct ImageManager<T: ImageLoader> {
-  loader: T,
+mod keys {
+  pub const FILE: &str = "file";
+  pub const FILTER: &str = "filter";
+  pub const NORMAL_MAP: &str = "normal_map";
 }
 
-impl<T: ImageLoader> ImageLoader for ImageManager<T> {}
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+  Nearest,
+  Linear,
+}
+
+impl TryFrom<&str> for Filter {
+  type Error = String;
+
+  fn try_from(value: &str) -> Result<Self, Self::Error> {
+    match value {
+      "nearest" => Ok(Filter::Nearest),
+      "linear" => Ok(Filter::Linear),
+      invalid => Err(format!("invalid texture filter '{}'", invalid)),
+    }
+  }
+}
+
+pub struct TextureLoader {
+  ctx: Rc<glium::backend::Context>,
+}
+
+impl TextureLoader {
+  pub fn new(ctx: Rc<glium::backend::Context>) -> Self {
+    Self { ctx }
+  }
+
+  fn read(id: &AssetId, file: &str) -> Result<RawImage2d<'static, u8>, String> {
+    read_image(
+      &assets::namespace_dir(id.namespace())
+        .join("textures")
+        .join(file),
+    )
+  }
+}
+
+/// Reads an image file the way textures expect it, bottom row first.
+pub(super) fn read_image(path: &Path) -> Result<RawImage2d<'static, u8>, String> {
+  let image = ::image::open(path)
+    .map_err(|e| format!("cannot open {}: {}", path.display(), e))?
+    .to_rgba8();
+  let dimensions = image.dimensions();
+  Ok(RawImage2d::from_raw_rgba_reversed(
+    &image.into_raw(),
+    dimensions,
+  ))
+}
+
+impl AssetLoader for TextureLoader {
+  type Asset = Texture;
+
+  fn kind(&self) -> AssetKind {
+    AssetKind::Texture
+  }
+
+  fn load(&self, id: &AssetId, _ctx: &mut LoadContext) -> Result<Texture, String> {
+    let entry = assets::read_json_entry(AssetKind::Texture, id)?;
+    let file = entry
+      .get(keys::FILE)
+      .and_then(|file| file.as_str())
+      .ok_or_else(|| format!("texture is missing '{}'", keys::FILE))?;
+    // pixel art is the common case, so textures are not smoothed unless asked to
+    let filter = match entry.get(keys::FILTER).and_then(|filter| filter.as_str()) {
+      Some(filter) => Filter::try_from(filter)?,
+      None => Filter::Nearest,
+    };
+
+    let mut texture = Texture::from(self.ctx.clone(), Self::read(id, file)?, filter)?;
+
+    if let Some(normal_map) = entry.get(keys::NORMAL_MAP) {
+      let file = normal_map
+        .as_str()
+        .ok_or_else(|| format!("texture '{}' must be a file name", keys::NORMAL_MAP))?;
+      let normal_map =
+        Texture2d::new(&self.ctx, Self::read(id, file)?).map_err(|e| e.to_string())?;
+      if normal_map.dimensions() != texture.texture.dimensions() {
+        return Err(format!(
+          "normal map {} is not the size of its texture",
+          file
+        ));
+      }
+      texture.normal_map = Some(normal_map);
+    }
+
+    Ok(texture)
+  }
+}
+
+pub struct Texture {
+  texture: Texture2d,
+  /// Which way each texel faces, for lighting, with green pointing up the image.
+  normal_map: Option<Texture2d>,
+  filter: Filter,
+}
+
+impl Texture {
+  pub fn from(
+    ctx: Rc<glium::backend::Context>,
+    raw: RawImage2d<u8>,
+    filter: Filter,
+  ) -> Result<Self, String> {
+    let texture = Texture2d::new(&ctx, raw).map_err(|e| e.to_string())?;
+    Ok(Self {
+      texture,
+      normal_map: None,
+      filter,
+    })
+  }
+
+  pub fn width(&self) -> u32 {
+    self.texture.width()
+  }
+
+  pub fn height(&self) -> u32 {
+    self.texture.height()
+  }
+
+  /// Replaces a `width` by `height` part of the texture, `left` and `top` pixels from its
+  /// top left, with rgba pixels given top row first.
+  pub fn write(&self, left: u32, top: u32, (width, height): (u32, u32), pixels: &[u8]) {
+    self.texture.write(
+      glium::Rect {
+        left,
+        bottom: self.height() - top - height,
+        width,
+        height,
+      },
+      RawImage2d::from_raw_rgba_reversed(pixels, (width, height)),
+    );
+  }
+
+  pub fn sampled(&self) -> Sampler<'_, Texture2d> {
+    self.sample(&self.texture)
+  }
+
+  /// The normal map sampled like the texture, if the texture has one.
+  pub fn normal_map(&self) -> Option<Sampler<'_, Texture2d>> {
+    self
+      .normal_map
+      .as_ref()
+      .map(|normal_map| self.sample(normal_map))
+  }
+
+  fn sample<'a>(&self, texture: &'a Texture2d) -> Sampler<'a, Texture2d> {
+    let (magnify, minify) = match self.filter {
+      Filter::Nearest => (MagnifySamplerFilter::Nearest, MinifySamplerFilter::Nearest),
+      Filter::Linear => (MagnifySamplerFilter::Linear, MinifySamplerFilter::Linear),
+    };
+
+    texture
+      .sampled()
+      .magnify_filter(magnify)
+      .minify_filter(minify)
+  }
+}
@@ -0,0 +1,162 @@
+use super::{Filter, RenderState, Scissor, Shader, Texture};
+use crate::assets::{AssetId, AssetServer, Handle};
+use crate::math::glm;
+use glium::{
+  index::PrimitiveType, texture::RawImage2d, uniform, IndexBuffer, Surface, VertexBuffer,
+};
+use imgui::{BackendFlags, DrawCmd, DrawCmdParams, DrawData, Textures};
+use std::rc::Rc;
+
+/// Laid out like `imgui::DrawVert`, so draw lists can be uploaded as they are.
+#[repr(C)]
+#[derive(Default, Debug, Clone, Copy)]
+struct ImguiVertex {
+  i_pos: [f32; 2],
+  i_uv: [f32; 2],
+  i_color: [u8; 4],
+}
+
+glium::implement_vertex!(
+  ImguiVertex,
+  i_pos normalize(false),
+  i_uv normalize(false),
+  i_color normalize(true)
+);
+
+/// Draws the frames of an imgui context with glium.
+pub struct ImguiRenderer {
+  ctx: Rc<glium::backend::Context>,
+  shader: Handle<Shader>,
+  textures: Textures<Rc<Texture>>,
+  pub render_state: RenderState,
+}
+
+impl ImguiRenderer {
+  /// Builds the font atlas of `imgui` and hands it the texture.
+  pub fn new(
+    ctx: Rc<glium::backend::Context>,
+    asset_server: &mut AssetServer,
+    shader: &AssetId,
+    imgui: &mut imgui::Context,
+  ) -> Result<Self, String> {
+    let shader = asset_server.load::<Shader>(shader)?;
+
+    let mut textures = Textures::new();
+    let font = {
+      let mut fonts = imgui.fonts();
+      let atlas = fonts.build_rgba32_texture();
+      // imgui reads its atlas top row first, which is how it is uploaded here
+      Texture::from(
+        ctx.clone(),
+        RawImage2d::from_raw_rgba(atlas.data.to_vec(), (atlas.width, atlas.height)),
+        Filter::Linear,
+      )?
+    };
+    imgui.fonts().tex_id = textures.insert(Rc::new(font));
+    imgui
+      .io_mut()
+      .backend_flags
+      .insert(BackendFlags::RENDERER_HAS_VTX_OFFSET);
+
+    Ok(Self {
+      ctx,
+      shader,
+      textures,
+      render_state: RenderState::default(),
+    })
+  }
+
+  pub fn draw<S: Surface>(&mut self, surface: &mut S, draw_data: &DrawData) -> Result<(), String> {
+    let shader = self
+      .shader
+      .get()
+      .ok_or_else(|| format!("shader {} is not loaded", self.shader.id()))?;
+
+    let [left, top] = draw_data.display_pos;
+    let [width, height] = draw_data.display_size;
+    let [scale_x, scale_y] = draw_data.framebuffer_scale;
+    let framebuffer_height = height * scale_y;
+    if width <= 0.0 || framebuffer_height <= 0.0 {
+      return Ok(());
+    }
+    let projection: [[f32; 4]; 4] =
+      glm::ortho(left, left + width, top + height, top, -1.0, 1.0).into();
+
+    for draw_list in draw_data.draw_lists() {
+      // safe as the vertex types share their layout
+      let vertices = unsafe { draw_list.transmute_vtx_buffer::<ImguiVertex>() };
+      let vertices = VertexBuffer::immutable(&self.ctx, vertices).map_err(|e| e.to_string())?;
+      let indices = IndexBuffer::immutable(
+        &self.ctx,
+        PrimitiveType::TrianglesList,
+        draw_list.idx_buffer(),
+      )
+      .map_err(|e| e.to_string())?;
+
+      for command in draw_list.commands() {
+        let (count, clip_rect, texture_id, vtx_offset, idx_offset) = match command {
+          DrawCmd::Elements {
+            count,
+            cmd_params:
+              DrawCmdParams {
+                clip_rect,
+                texture_id,
+                vtx_offset,
+                idx_offset,
+              },
+          } => (count, clip_rect, texture_id, vtx_offset, idx_offset),
+          // every command sets up all of its state, there is nothing to reset
+          DrawCmd::ResetRenderState => continue,
+          // no panel draws with callbacks of its own
+          DrawCmd::RawCallback { .. } => continue,
+        };
+
+        // the clip rect is in display pixels from the top left, scissors in framebuffer
+        // pixels from the bottom left
+        let min_x = ((clip_rect[0] - left) * scale_x).max(0.0);
+        let min_y = ((clip_rect[1] - top) * scale_y).max(0.0);
+        let max_x = (clip_rect[2] - left) * scale_x;
+        let max_y = ((clip_rect[3] - top) * scale_y).min(framebuffer_height);
+        if max_x <= min_x || max_y <= min_y {
+          continue;
+        }
+        let render_state = RenderState {
+          scissor: Some(Scissor {
+            x: min_x as u32,
+            y: (framebuffer_height - max_y) as u32,
+            width: (max_x - min_x) as u32,
+            height: (max_y - min_y) as u32,
+          }),
+          ..self.render_state
+        };
+
+        let texture = self
+          .textures
+          .get(texture_id)
+          .ok_or_else(|| format!("imgui texture {} is not registered", texture_id.id()))?;
+        let uniforms = uniform! {
+          u_projection: projection,
+          tex: texture.sampled(),
+        };
+
+        let vertices = vertices
+          .slice(vtx_offset..vertices.len())
+          .ok_or_else(|| String::from("imgui vertex offset is out of range"))?;
+        let indices = indices
+          .slice(idx_offset..idx_offset + count)
+          .ok_or_else(|| String::from("imgui index range is out of range"))?;
+        surface
+          .draw(
+            vertices,
+            indices,
+            shader.program(),
+            &uniforms,
+            &render_state.draw_parameters(),
+          )
+          .map_err(|e| e.to_string())?;
+      }
+    }
+
+    Ok(())
+  }
+}
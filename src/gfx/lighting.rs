@@ -0,0 +1,585 @@
+use super::{BlendMode, RenderState, Shader};
+use crate::assets::{AssetId, AssetServer, Handle};
+use crate::game::{components::Transform, World};
+use crate::math::glm::{self, Mat4, Vec2, Vec3};
+use crate::physics::{Collider, WorldShape};
+use geo::Rect;
+use glium::{
+  framebuffer::SimpleFrameBuffer,
+  index::{NoIndices, PrimitiveType},
+  texture::{MipmapsOption, RawImage2d, Texture2d, UncompressedFloatFormat},
+  uniform,
+  uniforms::{Sampler, SamplerWrapFunction},
+  Surface, VertexBuffer,
+};
+use serde_json::Value;
+use std::rc::Rc;
+
+mod keys {
+  pub const TYPE: &str = "type";
+  pub const COLOR: &str = "color";
+  pub const INTENSITY: &str = "intensity";
+  pub const RADIUS: &str = "radius";
+  pub const HEIGHT: &str = "height";
+  pub const DIRECTION: &str = "direction";
+  pub const ANGLE: &str = "angle";
+  pub const SHADOWS: &str = "shadows";
+  pub const SOFTNESS: &str = "softness";
+
+  pub const TYPE_AMBIENT: &str = "ambient";
+  pub const TYPE_POINT: &str = "point";
+  pub const TYPE_SPOT: &str = "spot";
+
+  pub const SHADOWS_NONE: &str = "none";
+  pub const SHADOWS_HARD: &str = "hard";
+  pub const SHADOWS_SOFT: &str = "soft";
+}
+
+/// Light positions averaged for a soft shadow.
+const SHADOW_SAMPLES: usize = 8;
+/// Corners of the polygon standing in for a round occluder.
+const CIRCLE_SEGMENTS: usize = 16;
+/// The part of a spot light's cone over which it fades out towards the edge.
+const SPOT_EDGE: f32 = 0.2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LightKind {
+  /// Lights everything evenly, whatever is in the way.
+  Ambient,
+  Point,
+  /// A cone pointing `direction` degrees from the rotation of the entity, `angle`
+  /// degrees wide.
+  Spot {
+    direction: f32,
+    angle: f32,
+  },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shadows {
+  None,
+  Hard,
+  /// The light is treated as a disc `softness` units across the middle, blurring the
+  /// edges of its shadows.
+  Soft {
+    softness: f32,
+  },
+}
+
+/// A light on an entity, placed by its transform.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Light {
+  pub kind: LightKind,
+  pub color: Vec3,
+  pub intensity: f32,
+  /// How far the light reaches in world units, fading out on the way.
+  pub radius: f32,
+  /// How far above the world the light hangs, lower lights catch more of the bumps
+  /// in normal maps.
+  pub height: f32,
+  pub shadows: Shadows,
+}
+
+impl Light {
+  pub fn new(kind: LightKind, radius: f32) -> Self {
+    Self {
+      kind,
+      color: glm::vec3(1.0, 1.0, 1.0),
+      intensity: 1.0,
+      radius,
+      height: 32.0,
+      shadows: Shadows::None,
+    }
+  }
+}
+
+impl TryFrom<&Value> for Light {
+  type Error = String;
+
+  fn try_from(value: &Value) -> Result<Self, Self::Error> {
+    let table = value
+      .as_object()
+      .ok_or_else(|| String::from("light must be an object"))?;
+
+    let allowed = [
+      keys::TYPE,
+      keys::COLOR,
+      keys::INTENSITY,
+      keys::RADIUS,
+      keys::HEIGHT,
+      keys::DIRECTION,
+      keys::ANGLE,
+      keys::SHADOWS,
+      keys::SOFTNESS,
+    ];
+    if let Some(key) = table.keys().find(|key| !allowed.contains(&key.as_str())) {
+      return Err(format!("unknown light key '{}'", key));
+    }
+
+    let string = |key: &str, default: &'static str| match value.get(key) {
+      Some(v) => v
+        .as_str()
+        .ok_or_else(|| format!("light '{}' must be a string", key)),
+      None => Ok(default),
+    };
+    let number = |key: &str, default: f32| match value.get(key) {
+      Some(v) => v
+        .as_f64()
+        .map(|v| v as f32)
+        .ok_or_else(|| format!("light '{}' must be a number", key)),
+      None => Ok(default),
+    };
+
+    let kind = match string(keys::TYPE, keys::TYPE_POINT)? {
+      keys::TYPE_AMBIENT => LightKind::Ambient,
+      keys::TYPE_POINT => LightKind::Point,
+      keys::TYPE_SPOT => LightKind::Spot {
+        direction: number(keys::DIRECTION, 0.0)?,
+        angle: number(keys::ANGLE, 45.0)?,
+      },
+      invalid => return Err(format!("unknown light type '{}'", invalid)),
+    };
+
+    let mut light = Self::new(kind, number(keys::RADIUS, 0.0)?);
+    light.intensity = number(keys::INTENSITY, light.intensity)?;
+    light.height = number(keys::HEIGHT, light.height)?;
+    if let Some(color) = value.get(keys::COLOR) {
+      light.color = match color.as_array().map(Vec::as_slice) {
+        Some([r, g, b]) => glm::vec3(
+          r.as_f64().ok_or("light color must be numbers")? as f32,
+          g.as_f64().ok_or("light color must be numbers")? as f32,
+          b.as_f64().ok_or("light color must be numbers")? as f32,
+        ),
+        _ => return Err(String::from("light color must be [r, g, b]")),
+      };
+    }
+    light.shadows = match string(keys::SHADOWS, keys::SHADOWS_NONE)? {
+      keys::SHADOWS_NONE => Shadows::None,
+      keys::SHADOWS_HARD => Shadows::Hard,
+      keys::SHADOWS_SOFT => Shadows::Soft {
+        softness: number(keys::SOFTNESS, 4.0)?,
+      },
+      invalid => return Err(format!("unknown shadows '{}'", invalid)),
+    };
+
+    if kind == LightKind::Ambient {
+      if light.shadows != Shadows::None {
+        return Err(String::from("ambient lights cannot cast shadows"));
+      }
+    } else if light.radius <= 0.0 {
+      return Err(format!("light '{}' must be positive", keys::RADIUS));
+    }
+
+    Ok(light)
+  }
+}
+
+/// Entities with a collider and this cast shadows, in the shape of their collider.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Occluder;
+
+#[derive(Default, Debug, Clone, Copy)]
+struct LightVertex {
+  i_pos: [f32; 2],
+}
+
+glium::implement_vertex!(LightVertex, i_pos);
+
+/// The triangles shadowed from a light at `light`, out to `reach` away. Occluders are
+/// convex, so their shadow is made of the edges facing away from the light pushed out
+/// away from it, and those never overlap each other. Occluders around the light, like
+/// the one of the entity carrying it, cast no shadow.
+fn shadow_volumes(light: &Vec2, reach: f32, occluders: &[Vec<Vec2>]) -> Vec<LightVertex> {
+  let cross = |a: &Vec2, b: &Vec2| a.x * b.y - a.y * b.x;
+  let push = |point: &Vec2| {
+    let away = point - light;
+    point + away.normalize() * reach
+  };
+
+  let mut vertices = Vec::new();
+  for points in occluders {
+    let center = points.iter().sum::<Vec2>() / points.len() as f32;
+    // how far the light is on the same side of each edge as the occluder, positive when
+    // the edge faces away from it
+    let sides: Vec<f32> = points
+      .iter()
+      .enumerate()
+      .map(|(i, a)| {
+        let edge = points[(i + 1) % points.len()] - a;
+        cross(&edge, &(light - a)) * cross(&edge, &(center - a))
+      })
+      .collect();
+    // every edge facing away means the light is inside
+    if sides.iter().all(|side| *side >= 0.0) {
+      continue;
+    }
+
+    for (i, a) in points.iter().enumerate() {
+      let b = &points[(i + 1) % points.len()];
+      if sides[i] <= 0.0 || *a == *light || *b == *light {
+        continue;
+      }
+
+      let (far_a, far_b) = (push(a), push(b));
+      for corner in [a, b, &far_b, a, &far_b, &far_a] {
+        vertices.push(LightVertex {
+          i_pos: (*corner).into(),
+        });
+      }
+    }
+  }
+  vertices
+}
+
+fn outline(shape: WorldShape) -> Vec<Vec2> {
+  match shape {
+    WorldShape::Polygon { points } => points,
+    WorldShape::Circle { center, radius } => (0..CIRCLE_SEGMENTS)
+      .map(|i| {
+        let angle = i as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+        center + glm::vec2(angle.cos(), angle.sin()) * radius
+      })
+      .collect(),
+  }
+}
+
+/// Where a light is sampled from for its shadows, spread over a disc for soft ones.
+fn shadow_samples(position: &Vec2, shadows: Shadows) -> Vec<Vec2> {
+  match shadows {
+    Shadows::None => Vec::new(),
+    Shadows::Hard => vec![*position],
+    // a sunflower spiral covers the disc evenly and stays put from frame to frame
+    Shadows::Soft { softness } => (0..SHADOW_SAMPLES)
+      .map(|i| {
+        let distance = softness / 2.0 * ((i as f32 + 0.5) / SHADOW_SAMPLES as f32).sqrt();
+        let angle = i as f32 * 2.399_963;
+        position + glm::vec2(angle.cos(), angle.sin()) * distance
+      })
+      .collect(),
+  }
+}
+
+/// Adds up the lights of a world into a light map, to be multiplied with the scene.
+/// Each light is drawn over the square it reaches, shaded by the normals of what was
+/// drawn there and darkened by a shadow mask drawn from the occluders around it.
+pub struct LightRenderer {
+  ctx: Rc<glium::backend::Context>,
+  light_shader: Handle<Shader>,
+  shadow_shader: Handle<Shader>,
+  shadow_mask: Option<Texture2d>,
+  /// Stands in for the normals when there are none, and for the mask of lights
+  /// without shadows.
+  flat: Texture2d,
+}
+
+impl LightRenderer {
+  pub fn new(
+    ctx: Rc<glium::backend::Context>,
+    asset_server: &mut AssetServer,
+    light_shader: &AssetId,
+    shadow_shader: &AssetId,
+  ) -> Result<Self, String> {
+    let light_shader = asset_server.load::<Shader>(light_shader)?;
+    let shadow_shader = asset_server.load::<Shader>(shadow_shader)?;
+    let flat = Texture2d::new(
+      &ctx,
+      RawImage2d::from_raw_rgba(vec![128u8, 128, 255, 0], (1, 1)),
+    )
+    .map_err(|e| e.to_string())?;
+
+    Ok(Self {
+      ctx,
+      light_shader,
+      shadow_shader,
+      shadow_mask: None,
+      flat,
+    })
+  }
+
+  /// Clears the surface to the ambient light and adds every other light over it. A
+  /// world without any lights is left fully lit.
+  pub fn draw<S: Surface>(
+    &mut self,
+    surface: &mut S,
+    world: &World,
+    view: &Mat4,
+    projection: &Mat4,
+    visible: &Rect<f32>,
+    normals: Option<Sampler<'_, Texture2d>>,
+  ) -> Result<(), String> {
+    let lights: Vec<(&Light, &Transform)> = world
+      .query::<Light>()
+      .filter_map(|(entity, light)| Some((light, world.get::<Transform>(entity)?)))
+      .collect();
+
+    let ambient = if lights.is_empty() {
+      glm::vec3(1.0, 1.0, 1.0)
+    } else {
+      lights
+        .iter()
+        .filter(|(light, _)| light.kind == LightKind::Ambient)
+        .map(|(light, _)| light.color * light.intensity)
+        .sum()
+    };
+    surface.clear_color(ambient.x, ambient.y, ambient.z, 1.0);
+
+    let occluders: Vec<Vec<Vec2>> = world
+      .query::<Occluder>()
+      .filter_map(|(entity, _)| {
+        let collider = world.get::<Collider>(entity)?;
+        let transform = world.get::<Transform>(entity)?;
+        Some(outline(collider.world_shape(transform)))
+      })
+      .collect();
+
+    let light_shader = self
+      .light_shader
+      .get()
+      .ok_or_else(|| format!("shader {} is not loaded", self.light_shader.id()))?;
+    let shadow_shader = self
+      .shadow_shader
+      .get()
+      .ok_or_else(|| format!("shader {} is not loaded", self.shadow_shader.id()))?;
+
+    let (width, height) = surface.get_dimensions();
+    if self
+      .shadow_mask
+      .as_ref()
+      .is_none_or(|mask| mask.dimensions() != (width, height))
+    {
+      let mask = Texture2d::empty_with_format(
+        &self.ctx,
+        UncompressedFloatFormat::U8U8U8U8,
+        MipmapsOption::NoMipmap,
+        width,
+        height,
+      )
+      .map_err(|e| format!("cannot make shadow mask: {}", e))?;
+      self.shadow_mask = Some(mask);
+    }
+
+    let view: [[f32; 4]; 4] = (*view).into();
+    let projection: [[f32; 4]; 4] = (*projection).into();
+    let additive = RenderState {
+      blend: BlendMode::Additive,
+      ..RenderState::default()
+    }
+    .draw_parameters();
+    let normals = normals.unwrap_or_else(|| self.flat.sampled());
+
+    for (light, transform) in &lights {
+      let (direction, cos_outer, cos_inner) = match light.kind {
+        LightKind::Ambient => continue,
+        LightKind::Point => (glm::vec2(1.0, 0.0), -1.0, -1.0),
+        LightKind::Spot { direction, angle } => {
+          let facing = (transform.rotation + direction).to_radians();
+          let half = (angle / 2.0).to_radians();
+          (
+            glm::vec2(facing.cos(), facing.sin()),
+            half.cos(),
+            (half * (1.0 - SPOT_EDGE)).cos(),
+          )
+        }
+      };
+
+      let position = transform.position;
+      let reach = glm::vec2(light.radius, light.radius);
+      let (min, max) = (position - reach, position + reach);
+      if max.x < visible.min().x
+        || max.y < visible.min().y
+        || min.x > visible.max().x
+        || min.y > visible.max().y
+      {
+        continue;
+      }
+
+      let samples = if occluders.is_empty() {
+        Vec::new()
+      } else {
+        shadow_samples(&position, light.shadows)
+      };
+      let mask = self
+        .shadow_mask
+        .as_ref()
+        .ok_or_else(|| String::from("shadow mask is not allocated"))?;
+      if !samples.is_empty() {
+        let mut mask_surface =
+          SimpleFrameBuffer::new(&self.ctx, mask).map_err(|e| e.to_string())?;
+        mask_surface.clear_color(0.0, 0.0, 0.0, 0.0);
+
+        // each sample darkens its share, so the mask adds up to how much of the light
+        // is hidden
+        let strength = 1.0 / samples.len() as f32;
+        for sample in &samples {
+          let vertices = shadow_volumes(sample, light.radius * 2.0, &occluders);
+          if vertices.is_empty() {
+            continue;
+          }
+          let vertices = VertexBuffer::new(&self.ctx, &vertices).map_err(|e| e.to_string())?;
+          mask_surface
+            .draw(
+              &vertices,
+              NoIndices(PrimitiveType::TrianglesList),
+              shadow_shader.program(),
+              &uniform! {
+                u_view: view,
+                u_projection: projection,
+                u_strength: strength,
+              },
+              &additive,
+            )
+            .map_err(|e| e.to_string())?;
+        }
+      }
+
+      let quad: Vec<LightVertex> = [
+        glm::vec2(min.x, min.y),
+        glm::vec2(max.x, min.y),
+        glm::vec2(max.x, max.y),
+        glm::vec2(min.x, min.y),
+        glm::vec2(max.x, max.y),
+        glm::vec2(min.x, max.y),
+      ]
+      .iter()
+      .map(|corner| LightVertex {
+        i_pos: (*corner).into(),
+      })
+      .collect();
+      let quad = VertexBuffer::new(&self.ctx, &quad).map_err(|e| e.to_string())?;
+      let shadow = if samples.is_empty() { &self.flat } else { mask };
+      let color: [f32; 3] = (light.color * light.intensity).into();
+
+      surface
+        .draw(
+          &quad,
+          NoIndices(PrimitiveType::TrianglesList),
+          light_shader.program(),
+          &uniform! {
+            u_view: view,
+            u_projection: projection,
+            u_resolution: [width as f32, height as f32],
+            normals: normals,
+            shadow: shadow.sampled().wrap_function(SamplerWrapFunction::Clamp),
+            u_shadowed: if samples.is_empty() { 0.0f32 } else { 1.0f32 },
+            u_position: <[f32; 2]>::from(position),
+            u_color: color,
+            u_radius: light.radius,
+            u_height: light.height,
+            u_direction: <[f32; 2]>::from(direction),
+            u_cos_outer: cos_outer,
+            u_cos_inner: cos_inner,
+          },
+          &additive,
+        )
+        .map_err(|e| e.to_string())?;
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parse(source: &str) -> Result<Light, String> {
+    Light::try_from(&serde_json::from_str::<Value>(source).unwrap())
+  }
+
+  /// A square `size` across with its top left at `x`, `y`.
+  fn square(x: f32, y: f32, size: f32) -> Vec<Vec2> {
+    vec![
+      glm::vec2(x, y),
+      glm::vec2(x + size, y),
+      glm::vec2(x + size, y + size),
+      glm::vec2(x, y + size),
+    ]
+  }
+
+  #[test]
+  fn lights_are_read_with_their_defaults() {
+    let light = parse(r#"{ "radius": 64 }"#).unwrap();
+    assert_eq!(light, Light::new(LightKind::Point, 64.0));
+
+    let spot = parse(
+      r#"{
+        "type": "spot",
+        "radius": 100,
+        "direction": 90,
+        "color": [1, 0.5, 0],
+        "intensity": 2,
+        "shadows": "soft"
+      }"#,
+    )
+    .unwrap();
+    assert_eq!(
+      spot.kind,
+      LightKind::Spot {
+        direction: 90.0,
+        angle: 45.0
+      }
+    );
+    assert_eq!(spot.color, glm::vec3(1.0, 0.5, 0.0));
+    assert_eq!(spot.intensity, 2.0);
+    assert_eq!(spot.shadows, Shadows::Soft { softness: 4.0 });
+
+    let ambient = parse(r#"{ "type": "ambient", "color": [0.1, 0.1, 0.2] }"#).unwrap();
+    assert_eq!(ambient.kind, LightKind::Ambient);
+  }
+
+  #[test]
+  fn invalid_lights_are_rejected() {
+    for source in [
+      r#"{}"#,
+      r#"{ "radius": -1 }"#,
+      r#"{ "radius": 8, "type": "area" }"#,
+      r#"{ "radius": 8, "colour": [1, 1, 1] }"#,
+      r#"{ "radius": 8, "color": [1, 1] }"#,
+      r#"{ "radius": 8, "shadows": "blurry" }"#,
+      r#"{ "type": "ambient", "shadows": "hard" }"#,
+    ] {
+      assert!(parse(source).is_err(), "{}", source);
+    }
+  }
+
+  #[test]
+  fn shadows_fall_behind_occluders() {
+    let light = glm::vec2(-10.0, 5.0);
+    let vertices = shadow_volumes(&light, 100.0, &[square(0.0, 0.0, 10.0)]);
+
+    // the top, right and bottom edges face away from the light
+    assert_eq!(vertices.len(), 18);
+    for vertex in &vertices {
+      assert!(vertex.i_pos[0] >= 0.0, "{:?}", vertex.i_pos);
+    }
+  }
+
+  #[test]
+  fn occluders_around_the_light_cast_no_shadow() {
+    let occluders = [square(0.0, 0.0, 10.0), square(20.0, 0.0, 10.0)];
+    let inside = shadow_volumes(&glm::vec2(5.0, 5.0), 100.0, &occluders);
+    let outside = shadow_volumes(&glm::vec2(5.0, 5.0), 100.0, &occluders[1..]);
+    assert_eq!(inside.len(), outside.len());
+    assert!(!inside.is_empty());
+
+    // like a light on the corner of its own occluder
+    assert!(shadow_volumes(&glm::vec2(0.0, 0.0), 100.0, &occluders[..1]).is_empty());
+  }
+
+  #[test]
+  fn soft_shadows_sample_a_disc_around_the_light() {
+    let position = glm::vec2(3.0, 4.0);
+    assert!(shadow_samples(&position, Shadows::None).is_empty());
+    assert_eq!(shadow_samples(&position, Shadows::Hard), [position]);
+
+    let samples = shadow_samples(&position, Shadows::Soft { softness: 6.0 });
+    assert_eq!(samples.len(), SHADOW_SAMPLES);
+    for sample in &samples {
+      assert!(glm::distance(sample, &position) <= 3.0);
+    }
+    assert_eq!(
+      samples,
+      shadow_samples(&position, Shadows::Soft { softness: 6.0 })
+    );
+  }
+}
@@ -0,0 +1,134 @@
+use crate::assets::{self, AssetId, AssetKind, AssetLoader, LoadContext};
+use glium::{index::PrimitiveType, IndexBuffer, VertexBuffer};
+use serde_json::Value;
+use std::rc::Rc;
+
+mod keys {
+  pub const VERTICES: &str = "vertices";
+  pub const POINTS: &str = "points";
+  pub const NORMALS: &str = "normals";
+  pub const UVS: &str = "uvs";
+  pub const INDICES: &str = "indices";
+}
+
+#[derive(Default, Debug, Clone, Copy)]
+pub struct Vertex {
+  pub i_pos: [f32; 3],
+  pub i_norm: [f32; 3],
+  pub i_uv: [f32; 2],
+}
+
+glium::implement_vertex!(Vertex, i_pos, i_norm, i_uv);
+
+#[derive(Default, Debug)]
+pub struct ModelSource {
+  vertices: Vec<Vertex>,
+  indices: Vec<u32>,
+}
+
+impl ModelSource {
+  fn floats(table: &Value, key: &str) -> Result<Vec<f32>, String> {
+    table
+      .get(key)
+      .and_then(Value::as_array)
+      .ok_or_else(|| format!("model is missing '{}'", key))?
+      .iter()
+      .map(|v| {
+        v.as_f64()
+          .map(|v| v as f32)
+          .ok_or_else(|| format!("'{}' must only contain numbers", key))
+      })
+      .collect()
+  }
+}
+
+impl TryFrom<&Value> for ModelSource {
+  type Error = String;
+
+  fn try_from(value: &Value) -> Result<Self, Self::Error> {
+    let vertices = value
+      .get(keys::VERTICES)
+      .ok_or_else(|| format!("model is missing '{}'", keys::VERTICES))?;
+
+    let points = Self::floats(vertices, keys::POINTS)?;
+    let normals = Self::floats(vertices, keys::NORMALS)?;
+    let uvs = Self::floats(vertices, keys::UVS)?;
+
+    let count = points.len() / 3;
+    if points.len() % 3 != 0 || normals.len() != count * 3 || uvs.len() != count * 2 {
+      return Err(String::from(
+        "model vertex attributes have mismatched lengths",
+      ));
+    }
+
+    let vertices = (0..count)
+      .map(|i| Vertex {
+        i_pos: [points[i * 3], points[i * 3 + 1], points[i * 3 + 2]],
+        i_norm: [normals[i * 3], normals[i * 3 + 1], normals[i * 3 + 2]],
+        i_uv: [uvs[i * 2], uvs[i * 2 + 1]],
+      })
+      .collect();
+
+    let indices = value
+      .get(keys::INDICES)
+      .and_then(Value::as_array)
+      .ok_or_else(|| format!("model is missing '{}'", keys::INDICES))?
+      .iter()
+      .map(|v| {
+        v.as_u64()
+          .and_then(|v| u32::try_from(v).ok())
+          .filter(|v| (*v as usize) < count)
+          .ok_or_else(|| String::from("model indices must reference existing vertices"))
+      })
+      .collect::<Result<Vec<u32>, String>>()?;
+
+    Ok(Self { vertices, indices })
+  }
+}
+
+pub struct ModelLoader {
+  ctx: Rc<glium::backend::Context>,
+}
+
+impl ModelLoader {
+  pub fn new(ctx: Rc<glium::backend::Context>) -> Self {
+    Self { ctx }
+  }
+}
+
+impl AssetLoader for ModelLoader {
+  type Asset = Model;
+
+  fn kind(&self) -> AssetKind {
+    AssetKind::Model
+  }
+
+  fn load(&self, id: &AssetId, _ctx: &mut LoadContext) -> Result<Model, String> {
+    let entry = assets::read_json_entry(AssetKind::Model, id)?;
+    let source = ModelSource::try_from(&entry)?;
+    Model::from(self.ctx.clone(), source)
+  }
+}
+
+pub struct Model {
+  vertices: VertexBuffer<Vertex>,
+  indices: IndexBuffer<u32>,
+}
+
+impl Model {
+  fn from(ctx: Rc<glium::backend::Context>, source: ModelSource) -> Result<Self, String> {
+    let vertices = VertexBuffer::new(&ctx, &source.vertices).map_err(|e| e.to_string())?;
+    let indices = IndexBuffer::new(&ctx, PrimitiveType::TrianglesList, &source.indices)
+      .map_err(|e| e.to_string())?;
+
+    Ok(Self { vertices, indices })
+  }
+
+  pub fn vertices(&self) -> &VertexBuffer<Vertex> {
+    &self.vertices
+  }
+
+  pub fn indices(&self) -> &IndexBuffer<u32> {
+    &self.indices
+  }
+}
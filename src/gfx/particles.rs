@@ -0,0 +1,490 @@
+mod emitter;
+mod renderer;
+
+use super::{BlendMode, Texture};
+use crate::assets::{self, AssetId, AssetKind, AssetLoader, Handle, LoadContext};
+use crate::math::glm::{self, Vec2, Vec4};
+use geo::Rect;
+use serde_json::Value;
+
+pub use emitter::{spawn_effect, update_emitters, ParticleEmitter};
+pub use renderer::ParticleRenderer;
+
+mod keys {
+  pub const TEXTURE: &str = "texture";
+  pub const REGION: &str = "region";
+  pub const BLEND: &str = "blend";
+  pub const MAX_PARTICLES: &str = "max_particles";
+  pub const RATE: &str = "rate";
+  pub const BURSTS: &str = "bursts";
+  pub const DURATION: &str = "duration";
+  pub const LOOPING: &str = "looping";
+  pub const LIFETIME: &str = "lifetime";
+  pub const SPEED: &str = "speed";
+  pub const DIRECTION: &str = "direction";
+  pub const SPREAD: &str = "spread";
+  pub const SIZE: &str = "size";
+  pub const GRAVITY: &str = "gravity";
+  pub const OFFSET: &str = "offset";
+  pub const SPACE: &str = "space";
+  pub const VELOCITY_OVER_LIFE: &str = "velocity_over_life";
+  pub const COLOR_OVER_LIFE: &str = "color_over_life";
+  pub const SIZE_OVER_LIFE: &str = "size_over_life";
+
+  pub const BURST_TIME: &str = "time";
+  pub const BURST_COUNT: &str = "count";
+
+  pub const SPACE_WORLD: &str = "world";
+  pub const SPACE_LOCAL: &str = "local";
+}
+
+const DEFAULT_MAX_PARTICLES: usize = 256;
+
+/// Values a curve can blend between.
+pub trait Lerp: Copy {
+  fn lerp(from: Self, to: Self, t: f32) -> Self;
+}
+
+impl Lerp for f32 {
+  fn lerp(from: Self, to: Self, t: f32) -> Self {
+    from + (to - from) * t
+  }
+}
+
+impl Lerp for Vec4 {
+  fn lerp(from: Self, to: Self, t: f32) -> Self {
+    glm::lerp(&from, &to, t)
+  }
+}
+
+/// A value over the life of a particle, from 0 when it spawns to 1 when it dies,
+/// blended in straight lines between keys.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Curve<T> {
+  /// By time, never empty.
+  keys: Vec<(f32, T)>,
+}
+
+impl<T: Lerp> Curve<T> {
+  pub fn constant(value: T) -> Self {
+    Self {
+      keys: vec![(0.0, value)],
+    }
+  }
+
+  pub fn sample(&self, t: f32) -> T {
+    let next = self.keys.partition_point(|(time, _)| *time <= t);
+    match (
+      next.checked_sub(1).map(|at| self.keys[at]),
+      self.keys.get(next),
+    ) {
+      (Some((from_time, from)), Some(&(to_time, to))) => {
+        T::lerp(from, to, (t - from_time) / (to_time - from_time))
+      }
+      (Some((_, value)), None) | (None, Some(&(_, value))) => value,
+      (None, None) => unreachable!("curves have at least one key"),
+    }
+  }
+
+  /// Reads a single value, or `[[time, value], ...]` with times rising from 0 to 1.
+  fn parse(value: &Value, key: &str, parse: impl Fn(&Value) -> Option<T>) -> Result<Self, String> {
+    if let Some(value) = parse(value) {
+      return Ok(Self::constant(value));
+    }
+
+    let invalid = || format!("'{}' must be a value or [[time, value], ...]", key);
+    let keys = value
+      .as_array()
+      .filter(|keys| !keys.is_empty())
+      .ok_or_else(invalid)?
+      .iter()
+      .map(|pair| match pair.as_array().map(Vec::as_slice) {
+        Some([time, value]) => Some((time.as_f64()? as f32, parse(value)?)),
+        _ => None,
+      })
+      .collect::<Option<Vec<_>>>()
+      .ok_or_else(invalid)?;
+
+    if keys.iter().any(|(time, _)| !(0.0..=1.0).contains(time)) {
+      return Err(format!("'{}' times must be between 0 and 1", key));
+    }
+    if keys.windows(2).any(|pair| pair[0].0 >= pair[1].0) {
+      return Err(format!("'{}' times must rise from key to key", key));
+    }
+    Ok(Self { keys })
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Space {
+  /// Particles stay where they were spawned when the emitter moves.
+  World,
+  /// Particles move with the emitter.
+  Local,
+}
+
+/// Particles spawned at once, `time` seconds after the emitter starts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Burst {
+  pub time: f32,
+  pub count: usize,
+}
+
+/// How an emitter spawns particles and how they move and look over their lives.
+/// Ranges are picked from evenly for every particle.
+pub struct ParticleEffect {
+  pub texture: Handle<Texture>,
+  /// The part of the texture to draw in pixels from its top left, all of it if `None`.
+  pub region: Option<Rect<f32>>,
+  pub blend: BlendMode,
+  /// Particles alive at once, spawning stops while there are this many.
+  pub max_particles: usize,
+  /// Particles spawned every second while emitting.
+  pub rate: f32,
+  /// In order of time.
+  pub bursts: Vec<Burst>,
+  /// Seconds the emitter runs for before stopping or looping, forever if `None`.
+  pub duration: Option<f32>,
+  pub looping: bool,
+  /// Seconds.
+  pub lifetime: (f32, f32),
+  /// World units a second.
+  pub speed: (f32, f32),
+  /// Degrees clockwise from the right on screen, turned with the entity.
+  pub direction: f32,
+  /// Degrees around `direction` particles leave in.
+  pub spread: f32,
+  /// The width and height of particles in world units.
+  pub size: (f32, f32),
+  /// World units a second added to the velocity of particles every second.
+  pub gravity: Vec2,
+  /// From the entity to where particles spawn, turned with the entity.
+  pub offset: Vec2,
+  pub space: Space,
+  /// Multiplies how fast particles move.
+  pub velocity_over_life: Curve<f32>,
+  /// Multiplies the texture color.
+  pub color_over_life: Curve<Vec4>,
+  /// Multiplies the size picked when particles spawn.
+  pub size_over_life: Curve<f32>,
+}
+
+impl ParticleEffect {
+  fn parse(value: &Value, ctx: &mut LoadContext) -> Result<Self, String> {
+    let table = value
+      .as_object()
+      .ok_or_else(|| String::from("particle effect must be an object"))?;
+
+    let allowed = [
+      keys::TEXTURE,
+      keys::REGION,
+      keys::BLEND,
+      keys::MAX_PARTICLES,
+      keys::RATE,
+      keys::BURSTS,
+      keys::DURATION,
+      keys::LOOPING,
+      keys::LIFETIME,
+      keys::SPEED,
+      keys::DIRECTION,
+      keys::SPREAD,
+      keys::SIZE,
+      keys::GRAVITY,
+      keys::OFFSET,
+      keys::SPACE,
+      keys::VELOCITY_OVER_LIFE,
+      keys::COLOR_OVER_LIFE,
+      keys::SIZE_OVER_LIFE,
+    ];
+    if let Some(key) = table.keys().find(|key| !allowed.contains(&key.as_str())) {
+      return Err(format!("unknown particle effect key '{}'", key));
+    }
+
+    let numbers = |value: &Value| -> Option<Vec<f32>> {
+      value
+        .as_array()?
+        .iter()
+        .map(|n| n.as_f64().map(|n| n as f32))
+        .collect()
+    };
+    let number = |key: &str, default: f32| match value.get(key) {
+      Some(v) => v
+        .as_f64()
+        .map(|v| v as f32)
+        .ok_or_else(|| format!("'{}' must be a number", key)),
+      None => Ok(default),
+    };
+    // a single number, or [min, max]
+    let range = |key: &str, default: f32| match value.get(key) {
+      Some(v) => match (v.as_f64(), numbers(v).as_deref()) {
+        (Some(n), _) => Ok((n as f32, n as f32)),
+        (None, Some(&[min, max])) if min <= max => Ok((min, max)),
+        _ => Err(format!("'{}' must be a number or [min, max]", key)),
+      },
+      None => Ok((default, default)),
+    };
+    let vector = |key: &str| match value.get(key) {
+      Some(v) => match numbers(v).as_deref() {
+        Some(&[x, y]) => Ok(glm::vec2(x, y)),
+        _ => Err(format!("'{}' must be [x, y]", key)),
+      },
+      None => Ok(glm::vec2(0.0, 0.0)),
+    };
+    let curve = |key: &str| match value.get(key) {
+      Some(v) => Curve::parse(v, key, |v| v.as_f64().map(|v| v as f32)),
+      None => Ok(Curve::constant(1.0)),
+    };
+
+    let texture = value
+      .get(keys::TEXTURE)
+      .and_then(Value::as_str)
+      .ok_or_else(|| format!("missing '{}'", keys::TEXTURE))?
+      .parse::<AssetId>()
+      .map_err(|e| format!("'{}' {}", keys::TEXTURE, e))?;
+
+    let region = match value.get(keys::REGION) {
+      Some(v) => match numbers(v).as_deref() {
+        Some(&[x, y, width, height]) if width > 0.0 && height > 0.0 => {
+          Some(Rect::new((x, y), (x + width, y + height)))
+        }
+        _ => return Err(format!("'{}' must be [x, y, width, height]", keys::REGION)),
+      },
+      None => None,
+    };
+
+    let blend = match value.get(keys::BLEND) {
+      Some(v) => BlendMode::try_from(
+        v.as_str()
+          .ok_or_else(|| format!("'{}' must be a string", keys::BLEND))?,
+      )?,
+      None => BlendMode::Alpha,
+    };
+
+    let max_particles = match value.get(keys::MAX_PARTICLES) {
+      Some(v) => v
+        .as_u64()
+        .filter(|max| *max > 0)
+        .ok_or_else(|| format!("'{}' must be a positive integer", keys::MAX_PARTICLES))?
+        as usize,
+      None => DEFAULT_MAX_PARTICLES,
+    };
+
+    let duration = match value.get(keys::DURATION) {
+      Some(v) => Some(
+        v.as_f64()
+          .map(|v| v as f32)
+          .filter(|duration| *duration > 0.0)
+          .ok_or_else(|| format!("'{}' must be a positive number", keys::DURATION))?,
+      ),
+      None => None,
+    };
+    let looping = match value.get(keys::LOOPING) {
+      Some(v) => v
+        .as_bool()
+        .ok_or_else(|| format!("'{}' must be a boolean", keys::LOOPING))?,
+      None => false,
+    };
+    if looping && duration.is_none() {
+      return Err(format!(
+        "'{}' needs a '{}' to loop after",
+        keys::LOOPING,
+        keys::DURATION
+      ));
+    }
+
+    let mut bursts = Vec::new();
+    for burst in value
+      .get(keys::BURSTS)
+      .map(|v| {
+        v.as_array()
+          .ok_or_else(|| format!("'{}' must be an array", keys::BURSTS))
+      })
+      .transpose()?
+      .into_iter()
+      .flatten()
+    {
+      let time = burst
+        .get(keys::BURST_TIME)
+        .and_then(Value::as_f64)
+        .filter(|time| *time >= 0.0)
+        .ok_or_else(|| format!("bursts need a non-negative '{}'", keys::BURST_TIME))?;
+      let count = burst
+        .get(keys::BURST_COUNT)
+        .and_then(Value::as_u64)
+        .filter(|count| *count > 0)
+        .ok_or_else(|| format!("bursts need a positive integer '{}'", keys::BURST_COUNT))?;
+      let burst = Burst {
+        time: time as f32,
+        count: count as usize,
+      };
+      if duration.is_some_and(|duration| burst.time >= duration) {
+        return Err(format!(
+          "a burst at {}s never happens in a {}s effect",
+          burst.time,
+          duration.unwrap_or_default()
+        ));
+      }
+      bursts.push(burst);
+    }
+    bursts.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+    let space = match value.get(keys::SPACE).map(Value::as_str) {
+      None | Some(Some(keys::SPACE_WORLD)) => Space::World,
+      Some(Some(keys::SPACE_LOCAL)) => Space::Local,
+      Some(_) => {
+        return Err(format!(
+          "'{}' must be '{}' or '{}'",
+          keys::SPACE,
+          keys::SPACE_WORLD,
+          keys::SPACE_LOCAL
+        ))
+      }
+    };
+
+    let color_over_life = match value.get(keys::COLOR_OVER_LIFE) {
+      Some(v) => Curve::parse(v, keys::COLOR_OVER_LIFE, |v| match numbers(v).as_deref() {
+        Some(&[r, g, b, a]) => Some(glm::vec4(r, g, b, a)),
+        _ => None,
+      })?,
+      None => Curve::constant(glm::vec4(1.0, 1.0, 1.0, 1.0)),
+    };
+
+    let effect = Self {
+      texture: ctx.load(&texture)?,
+      region,
+      blend,
+      max_particles,
+      rate: number(keys::RATE, 0.0)?,
+      bursts,
+      duration,
+      looping,
+      lifetime: range(keys::LIFETIME, 1.0)?,
+      speed: range(keys::SPEED, 0.0)?,
+      direction: number(keys::DIRECTION, 0.0)?,
+      spread: number(keys::SPREAD, 0.0)?,
+      size: range(keys::SIZE, 1.0)?,
+      gravity: vector(keys::GRAVITY)?,
+      offset: vector(keys::OFFSET)?,
+      space,
+      velocity_over_life: curve(keys::VELOCITY_OVER_LIFE)?,
+      color_over_life,
+      size_over_life: curve(keys::SIZE_OVER_LIFE)?,
+    };
+
+    if effect.rate < 0.0 {
+      return Err(format!("'{}' cannot be negative", keys::RATE));
+    }
+    if effect.lifetime.0 <= 0.0 {
+      return Err(format!("'{}' must be positive", keys::LIFETIME));
+    }
+    Ok(effect)
+  }
+}
+
+pub struct ParticleEffectLoader;
+
+impl ParticleEffectLoader {
+  pub fn new() -> Self {
+    Self
+  }
+}
+
+impl AssetLoader for ParticleEffectLoader {
+  type Asset = ParticleEffect;
+
+  fn kind(&self) -> AssetKind {
+    AssetKind::Particles
+  }
+
+  fn load(&self, id: &AssetId, ctx: &mut LoadContext) -> Result<ParticleEffect, String> {
+    let entry = assets::read_json_entry(AssetKind::Particles, id)?;
+    ParticleEffect::parse(&entry, ctx)
+      .map_err(|msg| format!("rejecting particle effect {}: {}", id, msg))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::assets::AssetServer;
+  use crate::view::headless::HeadlessBackend;
+
+  /// Reads an effect drawn with the particle texture, with `keys` added.
+  pub(super) fn effect(keys: &str) -> Result<ParticleEffect, String> {
+    let backend = HeadlessBackend::any((16, 16)).unwrap();
+    let ctx = unsafe { glium::backend::Context::new(backend, true, Default::default()) }.unwrap();
+    let mut server = AssetServer::new();
+    crate::register_loaders(&mut server, &ctx);
+    let source = format!(r#"{{ "texture": "exp.render.particle", {} }}"#, keys);
+    let mut ctx = LoadContext::new(&mut server);
+    ParticleEffect::parse(&serde_json::from_str::<Value>(&source).unwrap(), &mut ctx)
+  }
+
+  #[test]
+  fn curves_blend_between_their_keys() {
+    let curve = Curve::parse(
+      &serde_json::from_str::<Value>("[[0, 1], [0.5, 3], [1, 0]]").unwrap(),
+      "curve",
+      |v| v.as_f64().map(|v| v as f32),
+    )
+    .unwrap();
+    assert_eq!(curve.sample(0.0), 1.0);
+    assert_eq!(curve.sample(0.25), 2.0);
+    assert_eq!(curve.sample(0.75), 1.5);
+    assert_eq!(curve.sample(2.0), 0.0);
+  }
+
+  #[test]
+  fn effects_are_read_with_their_defaults() {
+    let effect = effect(r#""rate": 4, "lifetime": [0.5, 1], "speed": 8"#).unwrap();
+    assert_eq!(effect.rate, 4.0);
+    assert_eq!(effect.lifetime, (0.5, 1.0));
+    assert_eq!(effect.speed, (8.0, 8.0));
+    assert_eq!(effect.max_particles, DEFAULT_MAX_PARTICLES);
+    assert_eq!(effect.blend, BlendMode::Alpha);
+    assert_eq!(effect.space, Space::World);
+    assert_eq!(effect.duration, None);
+    assert!(!effect.looping);
+  }
+
+  #[test]
+  fn bursts_are_sorted_by_time() {
+    let effect = effect(
+      r#""duration": 2, "bursts": [{ "time": 1.5, "count": 2 }, { "time": 0, "count": 5 }]"#,
+    )
+    .unwrap();
+    assert_eq!(
+      effect.bursts,
+      [
+        Burst {
+          time: 0.0,
+          count: 5
+        },
+        Burst {
+          time: 1.5,
+          count: 2
+        }
+      ]
+    );
+  }
+
+  #[test]
+  fn invalid_effects_are_rejected() {
+    for keys in [
+      r#""bursts": [{ "time": 0, "count": 0 }]"#,
+      r#""bursts": [{ "time": -1, "count": 3 }]"#,
+      r#""bursts": [{ "time": 0, "count": 2.5 }]"#,
+      r#""duration": 1, "bursts": [{ "time": 1, "count": 3 }]"#,
+      r#""looping": true"#,
+      r#""rate": -1"#,
+      r#""lifetime": 0"#,
+      r#""speed": [4, 2]"#,
+      r#""max_particles": 0"#,
+      r#""space": "screen""#,
+      r#""size_over_life": [[0.5, 1], [0.25, 2]]"#,
+      r#""colour": [1, 1, 1, 1]"#,
+    ] {
+      assert!(effect(keys).is_err(), "{}", keys);
+    }
+  }
+}
@@ -0,0 +1,452 @@
+use super::{ParticleEffect, Space};
+use crate::assets::Handle;
+use crate::game::{components::Transform, Entity, World};
+use crate::math::glm::{self, Vec2, Vec4};
+use crate::util::Rng;
+
+/// Live particles, one array per attribute so each pass over them only touches
+/// what it needs. Dead particles are swapped out, keeping the arrays packed.
+#[derive(Debug, Clone, Default)]
+struct Particles {
+  positions: Vec<Vec2>,
+  velocities: Vec<Vec2>,
+  ages: Vec<f32>,
+  lifetimes: Vec<f32>,
+  sizes: Vec<f32>,
+}
+
+impl Particles {
+  fn len(&self) -> usize {
+    self.ages.len()
+  }
+
+  fn push(&mut self, position: Vec2, velocity: Vec2, lifetime: f32, size: f32) {
+    self.positions.push(position);
+    self.velocities.push(velocity);
+    self.ages.push(0.0);
+    self.lifetimes.push(lifetime);
+    self.sizes.push(size);
+  }
+
+  fn swap_remove(&mut self, index: usize) {
+    self.positions.swap_remove(index);
+    self.velocities.swap_remove(index);
+    self.ages.swap_remove(index);
+    self.lifetimes.swap_remove(index);
+    self.sizes.swap_remove(index);
+  }
+
+  fn clear(&mut self) {
+    self.positions.clear();
+    self.velocities.clear();
+    self.ages.clear();
+    self.lifetimes.clear();
+    self.sizes.clear();
+  }
+
+  /// Ages every particle, removing those past their lifetime, and moves the rest.
+  fn step(&mut self, effect: &ParticleEffect, dt: f32) {
+    let mut index = 0;
+    while index < self.len() {
+      self.ages[index] += dt;
+      if self.ages[index] >= self.lifetimes[index] {
+        self.swap_remove(index);
+      } else {
+        index += 1;
+      }
+    }
+
+    for velocity in &mut self.velocities {
+      *velocity += effect.gravity * dt;
+    }
+    for index in 0..self.len() {
+      let life = self.ages[index] / self.lifetimes[index];
+      self.positions[index] += self.velocities[index] * effect.velocity_over_life.sample(life) * dt;
+    }
+  }
+}
+
+/// A particle as it is drawn this frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParticleInstance {
+  /// The centre of the particle in the world.
+  pub position: Vec2,
+  pub size: f32,
+  pub color: Vec4,
+}
+
+/// Spawns and steps particles for the entity it is on, from its transform. Scripts
+/// drive it through `play`, `stop`, `burst` and `rate_scale`.
+pub struct ParticleEmitter {
+  pub effect: Handle<ParticleEffect>,
+  /// Multiplies the spawn rate of the effect, like dust thickening as a character
+  /// runs faster.
+  pub rate_scale: f32,
+  /// Despawns the entity once the emitter has stopped and its last particle died, for
+  /// one off effects like sparks.
+  pub despawn_when_finished: bool,
+  emitting: bool,
+  /// Seconds since the emitter started or last looped.
+  time: f32,
+  /// The first burst of the effect that has not happened yet.
+  next_burst: usize,
+  /// Particles asked for by `burst`, spawned on the next update.
+  queued: usize,
+  /// Fractions of a particle left over from spawning at the rate.
+  pending: f32,
+  /// Where the entity was on the last update, which local particles move with.
+  origin: Transform,
+  particles: Particles,
+  rng: Rng,
+}
+
+impl ParticleEmitter {
+  /// Starts out emitting. Emitters with the same `seed` spawn the same particles, so
+  /// giving each its own, like the index of its entity, keeps them from looking alike.
+  pub fn new(effect: Handle<ParticleEffect>, seed: u64) -> Self {
+    Self {
+      effect,
+      rate_scale: 1.0,
+      despawn_when_finished: false,
+      emitting: true,
+      time: 0.0,
+      next_burst: 0,
+      queued: 0,
+      pending: 0.0,
+      origin: Transform::default(),
+      particles: Particles::default(),
+      rng: Rng::new(seed),
+    }
+  }
+
+  /// Starts emitting from the beginning of the effect, bursts included.
+  pub fn play(&mut self) {
+    self.emitting = true;
+    self.time = 0.0;
+    self.next_burst = 0;
+    self.pending = 0.0;
+  }
+
+  /// Stops spawning particles, the ones already out live on.
+  pub fn stop(&mut self) {
+    self.emitting = false;
+  }
+
+  /// Spawns `count` particles on the next update, emitting or not.
+  pub fn burst(&mut self, count: usize) {
+    self.queued += count;
+  }
+
+  /// Removes every particle.
+  pub fn clear(&mut self) {
+    self.particles.clear();
+    self.queued = 0;
+  }
+
+  pub fn is_emitting(&self) -> bool {
+    self.emitting
+  }
+
+  /// Whether there is nothing left to spawn or draw.
+  pub fn is_finished(&self) -> bool {
+    !self.emitting && self.queued == 0 && self.particles.len() == 0
+  }
+
+  pub fn particle_count(&self) -> usize {
+    self.particles.len()
+  }
+
+  /// Moves the emitter to `origin` and steps it `dt` seconds.
+  pub fn update(&mut self, origin: &Transform, dt: f32) {
+    let effect = match self.effect.get() {
+      Some(effect) => effect,
+      None => return,
+    };
+    self.origin = origin.clone();
+    self.particles.step(&effect, dt);
+
+    let queued = std::mem::take(&mut self.queued);
+    self.spawn(&effect, queued);
+
+    let mut remaining = dt;
+    while self.emitting && remaining > 0.0 {
+      let end = match effect.duration {
+        Some(duration) => (self.time + remaining).min(duration),
+        None => self.time + remaining,
+      };
+      // a remainder too small to move the clock would never run out
+      if end <= self.time {
+        break;
+      }
+
+      while let Some(burst) = effect.bursts.get(self.next_burst) {
+        if burst.time >= end {
+          break;
+        }
+        self.spawn(&effect, burst.count);
+        self.next_burst += 1;
+      }
+      self.pending += effect.rate * self.rate_scale.max(0.0) * (end - self.time);
+      let count = self.pending.floor();
+      self.pending -= count;
+      self.spawn(&effect, count as usize);
+
+      remaining -= end - self.time;
+      self.time = end;
+      if effect
+        .duration
+        .is_some_and(|duration| self.time >= duration)
+      {
+        if effect.looping {
+          self.time = 0.0;
+          self.next_burst = 0;
+        } else {
+          self.emitting = false;
+        }
+      }
+    }
+  }
+
+  /// The live particles in the world, as they look at their age.
+  pub fn instances(&self) -> impl Iterator<Item = ParticleInstance> + '_ {
+    let effect = self.effect.get();
+    let particles = &self.particles;
+    effect.into_iter().flat_map(move |effect| {
+      (0..particles.len()).map(move |index| {
+        let life = particles.ages[index] / particles.lifetimes[index];
+        let position = match effect.space {
+          Space::World => particles.positions[index],
+          Space::Local => self.place(&particles.positions[index]),
+        };
+        ParticleInstance {
+          position,
+          size: particles.sizes[index] * effect.size_over_life.sample(life),
+          color: effect.color_over_life.sample(life),
+        }
+      })
+    })
+  }
+
+  /// From the entity to the world.
+  fn place(&self, local: &Vec2) -> Vec2 {
+    glm::rotate_vec2(local, self.origin.rotation.to_radians()) + self.origin.position
+  }
+
+  fn spawn(&mut self, effect: &ParticleEffect, count: usize) {
+    let room = effect.max_particles.saturating_sub(self.particles.len());
+    let range = |rng: &mut Rng, (min, max): (f32, f32)| rng.range_f32(min, max);
+
+    for _ in 0..count.min(room) {
+      let half_spread = effect.spread / 2.0;
+      let angle = effect.direction + self.rng.range_f32(-half_spread, half_spread);
+      let (speed, lifetime, size) = (
+        range(&mut self.rng, effect.speed),
+        range(&mut self.rng, effect.lifetime),
+        range(&mut self.rng, effect.size),
+      );
+
+      // local particles are turned with the entity when they are drawn instead
+      let (position, velocity) = match effect.space {
+        Space::World => (
+          self.place(&effect.offset),
+          glm::rotate_vec2(
+            &glm::vec2(speed, 0.0),
+            (angle + self.origin.rotation).to_radians(),
+          ),
+        ),
+        Space::Local => (
+          effect.offset,
+          glm::rotate_vec2(&glm::vec2(speed, 0.0), angle.to_radians()),
+        ),
+      };
+      self.particles.push(position, velocity, lifetime, size);
+    }
+  }
+}
+
+/// Steps every emitter from the transform of its entity, then despawns entities whose
+/// one off effects have finished.
+pub fn update_emitters(world: &mut World, dt: f32) {
+  let origins: Vec<_> = world
+    .query::<ParticleEmitter>()
+    .filter_map(|(entity, _)| Some((entity, world.get::<Transform>(entity)?.clone())))
+    .collect();
+
+  let mut finished = Vec::new();
+  for (entity, origin) in origins {
+    if let Some(emitter) = world.get_mut::<ParticleEmitter>(entity) {
+      emitter.update(&origin, dt);
+      if emitter.despawn_when_finished && emitter.is_finished() {
+        finished.push(entity);
+      }
+    }
+  }
+  for entity in finished {
+    world.despawn(entity);
+  }
+}
+
+/// Spawns an entity playing `effect` once at `transform`, gone again when it is done.
+pub fn spawn_effect(
+  world: &mut World,
+  effect: Handle<ParticleEffect>,
+  transform: Transform,
+) -> Entity {
+  let entity = world.spawn();
+  let mut emitter = ParticleEmitter::new(effect, entity.index() as u64);
+  emitter.despawn_when_finished = true;
+  world.insert(entity, transform);
+  world.insert(entity, emitter);
+  entity
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::gfx::particles::tests::effect;
+
+  fn emitter(keys: &str, seed: u64) -> ParticleEmitter {
+    let effect = Handle::detached("exp.test.effect".parse().unwrap(), effect(keys).unwrap());
+    ParticleEmitter::new(effect, seed)
+  }
+
+  fn step(emitter: &mut ParticleEmitter, dt: f32, times: usize) {
+    for _ in 0..times {
+      emitter.update(&Transform::default(), dt);
+    }
+  }
+
+  #[test]
+  fn particles_move_and_die_at_the_end_of_their_lifetime() {
+    let mut emitter = emitter(r#""lifetime": 1, "speed": 4, "gravity": [0, 2]"#, 0);
+    emitter.burst(1);
+    step(&mut emitter, 0.5, 1);
+    assert_eq!(emitter.particle_count(), 1);
+    assert_eq!(
+      emitter.instances().next().unwrap().position,
+      glm::vec2(0.0, 0.0)
+    );
+
+    step(&mut emitter, 0.5, 1);
+    // the velocity is sped up by gravity before the particle moves with it
+    assert_eq!(
+      emitter.instances().next().unwrap().position,
+      glm::vec2(2.0, 0.5)
+    );
+    step(&mut emitter, 0.5, 1);
+    assert_eq!(emitter.particle_count(), 0);
+  }
+
+  #[test]
+  fn the_rate_carries_fractions_of_a_particle_between_updates() {
+    let mut emitter = emitter(r#""rate": 10, "lifetime": 10"#, 0);
+    step(&mut emitter, 0.05, 1);
+    assert_eq!(emitter.particle_count(), 0);
+    step(&mut emitter, 0.05, 1);
+    assert_eq!(emitter.particle_count(), 1);
+    step(&mut emitter, 0.25, 4);
+    assert_eq!(emitter.particle_count(), 11);
+
+    emitter.rate_scale = 2.0;
+    step(&mut emitter, 0.5, 1);
+    assert_eq!(emitter.particle_count(), 21);
+  }
+
+  #[test]
+  fn bursts_happen_once_at_their_time() {
+    let mut emitter = emitter(
+      r#""lifetime": 10, "duration": 2, "bursts": [{ "time": 0, "count": 3 }, { "time": 1, "count": 2 }]"#,
+      0,
+    );
+    step(&mut emitter, 0.5, 1);
+    assert_eq!(emitter.particle_count(), 3);
+    step(&mut emitter, 0.5, 1);
+    assert_eq!(emitter.particle_count(), 3);
+    step(&mut emitter, 0.5, 1);
+    assert_eq!(emitter.particle_count(), 5);
+    step(&mut emitter, 0.5, 1);
+    assert!(!emitter.is_emitting());
+    step(&mut emitter, 0.5, 4);
+    assert_eq!(emitter.particle_count(), 5);
+  }
+
+  #[test]
+  fn looping_effects_burst_again_every_duration() {
+    let mut emitter = emitter(
+      r#""lifetime": 10, "duration": 1, "looping": true, "bursts": [{ "time": 0.5, "count": 2 }]"#,
+      0,
+    );
+    step(&mut emitter, 0.25, 3);
+    assert_eq!(emitter.particle_count(), 2);
+    // one update crossing the end of the effect bursts on both sides of it
+    step(&mut emitter, 2.0, 1);
+    assert_eq!(emitter.particle_count(), 6);
+    assert!(emitter.is_emitting());
+  }
+
+  #[test]
+  fn spawning_stops_at_the_most_particles_alive_at_once() {
+    let mut emitter = emitter(r#""rate": 100, "lifetime": 10, "max_particles": 8"#, 0);
+    emitter.burst(5);
+    step(&mut emitter, 1.0, 1);
+    assert_eq!(emitter.particle_count(), 8);
+  }
+
+  #[test]
+  fn finished_emitters_have_stopped_with_nothing_left() {
+    let mut emitter = emitter(r#""lifetime": 1, "duration": 0.5"#, 0);
+    assert!(!emitter.is_finished());
+    emitter.burst(1);
+    step(&mut emitter, 0.5, 1);
+    assert!(!emitter.is_emitting());
+    assert!(!emitter.is_finished());
+    step(&mut emitter, 1.0, 1);
+    assert!(emitter.is_finished());
+
+    emitter.play();
+    assert!(!emitter.is_finished());
+    emitter.stop();
+    emitter.burst(1);
+    assert!(!emitter.is_finished());
+    emitter.clear();
+    assert!(emitter.is_finished());
+  }
+
+  #[test]
+  fn emitters_with_the_same_seed_spawn_the_same_particles() {
+    let keys = r#""lifetime": [1, 2], "speed": [1, 8], "spread": 360, "size": [1, 4]"#;
+    let run = |seed| {
+      let mut emitter = emitter(keys, seed);
+      emitter.burst(16);
+      step(&mut emitter, 0.25, 2);
+      emitter.instances().collect::<Vec<_>>()
+    };
+    assert_eq!(run(3), run(3));
+    assert_ne!(run(3), run(4));
+  }
+
+  #[test]
+  fn spawned_effects_despawn_their_entity_once_finished() {
+    let mut world = World::new();
+    let effect = Handle::detached(
+      "exp.test.effect".parse().unwrap(),
+      effect(r#""lifetime": 1, "duration": 0.5, "bursts": [{ "time": 0, "count": 4 }]"#).unwrap(),
+    );
+    let entity = spawn_effect(&mut world, effect, Transform::default());
+    let kept = world.spawn();
+    world.insert(kept, Transform::default());
+    world.insert(kept, emitter(r#""lifetime": 1"#, 0));
+
+    update_emitters(&mut world, 0.5);
+    assert_eq!(
+      world
+        .get::<ParticleEmitter>(entity)
+        .unwrap()
+        .particle_count(),
+      4
+    );
+    update_emitters(&mut world, 1.0);
+    assert!(!world.contains(entity));
+    assert!(world.contains(kept));
+  }
+}
@@ -0,0 +1,167 @@
+use super::ParticleEmitter;
+use crate::assets::{AssetId, AssetServer, Handle};
+use crate::game::World;
+use crate::gfx::{RenderState, Shader};
+use crate::math::glm::Mat4;
+use geo::Rect;
+use glium::{
+  index::{NoIndices, PrimitiveType},
+  uniform, Surface, VertexBuffer,
+};
+use std::rc::Rc;
+
+/// Particles the instance buffer starts out with room for, it grows when a frame
+/// needs more.
+const INITIAL_CAPACITY: usize = 1024;
+
+#[derive(Default, Debug, Clone, Copy)]
+struct CornerVertex {
+  /// From the centre of a particle one unit across, y down.
+  i_corner: [f32; 2],
+}
+
+glium::implement_vertex!(CornerVertex, i_corner);
+
+#[derive(Default, Debug, Clone, Copy)]
+struct ParticleVertex {
+  i_center: [f32; 2],
+  i_size: f32,
+  i_color: [f32; 4],
+}
+
+glium::implement_vertex!(ParticleVertex, i_center, i_size, i_color);
+
+/// Draws the particles of every emitter in the world as instances of one quad, one
+/// draw call per emitter.
+pub struct ParticleRenderer {
+  ctx: Rc<glium::backend::Context>,
+  shader: Handle<Shader>,
+  quad: VertexBuffer<CornerVertex>,
+  instances: VertexBuffer<ParticleVertex>,
+  /// Drawn with the blend mode of each effect in place of its own.
+  pub render_state: RenderState,
+}
+
+impl ParticleRenderer {
+  pub fn new(
+    ctx: Rc<glium::backend::Context>,
+    asset_server: &mut AssetServer,
+    shader: &AssetId,
+  ) -> Result<Self, String> {
+    let shader = asset_server.load::<Shader>(shader)?;
+    let corners = [[-0.5, -0.5], [0.5, -0.5], [-0.5, 0.5], [0.5, 0.5]]
+      .map(|i_corner| CornerVertex { i_corner });
+    let quad = VertexBuffer::immutable(&ctx, &corners).map_err(|e| e.to_string())?;
+    let instances =
+      VertexBuffer::empty_dynamic(&ctx, INITIAL_CAPACITY).map_err(|e| e.to_string())?;
+
+    Ok(Self {
+      ctx,
+      shader,
+      quad,
+      instances,
+      render_state: RenderState::default(),
+    })
+  }
+
+  /// Draws the particles inside `visible`, returning how many. Emitters whose effect
+  /// or texture is not loaded are skipped.
+  pub fn draw<S: Surface>(
+    &mut self,
+    surface: &mut S,
+    world: &World,
+    view: &Mat4,
+    projection: &Mat4,
+    visible: &Rect<f32>,
+  ) -> Result<usize, String> {
+    let shader = self
+      .shader
+      .get()
+      .ok_or_else(|| format!("shader {} is not loaded", self.shader.id()))?;
+    let view: [[f32; 4]; 4] = (*view).into();
+    let projection: [[f32; 4]; 4] = (*projection).into();
+    let (min, max) = (visible.min(), visible.max());
+
+    let mut drawn = 0;
+    for (_, emitter) in world.query::<ParticleEmitter>() {
+      let effect = match emitter.effect.get() {
+        Some(effect) => effect,
+        None => continue,
+      };
+      let texture = match effect.texture.get() {
+        Some(texture) => texture,
+        None => continue,
+      };
+
+      let instances: Vec<ParticleVertex> = emitter
+        .instances()
+        .filter(|particle| {
+          let half = particle.size / 2.0;
+          particle.position.x + half >= min.x
+            && particle.position.x - half <= max.x
+            && particle.position.y + half >= min.y
+            && particle.position.y - half <= max.y
+        })
+        .map(|particle| ParticleVertex {
+          i_center: particle.position.into(),
+          i_size: particle.size,
+          i_color: particle.color.into(),
+        })
+        .collect();
+      if instances.is_empty() {
+        continue;
+      }
+
+      if instances.len() > self.instances.len() {
+        self.instances =
+          VertexBuffer::empty_dynamic(&self.ctx, instances.len().next_power_of_two())
+            .map_err(|e| e.to_string())?;
+      }
+      let slice = self
+        .instances
+        .slice(0..instances.len())
+        .ok_or_else(|| String::from("particle instance buffer is too small"))?;
+      slice.write(&instances);
+
+      // textures are uploaded bottom row first, so v runs up from the bottom of the image
+      let (width, height) = (texture.width() as f32, texture.height() as f32);
+      let region = effect
+        .region
+        .unwrap_or_else(|| Rect::new((0.0, 0.0), (width, height)));
+      let (region_min, region_max) = (region.min(), region.max());
+      let uv_rect = [
+        region_min.x / width,
+        1.0 - region_min.y / height,
+        region_max.x / width,
+        1.0 - region_max.y / height,
+      ];
+
+      let uniforms = uniform! {
+        u_view: view,
+        u_projection: projection,
+        u_uv_rect: uv_rect,
+        tex: texture.sampled(),
+      };
+      let render_state = RenderState {
+        blend: effect.blend,
+        ..self.render_state
+      };
+      let per_instance = slice
+        .per_instance()
+        .map_err(|_| String::from("instanced drawing is not supported"))?;
+
+      surface
+        .draw(
+          (&self.quad, per_instance),
+          NoIndices(PrimitiveType::TriangleStrip),
+          shader.program(),
+          &uniforms,
+          &render_state.draw_parameters(),
+        )
+        .map_err(|e| e.to_string())?;
+      drawn += instances.len();
+    }
+
+    Ok(drawn)
+  }
+}
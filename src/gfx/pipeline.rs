@@ -0,0 +1,511 @@
+use super::{BlendMode, Filter, Shader, Texture};
+use crate::assets::{self, AssetId, AssetKind, AssetLoader, Handle, LoadContext};
+use crate::util;
+use glium::uniforms::UniformValue;
+use serde_json::Value;
+use std::collections::BTreeSet;
+
+mod keys {
+  pub const TARGETS: &str = "targets";
+  pub const PASSES: &str = "passes";
+
+  pub const NAME: &str = "name";
+  pub const SCALE: &str = "scale";
+  pub const FORMAT: &str = "format";
+  pub const FILTER: &str = "filter";
+
+  pub const SHADER: &str = "shader";
+  pub const INPUTS: &str = "inputs";
+  pub const TEXTURES: &str = "textures";
+  pub const UNIFORMS: &str = "uniforms";
+  pub const OUTPUT: &str = "output";
+  pub const CLEAR: &str = "clear";
+  pub const BLEND: &str = "blend";
+  pub const ENABLED: &str = "enabled";
+}
+
+/// The output of passes that draw straight to the window.
+pub const SCREEN: &str = "screen";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetFormat {
+  Rgba8,
+  /// Half floats, for colors brighter than white before bloom and tone mapping.
+  Rgba16f,
+}
+
+impl TryFrom<&str> for TargetFormat {
+  type Error = String;
+
+  fn try_from(value: &str) -> Result<Self, Self::Error> {
+    match value {
+      "rgba8" => Ok(TargetFormat::Rgba8),
+      "rgba16f" => Ok(TargetFormat::Rgba16f),
+      invalid => Err(format!("unknown target format '{}'", invalid)),
+    }
+  }
+}
+
+/// A texture passes draw into, sized relative to the window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TargetDescription {
+  pub name: String,
+  pub scale: f32,
+  pub format: TargetFormat,
+  pub filter: Filter,
+}
+
+impl TargetDescription {
+  pub fn size(&self, (width, height): (u32, u32)) -> (u32, u32) {
+    let scaled = |size: u32| ((size as f32 * self.scale).round() as u32).max(1);
+    (scaled(width), scaled(height))
+  }
+}
+
+/// A constant handed to the shader of a pass.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UniformParam {
+  Float(f32),
+  Vec2([f32; 2]),
+  Vec3([f32; 3]),
+  Vec4([f32; 4]),
+}
+
+impl UniformParam {
+  pub fn as_uniform_value(&self) -> UniformValue<'static> {
+    match *self {
+      UniformParam::Float(value) => UniformValue::Float(value),
+      UniformParam::Vec2(value) => UniformValue::Vec2(value),
+      UniformParam::Vec3(value) => UniformValue::Vec3(value),
+      UniformParam::Vec4(value) => UniformValue::Vec4(value),
+    }
+  }
+}
+
+impl TryFrom<&Value> for UniformParam {
+  type Error = String;
+
+  fn try_from(value: &Value) -> Result<Self, Self::Error> {
+    if let Some(value) = value.as_f64() {
+      return Ok(UniformParam::Float(value as f32));
+    }
+
+    let floats = value
+      .as_array()
+      .and_then(|values| {
+        values
+          .iter()
+          .map(|value| value.as_f64().map(|value| value as f32))
+          .collect::<Option<Vec<f32>>>()
+      })
+      .ok_or_else(|| String::from("uniforms must be a number or a list of 2 to 4 numbers"))?;
+
+    match floats.as_slice() {
+      [x, y] => Ok(UniformParam::Vec2([*x, *y])),
+      [x, y, z] => Ok(UniformParam::Vec3([*x, *y, *z])),
+      [x, y, z, w] => Ok(UniformParam::Vec4([*x, *y, *z, *w])),
+      _ => Err(String::from(
+        "uniforms must be a number or a list of 2 to 4 numbers",
+      )),
+    }
+  }
+}
+
+/// A step of the pipeline. Passes with a shader draw it over their whole output and
+/// are post effects, passes without one are drawn by the game, like the world or the ui.
+pub struct PassDescription {
+  pub name: String,
+  pub shader: Option<Handle<Shader>>,
+  /// Targets sampled by the shader, as the uniform they are bound to and the target.
+  /// Passes drawn by the game are handed them instead.
+  pub inputs: Vec<(String, String)>,
+  /// Textures sampled by the shader, like color grading tables.
+  pub textures: Vec<(String, Handle<Texture>)>,
+  pub uniforms: Vec<(String, UniformParam)>,
+  /// A target name or `SCREEN`.
+  pub output: String,
+  pub clear: Option<[f32; 4]>,
+  pub blend: BlendMode,
+  /// Whether the pass runs until the game turns it on or off.
+  pub enabled: bool,
+}
+
+/// Targets and the passes drawing into them, in the order they run.
+pub struct Pipeline {
+  pub targets: Vec<TargetDescription>,
+  pub passes: Vec<PassDescription>,
+}
+
+impl Pipeline {
+  pub fn pass(&self, name: &str) -> Option<&PassDescription> {
+    self.passes.iter().find(|pass| pass.name == name)
+  }
+
+  fn parse(value: &Value, ctx: &mut LoadContext) -> Result<Self, String> {
+    let table = value
+      .as_object()
+      .ok_or_else(|| String::from("pipeline must be a table"))?;
+    if let Some(key) = table
+      .keys()
+      .find(|key| ![keys::TARGETS, keys::PASSES].contains(&key.as_str()))
+    {
+      return Err(format!("unknown pipeline key '{}'", key));
+    }
+
+    let list = |key: &str| -> Result<&Vec<Value>, String> {
+      value
+        .get(key)
+        .and_then(Value::as_array)
+        .ok_or_else(|| format!("pipeline '{}' must be a list", key))
+    };
+
+    let targets = list(keys::TARGETS)?
+      .iter()
+      .map(Self::parse_target)
+      .collect::<Result<Vec<_>, String>>()?;
+    let passes = list(keys::PASSES)?
+      .iter()
+      .map(|pass| Self::parse_pass(pass, ctx))
+      .collect::<Result<Vec<_>, String>>()?;
+
+    let pipeline = Self { targets, passes };
+    pipeline.validate()?;
+    Ok(pipeline)
+  }
+
+  fn parse_target(value: &Value) -> Result<TargetDescription, String> {
+    let table = value
+      .as_object()
+      .ok_or_else(|| String::from("targets must be tables"))?;
+    let allowed = [keys::NAME, keys::SCALE, keys::FORMAT, keys::FILTER];
+    if let Some(key) = table.keys().find(|key| !allowed.contains(&key.as_str())) {
+      return Err(format!("unknown target key '{}'", key));
+    }
+
+    let name = value
+      .get(keys::NAME)
+      .and_then(Value::as_str)
+      .ok_or_else(|| format!("targets need a '{}'", keys::NAME))?;
+    let scale = match value.get(keys::SCALE) {
+      Some(scale) => scale
+        .as_f64()
+        .filter(|scale| *scale > 0.0)
+        .ok_or_else(|| format!("target '{}' scale must be a positive number", name))?
+        as f32,
+      None => 1.0,
+    };
+    let string = |key: &str| -> Result<Option<&str>, String> {
+      value
+        .get(key)
+        .map(|v| {
+          v.as_str()
+            .ok_or_else(|| format!("target '{}' {} must be a string", name, key))
+        })
+        .transpose()
+    };
+
+    Ok(TargetDescription {
+      name: name.to_string(),
+      scale,
+      format: string(keys::FORMAT)?
+        .map(TargetFormat::try_from)
+        .transpose()?
+        .unwrap_or(TargetFormat::Rgba8),
+      filter: string(keys::FILTER)?
+        .map(Filter::try_from)
+        .transpose()?
+        .unwrap_or(Filter::Linear),
+    })
+  }
+
+  fn parse_pass(value: &Value, ctx: &mut LoadContext) -> Result<PassDescription, String> {
+    let table = value
+      .as_object()
+      .ok_or_else(|| String::from("passes must be tables"))?;
+    let allowed = [
+      keys::NAME,
+      keys::SHADER,
+      keys::INPUTS,
+      keys::TEXTURES,
+      keys::UNIFORMS,
+      keys::OUTPUT,
+      keys::CLEAR,
+      keys::BLEND,
+      keys::ENABLED,
+    ];
+    if let Some(key) = table.keys().find(|key| !allowed.contains(&key.as_str())) {
+      return Err(format!("unknown pass key '{}'", key));
+    }
+
+    let name = value
+      .get(keys::NAME)
+      .and_then(Value::as_str)
+      .ok_or_else(|| format!("passes need a '{}'", keys::NAME))?;
+    let string = |key: &str| -> Result<Option<&str>, String> {
+      value
+        .get(key)
+        .map(|v| {
+          v.as_str()
+            .ok_or_else(|| format!("pass '{}' {} must be a string", name, key))
+        })
+        .transpose()
+    };
+    let id = |value: &str| {
+      value
+        .parse::<AssetId>()
+        .map_err(|e| format!("pass '{}' {}", name, e))
+    };
+    // a table of uniform names to something else
+    let named = |key: &str| -> Result<Vec<(&String, &Value)>, String> {
+      match value.get(key) {
+        Some(entries) => entries
+          .as_object()
+          .map(|entries| entries.iter().collect())
+          .ok_or_else(|| format!("pass '{}' {} must be a table", name, key)),
+        None => Ok(Vec::new()),
+      }
+    };
+
+    let shader = string(keys::SHADER)?
+      .map(|shader| ctx.load::<Shader>(&id(shader)?))
+      .transpose()?;
+
+    let inputs = named(keys::INPUTS)?
+      .into_iter()
+      .map(|(uniform, target)| {
+        target
+          .as_str()
+          .map(|target| (uniform.clone(), target.to_string()))
+          .ok_or_else(|| format!("pass '{}' inputs must name targets", name))
+      })
+      .collect::<Result<Vec<_>, String>>()?;
+
+    let mut textures = Vec::new();
+    for (uniform, texture) in named(keys::TEXTURES)? {
+      let texture = texture
+        .as_str()
+        .ok_or_else(|| format!("pass '{}' textures must be texture ids", name))?;
+      textures.push((uniform.clone(), ctx.load::<Texture>(&id(texture)?)?));
+    }
+
+    let uniforms = named(keys::UNIFORMS)?
+      .into_iter()
+      .map(|(uniform, param)| {
+        UniformParam::try_from(param)
+          .map(|param| (uniform.clone(), param))
+          .map_err(|msg| format!("pass '{}' uniform '{}': {}", name, uniform, msg))
+      })
+      .collect::<Result<Vec<_>, String>>()?;
+
+    let clear = match value.get(keys::CLEAR) {
+      Some(clear) => match UniformParam::try_from(clear) {
+        Ok(UniformParam::Vec4(color)) => Some(color),
+        _ => return Err(format!("pass '{}' clear must be [r, g, b, a]", name)),
+      },
+      None => None,
+    };
+
+    let enabled = match value.get(keys::ENABLED) {
+      Some(enabled) => enabled
+        .as_bool()
+        .ok_or_else(|| format!("pass '{}' enabled must be a boolean", name))?,
+      None => true,
+    };
+
+    Ok(PassDescription {
+      name: name.to_string(),
+      shader,
+      inputs,
+      textures,
+      uniforms,
+      output: string(keys::OUTPUT)?.unwrap_or(SCREEN).to_string(),
+      clear,
+      blend: string(keys::BLEND)?
+        .map(BlendMode::try_from)
+        .transpose()?
+        .unwrap_or(BlendMode::None),
+      enabled,
+    })
+  }
+
+  fn validate(&self) -> Result<(), String> {
+    let mut targets = BTreeSet::new();
+    for target in &self.targets {
+      if target.name == SCREEN {
+        return Err(format!("'{}' is reserved for the window", SCREEN));
+      }
+      if !targets.insert(target.name.as_str()) {
+        return Err(format!("target '{}' is declared twice", target.name));
+      }
+    }
+
+    let mut passes = BTreeSet::new();
+    // a pass reading what no earlier pass drew would hand disabled passes after it
+    // targets that only ever alias each other
+    let mut drawn = BTreeSet::new();
+    for pass in &self.passes {
+      if !passes.insert(pass.name.as_str()) {
+        return Err(format!("pass '{}' is declared twice", pass.name));
+      }
+      if pass.output != SCREEN && !targets.contains(pass.output.as_str()) {
+        return Err(format!(
+          "pass '{}' draws to unknown target '{}'",
+          pass.name, pass.output
+        ));
+      }
+      if pass.shader.is_none() && !(pass.textures.is_empty() && pass.uniforms.is_empty()) {
+        return Err(format!(
+          "pass '{}' has textures or uniforms but no shader to read them",
+          pass.name
+        ));
+      }
+
+      for (_, input) in &pass.inputs {
+        if !targets.contains(input.as_str()) {
+          return Err(format!(
+            "pass '{}' reads unknown target '{}'",
+            pass.name, input
+          ));
+        }
+        if *input == pass.output {
+          return Err(format!(
+            "pass '{}' cannot read the target it draws to",
+            pass.name
+          ));
+        }
+        if !drawn.contains(input.as_str()) {
+          return Err(format!(
+            "pass '{}' reads target '{}' before any pass draws it",
+            pass.name, input
+          ));
+        }
+      }
+      drawn.insert(pass.output.as_str());
+    }
+
+    Ok(())
+  }
+}
+
+pub struct PipelineLoader;
+
+impl PipelineLoader {
+  pub fn new() -> Self {
+    Self
+  }
+}
+
+impl AssetLoader for PipelineLoader {
+  type Asset = Pipeline;
+
+  fn kind(&self) -> AssetKind {
+    AssetKind::Pipeline
+  }
+
+  fn load(&self, id: &AssetId, ctx: &mut LoadContext) -> Result<Pipeline, String> {
+    let entry = assets::read_toml_entry(AssetKind::Pipeline, id)?;
+    Pipeline::parse(&util::toml_to_json(&entry), ctx)
+      .map_err(|msg| format!("rejecting pipeline {}: {}", id, msg))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::assets::AssetServer;
+
+  fn parse(source: &str) -> Result<Pipeline, String> {
+    let mut server = AssetServer::new();
+    let mut ctx = LoadContext::new(&mut server);
+    Pipeline::parse(&serde_json::from_str::<Value>(source).unwrap(), &mut ctx)
+  }
+
+  /// A pipeline of `passes` drawn by the game over the targets `a`, `b` and `c`.
+  fn with_passes(passes: &str) -> Result<Pipeline, String> {
+    parse(&format!(
+      r#"{{ "targets": [{{ "name": "a" }}, {{ "name": "b" }}, {{ "name": "c" }}], "passes": {} }}"#,
+      passes
+    ))
+  }
+
+  #[test]
+  fn targets_and_passes_are_read_with_their_defaults() {
+    let pipeline = parse(
+      r#"{
+        "targets": [
+          { "name": "scene", "format": "rgba16f", "filter": "nearest" },
+          { "name": "half", "scale": 0.5 }
+        ],
+        "passes": [
+          { "name": "world", "output": "scene", "clear": [0, 0, 0, 1], "blend": "alpha" },
+          { "name": "shrink", "inputs": { "source": "scene" }, "output": "half" },
+          { "name": "ui", "inputs": { "source": "half" }, "enabled": false }
+        ]
+      }"#,
+    )
+    .unwrap();
+
+    let scene = &pipeline.targets[0];
+    assert_eq!(scene.format, TargetFormat::Rgba16f);
+    assert_eq!(scene.filter, Filter::Nearest);
+    assert_eq!(pipeline.targets[1].size((640, 481)), (320, 241));
+
+    let world = pipeline.pass("world").unwrap();
+    assert_eq!(world.clear, Some([0.0, 0.0, 0.0, 1.0]));
+    assert_eq!(world.blend, BlendMode::Alpha);
+    assert!(world.enabled);
+
+    let ui = pipeline.pass("ui").unwrap();
+    assert_eq!(ui.output, SCREEN);
+    assert_eq!(ui.inputs, [(String::from("source"), String::from("half"))]);
+    assert_eq!(ui.blend, BlendMode::None);
+    assert!(!ui.enabled);
+  }
+
+  #[test]
+  fn passes_may_draw_back_and_forth_between_targets() {
+    assert!(with_passes(
+      r#"[
+        { "name": "extract", "output": "a" },
+        { "name": "horizontal", "inputs": { "source": "a" }, "output": "b" },
+        { "name": "vertical", "inputs": { "source": "b" }, "output": "a" }
+      ]"#
+    )
+    .is_ok());
+  }
+
+  #[test]
+  fn passes_must_read_targets_drawn_before_them() {
+    let error = with_passes(
+      r#"[
+        { "name": "horizontal", "inputs": { "source": "a" }, "output": "b" },
+        { "name": "vertical", "inputs": { "source": "b" }, "output": "a" }
+      ]"#,
+    )
+    .err()
+    .unwrap();
+    assert!(error.contains("before any pass draws it"), "{}", error);
+
+    assert!(
+      with_passes(r#"[{ "name": "feedback", "inputs": { "source": "a" }, "output": "a" }]"#)
+        .is_err()
+    );
+  }
+
+  #[test]
+  fn unknown_or_repeated_names_are_rejected() {
+    for passes in [
+      r#"[{ "name": "world", "output": "d" }]"#,
+      r#"[{ "name": "world", "inputs": { "source": "d" } }]"#,
+      r#"[{ "name": "world", "output": "a" }, { "name": "world", "output": "b" }]"#,
+      r#"[{ "name": "world", "output": "a", "depth": true }]"#,
+      r#"[{ "name": "world", "uniforms": { "strength": 1.0 } }]"#,
+    ] {
+      assert!(with_passes(passes).is_err(), "{}", passes);
+    }
+
+    assert!(parse(r#"{ "targets": [{ "name": "screen" }], "passes": [] }"#).is_err());
+    assert!(parse(r#"{ "targets": [{ "name": "a" }, { "name": "a" }], "passes": [] }"#).is_err());
+    assert!(parse(r#"{ "targets": [{ "name": "a", "scale": 0 }], "passes": [] }"#).is_err());
+  }
+}
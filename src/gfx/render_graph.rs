@@ -0,0 +1,408 @@
+use super::pipeline::{PassDescription, Pipeline, TargetDescription, TargetFormat, SCREEN};
+use super::{Filter, RenderState};
+use crate::assets::{AssetId, AssetServer, Handle};
+use glium::{
+  framebuffer::SimpleFrameBuffer,
+  index::{NoIndices, PrimitiveType},
+  texture::{MipmapsOption, Texture2d, UncompressedFloatFormat},
+  uniforms::{
+    AsUniformValue, MagnifySamplerFilter, MinifySamplerFilter, Sampler, SamplerWrapFunction,
+    UniformValue, Uniforms,
+  },
+  vertex::EmptyVertexAttributes,
+  BlitTarget, Frame, Surface,
+};
+use std::{collections::BTreeMap, rc::Rc, time::Instant};
+
+/// The input a disabled pass hands on in place of its output.
+const PASSTHROUGH: &str = "source";
+
+/// Draws the passes that have no shader of their own, like the world and the ui.
+pub trait PassRenderer {
+  fn draw<S: Surface>(
+    &mut self,
+    pass: &str,
+    surface: &mut S,
+    inputs: &PassInputs<'_>,
+  ) -> Result<(), String>;
+}
+
+/// The targets a pass reads, by the name the pipeline gives them.
+#[derive(Default)]
+pub struct PassInputs<'a> {
+  samplers: Vec<(&'a str, Sampler<'a, Texture2d>)>,
+}
+
+impl<'a> PassInputs<'a> {
+  pub fn get(&self, name: &str) -> Option<Sampler<'a, Texture2d>> {
+    self
+      .samplers
+      .iter()
+      .find(|(input, _)| *input == name)
+      .map(|(_, sampler)| *sampler)
+  }
+}
+
+struct Target {
+  texture: Texture2d,
+  size: (u32, u32),
+  format: TargetFormat,
+  filter: Filter,
+}
+
+/// What a fullscreen pass hands its shader. Every pass also gets the size of its output
+/// as `u_resolution` and the seconds since the graph was made as `u_time`.
+struct PassUniforms<'a> {
+  samplers: Vec<(&'a str, Sampler<'a, Texture2d>)>,
+  pass: &'a PassDescription,
+  resolution: [f32; 2],
+  time: f32,
+}
+
+impl Uniforms for PassUniforms<'_> {
+  fn visit_values<'b, F: FnMut(&str, UniformValue<'b>)>(&'b self, mut visit: F) {
+    visit("u_resolution", UniformValue::Vec2(self.resolution));
+    visit("u_time", UniformValue::Float(self.time));
+    for (name, sampler) in &self.samplers {
+      visit(name, sampler.as_uniform_value());
+    }
+    for (name, param) in &self.pass.uniforms {
+      visit(name, param.as_uniform_value());
+    }
+  }
+}
+
+fn sampler(texture: &Texture2d, filter: Filter) -> Sampler<'_, Texture2d> {
+  let (magnify, minify) = match filter {
+    Filter::Nearest => (MagnifySamplerFilter::Nearest, MinifySamplerFilter::Nearest),
+    Filter::Linear => (MagnifySamplerFilter::Linear, MinifySamplerFilter::Linear),
+  };
+
+  texture
+    .sampled()
+    .magnify_filter(magnify)
+    .minify_filter(minify)
+    .wrap_function(SamplerWrapFunction::Clamp)
+}
+
+/// What targets hold while the passes drawing them are disabled, as the target that
+/// holds it instead.
+#[derive(Default)]
+struct Aliases(BTreeMap<String, String>);
+
+impl Aliases {
+  /// The target holding what `name` would, following disabled passes back.
+  fn resolve<'a>(&'a self, mut name: &'a str) -> &'a str {
+    while let Some(alias) = self.0.get(name) {
+      name = alias;
+    }
+    name
+  }
+
+  /// Makes `output` stand for what `input` holds. Aliases only ever point at targets
+  /// without one of their own, so following them always ends.
+  fn alias(&mut self, output: &str, input: &str) {
+    let input = self.resolve(input).to_string();
+    // like a blur whose passes draw back and forth, with all of them disabled
+    if input != output {
+      self.0.insert(output.to_string(), input);
+    }
+  }
+
+  /// The target is drawn for real, whatever it stood in for before.
+  fn remove(&mut self, output: &str) {
+    self.0.remove(output);
+  }
+}
+
+/// Runs the passes of a pipeline every frame, keeping its targets allocated at the
+/// size of the window. Targets are only made once an enabled pass uses them, and are
+/// remade when the window or the pipeline changes.
+pub struct RenderGraph {
+  ctx: Rc<glium::backend::Context>,
+  pipeline: Handle<Pipeline>,
+  size: (u32, u32),
+  targets: BTreeMap<String, Target>,
+  enabled: BTreeMap<String, bool>,
+  start: Instant,
+}
+
+impl RenderGraph {
+  pub fn new(
+    ctx: Rc<glium::backend::Context>,
+    asset_server: &mut AssetServer,
+    pipeline: &AssetId,
+    size: (u32, u32),
+  ) -> Result<Self, String> {
+    let pipeline = asset_server.load::<Pipeline>(pipeline)?;
+
+    Ok(Self {
+      ctx,
+      pipeline,
+      size,
+      targets: BTreeMap::new(),
+      enabled: BTreeMap::new(),
+      start: Instant::now(),
+    })
+  }
+
+  /// Targets are remade at the new size the next time the graph renders.
+  pub fn resize(&mut self, size: (u32, u32)) {
+    self.size = size;
+  }
+
+  /// The passes drawing a shader of their own, the post effects that can be turned off.
+  pub fn effects(&self) -> Vec<String> {
+    self
+      .pipeline
+      .get()
+      .map(|pipeline| {
+        pipeline
+          .passes
+          .iter()
+          .filter(|pass| pass.shader.is_some())
+          .map(|pass| pass.name.clone())
+          .collect()
+      })
+      .unwrap_or_default()
+  }
+
+  pub fn is_enabled(&self, pass: &str) -> bool {
+    match self.enabled.get(pass) {
+      Some(enabled) => *enabled,
+      None => self
+        .pipeline
+        .get()
+        .and_then(|pipeline| pipeline.pass(pass).map(|pass| pass.enabled))
+        .unwrap_or(false),
+    }
+  }
+
+  /// Turns a pass on or off. A disabled post effect hands its `source` input on unchanged,
+  /// so effects can be toggled without breaking the chain after them.
+  pub fn set_enabled(&mut self, pass: &str, enabled: bool) {
+    self.enabled.insert(pass.to_string(), enabled);
+  }
+
+  fn allocate(&mut self, pipeline: &Pipeline) -> Result<(), String> {
+    let used: Vec<&TargetDescription> = pipeline
+      .targets
+      .iter()
+      .filter(|target| {
+        pipeline.passes.iter().any(|pass| {
+          self.is_enabled(&pass.name)
+            && (pass.output == target.name || pass.inputs.iter().any(|(_, t)| *t == target.name))
+        })
+      })
+      .collect();
+
+    self
+      .targets
+      .retain(|name, _| used.iter().any(|target| target.name == *name));
+
+    for description in used {
+      let size = description.size(self.size);
+      let current = self.targets.get(&description.name);
+      if current.is_some_and(|t| t.size == size && t.format == description.format) {
+        if let Some(target) = self.targets.get_mut(&description.name) {
+          target.filter = description.filter;
+        }
+        continue;
+      }
+
+      let format = match description.format {
+        TargetFormat::Rgba8 => UncompressedFloatFormat::U8U8U8U8,
+        TargetFormat::Rgba16f => UncompressedFloatFormat::F16F16F16F16,
+      };
+      let texture =
+        Texture2d::empty_with_format(&self.ctx, format, MipmapsOption::NoMipmap, size.0, size.1)
+          .map_err(|e| format!("cannot make target '{}': {}", description.name, e))?;
+
+      self.targets.insert(
+        description.name.clone(),
+        Target {
+          texture,
+          size,
+          format: description.format,
+          filter: description.filter,
+        },
+      );
+    }
+
+    Ok(())
+  }
+
+  pub fn render<R: PassRenderer>(
+    &mut self,
+    frame: &mut Frame,
+    renderer: &mut R,
+  ) -> Result<(), String> {
+    let pipeline = self
+      .pipeline
+      .get()
+      .ok_or_else(|| format!("pipeline {} is not loaded", self.pipeline.id()))?;
+    self.allocate(&pipeline)?;
+
+    let time = self.start.elapsed().as_secs_f32();
+    let mut aliases = Aliases::default();
+
+    for pass in &pipeline.passes {
+      // a pass after a disabled one can end up reading the target it draws to, which
+      // already holds what the pass would hand on if it were disabled too
+      let reads_output = pass
+        .inputs
+        .iter()
+        .any(|(_, input)| aliases.resolve(input) == pass.output);
+      if !self.is_enabled(&pass.name) || reads_output {
+        if let Some((_, input)) = pass
+          .inputs
+          .iter()
+          .find(|(uniform, _)| uniform == PASSTHROUGH)
+        {
+          if pass.output == SCREEN {
+            self.blit_to_screen(frame, aliases.resolve(input))?;
+          } else {
+            aliases.alias(&pass.output, input);
+          }
+        }
+        continue;
+      }
+      aliases.remove(&pass.output);
+
+      if pass.output == SCREEN {
+        self.run(pass, frame, &aliases, time, renderer)?;
+      } else {
+        let target = self
+          .targets
+          .get(&pass.output)
+          .ok_or_else(|| format!("target '{}' is not allocated", pass.output))?;
+        let mut surface =
+          SimpleFrameBuffer::new(&self.ctx, &target.texture).map_err(|e| e.to_string())?;
+        self.run(pass, &mut surface, &aliases, time, renderer)?;
+      }
+    }
+
+    Ok(())
+  }
+
+  fn run<S: Surface, R: PassRenderer>(
+    &self,
+    pass: &PassDescription,
+    surface: &mut S,
+    aliases: &Aliases,
+    time: f32,
+    renderer: &mut R,
+  ) -> Result<(), String> {
+    if let Some([r, g, b, a]) = pass.clear {
+      surface.clear_color(r, g, b, a);
+    }
+
+    let textures: Vec<_> = pass
+      .textures
+      .iter()
+      .map(|(name, texture)| {
+        texture
+          .get()
+          .map(|texture| (name.as_str(), texture))
+          .ok_or_else(|| format!("texture {} is not loaded", texture.id()))
+      })
+      .collect::<Result<_, String>>()?;
+
+    let mut samplers = Vec::new();
+    for (name, input) in &pass.inputs {
+      let input = aliases.resolve(input);
+      let target = self
+        .targets
+        .get(input)
+        .ok_or_else(|| format!("target '{}' is not allocated", input))?;
+      samplers.push((name.as_str(), sampler(&target.texture, target.filter)));
+    }
+
+    let shader = match &pass.shader {
+      Some(shader) => shader
+        .get()
+        .ok_or_else(|| format!("shader {} is not loaded", shader.id()))?,
+      None => return renderer.draw(&pass.name, surface, &PassInputs { samplers }),
+    };
+
+    for (name, texture) in &textures {
+      samplers.push((
+        name,
+        texture.sampled().wrap_function(SamplerWrapFunction::Clamp),
+      ));
+    }
+
+    let (width, height) = surface.get_dimensions();
+    let uniforms = PassUniforms {
+      samplers,
+      pass,
+      resolution: [width as f32, height as f32],
+      time,
+    };
+    let parameters = RenderState {
+      blend: pass.blend,
+      ..RenderState::default()
+    }
+    .draw_parameters();
+
+    // one triangle covering the whole output, placed by the vertex shader from its index
+    surface
+      .draw(
+        EmptyVertexAttributes { len: 3 },
+        NoIndices(PrimitiveType::TrianglesList),
+        shader.program(),
+        &uniforms,
+        &parameters,
+      )
+      .map_err(|e| e.to_string())
+  }
+
+  fn blit_to_screen(&self, frame: &mut Frame, input: &str) -> Result<(), String> {
+    let target = self
+      .targets
+      .get(input)
+      .ok_or_else(|| format!("target '{}' is not allocated", input))?;
+    let (width, height) = frame.get_dimensions();
+
+    target.texture.as_surface().blit_whole_color_to(
+      frame,
+      &BlitTarget {
+        left: 0,
+        bottom: 0,
+        width: width as i32,
+        height: height as i32,
+      },
+      MagnifySamplerFilter::Linear,
+    );
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn aliases_follow_disabled_passes_back() {
+    let mut aliases = Aliases::default();
+    aliases.alias("graded", "lit");
+    aliases.alias("crt", "graded");
+    assert_eq!(aliases.resolve("crt"), "lit");
+    assert_eq!(aliases.resolve("lit"), "lit");
+
+    aliases.remove("graded");
+    assert_eq!(aliases.resolve("graded"), "graded");
+    assert_eq!(aliases.resolve("crt"), "lit");
+  }
+
+  #[test]
+  fn passes_drawing_back_and_forth_never_alias_a_target_to_itself() {
+    // both passes of a blur disabled, the first hands `bright` on as `blurred` and the
+    // second hands that back to where it came from
+    let mut aliases = Aliases::default();
+    aliases.alias("blurred", "bright");
+    aliases.alias("bright", "blurred");
+    assert_eq!(aliases.resolve("bright"), "bright");
+    assert_eq!(aliases.resolve("blurred"), "bright");
+  }
+}
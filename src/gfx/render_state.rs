@@ -0,0 +1,290 @@
+use glium::{
+  draw_parameters::{
+    BackfaceCullingMode, Blend, BlendingFunction, Depth, DepthTest, LinearBlendingFactor,
+  },
+  DrawParameters, PolygonMode, Rect,
+};
+use serde_json::Value;
+
+mod keys {
+  pub const WIREFRAME: &str = "wireframe";
+  pub const BLEND: &str = "blend";
+  pub const DEPTH_TEST: &str = "depth_test";
+  pub const DEPTH_WRITE: &str = "depth_write";
+  pub const CULL_BACKFACES: &str = "cull_backfaces";
+  pub const SCISSOR: &str = "scissor";
+
+  pub mod scissor {
+    pub const X: &str = "x";
+    pub const Y: &str = "y";
+    pub const WIDTH: &str = "width";
+    pub const HEIGHT: &str = "height";
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlendMode {
+  None,
+  Alpha,
+  Additive,
+  Multiply,
+  Premultiplied,
+}
+
+impl TryFrom<&str> for BlendMode {
+  type Error = String;
+
+  fn try_from(s: &str) -> Result<Self, Self::Error> {
+    match s {
+      "none" => Ok(BlendMode::None),
+      "alpha" => Ok(BlendMode::Alpha),
+      "additive" => Ok(BlendMode::Additive),
+      "multiply" => Ok(BlendMode::Multiply),
+      "premultiplied" => Ok(BlendMode::Premultiplied),
+      invalid => Err(format!("unsupported blend mode '{}'", invalid)),
+    }
+  }
+}
+
+impl From<BlendMode> for Blend {
+  fn from(mode: BlendMode) -> Self {
+    let blend = |source, destination| Blend {
+      color: BlendingFunction::Addition {
+        source,
+        destination,
+      },
+      alpha: BlendingFunction::Addition {
+        source,
+        destination,
+      },
+      constant_value: (0.0, 0.0, 0.0, 0.0),
+    };
+
+    match mode {
+      BlendMode::None => Blend::default(),
+      BlendMode::Alpha => Blend::alpha_blending(),
+      BlendMode::Additive => blend(LinearBlendingFactor::SourceAlpha, LinearBlendingFactor::One),
+      BlendMode::Multiply => blend(
+        LinearBlendingFactor::DestinationColor,
+        LinearBlendingFactor::Zero,
+      ),
+      BlendMode::Premultiplied => blend(
+        LinearBlendingFactor::One,
+        LinearBlendingFactor::OneMinusSourceAlpha,
+      ),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Scissor {
+  pub x: u32,
+  pub y: u32,
+  pub width: u32,
+  pub height: u32,
+}
+
+impl TryFrom<&Value> for Scissor {
+  type Error = String;
+
+  fn try_from(value: &Value) -> Result<Self, Self::Error> {
+    let table = value
+      .as_object()
+      .ok_or_else(|| format!("'{}' must be an object", keys::SCISSOR))?;
+
+    for key in table.keys() {
+      match key.as_str() {
+        keys::scissor::X | keys::scissor::Y | keys::scissor::WIDTH | keys::scissor::HEIGHT => (),
+        invalid => return Err(format!("unknown scissor key '{}'", invalid)),
+      }
+    }
+
+    let field = |key: &str| -> Result<u32, String> {
+      table
+        .get(key)
+        .and_then(Value::as_u64)
+        .and_then(|v| u32::try_from(v).ok())
+        .ok_or_else(|| format!("scissor '{}' must be a non-negative integer", key))
+    };
+
+    Ok(Self {
+      x: field(keys::scissor::X)?,
+      y: field(keys::scissor::Y)?,
+      width: field(keys::scissor::WIDTH)?,
+      height: field(keys::scissor::HEIGHT)?,
+    })
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderState {
+  pub wireframe: bool,
+  pub blend: BlendMode,
+  pub depth_test: bool,
+  pub depth_write: bool,
+  pub cull_backfaces: bool,
+  pub scissor: Option<Scissor>,
+}
+
+impl Default for RenderState {
+  fn default() -> Self {
+    Self {
+      wireframe: false,
+      blend: BlendMode::Alpha,
+      depth_test: false,
+      depth_write: false,
+      cull_backfaces: false,
+      scissor: None,
+    }
+  }
+}
+
+impl RenderState {
+  pub fn draw_parameters(&self) -> DrawParameters<'static> {
+    let polygon_mode = if self.wireframe {
+      PolygonMode::Line
+    } else {
+      PolygonMode::Fill
+    };
+
+    let depth = Depth {
+      test: if self.depth_test {
+        DepthTest::IfLessOrEqual
+      } else {
+        DepthTest::Overwrite
+      },
+      write: self.depth_write,
+      ..Default::default()
+    };
+
+    // models are wound clockwise, so the back faces are the counter clockwise ones
+    let backface_culling = if self.cull_backfaces {
+      BackfaceCullingMode::CullCounterClockwise
+    } else {
+      BackfaceCullingMode::CullingDisabled
+    };
+
+    let scissor = self.scissor.map(|s| Rect {
+      left: s.x,
+      bottom: s.y,
+      width: s.width,
+      height: s.height,
+    });
+
+    DrawParameters {
+      polygon_mode,
+      blend: self.blend.into(),
+      depth,
+      backface_culling,
+      scissor,
+      ..Default::default()
+    }
+  }
+}
+
+impl TryFrom<&Value> for RenderState {
+  type Error = String;
+
+  fn try_from(value: &Value) -> Result<Self, Self::Error> {
+    let table = value
+      .as_object()
+      .ok_or_else(|| String::from("draw description must be an object"))?;
+
+    let mut state = Self::default();
+
+    let flag = |key: &str, value: &Value| -> Result<bool, String> {
+      value
+        .as_bool()
+        .ok_or_else(|| format!("'{}' must be a boolean", key))
+    };
+
+    for (key, value) in table {
+      match key.as_str() {
+        keys::WIREFRAME => state.wireframe = flag(key, value)?,
+        keys::BLEND => {
+          let mode = value
+            .as_str()
+            .ok_or_else(|| format!("'{}' must be a string", key))?;
+          state.blend = BlendMode::try_from(mode)?;
+        }
+        keys::DEPTH_TEST => state.depth_test = flag(key, value)?,
+        keys::DEPTH_WRITE => state.depth_write = flag(key, value)?,
+        keys::CULL_BACKFACES => state.cull_backfaces = flag(key, value)?,
+        keys::SCISSOR => {
+          state.scissor = if value.is_null() {
+            None
+          } else {
+            Some(Scissor::try_from(value)?)
+          };
+        }
+        invalid => return Err(format!("unknown draw description key '{}'", invalid)),
+      }
+    }
+
+    Ok(state)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parse(source: &str) -> Result<RenderState, String> {
+    RenderState::try_from(&serde_json::from_str::<Value>(source).unwrap())
+  }
+
+  #[test]
+  fn an_empty_description_is_the_default() {
+    assert_eq!(parse("{}").unwrap(), RenderState::default());
+  }
+
+  #[test]
+  fn every_key_is_read() {
+    let state = parse(
+      r#"{
+        "wireframe": true,
+        "blend": "additive",
+        "depth_test": true,
+        "depth_write": true,
+        "cull_backfaces": true,
+        "scissor": { "x": 0, "y": 4, "width": 16, "height": 8 }
+      }"#,
+    )
+    .unwrap();
+
+    assert!(state.wireframe && state.depth_test && state.depth_write && state.cull_backfaces);
+    assert_eq!(state.blend, BlendMode::Additive);
+    assert_eq!(
+      state.scissor,
+      Some(Scissor {
+        x: 0,
+        y: 4,
+        width: 16,
+        height: 8
+      })
+    );
+  }
+
+  #[test]
+  fn a_null_scissor_is_none() {
+    assert_eq!(parse(r#"{ "scissor": null }"#).unwrap().scissor, None);
+  }
+
+  #[test]
+  fn unknown_keys_and_blend_modes_are_rejected() {
+    assert!(parse(r#"{ "wirefram": true }"#).is_err());
+    assert!(parse(r#"{ "blend": "screen" }"#).is_err());
+    assert!(
+      parse(r#"{ "scissor": { "x": 0, "y": 0, "width": 1, "height": 1, "z": 0 } }"#).is_err()
+    );
+  }
+
+  #[test]
+  fn values_of_the_wrong_type_are_rejected() {
+    assert!(parse("[]").is_err());
+    assert!(parse(r#"{ "wireframe": 1 }"#).is_err());
+    assert!(parse(r#"{ "blend": true }"#).is_err());
+    assert!(parse(r#"{ "scissor": { "x": -1, "y": 0, "width": 1, "height": 1 } }"#).is_err());
+    assert!(parse(r#"{ "scissor": { "x": 0, "y": 0, "width": 1 } }"#).is_err());
+  }
+}
@@ -1,17 +1,17 @@
-use crate::util::{self, DirID};
+use crate::assets::{self, AssetId, AssetKind, AssetLoader, LoadContext};
 use glium::program::{Program, ProgramCreationError, ShaderType};
 use lazy_static::lazy_static;
 use log::{error, info, warn};
 use regex::Regex;
 use std::{
-  collections::{BTreeMap, BTreeSet},
+  collections::BTreeSet,
   fs,
   path::{Path, PathBuf},
+  rc::Rc,
 };
 use toml::Value;
 
 lazy_static! {
-  static ref SRC_DIR: PathBuf = PathBuf::new().join("assets").join("shaders").join("src");
   static ref IMPORT_REGEX: Regex =
     Regex::new(r##"^\s*#\s*import\s*"(?P<file>[\-\w.]+)"\s*$"##).unwrap();
 }
@@ -98,87 +98,73 @@ impl PotentialProgramSources {
   }
 }
 
-pub struct ShaderSources {
-  sources: BTreeMap<DirID, ProgramSources>,
+pub struct ShaderLoader {
+  ctx: Rc<glium::backend::Context>,
 }
 
-impl ShaderSources {
-  pub fn new() -> Self {
-    Self {
-      sources: BTreeMap::default(),
-    }
+impl ShaderLoader {
+  pub fn new(ctx: Rc<glium::backend::Context>) -> Self {
+    Self { ctx }
   }
 
-  pub fn load_all(&mut self) {
-    let config = PathBuf::new().join("assets").join("cfg").join("shaders");
-    util::iterate_dir_with_id(&config, |path, id| {
-      let data = fs::read_to_string(path)
-        .map_err(|e| format!("cannot find {}, err = {}", path.display(), e))
-        .unwrap();
-      let table = data.parse::<Value>().unwrap();
-      let table = table.as_table().unwrap();
-
-      for (local_shader_id, shaders) in table {
-        let shaders = shaders.as_table().unwrap();
-        let new_id = id.extend(&local_shader_id);
-
-        let mut sources = PotentialProgramSources::default();
-
-        for (shader_type, filename) in shaders {
-          let current_source = match shader_type.as_str() {
-            "vertex" => &mut sources.vertex,
-            "fragment" => &mut sources.fragment,
-            invalid => {
-              warn!("unsupported shader type: {}", invalid);
-              continue;
-            }
-          };
-
-          if let Value::String(filename) = filename {
-            let src_path = SRC_DIR.join(Path::new(filename));
-            match ShaderSource::load_source(
-              &src_path,
-              &mut Vec::default(),
-              &mut BTreeSet::default(),
-            ) {
-              Ok(source) => {
-                *current_source = Some(source);
-              }
-              Err(msg) => {
-                error!("{}", msg);
-                continue;
-              }
-            }
-          } else {
-            error!("shader path is not a string");
-            continue;
-          }
+  fn load_sources(id: &AssetId) -> Result<ProgramSources, String> {
+    let table = assets::read_toml_entry(AssetKind::Shader, id)?;
+    let shaders = table
+      .as_table()
+      .ok_or_else(|| format!("shader {} is not a table", id))?;
+    let src_dir = assets::namespace_dir(id.namespace())
+      .join("shaders")
+      .join("src");
+
+    let mut sources = PotentialProgramSources::default();
+
+    for (shader_type, filename) in shaders {
+      let current_source = match shader_type.as_str() {
+        "vertex" => &mut sources.vertex,
+        "fragment" => &mut sources.fragment,
+        invalid => {
+          warn!("unsupported shader type: {}", invalid);
+          continue;
         }
+      };
 
-        if sources.is_valid() {
-          self.sources.insert(new_id, sources.into());
-        } else {
-          error!("required shader types not present for program");
+      if let Value::String(filename) = filename {
+        let src_path = src_dir.join(Path::new(filename));
+        match ShaderSource::load_source(&src_path, &mut Vec::default(), &mut BTreeSet::default()) {
+          Ok(source) => {
+            *current_source = Some(source);
+          }
+          Err(msg) => {
+            error!("{}", msg);
+            continue;
+          }
         }
+      } else {
+        error!("shader path is not a string");
+        continue;
       }
-    });
+    }
+
+    if sources.is_valid() {
+      Ok(sources.into())
+    } else {
+      Err(String::from(
+        "required shader types not present for program",
+      ))
+    }
   }
+}
 
-  pub fn load_repository(self, ctx: std::rc::Rc<glium::backend::Context>) -> ShaderRepository {
-    let mut repo = ShaderRepository::default();
+impl AssetLoader for ShaderLoader {
+  type Asset = Shader;
 
-    for (id, sources) in self.sources {
-      match Shader::from(ctx.clone(), sources) {
-        Ok(shader) => {
-          repo.shaders.insert(id, shader);
-        }
-        Err(msg) => {
-          error!("cannot load shader {}", msg);
-        }
-      }
-    }
+  fn kind(&self) -> AssetKind {
+    AssetKind::Shader
+  }
 
-    repo
+  fn load(&self, id: &AssetId, _ctx: &mut LoadContext) -> Result<Shader, String> {
+    let sources = Self::load_sources(id)?;
+    Shader::from(self.ctx.clone(), sources).map_err(|e| format!("cannot load shader {}", e))
   }
 }
 
@@ -188,18 +174,15 @@ pub struct Shader {
 
 impl Shader {
   fn from(
-    ctx: std::rc::Rc<glium::backend::Context>,
+    ctx: Rc<glium::backend::Context>,
     sources: ProgramSources,
   ) -> Result<Self, ProgramCreationError> {
     let program = Program::from_source(&ctx, &sources.vertex, &sources.fragment, None)?;
 
     Ok(Self { program })
   }
-}
 
-#[derive(Default)]
-pub struct ShaderRepository {
-  shaders: BTreeMap<DirID, Shader>,
+  pub fn program(&self) -> &Program {
+    &self.program
+  }
 }
-
-impl ShaderRepository {}
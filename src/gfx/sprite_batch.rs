@@ -0,0 +1,531 @@
+use super::{RenderState, Shader, Texture, UniformParam};
+use crate::assets::{AssetId, AssetServer, Handle};
+use crate::game::components::Transform;
+use crate::math::glm::{self, Mat4, Vec2, Vec4};
+use geo::Rect;
+use glium::{
+  index::PrimitiveType,
+  texture::{RawImage2d, Texture2d},
+  uniform,
+  uniforms::{UniformValue, Uniforms},
+  IndexBuffer, Surface, VertexBuffer,
+};
+use std::rc::Rc;
+
+/// Sprites the buffers start out with room for, they grow when a frame needs more.
+const INITIAL_CAPACITY: usize = 256;
+
+#[derive(Default, Debug, Clone, Copy)]
+pub struct SpriteVertex {
+  pub i_pos: [f32; 2],
+  pub i_uv: [f32; 2],
+  pub i_color: [f32; 4],
+  /// In radians, for turning normal maps with the sprite.
+  pub i_rotation: f32,
+}
+
+glium::implement_vertex!(SpriteVertex, i_pos, i_uv, i_color, i_rotation);
+
+/// A textured quad to draw this frame.
+#[derive(Clone)]
+pub struct Sprite {
+  pub texture: Handle<Texture>,
+  /// The part of the texture to draw in pixels from its top left, all of it if `None`.
+  pub region: Option<Rect<f32>>,
+  pub transform: Transform,
+  /// The point the sprite is placed and rotated around, from the top left at (0, 0)
+  /// to the bottom right at (1, 1).
+  pub origin: Vec2,
+  /// Multiplied with the texture color.
+  pub color: Vec4,
+  /// Higher layers are drawn over lower ones.
+  pub layer: i32,
+  /// Drawn with the shader of the batch if `None`.
+  pub shader: Option<Handle<Shader>>,
+  /// Extra uniforms for the shader, only sprites with the same ones are drawn together.
+  pub params: Option<Rc<Vec<(String, UniformParam)>>>,
+  /// Drawn with the render state of the batch if `None`.
+  pub render_state: Option<RenderState>,
+}
+
+impl Sprite {
+  pub fn new(texture: Handle<Texture>, transform: Transform) -> Self {
+    Self {
+      texture,
+      region: None,
+      transform,
+      origin: glm::vec2(0.5, 0.5),
+      color: glm::vec4(1.0, 1.0, 1.0, 1.0),
+      layer: 0,
+      shader: None,
+      params: None,
+      render_state: None,
+    }
+  }
+
+  pub fn with_region(mut self, region: Rect<f32>) -> Self {
+    self.region = Some(region);
+    self
+  }
+
+  pub fn with_origin(mut self, origin: Vec2) -> Self {
+    self.origin = origin;
+    self
+  }
+
+  pub fn with_color(mut self, color: Vec4) -> Self {
+    self.color = color;
+    self
+  }
+
+  pub fn with_layer(mut self, layer: i32) -> Self {
+    self.layer = layer;
+    self
+  }
+
+  pub fn with_shader(mut self, shader: Handle<Shader>) -> Self {
+    self.shader = Some(shader);
+    self
+  }
+
+  pub fn with_params(mut self, params: Rc<Vec<(String, UniformParam)>>) -> Self {
+    self.params = Some(params);
+    self
+  }
+
+  pub fn with_render_state(mut self, render_state: RenderState) -> Self {
+    self.render_state = Some(render_state);
+    self
+  }
+
+  /// The corners of the sprite in world units and their texture coordinates,
+  /// clockwise from the top left.
+  fn corners(&self, texture: &Texture) -> [(Vec2, Vec2); 4] {
+    let (tw, th) = (texture.width() as f32, texture.height() as f32);
+    let region = self
+      .region
+      .unwrap_or_else(|| Rect::new((0.0, 0.0), (tw, th)));
+    let (min, max) = (region.min(), region.max());
+    let size = glm::vec2(region.width(), region.height());
+
+    let rotation = self.transform.rotation.to_radians();
+    let place = |corner: Vec2| {
+      let local = (corner - self.origin)
+        .component_mul(&size)
+        .component_mul(&self.transform.scale);
+      glm::rotate_vec2(&local, rotation) + self.transform.position
+    };
+
+    // textures are uploaded bottom row first, so v runs up from the bottom of the image
+    let (u0, u1) = (min.x / tw, max.x / tw);
+    let (v0, v1) = (1.0 - min.y / th, 1.0 - max.y / th);
+
+    [
+      (place(glm::vec2(0.0, 0.0)), glm::vec2(u0, v0)),
+      (place(glm::vec2(1.0, 0.0)), glm::vec2(u1, v0)),
+      (place(glm::vec2(1.0, 1.0)), glm::vec2(u1, v1)),
+      (place(glm::vec2(0.0, 1.0)), glm::vec2(u0, v1)),
+    ]
+  }
+}
+
+/// Sprites drawn together with one draw call.
+struct Run {
+  shader: Rc<Shader>,
+  texture: Rc<Texture>,
+  params: Option<Rc<Vec<(String, UniformParam)>>>,
+  render_state: RenderState,
+  count: usize,
+}
+
+/// The uniforms of a run followed by the params of its sprites.
+struct RunUniforms<'a, U> {
+  uniforms: U,
+  params: Option<&'a [(String, UniformParam)]>,
+}
+
+impl<U: Uniforms> Uniforms for RunUniforms<'_, U> {
+  fn visit_values<'b, F: FnMut(&str, UniformValue<'b>)>(&'b self, mut visit: F) {
+    self.uniforms.visit_values(&mut visit);
+    for (name, param) in self.params.into_iter().flatten() {
+      visit(name, param.as_uniform_value());
+    }
+  }
+}
+
+/// What the last frame drew.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BatchStats {
+  pub sprites: usize,
+  pub draw_calls: usize,
+}
+
+/// Collects sprites over a frame and draws them with as few draw calls as it can.
+/// Sprites are sorted by layer, then shader and texture, and every run sharing a shader
+/// and texture becomes one draw call from a single dynamic vertex buffer.
+pub struct SpriteBatch {
+  ctx: Rc<glium::backend::Context>,
+  shader: Handle<Shader>,
+  normal_shader: Handle<Shader>,
+  /// The normal map of textures without one, facing straight out of the screen.
+  flat_normals: Texture2d,
+  pub render_state: RenderState,
+  sprites: Vec<Sprite>,
+  vertices: VertexBuffer<SpriteVertex>,
+  indices: IndexBuffer<u32>,
+  stats: BatchStats,
+}
+
+impl SpriteBatch {
+  pub fn new(
+    ctx: Rc<glium::backend::Context>,
+    asset_server: &mut AssetServer,
+    shader: &AssetId,
+    normal_shader: &AssetId,
+  ) -> Result<Self, String> {
+    let shader = asset_server.load::<Shader>(shader)?;
+    let normal_shader = asset_server.load::<Shader>(normal_shader)?;
+    let (vertices, indices) = Self::buffers(&ctx, INITIAL_CAPACITY)?;
+    let flat_normals = Texture2d::new(
+      &ctx,
+      RawImage2d::from_raw_rgba(vec![128u8, 128, 255, 255], (1, 1)),
+    )
+    .map_err(|e| e.to_string())?;
+
+    Ok(Self {
+      ctx,
+      shader,
+      normal_shader,
+      flat_normals,
+      render_state: RenderState::default(),
+      sprites: Vec::default(),
+      vertices,
+      indices,
+      stats: BatchStats::default(),
+    })
+  }
+
+  /// Buffers with room for `capacity` quads. The indices never change, every quad is
+  /// two triangles over its own four vertices.
+  fn buffers(
+    ctx: &Rc<glium::backend::Context>,
+    capacity: usize,
+  ) -> Result<(VertexBuffer<SpriteVertex>, IndexBuffer<u32>), String> {
+    let vertices = VertexBuffer::empty_dynamic(ctx, capacity * 4).map_err(|e| e.to_string())?;
+
+    let indices: Vec<u32> = (0..capacity as u32)
+      .flat_map(|quad| {
+        let base = quad * 4;
+        [base, base + 1, base + 2, base, base + 2, base + 3]
+      })
+      .collect();
+    let indices = IndexBuffer::immutable(ctx, PrimitiveType::TrianglesList, &indices)
+      .map_err(|e| e.to_string())?;
+
+    Ok((vertices, indices))
+  }
+
+  pub fn push(&mut self, sprite: Sprite) {
+    self.sprites.push(sprite);
+  }
+
+  /// The sprites and draw calls of the last frame, normal passes included.
+  pub fn stats(&self) -> BatchStats {
+    self.stats
+  }
+
+  /// Empties the batch for the next frame.
+  pub fn clear(&mut self) {
+    self.sprites.clear();
+    self.stats = BatchStats::default();
+  }
+
+  /// Draws everything pushed since the last clear. Sprites whose texture or shader is
+  /// not loaded are skipped.
+  pub fn draw<S: Surface>(
+    &mut self,
+    surface: &mut S,
+    view: &Mat4,
+    projection: &Mat4,
+  ) -> Result<BatchStats, String> {
+    let runs = self.prepare()?;
+    let view: [[f32; 4]; 4] = (*view).into();
+    let projection: [[f32; 4]; 4] = (*projection).into();
+
+    let mut first = 0;
+    for run in &runs {
+      let uniforms = RunUniforms {
+        uniforms: uniform! {
+          u_view: view,
+          u_projection: projection,
+          tex: run.texture.sampled(),
+        },
+        params: run.params.as_deref().map(Vec::as_slice),
+      };
+      self.draw_run(surface, run, &run.shader, first, &uniforms)?;
+      first += run.count;
+    }
+
+    Ok(self.record(first, runs.len()))
+  }
+
+  /// Draws the normals of everything pushed since the last clear, for lighting.
+  /// Textures without a normal map face straight out of the screen.
+  pub fn draw_normals<S: Surface>(
+    &mut self,
+    surface: &mut S,
+    view: &Mat4,
+    projection: &Mat4,
+  ) -> Result<BatchStats, String> {
+    let normal_shader = self
+      .normal_shader
+      .get()
+      .ok_or_else(|| format!("shader {} is not loaded", self.normal_shader.id()))?;
+    let runs = self.prepare()?;
+    let view: [[f32; 4]; 4] = (*view).into();
+    let projection: [[f32; 4]; 4] = (*projection).into();
+
+    let mut first = 0;
+    for run in &runs {
+      let uniforms = uniform! {
+        u_view: view,
+        u_projection: projection,
+        tex: run.texture.sampled(),
+        normal_map: run
+          .texture
+          .normal_map()
+          .unwrap_or_else(|| self.flat_normals.sampled()),
+      };
+      self.draw_run(surface, run, &normal_shader, first, &uniforms)?;
+      first += run.count;
+    }
+
+    Ok(self.record(first, runs.len()))
+  }
+
+  /// Sorts the sprites and writes their vertices, returning each run of sprites that
+  /// share a shader, texture, params and render state.
+  fn prepare(&mut self) -> Result<Vec<Run>, String> {
+    let (vertices, runs) = batch(&mut self.sprites, &self.shader, self.render_state);
+
+    let quads = vertices.len() / 4;
+    if quads > self.vertices.len() / 4 {
+      let (vertices, indices) = Self::buffers(&self.ctx, quads.next_power_of_two())?;
+      self.vertices = vertices;
+      self.indices = indices;
+    }
+    // glium refuses to write nothing
+    if vertices.is_empty() {
+      return Ok(runs);
+    }
+    if let Some(slice) = self.vertices.slice(0..vertices.len()) {
+      slice.write(&vertices);
+    }
+
+    Ok(runs)
+  }
+
+  /// Draws the quads of `run`, the first of them at `first`.
+  fn draw_run<S: Surface, U: Uniforms>(
+    &self,
+    surface: &mut S,
+    run: &Run,
+    shader: &Shader,
+    first: usize,
+    uniforms: &U,
+  ) -> Result<(), String> {
+    let indices = self
+      .indices
+      .slice(first * 6..(first + run.count) * 6)
+      .ok_or_else(|| String::from("sprite batch index buffer is too small"))?;
+
+    surface
+      .draw(
+        &self.vertices,
+        indices,
+        shader.program(),
+        uniforms,
+        &run.render_state.draw_parameters(),
+      )
+      .map_err(|e| e.to_string())
+  }
+
+  /// Adds a draw to the stats of the frame, the normals count as more draw calls over
+  /// the same sprites.
+  fn record(&mut self, sprites: usize, draw_calls: usize) -> BatchStats {
+    self.stats.sprites = self.stats.sprites.max(sprites);
+    self.stats.draw_calls += draw_calls;
+    BatchStats {
+      sprites,
+      draw_calls,
+    }
+  }
+}
+
+fn shader_of<'a>(sprite: &'a Sprite, shader: &'a Handle<Shader>) -> &'a Handle<Shader> {
+  sprite.shader.as_ref().unwrap_or(shader)
+}
+
+/// Sorts `sprites` into drawing order and builds their vertices, along with each run of
+/// sprites that share a shader, texture, params and render state. Sprites without their
+/// own shader or render state use `shader` and `render_state`, and sprites whose texture
+/// or shader is not loaded are left out.
+fn batch(
+  sprites: &mut [Sprite],
+  shader: &Handle<Shader>,
+  render_state: RenderState,
+) -> (Vec<SpriteVertex>, Vec<Run>) {
+  // stable, so sprites sharing everything keep the order they were pushed in
+  sprites.sort_by(|a, b| {
+    a.layer
+      .cmp(&b.layer)
+      .then_with(|| shader_of(a, shader).id().cmp(shader_of(b, shader).id()))
+      .then_with(|| a.texture.id().cmp(b.texture.id()))
+  });
+
+  let mut vertices = Vec::with_capacity(sprites.len() * 4);
+  let mut runs: Vec<Run> = Vec::new();
+  for sprite in sprites.iter() {
+    let (shader, texture) = match (shader_of(sprite, shader).get(), sprite.texture.get()) {
+      (Some(shader), Some(texture)) => (shader, texture),
+      _ => continue,
+    };
+
+    let render_state = sprite.render_state.unwrap_or(render_state);
+    let color: [f32; 4] = sprite.color.into();
+    let rotation = sprite.transform.rotation.to_radians();
+    for (position, uv) in sprite.corners(&texture) {
+      vertices.push(SpriteVertex {
+        i_pos: position.into(),
+        i_uv: uv.into(),
+        i_color: color,
+        i_rotation: rotation,
+      });
+    }
+
+    match runs.last_mut() {
+      Some(run)
+        if Rc::ptr_eq(&run.shader, &shader)
+          && Rc::ptr_eq(&run.texture, &texture)
+          && run.params == sprite.params
+          && run.render_state == render_state =>
+      {
+        run.count += 1
+      }
+      _ => runs.push(Run {
+        shader,
+        texture,
+        params: sprite.params.clone(),
+        render_state,
+        count: 1,
+      }),
+    }
+  }
+
+  (vertices, runs)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::gfx::BlendMode;
+  use crate::view::headless::HeadlessBackend;
+
+  fn server() -> AssetServer {
+    let backend = HeadlessBackend::any((16, 16)).unwrap();
+    let ctx = unsafe { glium::backend::Context::new(backend, true, Default::default()) }.unwrap();
+    let mut server = AssetServer::new();
+    crate::register_loaders(&mut server, &ctx);
+    server
+  }
+
+  fn sprite(server: &mut AssetServer, texture: &str, x: f32) -> Sprite {
+    let texture = server.load::<Texture>(&texture.parse().unwrap()).unwrap();
+    Sprite::new(
+      texture,
+      Transform {
+        position: glm::vec2(x, 0.0),
+        ..Transform::default()
+      },
+    )
+  }
+
+  /// Where each batched sprite ended up, by the x of its center.
+  fn order(vertices: &[SpriteVertex]) -> Vec<f32> {
+    vertices
+      .chunks(4)
+      .map(|corners| corners.iter().map(|corner| corner.i_pos[0]).sum::<f32>() / 4.0)
+      .collect()
+  }
+
+  fn counts(runs: &[Run]) -> Vec<usize> {
+    runs.iter().map(|run| run.count).collect()
+  }
+
+  #[test]
+  fn sprites_sort_by_layer_then_texture_keeping_the_push_order() {
+    let mut server = server();
+    let shader = server
+      .load::<Shader>(&"exp.render.sprite".parse().unwrap())
+      .unwrap();
+    let mut sprites = vec![
+      sprite(&mut server, "exp.ui.icons", 100.0).with_layer(1),
+      sprite(&mut server, "exp.ui.icons", 200.0),
+      sprite(&mut server, "exp.render.particle", 300.0),
+      sprite(&mut server, "exp.ui.icons", 400.0),
+    ];
+
+    let (vertices, runs) = batch(&mut sprites, &shader, RenderState::default());
+    assert_eq!(order(&vertices), [300.0, 200.0, 400.0, 100.0]);
+    // the icons of both layers end up next to each other, so they still share a run
+    assert_eq!(counts(&runs), [1, 3]);
+  }
+
+  #[test]
+  fn runs_split_where_texture_params_or_render_state_change() {
+    let mut server = server();
+    let shader = server
+      .load::<Shader>(&"exp.render.sprite".parse().unwrap())
+      .unwrap();
+    let additive = RenderState {
+      blend: BlendMode::Additive,
+      ..RenderState::default()
+    };
+    let params = Rc::new(vec![(String::from("u_amount"), UniformParam::Float(0.5))]);
+    let mut sprites = vec![
+      sprite(&mut server, "exp.ui.icons", 0.0),
+      sprite(&mut server, "exp.ui.icons", 1.0),
+      sprite(&mut server, "exp.ui.icons", 2.0).with_render_state(additive),
+      sprite(&mut server, "exp.ui.icons", 3.0).with_render_state(additive),
+      sprite(&mut server, "exp.ui.icons", 4.0).with_params(params.clone()),
+      sprite(&mut server, "exp.ui.icons", 5.0).with_params(params),
+      sprite(&mut server, "exp.ui.icons", 6.0),
+    ];
+
+    let (vertices, runs) = batch(&mut sprites, &shader, RenderState::default());
+    assert_eq!(order(&vertices), [0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+    assert_eq!(counts(&runs), [2, 2, 2, 1]);
+    assert_eq!(runs[1].render_state, additive);
+    assert!(runs[2].params.is_some());
+  }
+
+  #[test]
+  fn sprites_without_their_own_render_state_use_the_batch_one() {
+    let mut server = server();
+    let shader = server
+      .load::<Shader>(&"exp.render.sprite".parse().unwrap())
+      .unwrap();
+    let wireframe = RenderState {
+      wireframe: true,
+      ..RenderState::default()
+    };
+    let mut sprites = vec![
+      sprite(&mut server, "exp.ui.icons", 0.0),
+      sprite(&mut server, "exp.ui.icons", 1.0).with_render_state(wireframe),
+    ];
+
+    let (_, runs) = batch(&mut sprites, &shader, wireframe);
+    assert_eq!(counts(&runs), [2]);
+    assert_eq!(runs[0].render_state, wireframe);
+  }
+}
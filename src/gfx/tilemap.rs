@@ -0,0 +1,523 @@
+use super::{RenderState, Shader, Texture, Vertex};
+use crate::assets::{AssetId, AssetServer, Handle};
+use crate::map::{autotile, Gid, Map, TileLayer, EMPTY_TILE};
+use crate::math::glm::Mat4;
+use geo::Rect;
+use glium::{index::PrimitiveType, uniform, IndexBuffer, Surface, VertexBuffer};
+use std::{collections::BTreeSet, rc::Rc};
+
+/// The width and height of a chunk in tiles.
+pub const CHUNK_SIZE: u32 = 32;
+
+struct Mesh {
+  tileset: usize,
+  vertices: VertexBuffer<Vertex>,
+  indices: IndexBuffer<u32>,
+}
+
+#[derive(Default)]
+struct Chunk {
+  meshes: Vec<Mesh>,
+  /// The animated tiles drawn in this chunk, so frame changes only rebuild what they touch.
+  animated: BTreeSet<Gid>,
+  dirty: bool,
+}
+
+struct LayerChunks {
+  name: String,
+  chunks: Vec<Chunk>,
+}
+
+/// Draws the tile layers of a map with one static vertex buffer per chunk and tileset.
+pub struct TilemapRenderer {
+  ctx: Rc<glium::backend::Context>,
+  shader: Handle<Shader>,
+  textures: Vec<Handle<Texture>>,
+  render_state: RenderState,
+  /// The size of the map in tiles.
+  width: u32,
+  height: u32,
+  chunks_x: u32,
+  chunks_y: u32,
+  layers: Vec<LayerChunks>,
+  /// The size of each tileset texture when the chunks were built, since their uvs depend on it.
+  texture_sizes: Vec<Option<(u32, u32)>>,
+  clock_ms: u64,
+  frames: Vec<(Gid, Gid)>,
+}
+
+impl TilemapRenderer {
+  pub fn new(
+    ctx: Rc<glium::backend::Context>,
+    asset_server: &mut AssetServer,
+    shader: &AssetId,
+    map: &Map,
+  ) -> Result<Self, String> {
+    let shader = asset_server.load::<Shader>(shader)?;
+    let textures = map
+      .tilesets
+      .iter()
+      .map(|tileset| asset_server.load::<Texture>(&tileset.texture))
+      .collect::<Result<Vec<_>, String>>()?;
+
+    let mut renderer = Self {
+      ctx,
+      shader,
+      textures,
+      render_state: RenderState::default(),
+      width: 0,
+      height: 0,
+      chunks_x: 0,
+      chunks_y: 0,
+      layers: Vec::default(),
+      texture_sizes: Vec::default(),
+      clock_ms: 0,
+      frames: Vec::default(),
+    };
+    renderer.reset(map);
+    renderer.texture_sizes = renderer.texture_sizes();
+    renderer.frames = renderer.animation_frames(map);
+
+    Ok(renderer)
+  }
+
+  /// Starts over with dirty chunks sized for `map`.
+  fn reset(&mut self, map: &Map) {
+    self.width = map.width;
+    self.height = map.height;
+    self.chunks_x = map.width.div_ceil(CHUNK_SIZE);
+    self.chunks_y = map.height.div_ceil(CHUNK_SIZE);
+
+    let count = self.chunks_x * self.chunks_y;
+    self.layers = map
+      .tile_layers()
+      .map(|layer| LayerChunks {
+        name: layer.name.clone(),
+        chunks: (0..count)
+          .map(|_| Chunk {
+            dirty: true,
+            ..Chunk::default()
+          })
+          .collect(),
+      })
+      .collect();
+  }
+
+  /// Resets the chunks when `map` no longer has the size or tile layers they were made
+  /// for, like after it was reloaded.
+  fn sync_layout(&mut self, map: &Map) {
+    let same_layers = self
+      .layers
+      .iter()
+      .map(|chunks| &chunks.name)
+      .eq(map.tile_layers().map(|layer| &layer.name));
+
+    if !same_layers || map.width != self.width || map.height != self.height {
+      self.reset(map);
+    }
+  }
+
+  fn texture_sizes(&self) -> Vec<Option<(u32, u32)>> {
+    self
+      .textures
+      .iter()
+      .map(|handle| {
+        handle
+          .get()
+          .map(|texture| (texture.width(), texture.height()))
+      })
+      .collect()
+  }
+
+  /// Marks every chunk for rebuilding when a tileset texture was reloaded at another size.
+  fn sync_textures(&mut self) {
+    let sizes = self.texture_sizes();
+    if sizes == self.texture_sizes {
+      return;
+    }
+
+    self.texture_sizes = sizes;
+    for chunk in self.layers.iter_mut().flat_map(|layer| &mut layer.chunks) {
+      chunk.dirty = true;
+    }
+  }
+
+  /// The chunk holding a tile, `None` for tiles off the map.
+  fn chunk_index(&self, x: i32, y: i32) -> Option<usize> {
+    if x < 0 || y < 0 || x as u32 >= self.width || y as u32 >= self.height {
+      return None;
+    }
+
+    let (cx, cy) = (x as u32 / CHUNK_SIZE, y as u32 / CHUNK_SIZE);
+    Some((cy * self.chunks_x + cx) as usize)
+  }
+
+  /// Sets a tile in the loaded map, autotiling its neighbors, and marks the chunks they
+  /// are in for rebuilding.
+  pub fn set_tile(&mut self, map: &Handle<Map>, layer: &str, x: i32, y: i32, gid: Gid) -> bool {
+    let changed = map
+      .modify(|map| autotile::set_tile(map, layer, x, y, gid))
+      .unwrap_or_default();
+
+    for (x, y) in &changed {
+      self.invalidate(layer, *x, *y);
+    }
+
+    !changed.is_empty()
+  }
+
+  /// Marks the chunk holding a tile for rebuilding, for maps edited without `set_tile`.
+  pub fn invalidate(&mut self, layer: &str, x: i32, y: i32) {
+    let index = match self.chunk_index(x, y) {
+      Some(index) => index,
+      None => return,
+    };
+
+    if let Some(chunk) = self
+      .layers
+      .iter_mut()
+      .find(|chunks| chunks.name == layer)
+      .and_then(|chunks| chunks.chunks.get_mut(index))
+    {
+      chunk.dirty = true;
+    }
+  }
+
+  /// The frame every animated tile shows at the current time.
+  fn animation_frames(&self, map: &Map) -> Vec<(Gid, Gid)> {
+    let mut frames = Vec::new();
+
+    for tileset in &map.tilesets {
+      for (id, tile) in &tileset.tiles {
+        let total: u64 = tile
+          .animation
+          .iter()
+          .map(|frame| frame.duration_ms as u64)
+          .sum();
+        if total == 0 {
+          continue;
+        }
+
+        let mut time = self.clock_ms % total;
+        for frame in &tile.animation {
+          if time < frame.duration_ms as u64 {
+            frames.push((tileset.first_gid + id, tileset.first_gid + frame.tile));
+            break;
+          }
+          time -= frame.duration_ms as u64;
+        }
+      }
+    }
+
+    frames
+  }
+
+  /// Advances tile animations, rebuilding only the chunks whose tiles changed frame.
+  pub fn update(&mut self, map: &Map, elapsed_ms: u32) {
+    self.sync_layout(map);
+    self.clock_ms += elapsed_ms as u64;
+
+    let frames = self.animation_frames(map);
+    let changed: BTreeSet<Gid> = frames
+      .iter()
+      .filter(|frame| !self.frames.contains(frame))
+      .map(|(gid, _)| *gid)
+      .collect();
+    self.frames = frames;
+
+    if changed.is_empty() {
+      return;
+    }
+
+    for chunk in self.layers.iter_mut().flat_map(|layer| &mut layer.chunks) {
+      if !chunk.animated.is_disjoint(&changed) {
+        chunk.dirty = true;
+      }
+    }
+  }
+
+  fn animation_frame(&self, gid: Gid) -> Option<Gid> {
+    self
+      .frames
+      .iter()
+      .find(|(animated, _)| *animated == gid)
+      .map(|(_, frame)| *frame)
+  }
+
+  fn build_chunk(&self, map: &Map, layer: &TileLayer, cx: u32, cy: u32) -> Result<Chunk, String> {
+    let mut geometry: Vec<(Vec<Vertex>, Vec<u32>)> = vec![Default::default(); map.tilesets.len()];
+    let mut animated = BTreeSet::new();
+
+    let x_end = ((cx + 1) * CHUNK_SIZE).min(layer.width);
+    let y_end = ((cy + 1) * CHUNK_SIZE).min(layer.height);
+
+    for y in cy * CHUNK_SIZE..y_end {
+      for x in cx * CHUNK_SIZE..x_end {
+        let gid = layer.get(x as i32, y as i32).unwrap_or(EMPTY_TILE);
+        if gid == EMPTY_TILE {
+          continue;
+        }
+
+        let tileset_index = match map.tilesets.iter().position(|t| t.contains(gid)) {
+          Some(index) => index,
+          None => continue,
+        };
+        let tileset = &map.tilesets[tileset_index];
+        let texture = match self.textures.get(tileset_index).and_then(Handle::get) {
+          Some(texture) => texture,
+          None => continue,
+        };
+
+        let shown = match self.animation_frame(gid) {
+          Some(frame) => {
+            animated.insert(gid);
+            frame
+          }
+          None => gid,
+        };
+        let local = shown - tileset.first_gid;
+
+        // tiles larger than the grid overhang upwards, like they do in tiled
+        let left = (x * map.tile_width) as f32;
+        let bottom = ((y + 1) * map.tile_height) as f32;
+        let right = left + tileset.tile_width as f32;
+        let top = bottom - tileset.tile_height as f32;
+
+        // textures are uploaded bottom row first, so v runs up from the bottom of the image
+        let (tw, th) = (texture.width() as f32, texture.height() as f32);
+        let (column, row) = (local % tileset.columns, local / tileset.columns);
+        let u0 = (column * tileset.tile_width) as f32 / tw;
+        let u1 = ((column + 1) * tileset.tile_width) as f32 / tw;
+        let v0 = 1.0 - (row * tileset.tile_height) as f32 / th;
+        let v1 = 1.0 - ((row + 1) * tileset.tile_height) as f32 / th;
+
+        let (vertices, indices) = &mut geometry[tileset_index];
+        let base = vertices.len() as u32;
+        for (px, py, u, v) in [
+          (left, top, u0, v0),
+          (right, top, u1, v0),
+          (right, bottom, u1, v1),
+          (left, bottom, u0, v1),
+        ] {
+          vertices.push(Vertex {
+            i_pos: [px, py, 0.0],
+            i_norm: [0.0, 0.0, 1.0],
+            i_uv: [u, v],
+          });
+        }
+        indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+      }
+    }
+
+    let mut meshes = Vec::new();
+    for (tileset, (vertices, indices)) in geometry.into_iter().enumerate() {
+      if indices.is_empty() {
+        continue;
+      }
+
+      meshes.push(Mesh {
+        tileset,
+        vertices: VertexBuffer::immutable(&self.ctx, &vertices).map_err(|e| e.to_string())?,
+        indices: IndexBuffer::immutable(&self.ctx, PrimitiveType::TrianglesList, &indices)
+          .map_err(|e| e.to_string())?,
+      });
+    }
+
+    Ok(Chunk {
+      meshes,
+      animated,
+      dirty: false,
+    })
+  }
+
+  /// The range of chunks overlapping an area in world units.
+  fn visible_chunks(&self, map: &Map, visible: &Rect<f32>) -> (u32, u32, u32, u32) {
+    let chunk_width = (CHUNK_SIZE * map.tile_width) as f32;
+    let chunk_height = (CHUNK_SIZE * map.tile_height) as f32;
+
+    let first = |min: f32, size: f32| (min / size).floor().max(0.0) as u32;
+    let last = |max: f32, size: f32, count: u32| ((max / size).ceil().max(0.0) as u32).min(count);
+
+    (
+      first(visible.min().x, chunk_width),
+      first(visible.min().y, chunk_height),
+      last(visible.max().x, chunk_width, self.chunks_x),
+      last(visible.max().y, chunk_height, self.chunks_y),
+    )
+  }
+
+  /// Draws every visible layer, rebuilding dirty chunks inside `visible` first.
+  /// Returns the number of draw calls made.
+  pub fn draw<S: Surface>(
+    &mut self,
+    surface: &mut S,
+    map: &Map,
+    view: &Mat4,
+    projection: &Mat4,
+    visible: &Rect<f32>,
+  ) -> Result<usize, String> {
+    self.sync_layout(map);
+    self.sync_textures();
+    let (x0, y0, x1, y1) = self.visible_chunks(map, visible);
+
+    for (index, layer) in map.tile_layers().enumerate() {
+      for cy in y0..y1 {
+        for cx in x0..x1 {
+          let chunk = (cy * self.chunks_x + cx) as usize;
+          if self.layers[index].chunks[chunk].dirty {
+            let built = self.build_chunk(map, layer, cx, cy)?;
+            self.layers[index].chunks[chunk] = built;
+          }
+        }
+      }
+    }
+
+    let shader = self
+      .shader
+      .get()
+      .ok_or_else(|| format!("shader {} is not loaded", self.shader.id()))?;
+    let parameters = self.render_state.draw_parameters();
+    let view: [[f32; 4]; 4] = (*view).into();
+    let projection: [[f32; 4]; 4] = (*projection).into();
+
+    let mut draw_calls = 0;
+    for (index, layer) in map.tile_layers().enumerate() {
+      if !layer.visible {
+        continue;
+      }
+
+      for cy in y0..y1 {
+        for cx in x0..x1 {
+          let chunk = &self.layers[index].chunks[(cy * self.chunks_x + cx) as usize];
+          for mesh in &chunk.meshes {
+            let texture = match self.textures.get(mesh.tileset).and_then(Handle::get) {
+              Some(texture) => texture,
+              None => continue,
+            };
+
+            let uniforms = uniform! {
+              u_view: view,
+              u_projection: projection,
+              tex: texture.sampled(),
+            };
+
+            surface
+              .draw(
+                &mesh.vertices,
+                &mesh.indices,
+                shader.program(),
+                &uniforms,
+                &parameters,
+              )
+              .map_err(|e| e.to_string())?;
+            draw_calls += 1;
+          }
+        }
+      }
+    }
+
+    Ok(draw_calls)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::map::{Layer, Properties, Tileset};
+  use crate::view::headless::HeadlessBackend;
+  use std::collections::BTreeMap;
+
+  fn renderer(map: &Map) -> TilemapRenderer {
+    let backend = HeadlessBackend::any((16, 16)).unwrap();
+    let ctx = unsafe { glium::backend::Context::new(backend, true, Default::default()) }.unwrap();
+    let mut server = AssetServer::new();
+    crate::register_loaders(&mut server, &ctx);
+    TilemapRenderer::new(
+      ctx,
+      &mut server,
+      &"exp.render.tilemap".parse().unwrap(),
+      map,
+    )
+    .unwrap()
+  }
+
+  /// A map of walls with one tile in the last chunk of each of `layers` layers.
+  fn map(size: u32, layers: &[&str]) -> Map {
+    let mut map = Map::new(size, size, 16, 16);
+    map.tilesets.push(Tileset {
+      name: String::from("walls"),
+      texture: "exp.test.random.wall".parse().unwrap(),
+      first_gid: 1,
+      tile_width: 16,
+      tile_height: 16,
+      columns: 4,
+      tile_count: 16,
+      tiles: BTreeMap::default(),
+      terrains: Vec::default(),
+      properties: Properties::default(),
+    });
+
+    for name in layers {
+      let mut layer = TileLayer::new(name, size, size).unwrap();
+      layer.set(size as i32 - 1, size as i32 - 1, 6);
+      map.layers.push(Layer::Tiles(layer));
+    }
+
+    map
+  }
+
+  fn draw(renderer: &mut TilemapRenderer, map: &Map) -> usize {
+    let mut frame = glium::Frame::new(renderer.ctx.clone(), (16, 16));
+    let extent = (map.width * map.tile_width) as f32;
+    let visible = Rect::new((0.0, 0.0), (extent, extent));
+    let identity = Mat4::identity();
+    let draw_calls = renderer
+      .draw(&mut frame, map, &identity, &identity, &visible)
+      .unwrap();
+    frame.finish().unwrap();
+    draw_calls
+  }
+
+  #[test]
+  fn each_layer_draws_the_chunks_holding_tiles() {
+    let map = map(40, &["ground", "walls"]);
+    let mut renderer = renderer(&map);
+    assert_eq!(renderer.layers.len(), 2);
+    assert_eq!(renderer.layers[0].chunks.len(), 4);
+    assert_eq!(draw(&mut renderer, &map), 2);
+  }
+
+  #[test]
+  fn a_reloaded_map_with_other_layers_or_size_resets_the_chunks() {
+    let mut renderer = renderer(&map(4, &["ground"]));
+    assert_eq!(draw(&mut renderer, &map(4, &["ground"])), 1);
+
+    let reloaded = map(40, &["ground", "walls", "roof"]);
+    assert_eq!(draw(&mut renderer, &reloaded), 3);
+    assert_eq!(renderer.layers.len(), 3);
+    assert_eq!((renderer.chunks_x, renderer.chunks_y), (2, 2));
+
+    let shrunk = map(4, &["walls"]);
+    renderer.update(&shrunk, 16);
+    assert_eq!(renderer.layers.len(), 1);
+    assert_eq!(renderer.layers[0].chunks.len(), 1);
+    assert_eq!(draw(&mut renderer, &shrunk), 1);
+  }
+
+  #[test]
+  fn chunks_are_rebuilt_when_a_texture_changes_size() {
+    let map = map(4, &["ground"]);
+    let mut renderer = renderer(&map);
+    draw(&mut renderer, &map);
+    assert!(!renderer.layers[0].chunks[0].dirty);
+
+    renderer.sync_textures();
+    assert!(!renderer.layers[0].chunks[0].dirty);
+
+    // as if the texture was smaller before it was reloaded
+    renderer.texture_sizes = vec![Some((32, 32))];
+    renderer.sync_textures();
+    assert!(renderer.layers[0].chunks[0].dirty);
+    assert_eq!(renderer.texture_sizes, [Some((64, 64))]);
+  }
+}
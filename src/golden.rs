@@ -0,0 +1,276 @@
+use super::{
+  create_camera, register_loaders, update_effects, Renderers, COLLISION_CELL_SIZE, PHYSICS_RATE,
+};
+use crate::assets::AssetServer;
+use crate::game::{components::Transform, Prototype, World};
+use crate::gfx::{self, Light, LightKind, ParticleEffect, Shader};
+use crate::math::glm;
+use crate::physics::{CollisionSystem, PhysicsWorld};
+use crate::view::headless::HeadlessBackend;
+use glium::texture::RawImage2d;
+use std::path::{Path, PathBuf};
+
+const REFERENCE_DIR: &str = "tests/golden";
+/// Where the frames and diffs of failed comparisons are written.
+const OUTPUT_DIR: &str = "target/golden";
+/// Set to write the references anew after a change to how the game looks.
+const UPDATE_VAR: &str = "UPDATE_GOLDEN";
+/// What differing pixels are painted in the diff image.
+const DIFF_COLOR: [u8; 4] = [255, 0, 255, 255];
+
+/// How far a frame may stray from its reference, enough to let different drivers
+/// round differently.
+#[derive(Debug, Clone, Copy)]
+struct Tolerance {
+  /// The largest difference in any channel of a pixel that still counts as the same.
+  channel: u8,
+  /// The share of pixels that may differ, from 0 to 1.
+  pixels: f32,
+}
+
+const DEFAULT_TOLERANCE: Tolerance = Tolerance {
+  channel: 8,
+  pixels: 0.002,
+};
+
+struct GoldenScene {
+  /// The reference is `tests/golden/<name>.png`.
+  name: &'static str,
+  map: &'static str,
+  size: (u32, u32),
+  /// Each a physics step long.
+  frames: u32,
+  tolerance: Tolerance,
+  /// Adds to the world after the map is entered.
+  setup: fn(&mut World, &mut AssetServer) -> Result<(), String>,
+}
+
+/// Pixels of two images of the same size, compared.
+struct Comparison {
+  differing: usize,
+  /// The reference faded out, with the differing pixels painted over it.
+  diff: Vec<u8>,
+}
+
+/// Compares two images of rgba pixels, counting the pixels whose channels are further
+/// apart than `channel`.
+fn compare(actual: &[u8], reference: &[u8], channel: u8) -> Comparison {
+  let mut differing = 0;
+  let mut diff = Vec::with_capacity(reference.len());
+  for (actual, reference) in actual.chunks_exact(4).zip(reference.chunks_exact(4)) {
+    let apart = actual
+      .iter()
+      .zip(reference)
+      .any(|(a, b)| a.abs_diff(*b) > channel);
+    if apart {
+      differing += 1;
+      diff.extend_from_slice(&DIFF_COLOR);
+    } else {
+      let gray = ((reference[0] as u32 + reference[1] as u32 + reference[2] as u32) / 9) as u8;
+      diff.extend_from_slice(&[gray, gray, gray, 255]);
+    }
+  }
+  Comparison { differing, diff }
+}
+
+/// The last frame of `scene`, top row first.
+fn render(scene: &GoldenScene, backend: HeadlessBackend) -> Result<Vec<u8>, String> {
+  let ctx = unsafe { glium::backend::Context::new(backend, true, Default::default()) }
+    .map_err(|e| e.to_string())?;
+
+  let mut asset_server = AssetServer::new();
+  register_loaders(&mut asset_server, &ctx);
+  asset_server.load_all::<Shader>();
+  asset_server.load_all::<Prototype>();
+
+  let mut world = World::new();
+  world.enter_map(&mut asset_server, &scene.map.parse()?)?;
+  (scene.setup)(&mut world, &mut asset_server)?;
+
+  let map = world.map().and_then(|map| map.get());
+  // a renderer that is missing would leave its part of the frame, and the reference,
+  // blank
+  let (mut renderers, errors) =
+    Renderers::new(ctx.clone(), &mut asset_server, map.as_deref(), scene.size);
+  if !errors.is_empty() {
+    return Err(errors.join(", "));
+  }
+  let mut camera = create_camera(scene.size, map.as_deref());
+  drop(map);
+  let mut collisions = CollisionSystem::new(COLLISION_CELL_SIZE);
+  let mut physics = PhysicsWorld::new(glm::vec2(0.0, 0.0));
+  let dt = 1.0 / PHYSICS_RATE as f32;
+
+  for _ in 0..scene.frames {
+    renderers.update(
+      world.map().and_then(|map| map.get()).as_deref(),
+      1000 / PHYSICS_RATE,
+    );
+    physics.step(&mut world, &mut collisions, dt);
+    update_effects(&mut world, &mut camera, None, dt);
+
+    let mut frame = glium::Frame::new(ctx.clone(), scene.size);
+    let drawn = renderers.draw(
+      &mut frame,
+      &world,
+      world.map().and_then(|map| map.get()).as_deref(),
+      &camera,
+      PHYSICS_RATE as f32,
+    );
+    frame.finish().map_err(|e| format!("{:?}", e))?;
+    drawn?;
+  }
+
+  // read back bottom row first
+  let image: RawImage2d<u8> = ctx.read_front_buffer().map_err(|e| format!("{:?}", e))?;
+  let row = image.width as usize * 4;
+  Ok(
+    image
+      .data
+      .chunks_exact(row)
+      .rev()
+      .flatten()
+      .copied()
+      .collect(),
+  )
+}
+
+fn save(path: &Path, (width, height): (u32, u32), pixels: Vec<u8>) -> Result<(), String> {
+  if let Some(dir) = path.parent() {
+    std::fs::create_dir_all(dir).map_err(|e| format!("cannot create {}: {}", dir.display(), e))?;
+  }
+  image::RgbaImage::from_raw(width, height, pixels)
+    .ok_or_else(|| format!("{} has the wrong number of pixels", path.display()))?
+    .save(path)
+    .map_err(|e| format!("cannot write {}: {}", path.display(), e))
+}
+
+/// Renders `scene` and panics when it strays from its reference, writing what was
+/// rendered and the diff next to each other.
+fn check(scene: &GoldenScene) {
+  let backend = HeadlessBackend::any(scene.size)
+    .unwrap_or_else(|msg| panic!("cannot render {} headless: {}", scene.name, msg));
+  let actual =
+    render(scene, backend).unwrap_or_else(|msg| panic!("cannot render {}: {}", scene.name, msg));
+
+  let reference_path = PathBuf::from(REFERENCE_DIR).join(format!("{}.png", scene.name));
+  if std::env::var_os(UPDATE_VAR).is_some() {
+    save(&reference_path, scene.size, actual).unwrap();
+    return;
+  }
+
+  let reference = image::open(&reference_path)
+    .unwrap_or_else(|e| {
+      panic!(
+        "cannot open {}, run with {}=1 to create it: {}",
+        reference_path.display(),
+        UPDATE_VAR,
+        e
+      )
+    })
+    .to_rgba8();
+  assert_eq!(
+    reference.dimensions(),
+    scene.size,
+    "{} is not the size of the scene",
+    reference_path.display()
+  );
+
+  let comparison = compare(&actual, reference.as_raw(), scene.tolerance.channel);
+  let total = (scene.size.0 * scene.size.1) as usize;
+  let share = comparison.differing as f32 / total as f32;
+  if share > scene.tolerance.pixels {
+    let output = PathBuf::from(OUTPUT_DIR);
+    let actual_path = output.join(format!("{}.actual.png", scene.name));
+    let diff_path = output.join(format!("{}.diff.png", scene.name));
+    save(&actual_path, scene.size, actual).unwrap();
+    save(&diff_path, scene.size, comparison.diff).unwrap();
+    panic!(
+      "{} differs from its reference in {} of {} pixels, see {} and {}",
+      scene.name,
+      comparison.differing,
+      total,
+      actual_path.display(),
+      diff_path.display()
+    );
+  }
+}
+
+fn no_setup(_: &mut World, _: &mut AssetServer) -> Result<(), String> {
+  Ok(())
+}
+
+/// A burst of sparks lit by a point light, over the test map.
+fn sparks_setup(world: &mut World, asset_server: &mut AssetServer) -> Result<(), String> {
+  let sparks = asset_server.load::<ParticleEffect>(&"exp.effects.sparks".parse()?)?;
+  let position = glm::vec2(80.0, 40.0);
+  gfx::spawn_effect(
+    world,
+    sparks,
+    Transform {
+      position,
+      ..Transform::default()
+    },
+  );
+
+  let light = world.spawn();
+  world.insert(
+    light,
+    Transform {
+      position,
+      ..Transform::default()
+    },
+  );
+  let mut point = Light::new(LightKind::Point, 64.0);
+  point.color = glm::vec3(1.0, 0.7, 0.4);
+  world.insert(light, point);
+  Ok(())
+}
+
+#[test]
+fn test_map() {
+  check(&GoldenScene {
+    name: "test_map",
+    map: "exp.test",
+    size: (256, 192),
+    frames: 30,
+    tolerance: DEFAULT_TOLERANCE,
+    setup: no_setup,
+  });
+}
+
+#[test]
+fn sparks() {
+  check(&GoldenScene {
+    name: "sparks",
+    map: "exp.test",
+    size: (256, 192),
+    frames: 6,
+    tolerance: DEFAULT_TOLERANCE,
+    setup: sparks_setup,
+  });
+}
+
+#[test]
+fn same_images_match() {
+  let pixels = [10, 20, 30, 255, 200, 100, 0, 255];
+  assert_eq!(compare(&pixels, &pixels, 0).differing, 0);
+}
+
+#[test]
+fn differences_within_tolerance_match() {
+  let actual = [10, 20, 30, 255, 200, 100, 0, 255];
+  let reference = [12, 18, 30, 255, 200, 100, 3, 255];
+  assert_eq!(compare(&actual, &reference, 3).differing, 0);
+  assert_eq!(compare(&actual, &reference, 2).differing, 1);
+}
+
+#[test]
+fn differing_pixels_are_painted_in_the_diff() {
+  let actual = [0, 0, 0, 255, 90, 90, 90, 255];
+  let reference = [0, 0, 0, 255, 0, 0, 0, 255];
+  let comparison = compare(&actual, &reference, 8);
+  assert_eq!(comparison.differing, 1);
+  assert_eq!(&comparison.diff[..4], &[0, 0, 0, 255]);
+  assert_eq!(&comparison.diff[4..], &DIFF_COLOR);
+}
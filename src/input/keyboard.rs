@@ -34,6 +34,7 @@ pub enum Key {
 
   Esc,
   Tab,
+  F1,
 
   Unsupported,
 }
@@ -1,22 +1,550 @@
+mod assets;
+mod game;
 mod gfx;
+#[cfg(test)]
+mod golden;
 mod input;
+mod map;
 mod math;
+mod navigation;
+mod physics;
 mod util;
 mod view;
 
-use gfx::{ShaderRepository, ShaderSources};
-use glium::Surface;
+use assets::{AssetKey, AssetKind, AssetServer, Handle};
+use game::{
+  components::{Name, Renderable, Transform},
+  Prototype, PrototypeLoader, World,
+};
+use gfx::{
+  Align, BatchStats, Font, FontLoader, Icons, LightRenderer, ModelLoader, ParticleEffect,
+  ParticleEffectLoader, ParticleEmitter, ParticleRenderer, PassInputs, PassRenderer,
+  PipelineLoader, RenderGraph, RichLayout, RichText, Shader, ShaderLoader, Sprite, SpriteBatch,
+  TextEffects, TextLayout, Texture, TextureLoader, TilemapRenderer, Typewriter,
+};
+use glium::{uniform, Surface};
 use input::{
   keyboard::{Key, KeyAction},
   InputCheck, InputDevices,
 };
-use log::info;
-use std::path::Path;
-use util::{FpsManager, Settings};
-use view::window::{Window, WindowSettings};
+use log::{error, info, warn};
+use map::{Map, MapLoader};
+use math::glm;
+use physics::{CollisionEvents, CollisionPhase, CollisionSystem, PhysicsWorld, RigidBody};
+use std::{path::Path, rc::Rc, time::Instant};
+use util::{FixedTimestep, FpsManager, Settings};
+use view::{
+  camera::Camera2D,
+  headless::HeadlessBackend,
+  inspector::Inspector,
+  overlay::DebugOverlay,
+  window::{Window, WindowSettings},
+};
 
 static SETTINGS_FILE: &str = "config/settings.toml";
+static STARTING_MAP: &str = "exp.test";
+static TILEMAP_SHADER: &str = "exp.render.tilemap";
+static SPRITE_SHADER: &str = "exp.render.sprite";
+static SPRITE_NORMALS_SHADER: &str = "exp.render.sprite_normals";
+static LIGHT_SHADER: &str = "exp.render.light";
+static SHADOW_SHADER: &str = "exp.render.shadow";
+static PARTICLE_SHADER: &str = "exp.render.particle";
+static IMGUI_SHADER: &str = "exp.render.imgui";
+static CAMERA_TARGET: &str = "player";
+static PIPELINE: &str = "exp.render.default";
+static UI_FONT: &str = "exp.ui.mono";
+static IMPACT_EFFECT: &str = "exp.effects.sparks";
+static UI_ICONS: &str = "exp.ui.icons";
+/// Goes away once it has been up for the pause at its end.
+static HINT: &str = "[icon=key] [color=yellow][b]F1[/b][/color] toggles the debug overlay\n\
+                     [icon=key] [color=yellow][b]Esc[/b][/color] quits[pause=8]";
+static WORLD_PASS: &str = "world";
+static UI_PASS: &str = "ui";
+static NORMALS_PASS: &str = "normals";
+static LIGHTING_PASS: &str = "lighting";
+/// The input of the lighting pass holding the normals of the world.
+static NORMALS_INPUT: &str = "normals";
+const COLLISION_CELL_SIZE: f32 = 64.0;
+const PHYSICS_RATE: u32 = 60;
+const MAX_PHYSICS_STEPS: u32 = 5;
 const LOG_LIMIT: usize = 5;
+/// The space between the text of the ui and the edges of the window, in pixels.
+const UI_MARGIN: f32 = 8.0;
+/// The size of each icon in the ui icon atlas, in pixels.
+const ICON_SIZE: (f32, f32) = (16.0, 16.0);
+/// The icons of the ui icon atlas, left to right and top to bottom.
+const ICON_NAMES: [&str; 1] = ["key"];
+/// How many characters of the hint show up a second.
+const HINT_SPEED: f32 = 40.0;
+/// How fast a body moves, in world units a second, when its emitters reach their full rate.
+const FULL_EMISSION_SPEED: f32 = 80.0;
+/// The particles an emitter throws out when its entity bumps into something.
+const IMPACT_PARTICLES: usize = 8;
+/// How much the camera shakes when its target bumps into something, out of 1.
+const IMPACT_TRAUMA: f32 = 0.4;
+
+/// Draws the passes of the pipeline that have no shader of their own.
+struct Scene<'a> {
+  world: &'a World,
+  map: Option<&'a Map>,
+  tilemap: Option<&'a mut TilemapRenderer>,
+  sprites: Option<&'a mut SpriteBatch>,
+  particles: Option<&'a mut ParticleRenderer>,
+  lights: Option<&'a mut LightRenderer>,
+  camera: &'a Camera2D,
+  ui: Option<&'a mut SpriteBatch>,
+  font: Option<&'a Font>,
+  icons: &'a Icons,
+  /// The hint and how many of its characters are revealed.
+  hint: Option<(&'a RichText, usize)>,
+  /// Seconds since the ui started moving.
+  time: f32,
+  fps: f32,
+}
+
+impl Scene<'_> {
+  /// Draws the renderable entities without a texture through their prototype, the
+  /// others are in the sprite batch.
+  fn draw_models<S: Surface>(
+    &self,
+    surface: &mut S,
+    view: &glm::Mat4,
+    projection: &glm::Mat4,
+  ) -> Result<(), String> {
+    let view: [[f32; 4]; 4] = (*view).into();
+    let projection: [[f32; 4]; 4] = (*projection).into();
+    for (entity, renderable) in self.world.query::<Renderable>() {
+      let (transform, prototype) = match (
+        self.world.get::<Transform>(entity),
+        renderable.prototype.get(),
+      ) {
+        (Some(transform), Some(prototype)) if prototype.texture.is_none() => (transform, prototype),
+        _ => continue,
+      };
+
+      let model: [[f32; 4]; 4] = transform.matrix().into();
+      let uniforms = uniform! {
+        u_model: model,
+        u_view: view,
+        u_projection: projection,
+      };
+      prototype
+        .draw(surface, &uniforms, &renderable.render_state)
+        .map_err(|msg| format!("cannot draw {}: {}", renderable.prototype.id(), msg))?;
+    }
+    Ok(())
+  }
+
+  /// Draws in pixels from the top left of the surface, whatever the camera does.
+  fn draw_ui<S: Surface>(&mut self, surface: &mut S) -> Result<(), String> {
+    let (ui, font) = match (&mut self.ui, self.font) {
+      (Some(ui), Some(font)) => (ui, font),
+      _ => return Ok(()),
+    };
+
+    let (width, height) = surface.get_dimensions();
+    let white = glm::vec4(1.0, 1.0, 1.0, 1.0);
+
+    let frame_ms = if self.fps > 0.0 {
+      1000.0 / self.fps
+    } else {
+      0.0
+    };
+    let stats = format!("{:.0} fps\n{:.1} ms", self.fps, frame_ms);
+    let stats = TextLayout::new(font, &stats, None, Align::Right);
+    let corner = glm::vec2(width as f32 - stats.size.x - UI_MARGIN, UI_MARGIN);
+    for sprite in stats.sprites(font, corner, white) {
+      ui.push(sprite);
+    }
+
+    if let Some((hint, visible)) = self.hint {
+      let hint = RichLayout::new(font, hint, font.size(), None, Align::Center, self.icons)
+        .map_err(|msg| format!("cannot lay out hint: {}", msg))?;
+      let size = hint.layout.size;
+      let bottom = glm::vec2(
+        ((width as f32 - size.x) / 2.0).round(),
+        height as f32 - size.y - UI_MARGIN,
+      );
+      let effects = TextEffects::default();
+      for sprite in hint.sprites(font, bottom, white, &effects, self.time, visible) {
+        ui.push(sprite);
+      }
+    }
+
+    let projection = glm::ortho(0.0, width as f32, height as f32, 0.0, -1.0, 1.0);
+    ui.draw(surface, &glm::Mat4::identity(), &projection)
+      .map_err(|msg| format!("cannot draw ui: {}", msg))?;
+    Ok(())
+  }
+}
+
+impl PassRenderer for Scene<'_> {
+  fn draw<S: Surface>(
+    &mut self,
+    pass: &str,
+    surface: &mut S,
+    inputs: &PassInputs<'_>,
+  ) -> Result<(), String> {
+    let (view, projection) = (self.camera.view(), self.camera.projection());
+
+    if pass == NORMALS_PASS {
+      if let Some(sprites) = &mut self.sprites {
+        sprites
+          .draw_normals(surface, &view, &projection)
+          .map_err(|msg| format!("cannot draw sprite normals: {}", msg))?;
+      }
+      return Ok(());
+    }
+    if pass == LIGHTING_PASS {
+      if let Some(lights) = &mut self.lights {
+        lights
+          .draw(
+            surface,
+            self.world,
+            &view,
+            &projection,
+            &self.camera.visible_area(),
+            inputs.get(NORMALS_INPUT),
+          )
+          .map_err(|msg| format!("cannot draw lights: {}", msg))?;
+      }
+      return Ok(());
+    }
+    if pass == UI_PASS {
+      return self.draw_ui(surface);
+    }
+    if pass != WORLD_PASS {
+      return Ok(());
+    }
+
+    if let (Some(map), Some(tilemap)) = (self.map, &mut self.tilemap) {
+      tilemap
+        .draw(
+          surface,
+          map,
+          &view,
+          &projection,
+          &self.camera.visible_area(),
+        )
+        .map_err(|msg| format!("cannot draw tilemap: {}", msg))?;
+    }
+    self.draw_models(surface, &view, &projection)?;
+    if let Some(sprites) = &mut self.sprites {
+      sprites
+        .draw(surface, &view, &projection)
+        .map_err(|msg| format!("cannot draw sprites: {}", msg))?;
+    }
+    if let Some(particles) = &mut self.particles {
+      particles
+        .draw(
+          surface,
+          self.world,
+          &view,
+          &projection,
+          &self.camera.visible_area(),
+        )
+        .map_err(|msg| format!("cannot draw particles: {}", msg))?;
+    }
+
+    Ok(())
+  }
+}
+
+/// Everything the game draws with. Parts that cannot be created are left out with an
+/// error logged, and frames are drawn without them.
+struct Renderers {
+  tilemap: Option<TilemapRenderer>,
+  sprites: Option<SpriteBatch>,
+  particles: Option<ParticleRenderer>,
+  lights: Option<LightRenderer>,
+  ui: Option<SpriteBatch>,
+  ui_font: Option<Handle<Font>>,
+  icons: Icons,
+  /// The hint at the bottom of the window, revealed a character at a time.
+  hint: Option<(RichText, Typewriter)>,
+  /// Seconds since the ui started moving.
+  ui_time: f32,
+  render_graph: Option<RenderGraph>,
+  /// What the world sprite batch drew in the last frame.
+  sprite_stats: BatchStats,
+}
+
+impl Renderers {
+  /// Starts out drawing frames `size` pixels across, with `map` under everything else,
+  /// along with why any of the renderers could not be created.
+  fn new(
+    ctx: Rc<glium::backend::Context>,
+    asset_server: &mut AssetServer,
+    map: Option<&Map>,
+    size: (u32, u32),
+  ) -> (Self, Vec<String>) {
+    let mut errors = Vec::new();
+    let tilemap = map.and_then(|map| {
+      TilemapRenderer::new(
+        ctx.clone(),
+        asset_server,
+        &TILEMAP_SHADER.parse().unwrap(),
+        map,
+      )
+      .map_err(|msg| errors.push(format!("cannot create tilemap renderer: {}", msg)))
+      .ok()
+    });
+
+    let sprites = SpriteBatch::new(
+      ctx.clone(),
+      asset_server,
+      &SPRITE_SHADER.parse().unwrap(),
+      &SPRITE_NORMALS_SHADER.parse().unwrap(),
+    )
+    .map_err(|msg| errors.push(format!("cannot create sprite batch: {}", msg)))
+    .ok();
+
+    let ui = SpriteBatch::new(
+      ctx.clone(),
+      asset_server,
+      &SPRITE_SHADER.parse().unwrap(),
+      &SPRITE_NORMALS_SHADER.parse().unwrap(),
+    )
+    .map_err(|msg| errors.push(format!("cannot create ui sprite batch: {}", msg)))
+    .ok();
+
+    let ui_font = asset_server
+      .load::<Font>(&UI_FONT.parse().unwrap())
+      .map_err(|msg| errors.push(format!("cannot load ui font: {}", msg)))
+      .ok();
+
+    let mut icons = Icons::default();
+    let hint = asset_server
+      .load::<Texture>(&UI_ICONS.parse().unwrap())
+      .and_then(|atlas| icons.insert_grid(atlas, ICON_SIZE, &ICON_NAMES))
+      .and_then(|_| RichText::parse(HINT).map_err(|e| e.to_string()))
+      .map(|text| {
+        let typewriter = Typewriter::new(&text, HINT_SPEED);
+        (text, typewriter)
+      })
+      .map_err(|msg| errors.push(format!("cannot create hint: {}", msg)))
+      .ok();
+
+    let lights = LightRenderer::new(
+      ctx.clone(),
+      asset_server,
+      &LIGHT_SHADER.parse().unwrap(),
+      &SHADOW_SHADER.parse().unwrap(),
+    )
+    .map_err(|msg| errors.push(format!("cannot create light renderer: {}", msg)))
+    .ok();
+
+    let particles =
+      ParticleRenderer::new(ctx.clone(), asset_server, &PARTICLE_SHADER.parse().unwrap())
+        .map_err(|msg| errors.push(format!("cannot create particle renderer: {}", msg)))
+        .ok();
+
+    let render_graph = RenderGraph::new(ctx, asset_server, &PIPELINE.parse().unwrap(), size)
+      .map_err(|msg| errors.push(format!("cannot create render graph: {}", msg)))
+      .ok();
+
+    let renderers = Self {
+      tilemap,
+      sprites,
+      particles,
+      lights,
+      ui,
+      ui_font,
+      icons,
+      hint,
+      ui_time: 0.0,
+      render_graph,
+      sprite_stats: BatchStats::default(),
+    };
+    (renderers, errors)
+  }
+
+  /// Steps the animated tiles of `map` and the ui on by `elapsed_ms`.
+  fn update(&mut self, map: Option<&Map>, elapsed_ms: u32) {
+    if let (Some(tilemap), Some(map)) = (&mut self.tilemap, map) {
+      tilemap.update(map, elapsed_ms);
+    }
+
+    let dt = elapsed_ms as f32 / 1000.0;
+    self.ui_time += dt;
+    if let Some((_, typewriter)) = &mut self.hint {
+      typewriter.update(dt);
+    }
+  }
+
+  /// Draws `world` as `camera` sees it through the pipeline, or straight to `frame`
+  /// without any effects when there is no pipeline. The pipeline follows the size of
+  /// `frame`.
+  fn draw(
+    &mut self,
+    frame: &mut glium::Frame,
+    world: &World,
+    map: Option<&Map>,
+    camera: &Camera2D,
+    fps: f32,
+  ) -> Result<(), String> {
+    if let Some(sprites) = &mut self.sprites {
+      push_renderables(sprites, world, map);
+    }
+
+    // fetched every frame so a reloaded font shows up
+    let font = self.ui_font.as_ref().and_then(|font| font.get());
+    let mut scene = Scene {
+      world,
+      map,
+      tilemap: self.tilemap.as_mut(),
+      sprites: self.sprites.as_mut(),
+      particles: self.particles.as_mut(),
+      lights: self.lights.as_mut(),
+      camera,
+      ui: self.ui.as_mut(),
+      font: font.as_deref(),
+      icons: &self.icons,
+      hint: self
+        .hint
+        .as_ref()
+        .filter(|(_, typewriter)| !typewriter.is_finished())
+        .map(|(text, typewriter)| (text, typewriter.visible())),
+      time: self.ui_time,
+      fps,
+    };
+
+    let drawn = match &mut self.render_graph {
+      Some(render_graph) => {
+        render_graph.resize(frame.get_dimensions());
+        render_graph.render(frame, &mut scene)
+      }
+      // without a pipeline the world still gets drawn, just without any effects
+      None => {
+        frame.clear_color(0.0, 0.0, 0.0, 1.0);
+        scene
+          .draw(WORLD_PASS, frame, &PassInputs::default())
+          .and_then(|_| scene.draw(UI_PASS, frame, &PassInputs::default()))
+      }
+    };
+    self.sprite_stats = self
+      .sprites
+      .as_ref()
+      .map(|sprites| sprites.stats())
+      .unwrap_or_default();
+    for batch in [&mut self.sprites, &mut self.ui].into_iter().flatten() {
+      batch.clear();
+    }
+    drawn
+  }
+}
+
+/// Pushes a sprite for every renderable entity whose prototype has a texture, over the
+/// entities of the map layers below its own.
+fn push_renderables(sprites: &mut SpriteBatch, world: &World, map: Option<&Map>) {
+  for (entity, renderable) in world.query::<Renderable>() {
+    let (transform, prototype) = match (world.get::<Transform>(entity), renderable.prototype.get())
+    {
+      (Some(transform), Some(prototype)) => (transform, prototype),
+      _ => continue,
+    };
+    let texture = match &prototype.texture {
+      Some(texture) => texture.clone(),
+      None => continue,
+    };
+
+    let layer = map
+      .and_then(|map| {
+        map
+          .layers
+          .iter()
+          .position(|layer| layer.name() == renderable.layer)
+      })
+      .unwrap_or_default();
+    sprites.push(
+      Sprite::new(texture, transform.clone())
+        .with_layer(layer as i32)
+        .with_render_state(renderable.render_state),
+    );
+  }
+}
+
+fn register_loaders(asset_server: &mut AssetServer, ctx: &Rc<glium::backend::Context>) {
+  asset_server.register(ShaderLoader::new(ctx.clone()));
+  asset_server.register(ModelLoader::new(ctx.clone()));
+  asset_server.register(TextureLoader::new(ctx.clone()));
+  asset_server.register(PrototypeLoader::new());
+  asset_server.register(MapLoader::new());
+  asset_server.register(PipelineLoader::new());
+  asset_server.register(FontLoader::new(ctx.clone()));
+  asset_server.register(ParticleEffectLoader::new());
+}
+
+/// A camera showing `size` pixels of the world, kept inside `map`.
+fn create_camera(size: (u32, u32), map: Option<&Map>) -> Camera2D {
+  let mut camera = Camera2D::new(glm::vec2(size.0 as f32, size.1 as f32));
+  camera.dead_zone = glm::vec2(32.0, 24.0);
+  camera.smoothing = 8.0;
+  camera.bounds = map.map(|map| {
+    let bounds = map.bounds();
+    geo::Rect::new((0.0, 0.0), (bounds.x, bounds.y))
+  });
+  camera
+}
+
+/// Moves the camera after its target, shaking it and playing `impact_effect` where the
+/// target bumps into something, and steps particles `dt` seconds, once physics has moved
+/// everything.
+fn update_effects(
+  world: &mut World,
+  camera: &mut Camera2D,
+  impact_effect: Option<&Handle<ParticleEffect>>,
+  dt: f32,
+) {
+  let target = world
+    .query::<Name>()
+    .find(|(_, name)| name.0 == CAMERA_TARGET)
+    .map(|(entity, _)| entity);
+  if let Some(transform) = target.and_then(|target| world.get::<Transform>(target)) {
+    camera.follow(&transform.position, dt);
+  }
+
+  // moving bodies kick up more of their particles, standing ones none
+  let speeds: Vec<_> = world
+    .query::<RigidBody>()
+    .map(|(entity, body)| (entity, glm::length(&body.velocity)))
+    .collect();
+  for (entity, speed) in speeds {
+    if let Some(emitter) = world.get_mut::<ParticleEmitter>(entity) {
+      emitter.rate_scale = (speed / FULL_EMISSION_SPEED).min(1.0);
+    }
+  }
+
+  // and a puff of them whenever they bump into something solid
+  let impacts: Vec<_> = world
+    .query::<CollisionEvents>()
+    .filter_map(|(entity, events)| {
+      events
+        .0
+        .iter()
+        .find(|event| event.phase == CollisionPhase::Enter && !event.sensor)
+        .map(|event| (entity, event.contact))
+    })
+    .collect();
+  for (entity, contact) in impacts {
+    if let Some(emitter) = world.get_mut::<ParticleEmitter>(entity) {
+      emitter.burst(IMPACT_PARTICLES);
+    }
+    if Some(entity) != target {
+      continue;
+    }
+    camera.add_trauma(IMPACT_TRAUMA);
+    if let (Some(effect), Some(contact)) = (impact_effect, contact) {
+      let transform = Transform {
+        position: contact.point,
+        ..Transform::default()
+      };
+      gfx::spawn_effect(world, effect.clone(), transform);
+    }
+  }
+  camera.update(dt);
+  gfx::update_emitters(world, dt);
+}
 
 fn main() {
   let logs = util::read_log_dir();
@@ -29,21 +557,84 @@ fn main() {
 
   let settings = Settings::load(settings_file).unwrap();
 
-  let window_settings = WindowSettings::new(&settings);
-
-  let (window, draw_interface) = Window::new(window_settings);
-
   let behavior = glium::debug::DebugCallbackBehavior::Custom {
     callback: Box::new(util::gl_error_handler),
     synchronous: true,
   };
 
-  let gl_context = unsafe { glium::backend::Context::new(draw_interface, true, behavior).unwrap() };
+  // without a window nothing asks the game to stop, it runs until it is killed
+  let (window, gl_context) = match settings.display.headless {
+    Some(api) => {
+      info!("rendering headless with {}", api);
+      let backend =
+        match HeadlessBackend::new(api, (settings.display.width, settings.display.height)) {
+          Ok(backend) => backend,
+          Err(msg) => {
+            error!("cannot render headless: {}", msg);
+            return;
+          }
+        };
+      let gl_context = unsafe { glium::backend::Context::new(backend, true, behavior).unwrap() };
+      (None, gl_context)
+    }
+    None => {
+      let window_settings = WindowSettings::new(&settings);
+      let (window, draw_interface) = Window::new(window_settings);
+      let gl_context =
+        unsafe { glium::backend::Context::new(draw_interface, true, behavior).unwrap() };
+      (Some(window), gl_context)
+    }
+  };
+
+  let mut asset_server = AssetServer::new();
+  register_loaders(&mut asset_server, &gl_context);
 
-  let mut shaders = ShaderSources::new();
-  shaders.load_all();
+  asset_server.load_all::<Shader>();
+  asset_server.load_all::<Prototype>();
 
-  let shader_repository = shaders.load_repository(gl_context.clone());
+  for reference in assets::validate_references() {
+    warn!("dangling reference {}", reference);
+  }
+
+  let mut world = World::new();
+
+  if let Err(msg) = world.enter_map(&mut asset_server, &STARTING_MAP.parse().unwrap()) {
+    error!("cannot enter {}: {}", STARTING_MAP, msg);
+  }
+
+  // the framebuffer is larger than the window asked for on scaled displays
+  let size = gl_context.get_framebuffer_dimensions();
+
+  // the map is fetched whenever it is needed, holding on to it would make every edit
+  // to it land in a copy
+  let map = world.map().and_then(|map| map.get());
+  let (mut renderers, errors) =
+    Renderers::new(gl_context.clone(), &mut asset_server, map.as_deref(), size);
+  for msg in errors {
+    error!("{}", msg);
+  }
+  let mut camera = create_camera(size, map.as_deref());
+  drop(map);
+
+  let mut overlay = DebugOverlay::new(
+    gl_context.clone(),
+    &mut asset_server,
+    &IMGUI_SHADER.parse().unwrap(),
+  )
+  .map_err(|msg| error!("cannot create debug overlay: {}", msg))
+  .ok();
+
+  let mut inspector = Inspector::new();
+
+  let impact_effect = asset_server
+    .load::<ParticleEffect>(&IMPACT_EFFECT.parse().unwrap())
+    .map_err(|msg| error!("cannot load impact effect: {}", msg))
+    .ok();
+
+  let mut collisions = CollisionSystem::new(COLLISION_CELL_SIZE);
+  // the world is seen from above, so nothing falls
+  let mut physics = PhysicsWorld::new(glm::vec2(0.0, 0.0));
+  let mut timestep = FixedTimestep::new(PHYSICS_RATE, MAX_PHYSICS_STEPS);
 
   let mut input_devices = InputDevices::default();
 
@@ -51,38 +642,144 @@ fn main() {
 
   info!("target fps = {}", fps_manager.target());
 
-  window.show();
+  if let Some(window) = &window {
+    window.show();
+  }
 
-  let mut i: f32 = 0.0;
+  let mut last_frame = Instant::now();
   'main: loop {
     // frame setup
     fps_manager.begin();
 
-    window.poll_events(&mut input_devices);
+    if let Some(window) = &window {
+      window.poll_events(&mut input_devices, overlay.as_mut());
+    }
 
     // pre prossess game logic
 
     if input_devices.check(Key::Esc) == KeyAction::Press {
       break 'main;
     }
+    if input_devices.check(Key::F1) == KeyAction::Press {
+      if let Some(overlay) = &mut overlay {
+        overlay.visible = !overlay.visible;
+      }
+    }
 
     // game logic
-    i += 0.1;
+
+    let now = Instant::now();
+    let elapsed_ms = now.duration_since(last_frame).as_millis() as u32;
+    last_frame = now;
+
+    renderers.update(world.map().and_then(|map| map.get()).as_deref(), elapsed_ms);
 
     // post process game logic
 
+    for _ in 0..timestep.advance() {
+      physics.step(&mut world, &mut collisions, timestep.delta());
+    }
+
+    let size = gl_context.get_framebuffer_dimensions();
+    camera.viewport = glm::vec2(size.0 as f32, size.1 as f32);
+    update_effects(
+      &mut world,
+      &mut camera,
+      impact_effect.as_ref(),
+      elapsed_ms as f32 / 1000.0,
+    );
+
     input_devices.new_frame();
 
     // render logic
 
-    let mut frame = glium::Frame::new(
-      gl_context.clone(),
-      (settings.display.width, settings.display.height),
-    );
+    let mut frame = glium::Frame::new(gl_context.clone(), size);
 
     // draw
 
-    frame.clear_color(i.sin(), 0.30, 1.0 - i.sin(), 1.0);
+    let drawn = renderers.draw(
+      &mut frame,
+      &world,
+      world.map().and_then(|map| map.get()).as_deref(),
+      &camera,
+      fps_manager.fps(),
+    );
+    if let Err(msg) = drawn {
+      error!("cannot draw frame: {}", msg);
+    }
+
+    if let Some(overlay) = &mut overlay {
+      let fps = fps_manager.fps();
+      let sprite_stats = renderers.sprite_stats;
+      let particle_count: usize = world
+        .query::<ParticleEmitter>()
+        .map(|(_, emitter)| emitter.particle_count())
+        .sum();
+      let map = world.map().cloned();
+      let drawn = overlay.draw(&mut frame, elapsed_ms as f32 / 1000.0, |ui| {
+        imgui::Window::new("Stats")
+          .position([8.0, 32.0], imgui::Condition::FirstUseEver)
+          .always_auto_resize(true)
+          .build(ui, || {
+            ui.text(format!("{:.0} fps", fps));
+            ui.text(format!("{} particles", particle_count));
+            ui.text(format!(
+              "{} sprites in {} draw calls",
+              sprite_stats.sprites, sprite_stats.draw_calls
+            ));
+
+            let mut zoom = camera.zoom();
+            if imgui::Slider::new("zoom", 0.25, 4.0).build(ui, &mut zoom) {
+              if let Err(msg) = camera.set_zoom(zoom) {
+                error!("cannot zoom the camera: {}", msg);
+              }
+            }
+          });
+        imgui::Window::new("Assets")
+          .position([8.0, 128.0], imgui::Condition::FirstUseEver)
+          .always_auto_resize(true)
+          .build(ui, || {
+            if ui.button("reload shaders") {
+              asset_server.reload_all::<Shader>();
+            }
+            if ui.button("unload unused") {
+              info!("unloaded {} assets", asset_server.unload_unused());
+            }
+
+            let pipeline = AssetKey {
+              kind: AssetKind::Pipeline,
+              id: PIPELINE.parse().unwrap(),
+            };
+            ui.text(format!("{} uses", pipeline.id));
+            for dependency in asset_server.dependencies(&pipeline) {
+              ui.bullet_text(dependency.to_string());
+            }
+          });
+        if let Some(render_graph) = &mut renderers.render_graph {
+          imgui::Window::new("Effects")
+            .position([8.0, 256.0], imgui::Condition::FirstUseEver)
+            .always_auto_resize(true)
+            .build(ui, || {
+              for pass in render_graph.effects() {
+                let mut enabled = render_graph.is_enabled(&pass);
+                if ui.checkbox(&pass, &mut enabled) {
+                  render_graph.set_enabled(&pass, enabled);
+                }
+              }
+            });
+        }
+        inspector.build(ui, &mut world, &collisions, &camera);
+        if let (Some(map), Some(tilemap)) = (&map, &mut renderers.tilemap) {
+          inspector.build_tiles(ui, map, tilemap, &camera);
+        }
+        if let Some(map) = map.as_ref().and_then(|map| map.get()) {
+          inspector.build_paths(ui, &world, &map, &camera);
+        }
+      });
+      if let Err(msg) = drawn {
+        error!("cannot draw debug overlay: {}", msg);
+      }
+    }
 
     // finalize
 
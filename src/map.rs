@@ -0,0 +1,397 @@
+pub mod autotile;
+pub mod generator;
+mod native;
+mod properties;
+mod tiled;
+
+use crate::assets::AssetId;
+use crate::gfx::RenderState;
+use crate::math::glm;
+use autotile::Terrain;
+use geo::Polygon;
+use glm::Vec2;
+pub use native::MapLoader;
+pub use properties::{Properties, PropertyValue};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A global tile id, `0` means there is no tile.
+pub type Gid = u32;
+
+pub const EMPTY_TILE: Gid = 0;
+
+/// The number of tiles in a grid `width` by `height`, when every one of them can be
+/// indexed.
+pub fn tile_area(width: u32, height: u32) -> Result<usize, String> {
+  width
+    .checked_mul(height)
+    .map(|area| area as usize)
+    .ok_or_else(|| format!("{}x{} tiles are too many", width, height))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileFrame {
+  pub tile: u32,
+  pub duration_ms: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TileInfo {
+  pub properties: Properties,
+  pub animation: Vec<TileFrame>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tileset {
+  pub name: String,
+  pub texture: AssetId,
+  pub first_gid: Gid,
+  pub tile_width: u32,
+  pub tile_height: u32,
+  pub columns: u32,
+  pub tile_count: u32,
+  pub tiles: BTreeMap<u32, TileInfo>,
+  pub terrains: Vec<Terrain>,
+  pub properties: Properties,
+}
+
+impl Tileset {
+  pub fn contains(&self, gid: Gid) -> bool {
+    gid >= self.first_gid && gid - self.first_gid < self.tile_count
+  }
+
+  pub fn local_id(&self, gid: Gid) -> Option<u32> {
+    self.contains(gid).then(|| gid - self.first_gid)
+  }
+
+  pub fn tile(&self, gid: Gid) -> Option<&TileInfo> {
+    self.local_id(gid).and_then(|id| self.tiles.get(&id))
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileLayer {
+  pub name: String,
+  pub width: u32,
+  pub height: u32,
+  pub visible: bool,
+  pub tiles: Vec<Gid>,
+  pub properties: Properties,
+}
+
+impl TileLayer {
+  /// Fails when the layer has more tiles than can be indexed.
+  pub fn new(name: &str, width: u32, height: u32) -> Result<Self, String> {
+    Ok(Self {
+      name: name.to_string(),
+      width,
+      height,
+      visible: true,
+      tiles: vec![EMPTY_TILE; tile_area(width, height)?],
+      properties: Properties::default(),
+    })
+  }
+
+  pub fn in_bounds(&self, x: i32, y: i32) -> bool {
+    x >= 0 && y >= 0 && (x as u32) < self.width && (y as u32) < self.height
+  }
+
+  pub fn get(&self, x: i32, y: i32) -> Option<Gid> {
+    self
+      .in_bounds(x, y)
+      .then(|| self.tiles[(y as u32 * self.width + x as u32) as usize])
+  }
+
+  pub fn set(&mut self, x: i32, y: i32, gid: Gid) -> bool {
+    if self.in_bounds(x, y) {
+      self.tiles[(y as u32 * self.width + x as u32) as usize] = gid;
+      true
+    } else {
+      false
+    }
+  }
+}
+
+/// The parts of a prototype a placed object may replace.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PrototypeOverrides {
+  pub animation: Option<AssetId>,
+  pub script: Option<AssetId>,
+  pub render_state: Option<RenderState>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapObject {
+  pub name: Option<String>,
+  pub prototype: AssetId,
+  pub position: Vec2,
+  pub rotation: f32,
+  pub scale: Vec2,
+  pub overrides: PrototypeOverrides,
+  pub properties: Properties,
+}
+
+/// The class of areas agents can walk in.
+pub const WALKABLE_AREA: &str = "walkable";
+/// The class of areas agents have to go around.
+pub const OBSTACLE_AREA: &str = "obstacle";
+
+/// A region drawn on an object layer rather than something placed in the world.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapArea {
+  pub name: Option<String>,
+  pub class: String,
+  pub polygon: Polygon<f32>,
+  pub properties: Properties,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectLayer {
+  pub name: String,
+  pub visible: bool,
+  pub objects: Vec<MapObject>,
+  pub areas: Vec<MapArea>,
+  pub properties: Properties,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Layer {
+  Tiles(TileLayer),
+  Objects(ObjectLayer),
+}
+
+impl Layer {
+  pub fn name(&self) -> &str {
+    match self {
+      Layer::Tiles(layer) => &layer.name,
+      Layer::Objects(layer) => &layer.name,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SpawnPoint {
+  pub name: String,
+  pub position: Vec2,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Map {
+  pub width: u32,
+  pub height: u32,
+  pub tile_width: u32,
+  pub tile_height: u32,
+  pub tilesets: Vec<Tileset>,
+  pub layers: Vec<Layer>,
+  pub spawn_points: Vec<SpawnPoint>,
+  pub properties: Properties,
+}
+
+impl Map {
+  pub fn new(width: u32, height: u32, tile_width: u32, tile_height: u32) -> Self {
+    Self {
+      width,
+      height,
+      tile_width,
+      tile_height,
+      tilesets: Vec::default(),
+      layers: Vec::default(),
+      spawn_points: Vec::default(),
+      properties: Properties::default(),
+    }
+  }
+
+  pub fn tileset_for(&self, gid: Gid) -> Option<&Tileset> {
+    self.tilesets.iter().find(|tileset| tileset.contains(gid))
+  }
+
+  pub fn tile_layers(&self) -> impl Iterator<Item = &TileLayer> {
+    self.layers.iter().filter_map(|layer| match layer {
+      Layer::Tiles(layer) => Some(layer),
+      Layer::Objects(_) => None,
+    })
+  }
+
+  pub fn object_layers(&self) -> impl Iterator<Item = &ObjectLayer> {
+    self.layers.iter().filter_map(|layer| match layer {
+      Layer::Objects(layer) => Some(layer),
+      Layer::Tiles(_) => None,
+    })
+  }
+
+  /// Every area of a class on any object layer.
+  pub fn areas<'a>(&'a self, class: &'a str) -> impl Iterator<Item = &'a MapArea> + 'a {
+    self
+      .object_layers()
+      .flat_map(|layer| &layer.areas)
+      .filter(move |area| area.class == class)
+  }
+
+  pub fn tile_layer(&self, name: &str) -> Option<&TileLayer> {
+    self.tile_layers().find(|layer| layer.name == name)
+  }
+
+  /// The size of the map in world units.
+  pub fn bounds(&self) -> Vec2 {
+    glm::vec2(
+      self.width as f32 * self.tile_width as f32,
+      self.height as f32 * self.tile_height as f32,
+    )
+  }
+
+  /// Checks the invariants every map source must uphold, whichever format it came from.
+  pub fn validate(&self) -> Result<(), String> {
+    if self.width == 0 || self.height == 0 {
+      return Err(String::from("map dimensions must be positive"));
+    }
+
+    if self.tile_width == 0 || self.tile_height == 0 {
+      return Err(String::from("tile dimensions must be positive"));
+    }
+
+    tile_area(self.width, self.height)?;
+    if self.width.checked_mul(self.tile_width).is_none()
+      || self.height.checked_mul(self.tile_height).is_none()
+    {
+      return Err(String::from("map is too large in world units"));
+    }
+
+    let mut ranges: Vec<(Gid, Gid, &str)> = Vec::new();
+    for tileset in &self.tilesets {
+      if tileset.first_gid == EMPTY_TILE {
+        return Err(format!("tileset '{}' cannot start at gid 0", tileset.name));
+      }
+
+      if tileset.columns == 0 || tileset.tile_count == 0 {
+        return Err(format!("tileset '{}' has no tiles", tileset.name));
+      }
+
+      for frame in tileset.tiles.values().flat_map(|tile| &tile.animation) {
+        if frame.tile >= tileset.tile_count {
+          return Err(format!(
+            "tileset '{}' animates missing tile {}",
+            tileset.name, frame.tile
+          ));
+        }
+      }
+
+      for terrain in &tileset.terrains {
+        terrain
+          .validate(tileset)
+          .map_err(|e| format!("tileset '{}': {}", tileset.name, e))?;
+      }
+
+      let end = tileset
+        .first_gid
+        .checked_add(tileset.tile_count)
+        .ok_or_else(|| format!("tileset '{}' runs past the last gid", tileset.name))?;
+      if let Some((_, _, other)) = ranges
+        .iter()
+        .find(|(first, last, _)| tileset.first_gid < *last && *first < end)
+      {
+        return Err(format!(
+          "tilesets '{}' and '{}' overlap",
+          tileset.name, other
+        ));
+      }
+      ranges.push((tileset.first_gid, end, &tileset.name));
+    }
+
+    let mut names = BTreeSet::new();
+    for layer in &self.layers {
+      if !names.insert(layer.name()) {
+        return Err(format!("duplicate layer name '{}'", layer.name()));
+      }
+
+      if let Layer::Tiles(layer) = layer {
+        if layer.width != self.width || layer.height != self.height {
+          return Err(format!(
+            "layer '{}' is {}x{} but the map is {}x{}",
+            layer.name, layer.width, layer.height, self.width, self.height
+          ));
+        }
+
+        let area = tile_area(layer.width, layer.height)
+          .map_err(|e| format!("layer '{}': {}", layer.name, e))?;
+        if layer.tiles.len() != area {
+          return Err(format!(
+            "layer '{}' has {} tiles, expected {}",
+            layer.name,
+            layer.tiles.len(),
+            area
+          ));
+        }
+
+        if let Some(gid) = layer
+          .tiles
+          .iter()
+          .find(|gid| **gid != EMPTY_TILE && self.tileset_for(**gid).is_none())
+        {
+          return Err(format!(
+            "layer '{}' uses gid {} which no tileset contains",
+            layer.name, gid
+          ));
+        }
+      }
+
+      if let Layer::Objects(layer) = layer {
+        // rings are closed, so a triangle has four points
+        if let Some(area) = layer
+          .areas
+          .iter()
+          .find(|area| area.polygon.exterior().0.len() < 4)
+        {
+          return Err(format!(
+            "layer '{}' has an area '{}' with fewer than three corners",
+            layer.name,
+            area.name.as_deref().unwrap_or(&area.class)
+          ));
+        }
+      }
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn tileset(first_gid: Gid, tile_count: u32) -> Tileset {
+    Tileset {
+      name: String::from("tiles"),
+      texture: "exp.test.tiles".parse().unwrap(),
+      first_gid,
+      tile_width: 16,
+      tile_height: 16,
+      columns: 4,
+      tile_count,
+      tiles: BTreeMap::default(),
+      terrains: Vec::default(),
+      properties: Properties::default(),
+    }
+  }
+
+  #[test]
+  fn layers_too_large_to_index_are_refused() {
+    assert!(TileLayer::new("ground", u32::MAX, 2).is_err());
+    assert_eq!(TileLayer::new("ground", 3, 2).unwrap().tiles.len(), 6);
+  }
+
+  #[test]
+  fn maps_too_large_are_invalid() {
+    assert!(Map::new(u32::MAX, 2, 16, 16).validate().is_err());
+    assert!(Map::new(1 << 30, 1, 16, 16).validate().is_err());
+    assert!(Map::new(8, 6, 16, 16).validate().is_ok());
+  }
+
+  #[test]
+  fn tilesets_past_the_last_gid_are_invalid() {
+    let mut map = Map::new(8, 6, 16, 16);
+    map.tilesets.push(tileset(u32::MAX - 4, 16));
+    assert!(map.validate().is_err());
+
+    map.tilesets[0] = tileset(1, 16);
+    assert!(map.validate().is_ok());
+  }
+}
@@ -0,0 +1,462 @@
+use super::{Gid, Layer, Map, TileLayer, Tileset, EMPTY_TILE};
+use std::collections::BTreeMap;
+
+/// Neighbor bits of a 4-bit mask, which only looks at the edges.
+pub mod edges {
+  pub const NORTH: u8 = 1;
+  pub const EAST: u8 = 2;
+  pub const SOUTH: u8 = 4;
+  pub const WEST: u8 = 8;
+}
+
+/// Neighbor bits of an 8-bit blob mask.
+pub mod blob {
+  pub const NORTH_WEST: u8 = 1;
+  pub const NORTH: u8 = 2;
+  pub const NORTH_EAST: u8 = 4;
+  pub const WEST: u8 = 8;
+  pub const EAST: u8 = 16;
+  pub const SOUTH_WEST: u8 = 32;
+  pub const SOUTH: u8 = 64;
+  pub const SOUTH_EAST: u8 = 128;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AutotileMode {
+  /// 16 tiles chosen by the four edge neighbors.
+  Edges,
+  /// 47 tiles chosen by all eight neighbors, corners only count between two connected edges.
+  Blob,
+}
+
+impl AutotileMode {
+  /// Every mask a terrain in this mode can be asked for, lowest first.
+  pub fn masks(self) -> impl Iterator<Item = u8> {
+    (0..=u8::MAX).filter(move |mask| match self {
+      AutotileMode::Edges => *mask < 16,
+      AutotileMode::Blob => prune_corners(*mask) == *mask,
+    })
+  }
+}
+
+impl TryFrom<&str> for AutotileMode {
+  type Error = String;
+
+  fn try_from(value: &str) -> Result<Self, Self::Error> {
+    match value {
+      "4bit" => Ok(AutotileMode::Edges),
+      "8bit" => Ok(AutotileMode::Blob),
+      invalid => Err(format!("invalid autotile mode '{}'", invalid)),
+    }
+  }
+}
+
+/// A set of tiles in one tileset that are swapped for each other depending on
+/// which neighbors belong to the same terrain.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Terrain {
+  pub name: String,
+  pub mode: AutotileMode,
+  /// The local tile id to use for each neighbor mask.
+  pub tiles: BTreeMap<u8, u32>,
+  /// The tile for masks without an entry in `tiles`.
+  pub default: Option<u32>,
+}
+
+impl Terrain {
+  pub fn contains(&self, local_id: u32) -> bool {
+    self.default == Some(local_id) || self.tiles.values().any(|tile| *tile == local_id)
+  }
+
+  pub fn tile_for(&self, mask: u8) -> Option<u32> {
+    self.tiles.get(&mask).copied().or(self.default)
+  }
+
+  pub fn validate(&self, tileset: &Tileset) -> Result<(), String> {
+    for (mask, tile) in &self.tiles {
+      let valid = match self.mode {
+        AutotileMode::Edges => *mask < 16,
+        AutotileMode::Blob => prune_corners(*mask) == *mask,
+      };
+
+      if !valid {
+        return Err(format!(
+          "terrain '{}' has an unreachable mask {}",
+          self.name, mask
+        ));
+      }
+
+      if *tile >= tileset.tile_count {
+        return Err(format!(
+          "terrain '{}' uses missing tile {}",
+          self.name, tile
+        ));
+      }
+    }
+
+    match self.default {
+      Some(tile) if tile >= tileset.tile_count => Err(format!(
+        "terrain '{}' uses missing tile {}",
+        self.name, tile
+      )),
+      _ => Ok(()),
+    }
+  }
+}
+
+/// Clears the corner bits of a blob mask that are not between two connected edges.
+pub fn prune_corners(mask: u8) -> u8 {
+  let corners = [
+    (blob::NORTH_WEST, blob::NORTH | blob::WEST),
+    (blob::NORTH_EAST, blob::NORTH | blob::EAST),
+    (blob::SOUTH_WEST, blob::SOUTH | blob::WEST),
+    (blob::SOUTH_EAST, blob::SOUTH | blob::EAST),
+  ];
+
+  corners
+    .iter()
+    .filter(|(_, edges)| mask & edges != *edges)
+    .fold(mask, |mask, (corner, _)| mask & !corner)
+}
+
+pub fn edge_mask(connected: impl Fn(i32, i32) -> bool) -> u8 {
+  [
+    (0, -1, edges::NORTH),
+    (1, 0, edges::EAST),
+    (0, 1, edges::SOUTH),
+    (-1, 0, edges::WEST),
+  ]
+  .iter()
+  .filter(|(dx, dy, _)| connected(*dx, *dy))
+  .fold(0, |mask, (_, _, bit)| mask | bit)
+}
+
+pub fn blob_mask(connected: impl Fn(i32, i32) -> bool) -> u8 {
+  let mask = [
+    (-1, -1, blob::NORTH_WEST),
+    (0, -1, blob::NORTH),
+    (1, -1, blob::NORTH_EAST),
+    (-1, 0, blob::WEST),
+    (1, 0, blob::EAST),
+    (-1, 1, blob::SOUTH_WEST),
+    (0, 1, blob::SOUTH),
+    (1, 1, blob::SOUTH_EAST),
+  ]
+  .iter()
+  .filter(|(dx, dy, _)| connected(*dx, *dy))
+  .fold(0, |mask, (_, _, bit)| mask | bit);
+
+  prune_corners(mask)
+}
+
+fn terrain_of(tilesets: &[Tileset], gid: Gid) -> Option<(&Tileset, &Terrain)> {
+  let tileset = tilesets.iter().find(|tileset| tileset.contains(gid))?;
+  let local_id = gid - tileset.first_gid;
+  tileset
+    .terrains
+    .iter()
+    .find(|terrain| terrain.contains(local_id))
+    .map(|terrain| (tileset, terrain))
+}
+
+/// The tile a cell should show given its neighbors, or `None` if it is not part of a terrain.
+/// Cells past the edge of the layer count as connected, so terrain runs off the map cleanly.
+fn resolve(tilesets: &[Tileset], layer: &TileLayer, x: i32, y: i32) -> Option<Gid> {
+  let gid = layer.get(x, y).filter(|gid| *gid != EMPTY_TILE)?;
+  let (tileset, terrain) = terrain_of(tilesets, gid)?;
+
+  let connected = |dx: i32, dy: i32| match layer.get(x + dx, y + dy) {
+    Some(neighbor) => terrain_of(tilesets, neighbor)
+      .is_some_and(|(other_tileset, other)| other_tileset.name == tileset.name && other == terrain),
+    None => true,
+  };
+
+  let mask = match terrain.mode {
+    AutotileMode::Edges => edge_mask(connected),
+    AutotileMode::Blob => blob_mask(connected),
+  };
+
+  terrain
+    .tile_for(mask)
+    .map(|local_id| tileset.first_gid + local_id)
+}
+
+fn resolve_cells(
+  tilesets: &[Tileset],
+  layer: &mut TileLayer,
+  cells: impl Iterator<Item = (i32, i32)>,
+) -> Vec<(i32, i32)> {
+  let updates: Vec<(i32, i32, Gid)> = cells
+    .filter_map(|(x, y)| resolve(tilesets, layer, x, y).map(|gid| (x, y, gid)))
+    .filter(|(x, y, gid)| layer.get(*x, *y) != Some(*gid))
+    .collect();
+
+  updates
+    .into_iter()
+    .map(|(x, y, gid)| {
+      layer.set(x, y, gid);
+      (x, y)
+    })
+    .collect()
+}
+
+/// Picks the right tile for every terrain cell of every tile layer.
+pub fn apply(map: &mut Map) {
+  let Map {
+    tilesets, layers, ..
+  } = map;
+
+  if tilesets.iter().all(|tileset| tileset.terrains.is_empty()) {
+    return;
+  }
+
+  for layer in layers.iter_mut().filter_map(|layer| match layer {
+    Layer::Tiles(layer) => Some(layer),
+    Layer::Objects(_) => None,
+  }) {
+    let (width, height) = (layer.width as i32, layer.height as i32);
+    let cells = (0..height).flat_map(|y| (0..width).map(move |x| (x, y)));
+    resolve_cells(tilesets, layer, cells);
+  }
+}
+
+/// Sets a tile and re-evaluates it and its neighbors, returning every cell that changed.
+pub fn set_tile(map: &mut Map, layer: &str, x: i32, y: i32, gid: Gid) -> Vec<(i32, i32)> {
+  let Map {
+    tilesets, layers, ..
+  } = map;
+
+  let layer = match layers.iter_mut().find_map(|l| match l {
+    Layer::Tiles(l) if l.name == layer => Some(l),
+    _ => None,
+  }) {
+    Some(layer) => layer,
+    None => return Vec::default(),
+  };
+
+  if layer.get(x, y).is_none() {
+    return Vec::default();
+  }
+
+  let mut changed = Vec::new();
+  if layer.get(x, y) != Some(gid) {
+    layer.set(x, y, gid);
+    changed.push((x, y));
+  }
+
+  let neighborhood = (-1..=1).flat_map(|dy| (-1..=1).map(move |dx| (x + dx, y + dy)));
+  for cell in resolve_cells(tilesets, layer, neighborhood) {
+    if !changed.contains(&cell) {
+      changed.push(cell);
+    }
+  }
+
+  changed
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::assets::Handle;
+  use crate::map::Properties;
+  use std::rc::Rc;
+
+  const WALL: Gid = 1;
+  const FLOOR: Gid = 100;
+
+  /// A tileset whose 4-bit wall tiles sit at the index of their mask.
+  fn edge_tileset() -> Tileset {
+    Tileset {
+      name: String::from("walls"),
+      texture: "exp.test.random.wall".parse().unwrap(),
+      first_gid: 1,
+      tile_width: 16,
+      tile_height: 16,
+      columns: 4,
+      tile_count: 16,
+      tiles: BTreeMap::default(),
+      terrains: vec![Terrain {
+        name: String::from("wall"),
+        mode: AutotileMode::Edges,
+        tiles: (0..16).map(|mask| (mask, mask as u32)).collect(),
+        default: None,
+      }],
+      properties: Properties::default(),
+    }
+  }
+
+  fn floor_tileset() -> Tileset {
+    Tileset {
+      name: String::from("floor"),
+      texture: "exp.test.random.wall".parse().unwrap(),
+      first_gid: FLOOR,
+      tile_width: 16,
+      tile_height: 16,
+      columns: 1,
+      tile_count: 1,
+      tiles: BTreeMap::default(),
+      terrains: Vec::default(),
+      properties: Properties::default(),
+    }
+  }
+
+  /// Builds a map from rows where `#` is a wall and `.` is floor.
+  fn grid(tileset: Tileset, rows: &[&str]) -> Map {
+    let width = rows[0].len() as u32;
+    let height = rows.len() as u32;
+
+    let mut map = Map::new(width, height, 16, 16);
+    map.tilesets = vec![tileset, floor_tileset()];
+
+    let mut layer = TileLayer::new("ground", width, height).unwrap();
+    for (y, row) in rows.iter().enumerate() {
+      for (x, c) in row.chars().enumerate() {
+        layer.set(x as i32, y as i32, if c == '#' { WALL } else { FLOOR });
+      }
+    }
+    map.layers.push(Layer::Tiles(layer));
+
+    map
+  }
+
+  fn tile(map: &Map, x: i32, y: i32) -> Gid {
+    map.tile_layer("ground").unwrap().get(x, y).unwrap()
+  }
+
+  fn local(map: &Map, x: i32, y: i32) -> u32 {
+    tile(map, x, y) - 1
+  }
+
+  #[test]
+  fn isolated_tile_has_no_neighbors() {
+    let mut map = grid(edge_tileset(), &["...", ".#.", "..."]);
+    apply(&mut map);
+
+    assert_eq!(local(&map, 1, 1), 0);
+  }
+
+  #[test]
+  fn horizontal_run_connects_east_and_west() {
+    let mut map = grid(edge_tileset(), &[".....", ".###.", "....."]);
+    apply(&mut map);
+
+    assert_eq!(local(&map, 1, 1) as u8, edges::EAST);
+    assert_eq!(local(&map, 2, 1) as u8, edges::EAST | edges::WEST);
+    assert_eq!(local(&map, 3, 1) as u8, edges::WEST);
+  }
+
+  #[test]
+  fn edges_of_the_map_count_as_connected() {
+    let mut map = grid(edge_tileset(), &["#.", ".."]);
+    apply(&mut map);
+
+    assert_eq!(local(&map, 0, 0) as u8, edges::NORTH | edges::WEST);
+  }
+
+  #[test]
+  fn other_tiles_are_left_alone() {
+    let mut map = grid(edge_tileset(), &["...", ".#.", "..."]);
+    apply(&mut map);
+
+    assert_eq!(tile(&map, 0, 0), FLOOR);
+    assert_eq!(tile(&map, 2, 2), FLOOR);
+  }
+
+  #[test]
+  fn setting_a_tile_updates_its_neighbors() {
+    let mut map = grid(edge_tileset(), &[".....", ".#.#.", "....."]);
+    apply(&mut map);
+    assert_eq!(local(&map, 1, 1), 0);
+    assert_eq!(local(&map, 3, 1), 0);
+
+    let changed = set_tile(&mut map, "ground", 2, 1, WALL);
+
+    assert_eq!(local(&map, 1, 1) as u8, edges::EAST);
+    assert_eq!(local(&map, 2, 1) as u8, edges::EAST | edges::WEST);
+    assert_eq!(local(&map, 3, 1) as u8, edges::WEST);
+    assert_eq!(changed.len(), 3);
+
+    let changed = set_tile(&mut map, "ground", 2, 1, FLOOR);
+
+    assert_eq!(local(&map, 1, 1), 0);
+    assert_eq!(local(&map, 3, 1), 0);
+    assert_eq!(changed.len(), 3);
+  }
+
+  #[test]
+  fn setting_a_tile_through_the_handle_changes_the_stored_map() {
+    let mut map = grid(edge_tileset(), &[".....", ".#.#.", "....."]);
+    apply(&mut map);
+    let handle = Handle::detached("exp.test".parse().unwrap(), map);
+    let stored = Rc::as_ptr(&handle.get().unwrap());
+
+    let changed = handle
+      .modify(|map| set_tile(map, "ground", 2, 1, WALL))
+      .unwrap();
+    assert_eq!(changed.len(), 3);
+
+    // nothing held on to the map, so it was changed where it is stored
+    let map = handle.get().unwrap();
+    assert_eq!(Rc::as_ptr(&map), stored);
+    assert_eq!(local(&map, 1, 1) as u8, edges::EAST);
+    assert_eq!(local(&map, 2, 1) as u8, edges::EAST | edges::WEST);
+    assert_eq!(local(&map, 3, 1) as u8, edges::WEST);
+  }
+
+  #[test]
+  fn setting_outside_the_layer_changes_nothing() {
+    let mut map = grid(edge_tileset(), &["..", ".."]);
+
+    assert!(set_tile(&mut map, "ground", 5, 5, WALL).is_empty());
+    assert!(set_tile(&mut map, "missing", 0, 0, WALL).is_empty());
+  }
+
+  #[test]
+  fn blob_corners_need_both_edges() {
+    assert_eq!(prune_corners(blob::NORTH_WEST), 0);
+    assert_eq!(prune_corners(blob::NORTH_WEST | blob::NORTH), blob::NORTH);
+    assert_eq!(
+      prune_corners(blob::NORTH_WEST | blob::NORTH | blob::WEST),
+      blob::NORTH_WEST | blob::NORTH | blob::WEST
+    );
+    assert_eq!(
+      (0..=255)
+        .map(prune_corners)
+        .collect::<std::collections::BTreeSet<_>>()
+        .len(),
+      47
+    );
+  }
+
+  #[test]
+  fn blob_terrain_uses_default_for_unlisted_masks() {
+    let mut tileset = edge_tileset();
+    tileset.terrains = vec![Terrain {
+      name: String::from("wall"),
+      mode: AutotileMode::Blob,
+      tiles: [(255, 1)].into_iter().collect(),
+      default: Some(0),
+    }];
+    let mut map = grid(tileset, &["###", "###", "###"]);
+    apply(&mut map);
+
+    assert_eq!(local(&map, 1, 1), 1);
+    // the edges of the map connect, so every cell is surrounded
+    assert_eq!(local(&map, 0, 0), 1);
+
+    set_tile(&mut map, "ground", 2, 2, FLOOR);
+
+    assert_eq!(local(&map, 0, 0), 1);
+    assert_eq!(local(&map, 1, 1), 0);
+    assert_eq!(local(&map, 2, 1), 0);
+  }
+
+  #[test]
+  fn validation_rejects_unreachable_masks() {
+    let mut tileset = edge_tileset();
+    tileset.terrains[0].mode = AutotileMode::Blob;
+    tileset.terrains[0].tiles = [(blob::NORTH_WEST, 0)].into_iter().collect();
+
+    assert!(tileset.terrains[0].validate(&tileset).is_err());
+  }
+}
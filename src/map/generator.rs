@@ -0,0 +1,276 @@
+mod bsp;
+mod cellular;
+mod drunkard;
+
+use super::{Gid, Properties, PropertyValue, SpawnPoint, TileLayer};
+use crate::math::glm;
+use crate::util::Rng;
+use std::collections::VecDeque;
+
+mod keys {
+  pub const WALL: &str = "wall";
+  pub const FLOOR: &str = "floor";
+}
+
+/// Which cells of a generated layer can be walked on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Grid {
+  pub width: u32,
+  pub height: u32,
+  floor: Vec<bool>,
+}
+
+impl Grid {
+  /// A grid that is all wall.
+  pub fn new(width: u32, height: u32) -> Self {
+    Self {
+      width,
+      height,
+      floor: vec![false; (width * height) as usize],
+    }
+  }
+
+  pub fn in_bounds(&self, x: i32, y: i32) -> bool {
+    x >= 0 && y >= 0 && (x as u32) < self.width && (y as u32) < self.height
+  }
+
+  /// Cells outside the grid count as wall.
+  pub fn is_floor(&self, x: i32, y: i32) -> bool {
+    self.in_bounds(x, y) && self.floor[(y as u32 * self.width + x as u32) as usize]
+  }
+
+  pub fn set_floor(&mut self, x: i32, y: i32, floor: bool) {
+    if self.in_bounds(x, y) {
+      self.floor[(y as u32 * self.width + x as u32) as usize] = floor;
+    }
+  }
+
+  fn cells(&self) -> impl Iterator<Item = (i32, i32)> {
+    let width = self.width as i32;
+    (0..self.height as i32).flat_map(move |y| (0..width).map(move |x| (x, y)))
+  }
+
+  /// Walking distance from a cell to every floor cell it connects to, `None` where unreachable.
+  pub fn distances(&self, from: (i32, i32)) -> Vec<Option<u32>> {
+    let mut distances = vec![None; self.floor.len()];
+    if !self.is_floor(from.0, from.1) {
+      return distances;
+    }
+
+    let index = |(x, y): (i32, i32)| (y as u32 * self.width + x as u32) as usize;
+    let mut queue = VecDeque::from([from]);
+    distances[index(from)] = Some(0);
+
+    while let Some((x, y)) = queue.pop_front() {
+      let distance = distances[index((x, y))].unwrap_or_default();
+      for next in [(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)] {
+        if self.is_floor(next.0, next.1) && distances[index(next)].is_none() {
+          distances[index(next)] = Some(distance + 1);
+          queue.push_back(next);
+        }
+      }
+    }
+
+    distances
+  }
+
+  /// The floor cell furthest from `from` by walking distance.
+  pub fn furthest_from(&self, from: (i32, i32)) -> Option<(i32, i32)> {
+    let distances = self.distances(from);
+    self
+      .cells()
+      .zip(distances)
+      .filter_map(|(cell, distance)| distance.map(|distance| (cell, distance)))
+      .max_by_key(|(cell, distance)| (*distance, -cell.1, -cell.0))
+      .map(|(cell, _)| cell)
+  }
+
+  /// Turns every floor cell that is not connected to `keep` into wall.
+  pub fn keep_connected(&mut self, keep: (i32, i32)) {
+    let distances = self.distances(keep);
+    for (floor, distance) in self.floor.iter_mut().zip(distances) {
+      *floor = *floor && distance.is_some();
+    }
+  }
+}
+
+/// A named cell of the grid, turned into a spawn point at the cell centre.
+pub type Marker = (String, (i32, i32));
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Algorithm {
+  Cellular(cellular::Parameters),
+  Bsp(bsp::Parameters),
+  Drunkard(drunkard::Parameters),
+}
+
+/// Fills a tile layer procedurally, the same seed always giving the same layer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Generator {
+  pub algorithm: Algorithm,
+  pub seed: u64,
+  pub wall: Gid,
+  pub floor: Gid,
+}
+
+/// Reads the parameters of an algorithm, rejecting any the algorithm does not know.
+struct Parameters<'a> {
+  properties: &'a Properties,
+}
+
+impl<'a> Parameters<'a> {
+  fn new(properties: &'a Properties, allowed: &[&str]) -> Result<Self, String> {
+    match properties.keys().find(|key| {
+      ![keys::WALL, keys::FLOOR].contains(&key.as_str()) && !allowed.contains(&key.as_str())
+    }) {
+      Some(key) => Err(format!("unknown generator parameter '{}'", key)),
+      None => Ok(Self { properties }),
+    }
+  }
+
+  fn int(&self, key: &str, default: i64) -> Result<i64, String> {
+    match self.properties.get(key) {
+      Some(value) => value
+        .as_int()
+        .ok_or_else(|| format!("generator parameter '{}' must be an integer", key)),
+      None => Ok(default),
+    }
+  }
+
+  fn positive(&self, key: &str, default: u32) -> Result<u32, String> {
+    u32::try_from(self.int(key, default as i64)?)
+      .map_err(|_| format!("generator parameter '{}' must be positive", key))
+  }
+
+  fn fraction(&self, key: &str, default: f64) -> Result<f64, String> {
+    let value = match self.properties.get(key) {
+      Some(value) => value
+        .as_float()
+        .ok_or_else(|| format!("generator parameter '{}' must be a number", key))?,
+      None => default,
+    };
+
+    if (0.0..=1.0).contains(&value) {
+      Ok(value)
+    } else {
+      Err(format!(
+        "generator parameter '{}' must be between 0 and 1",
+        key
+      ))
+    }
+  }
+}
+
+impl Generator {
+  pub fn new(algorithm: &str, seed: u64, parameters: &Properties) -> Result<Self, String> {
+    let algorithm = match algorithm {
+      "cellular" => Algorithm::Cellular(cellular::Parameters::read(parameters)?),
+      "bsp" => Algorithm::Bsp(bsp::Parameters::read(parameters)?),
+      "drunkard" => Algorithm::Drunkard(drunkard::Parameters::read(parameters)?),
+      invalid => return Err(format!("unknown generator '{}'", invalid)),
+    };
+
+    let tile = |key: &str| match parameters.get(key) {
+      Some(PropertyValue::Int(gid)) => {
+        Gid::try_from(*gid).map_err(|_| format!("generator '{}' must be a tile id", key))
+      }
+      Some(_) => Err(format!("generator '{}' must be a tile id", key)),
+      None => Err(format!("generator is missing '{}'", key)),
+    };
+
+    Ok(Self {
+      algorithm,
+      seed,
+      wall: tile(keys::WALL)?,
+      floor: tile(keys::FLOOR)?,
+    })
+  }
+
+  /// Generates which cells are floor, and the named cells spawn points go in.
+  pub fn grid(&self, width: u32, height: u32) -> (Grid, Vec<Marker>) {
+    let mut rng = Rng::new(self.seed);
+    let mut grid = Grid::new(width, height);
+
+    let markers = match &self.algorithm {
+      Algorithm::Cellular(parameters) => cellular::generate(&mut grid, &mut rng, parameters),
+      Algorithm::Bsp(parameters) => bsp::generate(&mut grid, &mut rng, parameters),
+      Algorithm::Drunkard(parameters) => drunkard::generate(&mut grid, &mut rng, parameters),
+    };
+
+    (grid, markers)
+  }
+
+  pub fn generate(
+    &self,
+    name: &str,
+    width: u32,
+    height: u32,
+    tile_size: (u32, u32),
+  ) -> Result<(TileLayer, Vec<SpawnPoint>), String> {
+    // made first so a layer too large to index is turned down before generating
+    let mut layer = TileLayer::new(name, width, height)?;
+    let (grid, markers) = self.grid(width, height);
+
+    for (x, y) in grid.cells() {
+      let gid = if grid.is_floor(x, y) {
+        self.floor
+      } else {
+        self.wall
+      };
+      layer.set(x, y, gid);
+    }
+
+    let spawn_points = markers
+      .into_iter()
+      .map(|(name, (x, y))| SpawnPoint {
+        name,
+        position: glm::vec2(
+          (x as f32 + 0.5) * tile_size.0 as f32,
+          (y as f32 + 0.5) * tile_size.1 as f32,
+        ),
+      })
+      .collect();
+
+    Ok((layer, spawn_points))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const ALGORITHMS: [&str; 3] = ["cellular", "bsp", "drunkard"];
+  const WALL: Gid = 1;
+  const FLOOR: Gid = 2;
+
+  fn generate(algorithm: &str, seed: u64) -> (TileLayer, Vec<SpawnPoint>) {
+    let parameters = Properties::from([
+      (String::from(keys::WALL), PropertyValue::Int(WALL as i64)),
+      (String::from(keys::FLOOR), PropertyValue::Int(FLOOR as i64)),
+    ]);
+    Generator::new(algorithm, seed, &parameters)
+      .unwrap()
+      .generate("ground", 48, 32, (16, 16))
+      .unwrap()
+  }
+
+  #[test]
+  fn same_seed_generates_the_same_layer() {
+    for algorithm in ALGORITHMS {
+      let (layer, spawn_points) = generate(algorithm, 7);
+      let (again, spawn_points_again) = generate(algorithm, 7);
+      assert_eq!(layer, again, "{} differs between runs", algorithm);
+      assert_eq!(spawn_points, spawn_points_again);
+      assert!(layer.tiles.contains(&FLOOR), "{} made no floor", algorithm);
+    }
+  }
+
+  #[test]
+  fn different_seeds_generate_different_layers() {
+    for algorithm in ALGORITHMS {
+      let (layer, _) = generate(algorithm, 7);
+      let (other, _) = generate(algorithm, 8);
+      assert_ne!(layer, other, "{} ignores its seed", algorithm);
+    }
+  }
+}
@@ -0,0 +1,180 @@
+use super::{Grid, Marker};
+use crate::util::Rng;
+
+mod keys {
+  pub const MIN_LEAF: &str = "min_leaf";
+  pub const MIN_ROOM: &str = "min_room";
+  pub const MAX_ROOM: &str = "max_room";
+}
+
+/// Rooms in a binary space partition, joined by corridors between siblings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parameters {
+  /// Partitions are not split below this size.
+  pub min_leaf: u32,
+  pub min_room: u32,
+  pub max_room: u32,
+}
+
+impl Parameters {
+  pub fn read(properties: &crate::map::Properties) -> Result<Self, String> {
+    let parameters = super::Parameters::new(
+      properties,
+      &[keys::MIN_LEAF, keys::MIN_ROOM, keys::MAX_ROOM],
+    )?;
+
+    let parameters = Self {
+      min_leaf: parameters.positive(keys::MIN_LEAF, 8)?,
+      min_room: parameters.positive(keys::MIN_ROOM, 3)?,
+      max_room: parameters.positive(keys::MAX_ROOM, 10)?,
+    };
+
+    // rooms keep a wall between them and the edge of their partition
+    if parameters.min_room == 0
+      || parameters.min_room > parameters.max_room
+      || parameters.min_room + 2 > parameters.min_leaf
+    {
+      return Err(format!(
+        "bsp needs 0 < {} <= {} and {} + 2 <= {}",
+        keys::MIN_ROOM,
+        keys::MAX_ROOM,
+        keys::MIN_ROOM,
+        keys::MIN_LEAF
+      ));
+    }
+
+    Ok(parameters)
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Area {
+  x: i32,
+  y: i32,
+  width: i32,
+  height: i32,
+}
+
+impl Area {
+  fn center(&self) -> (i32, i32) {
+    (self.x + self.width / 2, self.y + self.height / 2)
+  }
+}
+
+fn carve_corridor(grid: &mut Grid, rng: &mut Rng, from: (i32, i32), to: (i32, i32)) {
+  let corner = if rng.chance(0.5) {
+    (to.0, from.1)
+  } else {
+    (from.0, to.1)
+  };
+
+  for (a, b) in [(from, corner), (corner, to)] {
+    for x in a.0.min(b.0)..=a.0.max(b.0) {
+      for y in a.1.min(b.1)..=a.1.max(b.1) {
+        grid.set_floor(x, y, true);
+      }
+    }
+  }
+}
+
+/// Splits an area until it is too small, then places a room in it.
+/// Returns the rooms in the area in order, the first and last being furthest apart in the tree.
+fn split(grid: &mut Grid, rng: &mut Rng, parameters: &Parameters, area: Area) -> Vec<Area> {
+  let min_leaf = parameters.min_leaf as i32;
+  let can_split_x = area.width >= min_leaf * 2;
+  let can_split_y = area.height >= min_leaf * 2;
+
+  let vertical = match (can_split_x, can_split_y) {
+    (false, false) => return vec![place_room(grid, rng, parameters, area)],
+    (true, false) => true,
+    (false, true) => false,
+    (true, true) if area.width * 4 > area.height * 5 => true,
+    (true, true) if area.height * 4 > area.width * 5 => false,
+    (true, true) => rng.chance(0.5),
+  };
+
+  let (first, second) = if vertical {
+    let at = rng.range(min_leaf, area.width - min_leaf + 1);
+    (
+      Area { width: at, ..area },
+      Area {
+        x: area.x + at,
+        width: area.width - at,
+        ..area
+      },
+    )
+  } else {
+    let at = rng.range(min_leaf, area.height - min_leaf + 1);
+    (
+      Area { height: at, ..area },
+      Area {
+        y: area.y + at,
+        height: area.height - at,
+        ..area
+      },
+    )
+  };
+
+  let mut rooms = split(grid, rng, parameters, first);
+  let second = split(grid, rng, parameters, second);
+
+  if let (Some(a), Some(b)) = (rooms.last(), second.first()) {
+    let (from, to) = (a.center(), b.center());
+    carve_corridor(grid, rng, from, to);
+  }
+
+  rooms.extend(second);
+  rooms
+}
+
+fn place_room(grid: &mut Grid, rng: &mut Rng, parameters: &Parameters, leaf: Area) -> Area {
+  let max_width = (parameters.max_room as i32).min(leaf.width - 2);
+  let max_height = (parameters.max_room as i32).min(leaf.height - 2);
+  let width = rng.range(parameters.min_room as i32, max_width + 1);
+  let height = rng.range(parameters.min_room as i32, max_height + 1);
+
+  let room = Area {
+    x: leaf.x + rng.range(1, leaf.width - width),
+    y: leaf.y + rng.range(1, leaf.height - height),
+    width,
+    height,
+  };
+
+  for y in room.y..room.y + room.height {
+    for x in room.x..room.x + room.width {
+      grid.set_floor(x, y, true);
+    }
+  }
+
+  room
+}
+
+pub fn generate(grid: &mut Grid, rng: &mut Rng, parameters: &Parameters) -> Vec<Marker> {
+  // the partitions cover everything inside the outer wall
+  let area = Area {
+    x: 1,
+    y: 1,
+    width: grid.width as i32 - 2,
+    height: grid.height as i32 - 2,
+  };
+  if area.width < parameters.min_room as i32 + 2 || area.height < parameters.min_room as i32 + 2 {
+    return Vec::default();
+  }
+
+  let rooms = split(grid, rng, parameters, area);
+
+  let mut markers: Vec<Marker> = rooms
+    .iter()
+    .enumerate()
+    .map(|(index, room)| (format!("room_{}", index), room.center()))
+    .collect();
+
+  if let (Some(first), Some(last)) = (rooms.first(), rooms.last()) {
+    markers.insert(0, (String::from("start"), first.center()));
+    if rooms.len() > 1 {
+      markers.insert(1, (String::from("exit"), last.center()));
+    }
+  }
+
+  markers
+}
@@ -0,0 +1,106 @@
+use super::{Grid, Marker};
+use crate::util::Rng;
+use std::collections::BTreeSet;
+
+mod keys {
+  pub const FILL: &str = "fill";
+  pub const ITERATIONS: &str = "iterations";
+  pub const THRESHOLD: &str = "threshold";
+}
+
+/// Caves grown by smoothing random noise.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parameters {
+  /// How much of the map starts out as wall.
+  pub fill: f64,
+  pub iterations: u32,
+  /// A cell becomes wall with this many wall neighbors, and stays wall with one less.
+  pub threshold: u32,
+}
+
+impl Parameters {
+  pub fn read(properties: &crate::map::Properties) -> Result<Self, String> {
+    let parameters =
+      super::Parameters::new(properties, &[keys::FILL, keys::ITERATIONS, keys::THRESHOLD])?;
+
+    Ok(Self {
+      fill: parameters.fraction(keys::FILL, 0.45)?,
+      iterations: parameters.positive(keys::ITERATIONS, 5)?,
+      threshold: parameters.positive(keys::THRESHOLD, 5)?,
+    })
+  }
+}
+
+fn wall_neighbors(grid: &Grid, x: i32, y: i32) -> u32 {
+  let mut walls = 0;
+  for dy in -1..=1 {
+    for dx in -1..=1 {
+      if (dx != 0 || dy != 0) && !grid.is_floor(x + dx, y + dy) {
+        walls += 1;
+      }
+    }
+  }
+  walls
+}
+
+pub fn generate(grid: &mut Grid, rng: &mut Rng, parameters: &Parameters) -> Vec<Marker> {
+  let (width, height) = (grid.width as i32, grid.height as i32);
+
+  for y in 1..height - 1 {
+    for x in 1..width - 1 {
+      grid.set_floor(x, y, !rng.chance(parameters.fill));
+    }
+  }
+
+  for _ in 0..parameters.iterations {
+    let previous = grid.clone();
+    for y in 1..height - 1 {
+      for x in 1..width - 1 {
+        let walls = wall_neighbors(&previous, x, y);
+        let wall = walls >= parameters.threshold
+          || (!previous.is_floor(x, y) && walls + 1 >= parameters.threshold);
+        grid.set_floor(x, y, !wall);
+      }
+    }
+  }
+
+  // only the largest cave is kept, so everything generated can be reached
+  let mut seen = BTreeSet::new();
+  let mut largest: Option<((i32, i32), usize)> = None;
+  for (x, y) in grid.cells() {
+    if !grid.is_floor(x, y) || seen.contains(&(x, y)) {
+      continue;
+    }
+
+    let size = grid
+      .cells()
+      .zip(grid.distances((x, y)))
+      .filter(|(_, distance)| distance.is_some())
+      .map(|(cell, _)| seen.insert(cell))
+      .count();
+
+    if largest.is_none_or(|(_, largest)| size > largest) {
+      largest = Some(((x, y), size));
+    }
+  }
+
+  let start = match largest {
+    Some((cell, _)) => cell,
+    None => return Vec::default(),
+  };
+  grid.keep_connected(start);
+
+  let center = (width / 2, height / 2);
+  let start = grid
+    .cells()
+    .filter(|(x, y)| grid.is_floor(*x, *y))
+    .min_by_key(|(x, y)| (x - center.0).pow(2) + (y - center.1).pow(2))
+    .unwrap_or(start);
+
+  let mut markers = vec![(String::from("start"), start)];
+  if let Some(exit) = grid.furthest_from(start) {
+    markers.push((String::from("exit"), exit));
+  }
+
+  markers
+}
@@ -0,0 +1,66 @@
+use super::{Grid, Marker};
+use crate::util::Rng;
+
+mod keys {
+  pub const COVERAGE: &str = "coverage";
+  pub const MAX_STEPS: &str = "max_steps";
+}
+
+/// Tunnels carved by a single random walk from the centre of the map.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parameters {
+  /// How much of the map to carve before stopping.
+  pub coverage: f64,
+  /// Stops the walk early when the coverage takes too long to reach, `0` picks a limit from the map size.
+  pub max_steps: u32,
+}
+
+impl Parameters {
+  pub fn read(properties: &crate::map::Properties) -> Result<Self, String> {
+    let parameters = super::Parameters::new(properties, &[keys::COVERAGE, keys::MAX_STEPS])?;
+
+    Ok(Self {
+      coverage: parameters.fraction(keys::COVERAGE, 0.4)?,
+      max_steps: parameters.positive(keys::MAX_STEPS, 0)?,
+    })
+  }
+}
+
+pub fn generate(grid: &mut Grid, rng: &mut Rng, parameters: &Parameters) -> Vec<Marker> {
+  let (width, height) = (grid.width as i32, grid.height as i32);
+  if width < 3 || height < 3 {
+    return Vec::default();
+  }
+
+  let interior = ((width - 2) * (height - 2)) as u64;
+  let target = ((interior as f64 * parameters.coverage) as u64).max(1);
+  let max_steps = match parameters.max_steps {
+    0 => interior * 100,
+    steps => steps as u64,
+  };
+
+  let start = (width / 2, height / 2);
+  let (mut x, mut y) = start;
+  grid.set_floor(x, y, true);
+
+  let mut carved = 1;
+  let mut steps = 0;
+  while carved < target && steps < max_steps {
+    let (dx, dy) = [(1, 0), (-1, 0), (0, 1), (0, -1)][rng.range(0, 4) as usize];
+    x = (x + dx).clamp(1, width - 2);
+    y = (y + dy).clamp(1, height - 2);
+
+    if !grid.is_floor(x, y) {
+      grid.set_floor(x, y, true);
+      carved += 1;
+    }
+    steps += 1;
+  }
+
+  let mut markers = vec![(String::from("start"), start)];
+  if let Some(exit) = grid.furthest_from(start) {
+    markers.push((String::from("exit"), exit));
+  }
+
+  markers
+}
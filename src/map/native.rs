@@ -0,0 +1,928 @@
+use super::{
+  autotile::{self, AutotileMode, Terrain},
+  generator::Generator,
+  properties::properties_from_table,
+  tiled, Layer, Map, MapArea, MapObject, ObjectLayer, Properties, PrototypeOverrides, SpawnPoint,
+  TileFrame, TileInfo, TileLayer, Tileset,
+};
+use crate::assets::{self, AssetId, AssetKind, AssetLoader, LoadContext};
+use crate::game::Prototype;
+use crate::gfx::RenderState;
+use crate::math::glm;
+use crate::util;
+use geo::{Coordinate, LineString, Polygon};
+use std::{collections::BTreeMap, fs, path::Path};
+use toml::{value::Table, Value};
+
+mod keys {
+  pub const MAP: &str = "map";
+  pub const PROPERTIES: &str = "properties";
+  pub const TILESETS: &str = "tilesets";
+  pub const LAYERS: &str = "layers";
+  pub const SPAWN_POINTS: &str = "spawn_points";
+
+  pub const WIDTH: &str = "width";
+  pub const HEIGHT: &str = "height";
+  pub const TILE_WIDTH: &str = "tile_width";
+  pub const TILE_HEIGHT: &str = "tile_height";
+
+  pub const NAME: &str = "name";
+  pub const TEXTURE: &str = "texture";
+  pub const FIRST_GID: &str = "first_gid";
+  pub const COLUMNS: &str = "columns";
+  pub const TILE_COUNT: &str = "tile_count";
+  pub const TILES: &str = "tiles";
+  pub const ANIMATION: &str = "animation";
+  pub const TILE: &str = "tile";
+  pub const DURATION: &str = "duration";
+  pub const AUTOTILE: &str = "autotile";
+  pub const MODE: &str = "mode";
+  pub const DEFAULT: &str = "default";
+
+  pub const TYPE: &str = "type";
+  pub const VISIBLE: &str = "visible";
+  pub const DATA: &str = "data";
+  pub const OBJECTS: &str = "objects";
+  pub const AREAS: &str = "areas";
+  pub const GENERATOR: &str = "generator";
+  pub const SEED: &str = "seed";
+  pub const PARAMETERS: &str = "parameters";
+
+  pub const PROTOTYPE: &str = "prototype";
+  pub const X: &str = "x";
+  pub const Y: &str = "y";
+  pub const ROTATION: &str = "rotation";
+  pub const SCALE: &str = "scale";
+  pub const OVERRIDES: &str = "overrides";
+  pub const SCRIPT: &str = "script";
+  pub const DRAW_DESCRIPTION: &str = "draw_description";
+
+  pub const CLASS: &str = "class";
+  pub const POINTS: &str = "points";
+  pub const HOLES: &str = "holes";
+
+  pub const LAYER_TILES: &str = "tiles";
+  pub const LAYER_OBJECTS: &str = "objects";
+}
+
+fn check_keys(table: &Table, allowed: &[&str], context: &str) -> Result<(), String> {
+  match table.keys().find(|key| !allowed.contains(&key.as_str())) {
+    Some(key) => Err(format!("unknown key '{}' in {}", key, context)),
+    None => Ok(()),
+  }
+}
+
+fn table<'a>(value: &'a Value, context: &str) -> Result<&'a Table, String> {
+  value
+    .as_table()
+    .ok_or_else(|| format!("{} must be a table", context))
+}
+
+fn tables<'a>(table: &'a Table, key: &str) -> Result<Vec<&'a Table>, String> {
+  match table.get(key) {
+    Some(Value::Array(values)) => values.iter().map(|value| self::table(value, key)).collect(),
+    Some(_) => Err(format!("'{}' must be an array of tables", key)),
+    None => Ok(Vec::default()),
+  }
+}
+
+fn u32_of(table: &Table, key: &str) -> Result<Option<u32>, String> {
+  match table.get(key) {
+    Some(Value::Integer(i)) => u32::try_from(*i)
+      .map(Some)
+      .map_err(|_| format!("'{}' must be a positive integer", key)),
+    Some(_) => Err(format!("'{}' must be an integer", key)),
+    None => Ok(None),
+  }
+}
+
+fn required_u32(table: &Table, key: &str) -> Result<u32, String> {
+  u32_of(table, key)?.ok_or_else(|| format!("missing '{}'", key))
+}
+
+fn f32_of(table: &Table, key: &str) -> Result<Option<f32>, String> {
+  match table.get(key) {
+    Some(Value::Float(f)) => Ok(Some(*f as f32)),
+    Some(Value::Integer(i)) => Ok(Some(*i as f32)),
+    Some(_) => Err(format!("'{}' must be a number", key)),
+    None => Ok(None),
+  }
+}
+
+fn str_of<'a>(table: &'a Table, key: &str) -> Result<Option<&'a str>, String> {
+  match table.get(key) {
+    Some(Value::String(s)) => Ok(Some(s)),
+    Some(_) => Err(format!("'{}' must be a string", key)),
+    None => Ok(None),
+  }
+}
+
+fn id_of(table: &Table, key: &str) -> Result<Option<AssetId>, String> {
+  str_of(table, key)?
+    .map(|s| s.parse::<AssetId>().map_err(|e| format!("'{}' {}", key, e)))
+    .transpose()
+}
+
+fn bool_of(table: &Table, key: &str, default: bool) -> Result<bool, String> {
+  match table.get(key) {
+    Some(Value::Boolean(b)) => Ok(*b),
+    Some(_) => Err(format!("'{}' must be a boolean", key)),
+    None => Ok(default),
+  }
+}
+
+fn properties_of(table: &Table) -> Result<Properties, String> {
+  match table.get(keys::PROPERTIES) {
+    Some(value) => properties_from_table(self::table(value, keys::PROPERTIES)?),
+    None => Ok(Properties::default()),
+  }
+}
+
+fn parse_tileset(table: &Table, map: &Map) -> Result<Tileset, String> {
+  let name = str_of(table, keys::NAME)?
+    .ok_or_else(|| format!("tileset is missing '{}'", keys::NAME))?
+    .to_string();
+
+  let context = format!("tileset '{}'", name);
+  check_keys(
+    table,
+    &[
+      keys::NAME,
+      keys::TEXTURE,
+      keys::FIRST_GID,
+      keys::COLUMNS,
+      keys::TILE_COUNT,
+      keys::TILE_WIDTH,
+      keys::TILE_HEIGHT,
+      keys::TILES,
+      keys::AUTOTILE,
+      keys::PROPERTIES,
+    ],
+    &context,
+  )?;
+
+  let mut tiles = BTreeMap::new();
+  if let Some(value) = table.get(keys::TILES) {
+    for (local_id, tile) in self::table(value, keys::TILES)? {
+      let local_id = local_id
+        .parse::<u32>()
+        .map_err(|_| format!("{} tile '{}' is not a tile index", context, local_id))?;
+      let tile = self::table(tile, keys::TILES)?;
+      check_keys(tile, &[keys::PROPERTIES, keys::ANIMATION], &context)?;
+
+      let mut animation = Vec::new();
+      for frame in tables(tile, keys::ANIMATION)? {
+        check_keys(frame, &[keys::TILE, keys::DURATION], &context)?;
+        animation.push(TileFrame {
+          tile: required_u32(frame, keys::TILE)?,
+          duration_ms: required_u32(frame, keys::DURATION)?,
+        });
+      }
+
+      tiles.insert(
+        local_id,
+        TileInfo {
+          properties: properties_of(tile)?,
+          animation,
+        },
+      );
+    }
+  }
+
+  let terrains = tables(table, keys::AUTOTILE)?
+    .into_iter()
+    .map(|terrain| parse_terrain(terrain).map_err(|e| format!("{}: {}", context, e)))
+    .collect::<Result<Vec<_>, String>>()?;
+
+  Ok(Tileset {
+    texture: id_of(table, keys::TEXTURE)?
+      .ok_or_else(|| format!("{} is missing '{}'", context, keys::TEXTURE))?,
+    first_gid: required_u32(table, keys::FIRST_GID)?,
+    tile_width: u32_of(table, keys::TILE_WIDTH)?.unwrap_or(map.tile_width),
+    tile_height: u32_of(table, keys::TILE_HEIGHT)?.unwrap_or(map.tile_height),
+    columns: required_u32(table, keys::COLUMNS)?,
+    tile_count: required_u32(table, keys::TILE_COUNT)?,
+    tiles,
+    terrains,
+    properties: properties_of(table)?,
+    name,
+  })
+}
+
+/// Terrain tiles are either an array following the masks of the mode in order, or a
+/// table of mask to tile.
+fn parse_terrain(table: &Table) -> Result<Terrain, String> {
+  let name = str_of(table, keys::NAME)?
+    .ok_or_else(|| format!("autotile is missing '{}'", keys::NAME))?
+    .to_string();
+
+  let context = format!("autotile '{}'", name);
+  check_keys(
+    table,
+    &[keys::NAME, keys::MODE, keys::TILES, keys::DEFAULT],
+    &context,
+  )?;
+
+  let mode = AutotileMode::try_from(
+    str_of(table, keys::MODE)?.ok_or_else(|| format!("{} is missing '{}'", context, keys::MODE))?,
+  )?;
+
+  let tile = |value: &Value| {
+    value
+      .as_integer()
+      .and_then(|tile| u32::try_from(tile).ok())
+      .ok_or_else(|| format!("{} tiles must be positive integers", context))
+  };
+
+  let mut tiles = BTreeMap::new();
+  match table.get(keys::TILES) {
+    Some(Value::Array(values)) => {
+      let masks: Vec<u8> = mode.masks().collect();
+      if values.len() > masks.len() {
+        return Err(format!(
+          "{} has {} tiles but only {} masks",
+          context,
+          values.len(),
+          masks.len()
+        ));
+      }
+      for (mask, value) in masks.into_iter().zip(values) {
+        tiles.insert(mask, tile(value)?);
+      }
+    }
+    Some(Value::Table(values)) => {
+      for (mask, value) in values {
+        let mask = mask
+          .parse::<u8>()
+          .map_err(|_| format!("{} mask '{}' is not a number below 256", context, mask))?;
+        tiles.insert(mask, tile(value)?);
+      }
+    }
+    Some(_) => return Err(format!("{} tiles must be an array or a table", context)),
+    None => return Err(format!("{} is missing '{}'", context, keys::TILES)),
+  }
+
+  Ok(Terrain {
+    name,
+    mode,
+    tiles,
+    default: u32_of(table, keys::DEFAULT)?,
+  })
+}
+
+fn parse_overrides(table: &Table) -> Result<PrototypeOverrides, String> {
+  check_keys(
+    table,
+    &[keys::ANIMATION, keys::SCRIPT, keys::DRAW_DESCRIPTION],
+    keys::OVERRIDES,
+  )?;
+
+  let render_state = match table.get(keys::DRAW_DESCRIPTION) {
+    Some(value) => Some(RenderState::try_from(&util::toml_to_json(value))?),
+    None => None,
+  };
+
+  Ok(PrototypeOverrides {
+    animation: id_of(table, keys::ANIMATION)?,
+    script: id_of(table, keys::SCRIPT)?,
+    render_state,
+  })
+}
+
+fn parse_object(table: &Table) -> Result<MapObject, String> {
+  check_keys(
+    table,
+    &[
+      keys::NAME,
+      keys::PROTOTYPE,
+      keys::X,
+      keys::Y,
+      keys::ROTATION,
+      keys::SCALE,
+      keys::OVERRIDES,
+      keys::PROPERTIES,
+    ],
+    "object",
+  )?;
+
+  let scale = match table.get(keys::SCALE) {
+    Some(Value::Array(values)) if values.len() == 2 => {
+      let component = |v: &Value| {
+        v.as_float()
+          .or_else(|| v.as_integer().map(|i| i as f64))
+          .map(|f| f as f32)
+          .ok_or_else(|| format!("'{}' must contain numbers", keys::SCALE))
+      };
+      glm::vec2(component(&values[0])?, component(&values[1])?)
+    }
+    Some(_) => return Err(format!("'{}' must be [x, y]", keys::SCALE)),
+    None => glm::vec2(1.0, 1.0),
+  };
+
+  let overrides = match table.get(keys::OVERRIDES) {
+    Some(value) => parse_overrides(self::table(value, keys::OVERRIDES)?)?,
+    None => PrototypeOverrides::default(),
+  };
+
+  Ok(MapObject {
+    name: str_of(table, keys::NAME)?.map(String::from),
+    prototype: id_of(table, keys::PROTOTYPE)?
+      .ok_or_else(|| format!("object is missing '{}'", keys::PROTOTYPE))?,
+    position: glm::vec2(
+      f32_of(table, keys::X)?.unwrap_or_default(),
+      f32_of(table, keys::Y)?.unwrap_or_default(),
+    ),
+    rotation: f32_of(table, keys::ROTATION)?.unwrap_or_default(),
+    scale,
+    overrides,
+    properties: properties_of(table)?,
+  })
+}
+
+/// A ring of `[x, y]` points.
+fn ring_of(value: &Value, context: &str) -> Result<LineString<f32>, String> {
+  let invalid = || format!("{} must be a list of [x, y] points", context);
+
+  value
+    .as_array()
+    .ok_or_else(invalid)?
+    .iter()
+    .map(|point| match point.as_array().map(Vec::as_slice) {
+      Some([x, y]) => {
+        let component = |v: &Value| {
+          v.as_float()
+            .or_else(|| v.as_integer().map(|i| i as f64))
+            .map(|f| f as f32)
+            .ok_or_else(invalid)
+        };
+        Ok(Coordinate {
+          x: component(x)?,
+          y: component(y)?,
+        })
+      }
+      _ => Err(invalid()),
+    })
+    .collect::<Result<Vec<_>, String>>()
+    .map(LineString::from)
+}
+
+fn parse_area(table: &Table) -> Result<MapArea, String> {
+  check_keys(
+    table,
+    &[
+      keys::NAME,
+      keys::CLASS,
+      keys::POINTS,
+      keys::HOLES,
+      keys::PROPERTIES,
+    ],
+    "area",
+  )?;
+
+  let exterior = ring_of(
+    table
+      .get(keys::POINTS)
+      .ok_or_else(|| format!("area is missing '{}'", keys::POINTS))?,
+    keys::POINTS,
+  )?;
+  let holes = match table.get(keys::HOLES) {
+    Some(Value::Array(holes)) => holes
+      .iter()
+      .map(|hole| ring_of(hole, keys::HOLES))
+      .collect::<Result<Vec<_>, String>>()?,
+    Some(_) => return Err(format!("'{}' must be a list of rings", keys::HOLES)),
+    None => Vec::default(),
+  };
+
+  Ok(MapArea {
+    name: str_of(table, keys::NAME)?.map(String::from),
+    class: str_of(table, keys::CLASS)?
+      .ok_or_else(|| format!("area is missing '{}'", keys::CLASS))?
+      .to_string(),
+    polygon: Polygon::new(exterior, holes),
+    properties: properties_of(table)?,
+  })
+}
+
+/// Generated layers add their spawn points to the map.
+fn parse_generated_layer(table: &Table, map: &mut Map, name: String) -> Result<TileLayer, String> {
+  let context = format!("layer '{}'", name);
+  let algorithm = str_of(table, keys::GENERATOR)?.unwrap_or_default();
+
+  let seed = match table.get(keys::SEED) {
+    Some(Value::Integer(seed)) => u64::try_from(*seed)
+      .map_err(|_| format!("{} '{}' cannot be negative", context, keys::SEED))?,
+    Some(_) => return Err(format!("{} '{}' must be an integer", context, keys::SEED)),
+    None => return Err(format!("{} is missing '{}'", context, keys::SEED)),
+  };
+
+  let parameters = match table.get(keys::PARAMETERS) {
+    Some(value) => properties_from_table(self::table(value, keys::PARAMETERS)?)?,
+    None => Properties::default(),
+  };
+
+  let generator =
+    Generator::new(algorithm, seed, &parameters).map_err(|e| format!("{}: {}", context, e))?;
+  let (mut layer, spawn_points) = generator
+    .generate(
+      &name,
+      map.width,
+      map.height,
+      (map.tile_width, map.tile_height),
+    )
+    .map_err(|e| format!("{}: {}", context, e))?;
+
+  layer.visible = bool_of(table, keys::VISIBLE, true)?;
+  layer.properties = properties_of(table)?;
+  map.spawn_points.extend(spawn_points);
+
+  Ok(layer)
+}
+
+fn parse_layer(table: &Table, map: &mut Map) -> Result<Layer, String> {
+  let name = str_of(table, keys::NAME)?
+    .ok_or_else(|| format!("layer is missing '{}'", keys::NAME))?
+    .to_string();
+  let context = format!("layer '{}'", name);
+
+  match str_of(table, keys::TYPE)? {
+    Some(keys::LAYER_TILES) => {
+      check_keys(
+        table,
+        &[
+          keys::NAME,
+          keys::TYPE,
+          keys::VISIBLE,
+          keys::DATA,
+          keys::GENERATOR,
+          keys::SEED,
+          keys::PARAMETERS,
+          keys::PROPERTIES,
+        ],
+        &context,
+      )?;
+
+      if table.contains_key(keys::GENERATOR) {
+        if table.contains_key(keys::DATA) {
+          return Err(format!(
+            "{} cannot have both '{}' and '{}'",
+            context,
+            keys::DATA,
+            keys::GENERATOR
+          ));
+        }
+
+        return parse_generated_layer(table, map, name).map(Layer::Tiles);
+      }
+
+      let tiles = table
+        .get(keys::DATA)
+        .and_then(Value::as_array)
+        .ok_or_else(|| format!("{} is missing '{}'", context, keys::DATA))?
+        .iter()
+        .map(|gid| {
+          gid
+            .as_integer()
+            .and_then(|gid| u32::try_from(gid).ok())
+            .ok_or_else(|| format!("{} data must be tile ids", context))
+        })
+        .collect::<Result<Vec<u32>, String>>()?;
+
+      Ok(Layer::Tiles(TileLayer {
+        width: map.width,
+        height: map.height,
+        visible: bool_of(table, keys::VISIBLE, true)?,
+        tiles,
+        properties: properties_of(table)?,
+        name,
+      }))
+    }
+    Some(keys::LAYER_OBJECTS) => {
+      check_keys(
+        table,
+        &[
+          keys::NAME,
+          keys::TYPE,
+          keys::VISIBLE,
+          keys::OBJECTS,
+          keys::AREAS,
+          keys::PROPERTIES,
+        ],
+        &context,
+      )?;
+
+      let objects = tables(table, keys::OBJECTS)?
+        .into_iter()
+        .map(parse_object)
+        .collect::<Result<Vec<MapObject>, String>>()
+        .map_err(|e| format!("{}: {}", context, e))?;
+      let areas = tables(table, keys::AREAS)?
+        .into_iter()
+        .map(parse_area)
+        .collect::<Result<Vec<MapArea>, String>>()
+        .map_err(|e| format!("{}: {}", context, e))?;
+
+      Ok(Layer::Objects(ObjectLayer {
+        visible: bool_of(table, keys::VISIBLE, true)?,
+        objects,
+        areas,
+        properties: properties_of(table)?,
+        name,
+      }))
+    }
+    Some(invalid) => Err(format!("{} has unknown type '{}'", context, invalid)),
+    None => Err(format!("{} is missing '{}'", context, keys::TYPE)),
+  }
+}
+
+pub fn parse_map(root: &Value) -> Result<Map, String> {
+  let root = table(root, "map file")?;
+  check_keys(
+    root,
+    &[
+      keys::MAP,
+      keys::PROPERTIES,
+      keys::TILESETS,
+      keys::LAYERS,
+      keys::SPAWN_POINTS,
+    ],
+    "map file",
+  )?;
+
+  let header = table(
+    root
+      .get(keys::MAP)
+      .ok_or_else(|| format!("missing [{}]", keys::MAP))?,
+    keys::MAP,
+  )?;
+  check_keys(
+    header,
+    &[
+      keys::WIDTH,
+      keys::HEIGHT,
+      keys::TILE_WIDTH,
+      keys::TILE_HEIGHT,
+    ],
+    keys::MAP,
+  )?;
+
+  let mut map = Map::new(
+    required_u32(header, keys::WIDTH)?,
+    required_u32(header, keys::HEIGHT)?,
+    required_u32(header, keys::TILE_WIDTH)?,
+    required_u32(header, keys::TILE_HEIGHT)?,
+  );
+
+  if let Some(value) = root.get(keys::PROPERTIES) {
+    map.properties = properties_from_table(table(value, keys::PROPERTIES)?)?;
+  }
+
+  for tileset in tables(root, keys::TILESETS)? {
+    let tileset = parse_tileset(tileset, &map)?;
+    map.tilesets.push(tileset);
+  }
+
+  for layer in tables(root, keys::LAYERS)? {
+    let layer = parse_layer(layer, &mut map)?;
+    map.layers.push(layer);
+  }
+
+  for spawn in tables(root, keys::SPAWN_POINTS)? {
+    check_keys(spawn, &[keys::NAME, keys::X, keys::Y], keys::SPAWN_POINTS)?;
+    map.spawn_points.push(SpawnPoint {
+      name: str_of(spawn, keys::NAME)?
+        .ok_or_else(|| format!("spawn point is missing '{}'", keys::NAME))?
+        .to_string(),
+      position: glm::vec2(
+        f32_of(spawn, keys::X)?.unwrap_or_default(),
+        f32_of(spawn, keys::Y)?.unwrap_or_default(),
+      ),
+    });
+  }
+
+  map.validate()?;
+
+  Ok(map)
+}
+
+pub fn read_map(path: &Path) -> Result<Map, String> {
+  let data =
+    fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+  let root = data
+    .parse::<Value>()
+    .map_err(|e| format!("cannot parse {}: {}", path.display(), e))?;
+
+  parse_map(&root).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+pub struct MapLoader;
+
+impl MapLoader {
+  pub fn new() -> Self {
+    Self
+  }
+
+  /// Makes sure everything the map refers to exists, and records the prototypes
+  /// as dependencies so the map reloads with them.
+  fn check_references(map: &Map, ctx: &mut LoadContext) -> Result<(), String> {
+    for tileset in &map.tilesets {
+      assets::read_json_entry(AssetKind::Texture, &tileset.texture)
+        .map_err(|e| format!("tileset '{}': {}", tileset.name, e))?;
+    }
+
+    for object in map.object_layers().flat_map(|layer| &layer.objects) {
+      ctx
+        .load::<Prototype>(&object.prototype)
+        .map_err(|e| format!("object prototype {}: {}", object.prototype, e))?;
+    }
+
+    Ok(())
+  }
+}
+
+impl AssetLoader for MapLoader {
+  type Asset = Map;
+
+  fn kind(&self) -> AssetKind {
+    AssetKind::Map
+  }
+
+  fn load(&self, id: &AssetId, ctx: &mut LoadContext) -> Result<Map, String> {
+    let path = assets::locate_file(AssetKind::Map, id, &["toml", "tmx", "tmj"])?;
+    let mut map = match path.extension().and_then(|ext| ext.to_str()) {
+      Some("tmx") => tiled::read_tmx(&path)?,
+      Some("tmj") => tiled::read_tmj(&path)?,
+      _ => read_map(&path)?,
+    };
+    autotile::apply(&mut map);
+    Self::check_references(&map, ctx)?;
+    Ok(map)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::map::PropertyValue;
+
+  /// A map with one 48 tile tileset holding a terrain made of `tiles`.
+  fn map_with_terrain(mode: &str, tiles: &str) -> Result<Map, String> {
+    let source = format!(
+      r#"
+      [map]
+      width = 4
+      height = 4
+      tile_width = 16
+      tile_height = 16
+
+      [[tilesets]]
+      name = "walls"
+      texture = "exp.test.walls"
+      first_gid = 1
+      columns = 8
+      tile_count = 48
+
+      [[tilesets.autotile]]
+      name = "wall"
+      mode = "{}"
+      tiles = {}
+      "#,
+      mode, tiles
+    );
+    parse_map(&toml::from_str::<Value>(&source).unwrap())
+  }
+
+  /// A map whose only layer is an object layer holding `objects`.
+  fn map_with_objects(objects: &str) -> Result<Map, String> {
+    let source = format!(
+      r#"
+      [map]
+      width = 4
+      height = 4
+      tile_width = 16
+      tile_height = 16
+
+      [[layers]]
+      name = "entities"
+      type = "objects"
+
+      {}
+      "#,
+      objects
+    );
+    parse_map(&toml::from_str::<Value>(&source).unwrap())
+  }
+
+  fn objects(map: &Map) -> &[MapObject] {
+    &map.object_layers().next().unwrap().objects
+  }
+
+  fn terrain(map: &Map) -> &Terrain {
+    &map.tilesets[0].terrains[0]
+  }
+
+  fn tile_list(count: u32) -> String {
+    let tiles: Vec<String> = (0..count).map(|tile| tile.to_string()).collect();
+    format!("[{}]", tiles.join(", "))
+  }
+
+  #[test]
+  fn edge_tile_arrays_are_indexed_by_mask() {
+    let map = map_with_terrain("4bit", &tile_list(16)).unwrap();
+    assert_eq!(terrain(&map).tiles.len(), 16);
+    for mask in 0..16 {
+      assert_eq!(terrain(&map).tile_for(mask), Some(mask as u32));
+    }
+
+    assert!(map_with_terrain("4bit", &tile_list(17)).is_err());
+  }
+
+  #[test]
+  fn blob_tile_arrays_follow_the_reachable_masks() {
+    let map = map_with_terrain("8bit", &tile_list(47)).unwrap();
+    let terrain = terrain(&map);
+    assert_eq!(terrain.tiles.len(), 47);
+    assert_eq!(terrain.tile_for(0), Some(0));
+    assert_eq!(terrain.tile_for(autotile::blob::NORTH), Some(1));
+    assert_eq!(terrain.tile_for(u8::MAX), Some(46));
+    assert!(terrain
+      .tiles
+      .keys()
+      .all(|mask| autotile::prune_corners(*mask) == *mask));
+
+    assert!(map_with_terrain("8bit", &tile_list(48)).is_err());
+  }
+
+  #[test]
+  fn short_tile_arrays_fill_the_first_masks() {
+    let map = map_with_terrain("8bit", "[5, 6]").unwrap();
+    assert_eq!(terrain(&map).tile_for(0), Some(5));
+    assert_eq!(terrain(&map).tile_for(autotile::blob::NORTH), Some(6));
+    assert_eq!(terrain(&map).tile_for(autotile::blob::WEST), None);
+  }
+
+  #[test]
+  fn tile_tables_name_their_masks() {
+    let map = map_with_terrain("8bit", r#"{ "0" = 3, "255" = 4 }"#).unwrap();
+    assert_eq!(terrain(&map).tile_for(0), Some(3));
+    assert_eq!(terrain(&map).tile_for(255), Some(4));
+
+    // a corner without both of its edges can never be looked up
+    assert!(map_with_terrain("8bit", r#"{ "1" = 3 }"#).is_err());
+    assert!(map_with_terrain("4bit", r#"{ "16" = 3 }"#).is_err());
+  }
+
+  #[test]
+  fn objects_are_placed_where_the_map_says() {
+    let map = map_with_objects(
+      r#"
+      [[layers.objects]]
+      name = "player"
+      prototype = "exp.characters.main.player"
+      x = 32.0
+      y = 48
+      rotation = 1.5
+      scale = [2, 0.5]
+      "#,
+    )
+    .unwrap();
+
+    let object = &objects(&map)[0];
+    assert_eq!(object.name.as_deref(), Some("player"));
+    assert_eq!(
+      object.prototype,
+      "exp.characters.main.player".parse().unwrap()
+    );
+    assert_eq!(object.position, glm::vec2(32.0, 48.0));
+    assert_eq!(object.rotation, 1.5);
+    assert_eq!(object.scale, glm::vec2(2.0, 0.5));
+    assert_eq!(object.overrides, PrototypeOverrides::default());
+    assert!(object.properties.is_empty());
+  }
+
+  #[test]
+  fn objects_default_to_the_origin_at_their_own_size() {
+    let map = map_with_objects(
+      r#"
+      [[layers.objects]]
+      prototype = "exp.test.random.square"
+      "#,
+    )
+    .unwrap();
+
+    let object = &objects(&map)[0];
+    assert_eq!(object.name, None);
+    assert_eq!(object.position, glm::vec2(0.0, 0.0));
+    assert_eq!(object.rotation, 0.0);
+    assert_eq!(object.scale, glm::vec2(1.0, 1.0));
+  }
+
+  #[test]
+  fn objects_need_a_prototype_and_known_keys() {
+    assert!(map_with_objects("[[layers.objects]]\nx = 1.0").is_err());
+    assert!(map_with_objects(
+      "[[layers.objects]]\nprototype = \"exp.test.random.square\"\nz = 1.0"
+    )
+    .is_err());
+    assert!(map_with_objects(
+      "[[layers.objects]]\nprototype = \"exp.test.random.square\"\nscale = [1.0]"
+    )
+    .is_err());
+  }
+
+  #[test]
+  fn overrides_replace_parts_of_the_prototype() {
+    let map = map_with_objects(
+      r#"
+      [[layers.objects]]
+      prototype = "exp.test.random.square"
+
+      [layers.objects.overrides]
+      animation = "exp.test.random.wall"
+      script = "exp.game.spinner"
+
+      [layers.objects.overrides.draw_description]
+      wireframe = true
+      "#,
+    )
+    .unwrap();
+
+    let overrides = &objects(&map)[0].overrides;
+    assert_eq!(
+      overrides.animation,
+      Some("exp.test.random.wall".parse().unwrap())
+    );
+    assert_eq!(overrides.script, Some("exp.game.spinner".parse().unwrap()));
+    assert!(overrides.render_state.unwrap().wireframe);
+
+    assert!(map_with_objects(
+      r#"
+      [[layers.objects]]
+      prototype = "exp.test.random.square"
+
+      [layers.objects.overrides]
+      shader = "exp.render.sprite"
+      "#,
+    )
+    .is_err());
+  }
+
+  #[test]
+  fn properties_keep_their_type() {
+    let map = map_with_objects(
+      r#"
+      [[layers.objects]]
+      prototype = "exp.test.random.square"
+
+      [layers.objects.properties]
+      solid = true
+      hits = 3
+      spin = 1.5
+      label = "door"
+      "#,
+    )
+    .unwrap();
+
+    let properties = &objects(&map)[0].properties;
+    assert_eq!(properties["solid"], PropertyValue::Bool(true));
+    assert_eq!(properties["hits"], PropertyValue::Int(3));
+    assert_eq!(properties["spin"], PropertyValue::Float(1.5));
+    assert_eq!(
+      properties["label"],
+      PropertyValue::String(String::from("door"))
+    );
+    // integers are numbers too
+    assert_eq!(properties["hits"].as_float(), Some(3.0));
+  }
+
+  #[test]
+  fn properties_cannot_be_arrays_or_tables() {
+    for value in ["[1, 2]", "{ a = 1 }"] {
+      let objects = format!(
+        "[[layers.objects]]\nprototype = \"exp.test.random.square\"\n\
+         [layers.objects.properties]\nlist = {}",
+        value
+      );
+      assert!(map_with_objects(&objects).is_err());
+    }
+  }
+
+  #[test]
+  fn generated_layers_reject_negative_seeds() {
+    let source = r#"
+      [map]
+      width = 8
+      height = 8
+      tile_width = 16
+      tile_height = 16
+
+      [[layers]]
+      name = "ground"
+      type = "tiles"
+      generator = "cellular"
+      seed = -1
+    "#;
+    let error = parse_map(&toml::from_str::<Value>(source).unwrap()).unwrap_err();
+    assert!(error.contains("negative"), "{}", error);
+  }
+}
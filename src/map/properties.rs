@@ -0,0 +1,75 @@
+use std::{
+  collections::BTreeMap,
+  fmt::{self, Display, Formatter},
+};
+use toml::{value::Table, Value};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PropertyValue {
+  Bool(bool),
+  Int(i64),
+  Float(f64),
+  String(String),
+}
+
+impl PropertyValue {
+  pub fn as_bool(&self) -> Option<bool> {
+    match self {
+      PropertyValue::Bool(b) => Some(*b),
+      _ => None,
+    }
+  }
+
+  pub fn as_int(&self) -> Option<i64> {
+    match self {
+      PropertyValue::Int(i) => Some(*i),
+      _ => None,
+    }
+  }
+
+  pub fn as_float(&self) -> Option<f64> {
+    match self {
+      PropertyValue::Float(f) => Some(*f),
+      PropertyValue::Int(i) => Some(*i as f64),
+      _ => None,
+    }
+  }
+}
+
+impl Display for PropertyValue {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    match self {
+      PropertyValue::Bool(b) => write!(f, "{}", b),
+      PropertyValue::Int(i) => write!(f, "{}", i),
+      PropertyValue::Float(v) => write!(f, "{}", v),
+      PropertyValue::String(s) => write!(f, "{}", s),
+    }
+  }
+}
+
+impl TryFrom<&Value> for PropertyValue {
+  type Error = String;
+
+  fn try_from(value: &Value) -> Result<Self, Self::Error> {
+    match value {
+      Value::Boolean(b) => Ok(PropertyValue::Bool(*b)),
+      Value::Integer(i) => Ok(PropertyValue::Int(*i)),
+      Value::Float(f) => Ok(PropertyValue::Float(*f)),
+      Value::String(s) => Ok(PropertyValue::String(s.clone())),
+      other => Err(format!("properties cannot be of type {}", other.type_str())),
+    }
+  }
+}
+
+pub type Properties = BTreeMap<String, PropertyValue>;
+
+pub fn properties_from_table(table: &Table) -> Result<Properties, String> {
+  table
+    .iter()
+    .map(|(key, value)| {
+      PropertyValue::try_from(value)
+        .map(|value| (key.clone(), value))
+        .map_err(|e| format!("property '{}': {}", key, e))
+    })
+    .collect()
+}
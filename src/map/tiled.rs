@@ -0,0 +1,470 @@
+mod tmj;
+mod tmx;
+
+use super::{
+  Gid, Map, MapArea, MapObject, ObjectLayer, Properties, PropertyValue, PrototypeOverrides,
+  SpawnPoint, Tileset, OBSTACLE_AREA, WALKABLE_AREA,
+};
+use crate::assets::{self, AssetId, AssetKind};
+use crate::math::glm;
+use geo::Polygon;
+use std::{collections::BTreeMap, fs, path::Path};
+
+pub use tmj::read_tmj;
+pub use tmx::read_tmx;
+
+// tiled stores flipping and rotation in the top bits of every gid, which the engine does not support
+const FLIP_FLAGS: Gid = 0xE000_0000;
+
+mod properties {
+  pub const PROTOTYPE: &str = "prototype";
+  pub const TEXTURE: &str = "texture";
+}
+
+const SPAWN_POINT_CLASS: &str = "spawn_point";
+
+fn strip_flags(gid: Gid) -> Gid {
+  gid & !FLIP_FLAGS
+}
+
+/// A tileset as tiled describes it, before its image is resolved to a texture.
+#[derive(Default)]
+struct RawTileset {
+  name: String,
+  first_gid: Gid,
+  tile_width: u32,
+  tile_height: u32,
+  columns: u32,
+  tile_count: u32,
+  image: Option<String>,
+  tiles: BTreeMap<u32, super::TileInfo>,
+  properties: Properties,
+}
+
+impl RawTileset {
+  /// Tilesets name their texture with a `texture` property, otherwise the image
+  /// file is matched against the `file` of every texture config.
+  fn resolve(mut self, dir: &Path) -> Result<Tileset, String> {
+    let texture = match self.properties.remove(properties::TEXTURE) {
+      Some(PropertyValue::String(id)) => id
+        .parse::<AssetId>()
+        .map_err(|e| format!("tileset '{}' texture {}", self.name, e))?,
+      Some(_) => {
+        return Err(format!(
+          "tileset '{}' texture property must be a string",
+          self.name
+        ))
+      }
+      None => {
+        let image = self
+          .image
+          .as_ref()
+          .ok_or_else(|| format!("tileset '{}' has no image", self.name))?;
+        texture_for_image(&dir.join(image))
+          .ok_or_else(|| format!("no texture config uses the image {}", image))?
+      }
+    };
+
+    Ok(Tileset {
+      name: self.name,
+      texture,
+      first_gid: self.first_gid,
+      tile_width: self.tile_width,
+      tile_height: self.tile_height,
+      columns: self.columns,
+      tile_count: self.tile_count,
+      tiles: self.tiles,
+      terrains: Vec::default(),
+      properties: self.properties,
+    })
+  }
+}
+
+fn texture_for_image(image: &Path) -> Option<AssetId> {
+  let file_name = image.file_name()?.to_string_lossy().to_string();
+
+  let mut found = None;
+  assets::iterate_configs(AssetKind::Texture, |path, file_id| {
+    let root = fs::read_to_string(path)
+      .ok()
+      .and_then(|data| serde_json::from_str::<serde_json::Value>(&data).ok());
+
+    for (local_id, entry) in root.iter().filter_map(|root| root.as_object()).flatten() {
+      if found.is_none() && entry.get("file").and_then(|file| file.as_str()) == Some(&file_name) {
+        found = Some(file_id.extend(local_id));
+      }
+    }
+  });
+
+  found
+}
+
+/// An object as tiled describes it.
+struct RawObject {
+  name: String,
+  class: String,
+  x: f32,
+  y: f32,
+  width: f32,
+  height: f32,
+  rotation: f32,
+  gid: Option<Gid>,
+  /// Polygon objects list their corners relative to the object position.
+  points: Option<Vec<(f32, f32)>>,
+  properties: Properties,
+}
+
+enum Placement {
+  Object(Box<MapObject>),
+  Spawn(SpawnPoint),
+  Area(MapArea),
+}
+
+impl RawObject {
+  /// Polygons, and rectangles of an area class, are areas rather than objects.
+  fn area(&mut self) -> Option<MapArea> {
+    let corners = match self.points.take() {
+      Some(points) => points,
+      None if [WALKABLE_AREA, OBSTACLE_AREA].contains(&self.class.as_str()) => vec![
+        (0.0, 0.0),
+        (self.width, 0.0),
+        (self.width, self.height),
+        (0.0, self.height),
+      ],
+      None => return None,
+    };
+
+    // objects rotate clockwise around their position
+    let (sin, cos) = self.rotation.to_radians().sin_cos();
+    let exterior: Vec<(f32, f32)> = corners
+      .into_iter()
+      .map(|(x, y)| (self.x + x * cos - y * sin, self.y + x * sin + y * cos))
+      .collect();
+
+    Some(MapArea {
+      name: (!self.name.is_empty()).then(|| self.name.clone()),
+      class: self.class.clone(),
+      polygon: Polygon::new(exterior.into(), Vec::default()),
+      properties: std::mem::take(&mut self.properties),
+    })
+  }
+
+  fn into_placement(mut self) -> Result<Placement, String> {
+    if let Some(area) = self.area() {
+      return Ok(Placement::Area(area));
+    }
+
+    // tile objects are anchored at their bottom left corner, everything else at the top left
+    let (x, y) = if self.gid.is_some() {
+      (self.x + self.width / 2.0, self.y - self.height / 2.0)
+    } else {
+      (self.x + self.width / 2.0, self.y + self.height / 2.0)
+    };
+
+    if self.class == SPAWN_POINT_CLASS {
+      return Ok(Placement::Spawn(SpawnPoint {
+        name: self.name,
+        position: glm::vec2(x, y),
+      }));
+    }
+
+    let prototype = match self.properties.remove(properties::PROTOTYPE) {
+      Some(PropertyValue::String(id)) => id,
+      Some(_) => {
+        return Err(format!(
+          "object '{}' prototype property must be a string",
+          self.name
+        ))
+      }
+      None if !self.class.is_empty() => self.class.clone(),
+      None => {
+        return Err(format!(
+          "object '{}' needs a class or a prototype property",
+          self.name
+        ))
+      }
+    };
+
+    Ok(Placement::Object(Box::new(MapObject {
+      name: (!self.name.is_empty()).then(|| self.name.clone()),
+      prototype: prototype
+        .parse::<AssetId>()
+        .map_err(|e| format!("object '{}' prototype {}", self.name, e))?,
+      position: glm::vec2(x, y),
+      rotation: self.rotation,
+      scale: glm::vec2(1.0, 1.0),
+      overrides: PrototypeOverrides::default(),
+      properties: self.properties,
+    })))
+  }
+}
+
+fn property_value(kind: &str, value: &str) -> Result<PropertyValue, String> {
+  match kind {
+    "bool" => value
+      .parse::<bool>()
+      .map(PropertyValue::Bool)
+      .map_err(|e| e.to_string()),
+    "int" | "object" => value
+      .parse::<i64>()
+      .map(PropertyValue::Int)
+      .map_err(|e| e.to_string()),
+    "float" => value
+      .parse::<f64>()
+      .map(PropertyValue::Float)
+      .map_err(|e| e.to_string()),
+    "string" | "color" | "file" | "" => Ok(PropertyValue::String(value.to_string())),
+    invalid => Err(format!("unsupported property type '{}'", invalid)),
+  }
+}
+
+fn decode_base64(data: &str) -> Result<Vec<u8>, String> {
+  let mut bytes = Vec::with_capacity(data.len() * 3 / 4);
+  let mut buffer: u32 = 0;
+  let mut bits = 0;
+
+  for c in data.bytes() {
+    let value = match c {
+      b'A'..=b'Z' => c - b'A',
+      b'a'..=b'z' => c - b'a' + 26,
+      b'0'..=b'9' => c - b'0' + 52,
+      b'+' => 62,
+      b'/' => 63,
+      b'=' => break,
+      c if c.is_ascii_whitespace() => continue,
+      invalid => return Err(format!("invalid base64 character '{}'", invalid as char)),
+    };
+
+    buffer = (buffer << 6) | value as u32;
+    bits += 6;
+
+    if bits >= 8 {
+      bits -= 8;
+      bytes.push((buffer >> bits) as u8);
+      buffer &= (1 << bits) - 1;
+    }
+  }
+
+  Ok(bytes)
+}
+
+fn decompress_gzip(data: &[u8]) -> Result<Vec<u8>, String> {
+  const FEXTRA: u8 = 0x04;
+  const FNAME: u8 = 0x08;
+  const FCOMMENT: u8 = 0x10;
+  const FHCRC: u8 = 0x02;
+
+  if data.len() < 18 || data[0] != 0x1f || data[1] != 0x8b {
+    return Err(String::from("invalid gzip header"));
+  }
+
+  let flags = data[3];
+  let mut offset = 10;
+
+  if flags & FEXTRA != 0 {
+    let len = *data.get(offset).ok_or("truncated gzip header")? as usize
+      | (*data.get(offset + 1).ok_or("truncated gzip header")? as usize) << 8;
+    offset += 2 + len;
+  }
+
+  for flag in [FNAME, FCOMMENT] {
+    if flags & flag != 0 {
+      while *data.get(offset).ok_or("truncated gzip header")? != 0 {
+        offset += 1;
+      }
+      offset += 1;
+    }
+  }
+
+  if flags & FHCRC != 0 {
+    offset += 2;
+  }
+
+  let body = data
+    .get(offset..data.len() - 8)
+    .ok_or("truncated gzip data")?;
+  miniz_oxide::inflate::decompress_to_vec(body).map_err(|e| format!("cannot inflate: {:?}", e))
+}
+
+/// Decodes base64 layer data, inflating it first when it is compressed.
+fn decode_tile_data(data: &str, compression: Option<&str>) -> Result<Vec<Gid>, String> {
+  let bytes = decode_base64(data)?;
+
+  let bytes = match compression {
+    None | Some("") => bytes,
+    Some("zlib") => miniz_oxide::inflate::decompress_to_vec_zlib(&bytes)
+      .map_err(|e| format!("cannot inflate: {:?}", e))?,
+    Some("gzip") => decompress_gzip(&bytes)?,
+    Some(invalid) => return Err(format!("unsupported compression '{}'", invalid)),
+  };
+
+  if bytes.len() % 4 != 0 {
+    return Err(String::from("layer data is not a whole number of tiles"));
+  }
+
+  Ok(
+    bytes
+      .chunks_exact(4)
+      .map(|b| strip_flags(u32::from_le_bytes([b[0], b[1], b[2], b[3]])))
+      .collect(),
+  )
+}
+
+fn decode_csv(data: &str) -> Result<Vec<Gid>, String> {
+  data
+    .split(',')
+    .map(str::trim)
+    .filter(|s| !s.is_empty())
+    .map(|s| {
+      s.parse::<u32>()
+        .map(strip_flags)
+        .map_err(|_| format!("invalid tile '{}' in csv data", s))
+    })
+    .collect()
+}
+
+/// Only the orthogonal, fixed size maps the engine renders can be imported.
+fn check_map_kind(orientation: &str, infinite: bool) -> Result<(), String> {
+  if orientation != "orthogonal" {
+    return Err(format!("unsupported orientation '{}'", orientation));
+  }
+
+  if infinite {
+    return Err(String::from("infinite maps are not supported"));
+  }
+
+  Ok(())
+}
+
+/// Builds an object layer, moving spawn point objects into the map instead.
+fn object_layer(
+  map: &mut Map,
+  name: String,
+  visible: bool,
+  objects: Vec<RawObject>,
+  properties: Properties,
+) -> Result<ObjectLayer, String> {
+  let mut layer = ObjectLayer {
+    name,
+    visible,
+    objects: Vec::default(),
+    areas: Vec::default(),
+    properties,
+  };
+
+  for object in objects {
+    match object
+      .into_placement()
+      .map_err(|e| format!("layer '{}': {}", layer.name, e))?
+    {
+      Placement::Object(object) => layer.objects.push(*object),
+      Placement::Spawn(spawn) => map.spawn_points.push(spawn),
+      Placement::Area(area) => layer.areas.push(area),
+    }
+  }
+
+  Ok(layer)
+}
+
+/// Layers inside groups keep the group names as a prefix, so they stay unique.
+fn group_name(group: Option<&str>, name: &str) -> String {
+  match group {
+    Some(group) => format!("{}/{}", group, name),
+    None => name.to_string(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const TILES: [Gid; 6] = [1, 2, 0, 3, 4, 1];
+
+  fn tile_bytes(tiles: &[Gid]) -> Vec<u8> {
+    tiles.iter().flat_map(|gid| gid.to_le_bytes()).collect()
+  }
+
+  fn encode_base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+      let buffer = chunk.iter().enumerate().fold(0u32, |buffer, (i, byte)| {
+        buffer | (*byte as u32) << (16 - 8 * i)
+      });
+      for i in 0..4 {
+        if i <= chunk.len() {
+          encoded.push(ALPHABET[(buffer >> (18 - 6 * i) & 0x3f) as usize] as char);
+        } else {
+          encoded.push('=');
+        }
+      }
+    }
+    encoded
+  }
+
+  /// A gzip member with an extra field and a file name before the data. The trailer
+  /// is left zeroed, the decoder does not check it.
+  fn gzip(bytes: &[u8]) -> Vec<u8> {
+    let mut data = vec![0x1f, 0x8b, 8, 0x04 | 0x08, 0, 0, 0, 0, 0, 0xff];
+    data.extend_from_slice(&[3, 0, b'a', b'b', b'c']);
+    data.extend_from_slice(b"ground.bin\0");
+    data.extend(miniz_oxide::deflate::compress_to_vec(bytes, 6));
+    data.extend_from_slice(&[0; 8]);
+    data
+  }
+
+  #[test]
+  fn base64_decodes_with_and_without_padding() {
+    assert_eq!(decode_base64("TWFu").unwrap(), b"Man");
+    assert_eq!(decode_base64("TWE=").unwrap(), b"Ma");
+    assert_eq!(decode_base64("TQ==").unwrap(), b"M");
+    assert_eq!(decode_base64("").unwrap(), b"");
+  }
+
+  #[test]
+  fn base64_skips_whitespace() {
+    assert_eq!(decode_base64("\n   TW\tFu\r\n  TQ==\n").unwrap(), b"ManM");
+    assert!(decode_base64("TW*u").is_err());
+  }
+
+  #[test]
+  fn uncompressed_tile_data_round_trips() {
+    let data = encode_base64(&tile_bytes(&TILES));
+    assert_eq!(decode_tile_data(&data, None).unwrap(), TILES);
+    assert_eq!(decode_tile_data(&data, Some("")).unwrap(), TILES);
+  }
+
+  #[test]
+  fn zlib_tile_data_round_trips() {
+    let compressed = miniz_oxide::deflate::compress_to_vec_zlib(&tile_bytes(&TILES), 6);
+    let data = encode_base64(&compressed);
+    assert_eq!(decode_tile_data(&data, Some("zlib")).unwrap(), TILES);
+  }
+
+  #[test]
+  fn gzip_tile_data_round_trips() {
+    let data = encode_base64(&gzip(&tile_bytes(&TILES)));
+    assert_eq!(decode_tile_data(&data, Some("gzip")).unwrap(), TILES);
+  }
+
+  #[test]
+  fn gzip_needs_a_whole_header() {
+    assert!(decompress_gzip(&[0x1f, 0x8b, 8, 0]).is_err());
+    let mut data = gzip(&tile_bytes(&TILES));
+    data[0] = 0;
+    assert!(decompress_gzip(&data).is_err());
+  }
+
+  #[test]
+  fn tile_data_strips_flip_flags() {
+    let data = encode_base64(&tile_bytes(&[FLIP_FLAGS | 3, 0x8000_0001]));
+    assert_eq!(decode_tile_data(&data, None).unwrap(), [3, 1]);
+    assert!(decode_tile_data(&encode_base64(&[1, 0, 0]), None).is_err());
+  }
+
+  #[test]
+  fn csv_ignores_trailing_newlines() {
+    assert_eq!(decode_csv("\n1,2,0,\n3,4,1\n\n").unwrap(), TILES);
+    assert!(decode_csv("1,x,0").is_err());
+  }
+}
@@ -0,0 +1,334 @@
+use super::{
+  check_map_kind, decode_tile_data, group_name, object_layer, strip_flags, RawObject, RawTileset,
+};
+use crate::map::{
+  Gid, Layer, Map, Properties, PropertyValue, TileFrame, TileInfo, TileLayer, Tileset,
+};
+use log::warn;
+use serde_json::Value;
+use std::{collections::BTreeMap, fs, path::Path};
+
+fn u32_of(value: &Value, key: &str) -> Result<Option<u32>, String> {
+  match value.get(key) {
+    Some(v) => v
+      .as_u64()
+      .and_then(|v| u32::try_from(v).ok())
+      .map(Some)
+      .ok_or_else(|| format!("'{}' must be a positive integer", key)),
+    None => Ok(None),
+  }
+}
+
+fn required_u32(value: &Value, key: &str) -> Result<u32, String> {
+  u32_of(value, key)?.ok_or_else(|| format!("missing '{}'", key))
+}
+
+fn f32_of(value: &Value, key: &str) -> Result<f32, String> {
+  match value.get(key) {
+    Some(v) => v
+      .as_f64()
+      .map(|v| v as f32)
+      .ok_or_else(|| format!("'{}' must be a number", key)),
+    None => Ok(0.0),
+  }
+}
+
+fn str_of<'a>(value: &'a Value, key: &str) -> Result<Option<&'a str>, String> {
+  match value.get(key) {
+    Some(v) => v
+      .as_str()
+      .map(Some)
+      .ok_or_else(|| format!("'{}' must be a string", key)),
+    None => Ok(None),
+  }
+}
+
+fn bool_of(value: &Value, key: &str, default: bool) -> Result<bool, String> {
+  match value.get(key) {
+    Some(v) => v
+      .as_bool()
+      .ok_or_else(|| format!("'{}' must be a boolean", key)),
+    None => Ok(default),
+  }
+}
+
+fn array_of<'a>(value: &'a Value, key: &str) -> Result<&'a [Value], String> {
+  match value.get(key) {
+    Some(v) => v
+      .as_array()
+      .map(Vec::as_slice)
+      .ok_or_else(|| format!("'{}' must be an array", key)),
+    None => Ok(&[]),
+  }
+}
+
+fn property_of(kind: &str, value: &Value) -> Option<PropertyValue> {
+  match kind {
+    "bool" => value.as_bool().map(PropertyValue::Bool),
+    "int" | "object" => value.as_i64().map(PropertyValue::Int),
+    "float" => value.as_f64().map(PropertyValue::Float),
+    "string" | "color" | "file" | "" => {
+      value.as_str().map(|s| PropertyValue::String(s.to_string()))
+    }
+    _ => None,
+  }
+}
+
+fn parse_properties(value: &Value) -> Result<Properties, String> {
+  let mut properties = Properties::default();
+
+  for property in array_of(value, "properties")? {
+    let name = str_of(property, "name")?.ok_or("property is missing 'name'")?;
+    let kind = str_of(property, "type")?.unwrap_or_default();
+    let value = property
+      .get("value")
+      .and_then(|value| property_of(kind, value))
+      .ok_or_else(|| format!("property '{}' is not a valid {}", name, kind))?;
+    properties.insert(name.to_string(), value);
+  }
+
+  Ok(properties)
+}
+
+fn parse_tileset(value: &Value, first_gid: Gid) -> Result<RawTileset, String> {
+  let mut tiles = BTreeMap::new();
+  for tile in array_of(value, "tiles")? {
+    let frames = array_of(tile, "animation")?
+      .iter()
+      .map(|frame| {
+        Ok(TileFrame {
+          tile: required_u32(frame, "tileid")?,
+          duration_ms: required_u32(frame, "duration")?,
+        })
+      })
+      .collect::<Result<Vec<_>, String>>()?;
+
+    tiles.insert(
+      required_u32(tile, "id")?,
+      TileInfo {
+        properties: parse_properties(tile)?,
+        animation: frames,
+      },
+    );
+  }
+
+  Ok(RawTileset {
+    name: str_of(value, "name")?.unwrap_or_default().to_string(),
+    first_gid,
+    tile_width: required_u32(value, "tilewidth")?,
+    tile_height: required_u32(value, "tileheight")?,
+    columns: required_u32(value, "columns")?,
+    tile_count: required_u32(value, "tilecount")?,
+    image: str_of(value, "image")?.map(str::to_string),
+    tiles,
+    properties: parse_properties(value)?,
+  })
+}
+
+fn read_document(path: &Path) -> Result<Value, String> {
+  let data =
+    fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+  serde_json::from_str::<Value>(&data)
+    .map_err(|e| format!("cannot parse {}: {}", path.display(), e))
+}
+
+/// Tilesets are either inline or refer to an external `.tsj` next to the map.
+fn load_tileset(value: &Value, dir: &Path) -> Result<Tileset, String> {
+  let first_gid = required_u32(value, "firstgid")?;
+
+  match str_of(value, "source")? {
+    Some(source) => {
+      let path = dir.join(source);
+      let root = read_document(&path)?;
+      let tileset_dir = path.parent().unwrap_or(dir);
+      parse_tileset(&root, first_gid)
+        .and_then(|tileset| tileset.resolve(tileset_dir))
+        .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+    None => parse_tileset(value, first_gid)?.resolve(dir),
+  }
+}
+
+fn parse_tile_layer(value: &Value, name: String, visible: bool) -> Result<TileLayer, String> {
+  let tiles = match value.get("data") {
+    Some(Value::Array(tiles)) => tiles
+      .iter()
+      .map(|tile| {
+        tile
+          .as_u64()
+          .and_then(|gid| Gid::try_from(gid).ok())
+          .map(strip_flags)
+          .ok_or_else(|| format!("invalid tile {}", tile))
+      })
+      .collect(),
+    Some(Value::String(data)) => match str_of(value, "encoding")? {
+      Some("base64") => decode_tile_data(data, str_of(value, "compression")?),
+      encoding => Err(format!(
+        "unsupported encoding '{}'",
+        encoding.unwrap_or_default()
+      )),
+    },
+    _ => Err(String::from("missing 'data'")),
+  }
+  .map_err(|e| format!("layer '{}': {}", name, e))?;
+
+  Ok(TileLayer {
+    name,
+    width: required_u32(value, "width")?,
+    height: required_u32(value, "height")?,
+    visible,
+    tiles,
+    properties: parse_properties(value)?,
+  })
+}
+
+fn parse_points(value: &Value) -> Result<Option<Vec<(f32, f32)>>, String> {
+  match value.get("polygon") {
+    Some(_) => array_of(value, "polygon")?
+      .iter()
+      .map(|point| Ok((f32_of(point, "x")?, f32_of(point, "y")?)))
+      .collect::<Result<Vec<_>, String>>()
+      .map(Some),
+    None => Ok(None),
+  }
+}
+
+fn parse_object(value: &Value) -> Result<RawObject, String> {
+  Ok(RawObject {
+    name: str_of(value, "name")?.unwrap_or_default().to_string(),
+    // tiled renamed the object type to class in 1.9
+    class: str_of(value, "class")?
+      .or(str_of(value, "type")?)
+      .unwrap_or_default()
+      .to_string(),
+    x: f32_of(value, "x")?,
+    y: f32_of(value, "y")?,
+    width: f32_of(value, "width")?,
+    height: f32_of(value, "height")?,
+    rotation: f32_of(value, "rotation")?,
+    gid: u32_of(value, "gid")?.map(strip_flags),
+    points: parse_points(value)?,
+    properties: parse_properties(value)?,
+  })
+}
+
+fn parse_layers(
+  map: &mut Map,
+  layers: &[Value],
+  group: Option<&str>,
+  visible: bool,
+) -> Result<(), String> {
+  for layer in layers {
+    let name = group_name(group, str_of(layer, "name")?.unwrap_or_default());
+    let visible = visible && bool_of(layer, "visible", true)?;
+
+    match str_of(layer, "type")?.unwrap_or_default() {
+      "tilelayer" => {
+        let layer = parse_tile_layer(layer, name, visible)?;
+        map.layers.push(Layer::Tiles(layer));
+      }
+      "objectgroup" => {
+        let objects = array_of(layer, "objects")?
+          .iter()
+          .map(parse_object)
+          .collect::<Result<Vec<_>, String>>()
+          .map_err(|e| format!("layer '{}': {}", name, e))?;
+        let properties = parse_properties(layer)?;
+        let layer = object_layer(map, name, visible, objects, properties)?;
+        map.layers.push(Layer::Objects(layer));
+      }
+      "group" => parse_layers(map, array_of(layer, "layers")?, Some(&name), visible)?,
+      "imagelayer" => warn!("skipping image layer '{}'", name),
+      invalid => return Err(format!("unknown layer type '{}'", invalid)),
+    }
+  }
+
+  Ok(())
+}
+
+fn parse_map(root: &Value, dir: &Path) -> Result<Map, String> {
+  check_map_kind(
+    str_of(root, "orientation")?.unwrap_or_default(),
+    bool_of(root, "infinite", false)?,
+  )?;
+
+  let mut map = Map::new(
+    required_u32(root, "width")?,
+    required_u32(root, "height")?,
+    required_u32(root, "tilewidth")?,
+    required_u32(root, "tileheight")?,
+  );
+  map.properties = parse_properties(root)?;
+
+  for tileset in array_of(root, "tilesets")? {
+    map.tilesets.push(load_tileset(tileset, dir)?);
+  }
+
+  parse_layers(&mut map, array_of(root, "layers")?, None, true)?;
+
+  map.validate()?;
+
+  Ok(map)
+}
+
+pub fn read_tmj(path: &Path) -> Result<Map, String> {
+  let root = read_document(path)?;
+  let dir = path.parent().unwrap_or_else(|| Path::new("."));
+  parse_map(&root, dir).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::math::glm;
+
+  #[test]
+  fn minimal_map_loads() {
+    let map = read_tmj(Path::new("tests/maps/minimal.tmj")).unwrap();
+    assert_eq!((map.width, map.height), (3, 2));
+    assert_eq!(map.tilesets[0].texture.to_string(), "exp.test.ground");
+    assert_eq!(map.tile_layer("ground").unwrap().tiles, [1, 2, 0, 3, 4, 1]);
+
+    assert_eq!(map.spawn_points[0].name, "player");
+    assert_eq!(map.spawn_points[0].position, glm::vec2(8.0, 24.0));
+    let object = &map.object_layers().next().unwrap().objects[0];
+    assert_eq!(object.prototype.to_string(), "exp.test.crate");
+    assert_eq!(object.position, glm::vec2(24.0, 8.0));
+  }
+
+  #[test]
+  fn external_tilesets_load_with_their_animations() {
+    let map = read_tmj(Path::new("tests/maps/external.tmj")).unwrap();
+    let tileset = &map.tilesets[0];
+    assert_eq!(tileset.name, "water");
+    assert_eq!(tileset.first_gid, 1);
+    assert_eq!(tileset.texture.to_string(), "exp.test.water");
+
+    let tile = tileset.tile(1).unwrap();
+    assert_eq!(tile.properties["solid"], PropertyValue::Bool(true));
+    let frames: Vec<_> = tile
+      .animation
+      .iter()
+      .map(|frame| (frame.tile, frame.duration_ms))
+      .collect();
+    assert_eq!(frames, [(0, 100), (1, 150), (2, 200)]);
+    assert!(tileset.tile(4).is_none());
+  }
+
+  #[test]
+  fn unknown_layer_types_are_rejected() {
+    let root = serde_json::from_str::<Value>(
+      r#"{
+        "orientation": "orthogonal",
+        "width": 1,
+        "height": 1,
+        "tilewidth": 16,
+        "tileheight": 16,
+        "layers": [{ "type": "mystery", "name": "what" }]
+      }"#,
+    )
+    .unwrap();
+    let error = parse_map(&root, Path::new("tests/maps")).unwrap_err();
+    assert!(error.contains("mystery"), "{}", error);
+  }
+}
@@ -0,0 +1,359 @@
+use super::{
+  check_map_kind, decode_csv, decode_tile_data, group_name, object_layer, property_value,
+  strip_flags, RawObject, RawTileset,
+};
+use crate::map::{Gid, Layer, Map, Properties, TileFrame, TileInfo, TileLayer, Tileset};
+use log::warn;
+use std::{collections::BTreeMap, fs, path::Path, str::FromStr};
+use xml::reader::{EventReader, XmlEvent};
+
+/// Just enough of an xml tree to walk tiled documents.
+struct Element {
+  name: String,
+  attributes: BTreeMap<String, String>,
+  children: Vec<Element>,
+  text: String,
+}
+
+impl Element {
+  fn attr(&self, name: &str) -> Option<&str> {
+    self.attributes.get(name).map(String::as_str)
+  }
+
+  fn parse<T: FromStr>(&self, name: &str) -> Result<Option<T>, String> {
+    self
+      .attr(name)
+      .map(|value| {
+        value
+          .parse::<T>()
+          .map_err(|_| format!("invalid {} '{}' on <{}>", name, value, self.name))
+      })
+      .transpose()
+  }
+
+  fn required<T: FromStr>(&self, name: &str) -> Result<T, String> {
+    self
+      .parse(name)?
+      .ok_or_else(|| format!("<{}> is missing '{}'", self.name, name))
+  }
+
+  /// Tiled writes booleans as `0` and `1`.
+  fn flag(&self, name: &str, default: bool) -> Result<bool, String> {
+    Ok(self.parse::<u32>(name)?.map_or(default, |flag| flag != 0))
+  }
+
+  fn child(&self, name: &str) -> Option<&Element> {
+    self.children.iter().find(|child| child.name == name)
+  }
+
+  fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+    self.children.iter().filter(move |child| child.name == name)
+  }
+}
+
+fn parse_document(data: &str) -> Result<Element, String> {
+  let mut stack: Vec<Element> = Vec::new();
+  let mut root = None;
+
+  for event in EventReader::from_str(data) {
+    match event.map_err(|e| e.to_string())? {
+      XmlEvent::StartElement {
+        name, attributes, ..
+      } => stack.push(Element {
+        name: name.local_name,
+        attributes: attributes
+          .into_iter()
+          .map(|attribute| (attribute.name.local_name, attribute.value))
+          .collect(),
+        children: Vec::default(),
+        text: String::default(),
+      }),
+      XmlEvent::EndElement { .. } => {
+        let element = stack.pop().ok_or("unbalanced document")?;
+        match stack.last_mut() {
+          Some(parent) => parent.children.push(element),
+          None => root = Some(element),
+        }
+      }
+      XmlEvent::Characters(text) | XmlEvent::CData(text) => {
+        if let Some(element) = stack.last_mut() {
+          element.text.push_str(&text);
+        }
+      }
+      _ => {}
+    }
+  }
+
+  root.ok_or_else(|| String::from("empty document"))
+}
+
+fn read_document(path: &Path) -> Result<Element, String> {
+  let data =
+    fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+  parse_document(&data).map_err(|e| format!("cannot parse {}: {}", path.display(), e))
+}
+
+fn parse_properties(element: &Element) -> Result<Properties, String> {
+  let mut properties = Properties::default();
+
+  for property in element
+    .child("properties")
+    .into_iter()
+    .flat_map(|properties| properties.children("property"))
+  {
+    let name = property.required::<String>("name")?;
+    // multiline strings are stored as text instead of a value attribute
+    let value = property.attr("value").unwrap_or(&property.text);
+    let value = property_value(property.attr("type").unwrap_or(""), value)
+      .map_err(|e| format!("property '{}': {}", name, e))?;
+    properties.insert(name, value);
+  }
+
+  Ok(properties)
+}
+
+fn parse_tileset(element: &Element, first_gid: Gid) -> Result<RawTileset, String> {
+  let mut tiles = BTreeMap::new();
+  for tile in element.children("tile") {
+    let frames = tile
+      .child("animation")
+      .into_iter()
+      .flat_map(|animation| animation.children("frame"))
+      .map(|frame| {
+        Ok(TileFrame {
+          tile: frame.required("tileid")?,
+          duration_ms: frame.required("duration")?,
+        })
+      })
+      .collect::<Result<Vec<_>, String>>()?;
+
+    tiles.insert(
+      tile.required::<u32>("id")?,
+      TileInfo {
+        properties: parse_properties(tile)?,
+        animation: frames,
+      },
+    );
+  }
+
+  Ok(RawTileset {
+    name: element.required("name")?,
+    first_gid,
+    tile_width: element.required("tilewidth")?,
+    tile_height: element.required("tileheight")?,
+    columns: element.required("columns")?,
+    tile_count: element.required("tilecount")?,
+    image: element
+      .child("image")
+      .and_then(|image| image.attr("source"))
+      .map(str::to_string),
+    tiles,
+    properties: parse_properties(element)?,
+  })
+}
+
+/// Tilesets are either inline or refer to an external `.tsx` next to the map.
+fn load_tileset(element: &Element, dir: &Path) -> Result<Tileset, String> {
+  let first_gid = element.required("firstgid")?;
+
+  match element.attr("source") {
+    Some(source) => {
+      let path = dir.join(source);
+      let root = read_document(&path)?;
+      let tileset_dir = path.parent().unwrap_or(dir);
+      parse_tileset(&root, first_gid)
+        .and_then(|tileset| tileset.resolve(tileset_dir))
+        .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+    None => parse_tileset(element, first_gid)?.resolve(dir),
+  }
+}
+
+fn parse_tile_layer(element: &Element, name: String, visible: bool) -> Result<TileLayer, String> {
+  let data = element
+    .child("data")
+    .ok_or_else(|| format!("layer '{}' has no data", name))?;
+
+  let tiles = match data.attr("encoding") {
+    Some("csv") => decode_csv(&data.text),
+    Some("base64") => decode_tile_data(data.text.trim(), data.attr("compression")),
+    Some(invalid) => Err(format!("unsupported encoding '{}'", invalid)),
+    None => data
+      .children("tile")
+      .map(|tile| Ok(strip_flags(tile.parse("gid")?.unwrap_or_default())))
+      .collect(),
+  }
+  .map_err(|e| format!("layer '{}': {}", name, e))?;
+
+  Ok(TileLayer {
+    name,
+    width: element.required("width")?,
+    height: element.required("height")?,
+    visible,
+    tiles,
+    properties: parse_properties(element)?,
+  })
+}
+
+/// Polygon points are written as `x,y` pairs separated by spaces.
+fn parse_points(points: &str) -> Result<Vec<(f32, f32)>, String> {
+  points
+    .split_whitespace()
+    .map(|point| {
+      let (x, y) = point
+        .split_once(',')
+        .ok_or_else(|| format!("invalid polygon point '{}'", point))?;
+      match (x.parse::<f32>(), y.parse::<f32>()) {
+        (Ok(x), Ok(y)) => Ok((x, y)),
+        _ => Err(format!("invalid polygon point '{}'", point)),
+      }
+    })
+    .collect()
+}
+
+fn parse_object(element: &Element) -> Result<RawObject, String> {
+  Ok(RawObject {
+    name: element.attr("name").unwrap_or_default().to_string(),
+    // tiled renamed the object type to class in 1.9
+    class: element
+      .attr("class")
+      .or_else(|| element.attr("type"))
+      .unwrap_or_default()
+      .to_string(),
+    x: element.required("x")?,
+    y: element.required("y")?,
+    width: element.parse("width")?.unwrap_or_default(),
+    height: element.parse("height")?.unwrap_or_default(),
+    rotation: element.parse("rotation")?.unwrap_or_default(),
+    gid: element.parse::<Gid>("gid")?.map(strip_flags),
+    points: element
+      .child("polygon")
+      .map(|polygon| parse_points(polygon.attr("points").unwrap_or_default()))
+      .transpose()?,
+    properties: parse_properties(element)?,
+  })
+}
+
+fn parse_layers(
+  map: &mut Map,
+  element: &Element,
+  group: Option<&str>,
+  visible: bool,
+) -> Result<(), String> {
+  for child in &element.children {
+    let name = group_name(group, child.attr("name").unwrap_or_default());
+    let visible = visible && child.flag("visible", true)?;
+
+    match child.name.as_str() {
+      "layer" => {
+        let layer = parse_tile_layer(child, name, visible)?;
+        map.layers.push(Layer::Tiles(layer));
+      }
+      "objectgroup" => {
+        let objects = child
+          .children("object")
+          .map(parse_object)
+          .collect::<Result<Vec<_>, String>>()
+          .map_err(|e| format!("layer '{}': {}", name, e))?;
+        let properties = parse_properties(child)?;
+        let layer = object_layer(map, name, visible, objects, properties)?;
+        map.layers.push(Layer::Objects(layer));
+      }
+      "group" => parse_layers(map, child, Some(&name), visible)?,
+      "imagelayer" => warn!("skipping image layer '{}'", name),
+      // the rest of what a map or group holds besides its layers
+      "properties" | "tileset" | "editorsettings" => {}
+      invalid => return Err(format!("unknown layer type '{}'", invalid)),
+    }
+  }
+
+  Ok(())
+}
+
+fn parse_map(root: &Element, dir: &Path) -> Result<Map, String> {
+  if root.name != "map" {
+    return Err(format!("expected <map> but found <{}>", root.name));
+  }
+
+  check_map_kind(
+    root.attr("orientation").unwrap_or_default(),
+    root.flag("infinite", false)?,
+  )?;
+
+  let mut map = Map::new(
+    root.required("width")?,
+    root.required("height")?,
+    root.required("tilewidth")?,
+    root.required("tileheight")?,
+  );
+  map.properties = parse_properties(root)?;
+
+  for tileset in root.children("tileset") {
+    map.tilesets.push(load_tileset(tileset, dir)?);
+  }
+
+  parse_layers(&mut map, root, None, true)?;
+
+  map.validate()?;
+
+  Ok(map)
+}
+
+pub fn read_tmx(path: &Path) -> Result<Map, String> {
+  let root = read_document(path)?;
+  let dir = path.parent().unwrap_or_else(|| Path::new("."));
+  parse_map(&root, dir).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::map::PropertyValue;
+  use crate::math::glm;
+
+  #[test]
+  fn minimal_map_loads() {
+    let map = read_tmx(Path::new("tests/maps/minimal.tmx")).unwrap();
+    assert_eq!((map.width, map.height), (3, 2));
+    assert_eq!(map.tilesets[0].texture.to_string(), "exp.test.ground");
+    assert_eq!(map.tile_layer("ground").unwrap().tiles, [1, 2, 0, 3, 4, 1]);
+
+    assert_eq!(map.spawn_points[0].name, "player");
+    assert_eq!(map.spawn_points[0].position, glm::vec2(8.0, 24.0));
+    let object = &map.object_layers().next().unwrap().objects[0];
+    assert_eq!(object.prototype.to_string(), "exp.test.crate");
+    assert_eq!(object.position, glm::vec2(24.0, 8.0));
+  }
+
+  #[test]
+  fn external_tilesets_load_with_their_animations() {
+    let map = read_tmx(Path::new("tests/maps/external.tmx")).unwrap();
+    let tileset = &map.tilesets[0];
+    assert_eq!(tileset.name, "water");
+    assert_eq!(tileset.first_gid, 1);
+    assert_eq!(tileset.texture.to_string(), "exp.test.water");
+
+    let tile = tileset.tile(1).unwrap();
+    assert_eq!(tile.properties["solid"], PropertyValue::Bool(true));
+    let frames: Vec<_> = tile
+      .animation
+      .iter()
+      .map(|frame| (frame.tile, frame.duration_ms))
+      .collect();
+    assert_eq!(frames, [(0, 100), (1, 150), (2, 200)]);
+    assert!(tileset.tile(4).is_none());
+  }
+
+  #[test]
+  fn unknown_layer_elements_are_rejected() {
+    let root = parse_document(
+      r#"<map orientation="orthogonal" width="1" height="1" tilewidth="16" tileheight="16">
+        <properties/>
+        <mystery name="what"/>
+      </map>"#,
+    )
+    .unwrap();
+    let error = parse_map(&root, Path::new("tests/maps")).unwrap_err();
+    assert!(error.contains("mystery"), "{}", error);
+  }
+}
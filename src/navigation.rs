@@ -0,0 +1,15 @@
+mod astar;
+mod flow_field;
+mod funnel;
+mod geometry;
+mod grid;
+mod jps;
+mod navmesh;
+mod smoothing;
+
+pub use astar::find_path;
+pub use flow_field::FlowField;
+pub use grid::{DiagonalMovement, NavGrid};
+pub use jps::jump_point_search;
+pub use navmesh::NavMesh;
+pub use smoothing::{line_of_sight, smooth_path};
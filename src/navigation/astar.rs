@@ -0,0 +1,170 @@
+use super::grid::{distance, Cell, DiagonalMovement, NavGrid};
+use std::{
+  cmp::Ordering,
+  collections::{BTreeMap, BinaryHeap},
+};
+
+/// An open node, ordered so the heap pops the lowest estimate first and breaks ties
+/// towards the goal, then by node so searches are deterministic.
+struct Open<N> {
+  estimate: f32,
+  remaining: f32,
+  node: N,
+}
+
+impl<N: Ord> PartialEq for Open<N> {
+  fn eq(&self, other: &Self) -> bool {
+    self.cmp(other) == Ordering::Equal
+  }
+}
+
+impl<N: Ord> Eq for Open<N> {}
+
+impl<N: Ord> PartialOrd for Open<N> {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl<N: Ord> Ord for Open<N> {
+  fn cmp(&self, other: &Self) -> Ordering {
+    other
+      .estimate
+      .total_cmp(&self.estimate)
+      .then(other.remaining.total_cmp(&self.remaining))
+      .then(other.node.cmp(&self.node))
+  }
+}
+
+/// A best first search from `start` to `goal`. `successors` is given a cell and the cell
+/// it was reached from, and returns where the search can go next with what it costs.
+pub(super) fn search<N, H, S>(start: N, goal: N, heuristic: H, mut successors: S) -> Option<Vec<N>>
+where
+  N: Ord + Copy,
+  H: Fn(N) -> f32,
+  S: FnMut(N, Option<N>) -> Vec<(N, f32)>,
+{
+  let mut open = BinaryHeap::new();
+  let mut costs = BTreeMap::from([(start, 0.0f32)]);
+  let mut parents: BTreeMap<N, N> = BTreeMap::new();
+
+  open.push(Open {
+    estimate: heuristic(start),
+    remaining: heuristic(start),
+    node: start,
+  });
+
+  while let Some(Open { node, estimate, .. }) = open.pop() {
+    if node == goal {
+      let mut path = vec![goal];
+      while let Some(parent) = parents.get(path.last().unwrap_or(&goal)) {
+        path.push(*parent);
+      }
+      path.reverse();
+      return Some(path);
+    }
+
+    let cost = costs[&node];
+    // a cheaper way here was found after this entry was queued
+    if estimate > cost + heuristic(node) {
+      continue;
+    }
+
+    for (next, step) in successors(node, parents.get(&node).copied()) {
+      let next_cost = cost + step;
+      if costs.get(&next).is_some_and(|known| *known <= next_cost) {
+        continue;
+      }
+
+      costs.insert(next, next_cost);
+      parents.insert(next, node);
+      let remaining = heuristic(next);
+      open.push(Open {
+        estimate: next_cost + remaining,
+        remaining,
+        node: next,
+      });
+    }
+  }
+
+  None
+}
+
+/// The cheapest path between two cells with A*, both ends included.
+pub fn find_path(
+  grid: &NavGrid,
+  start: Cell,
+  goal: Cell,
+  diagonal: DiagonalMovement,
+) -> Option<Vec<Cell>> {
+  if !grid.is_walkable(start) || !grid.is_walkable(goal) {
+    return None;
+  }
+
+  let scale = grid.min_cost();
+  search(
+    start,
+    goal,
+    |cell| distance(cell, goal, diagonal) * scale,
+    |cell, _| grid.neighbors(cell, diagonal),
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn straight_line_on_an_open_grid() {
+    let grid = NavGrid::from_rows(&["....."]);
+
+    let path = find_path(&grid, (0, 0), (4, 0), DiagonalMovement::Never).unwrap();
+
+    assert_eq!(path, vec![(0, 0), (1, 0), (2, 0), (3, 0), (4, 0)]);
+  }
+
+  #[test]
+  fn goes_around_walls() {
+    let grid = NavGrid::from_rows(&["...", "##.", "..."]);
+
+    let path = find_path(&grid, (0, 0), (0, 2), DiagonalMovement::Never).unwrap();
+
+    assert_eq!(path.len(), 7);
+    assert!(path.iter().all(|cell| grid.is_walkable(*cell)));
+  }
+
+  #[test]
+  fn unreachable_goals_have_no_path() {
+    let grid = NavGrid::from_rows(&["..#..", "..#.."]);
+
+    assert_eq!(
+      find_path(&grid, (0, 0), (4, 0), DiagonalMovement::Always),
+      None
+    );
+    assert_eq!(
+      find_path(&grid, (0, 0), (2, 0), DiagonalMovement::Always),
+      None
+    );
+  }
+
+  #[test]
+  fn avoids_expensive_cells_when_cheaper() {
+    let grid = NavGrid::from_rows(&[".9.", "..."]);
+
+    let path = find_path(&grid, (0, 0), (2, 0), DiagonalMovement::Never).unwrap();
+
+    assert!(!path.contains(&(1, 0)));
+    assert_eq!(grid.path_cost(&path), 4.0);
+  }
+
+  #[test]
+  fn diagonal_modes_change_the_path() {
+    let grid = NavGrid::from_rows(&[".#", ".."]);
+
+    let always = find_path(&grid, (0, 0), (1, 1), DiagonalMovement::Always).unwrap();
+    let no_corners = find_path(&grid, (0, 0), (1, 1), DiagonalMovement::NoCornerCutting).unwrap();
+
+    assert_eq!(always, vec![(0, 0), (1, 1)]);
+    assert_eq!(no_corners, vec![(0, 0), (0, 1), (1, 1)]);
+  }
+}
@@ -0,0 +1,188 @@
+use super::grid::{step_length, Cell, DiagonalMovement, NavGrid, STEPS};
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+/// The cheapest step towards one goal from every cell of a grid, so any number of
+/// agents heading to the same place share a single search.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlowField {
+  width: u32,
+  height: u32,
+  goal: Cell,
+  distances: Vec<Option<f32>>,
+  directions: Vec<Option<Cell>>,
+}
+
+impl FlowField {
+  pub fn new(grid: &NavGrid, goal: Cell, diagonal: DiagonalMovement) -> Self {
+    let mut field = Self {
+      width: grid.width,
+      height: grid.height,
+      goal,
+      distances: vec![None; (grid.width * grid.height) as usize],
+      directions: vec![None; (grid.width * grid.height) as usize],
+    };
+    if !grid.is_walkable(goal) {
+      return field;
+    }
+
+    // dijkstra outwards from the goal, with each step costing what entering its cell does
+    let goal_index = field.index(goal);
+    field.distances[goal_index] = Some(0.0);
+    let mut open = BinaryHeap::from([(Reverse(Distance(0.0)), Reverse(goal))]);
+
+    while let Some((Reverse(Distance(distance)), Reverse(cell))) = open.pop() {
+      if field.distances[field.index(cell)].is_some_and(|known| known < distance) {
+        continue;
+      }
+      let cost = grid.cost(cell).unwrap_or_default();
+
+      for (dx, dy) in STEPS {
+        let from = (cell.0 - dx, cell.1 - dy);
+        if !grid.is_walkable(from) || !grid.can_step(from, (dx, dy), diagonal) {
+          continue;
+        }
+
+        let through = distance + cost * step_length((dx, dy));
+        let index = field.index(from);
+        if field.distances[index].is_none_or(|known| through < known) {
+          field.distances[index] = Some(through);
+          open.push((Reverse(Distance(through)), Reverse(from)));
+        }
+      }
+    }
+
+    for y in 0..grid.height as i32 {
+      for x in 0..grid.width as i32 {
+        let index = field.index((x, y));
+        if (x, y) == goal || field.distances[index].is_none() {
+          continue;
+        }
+
+        field.directions[index] = STEPS
+          .iter()
+          .filter(|step| grid.can_step((x, y), **step, diagonal))
+          .filter_map(|(dx, dy)| {
+            let to = (x + dx, y + dy);
+            let remaining = field.distance(to)?;
+            Some((
+              (*dx, *dy),
+              remaining + grid.cost(to)? * step_length((*dx, *dy)),
+            ))
+          })
+          .min_by(|(_, a), (_, b)| a.total_cmp(b))
+          .map(|(step, _)| step);
+      }
+    }
+
+    field
+  }
+
+  fn index(&self, (x, y): Cell) -> usize {
+    (y as u32 * self.width + x as u32) as usize
+  }
+
+  fn in_bounds(&self, (x, y): Cell) -> bool {
+    x >= 0 && y >= 0 && (x as u32) < self.width && (y as u32) < self.height
+  }
+
+  pub fn goal(&self) -> Cell {
+    self.goal
+  }
+
+  /// The cost of the cheapest path from a cell to the goal, `None` if there is none.
+  pub fn distance(&self, cell: Cell) -> Option<f32> {
+    if self.in_bounds(cell) {
+      self.distances[self.index(cell)]
+    } else {
+      None
+    }
+  }
+
+  /// The step to take from a cell, `None` at the goal and where it cannot be reached.
+  pub fn direction(&self, cell: Cell) -> Option<Cell> {
+    if self.in_bounds(cell) {
+      self.directions[self.index(cell)]
+    } else {
+      None
+    }
+  }
+
+  /// Follows the field from a cell to the goal, both ends included.
+  pub fn path(&self, from: Cell) -> Option<Vec<Cell>> {
+    self.distance(from)?;
+
+    let mut path = vec![from];
+    let mut cell = from;
+    while let Some((dx, dy)) = self.direction(cell) {
+      cell = (cell.0 + dx, cell.1 + dy);
+      path.push(cell);
+    }
+
+    Some(path)
+  }
+}
+
+/// Orders distances so they can go in a heap.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Distance(f32);
+
+impl Eq for Distance {}
+
+impl PartialOrd for Distance {
+  fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Ord for Distance {
+  fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+    self.0.total_cmp(&other.0)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::navigation::find_path;
+
+  #[test]
+  fn every_reachable_cell_leads_to_the_goal() {
+    let grid = NavGrid::from_rows(&["....#", ".##.#", "....#", "#...."]);
+    let field = FlowField::new(&grid, (4, 3), DiagonalMovement::NoCornerCutting);
+
+    for y in 0..4 {
+      for x in 0..5 {
+        if !grid.is_walkable((x, y)) {
+          assert_eq!(field.distance((x, y)), None);
+          continue;
+        }
+
+        let path = field.path((x, y)).unwrap();
+        assert_eq!(path.last(), Some(&(4, 3)));
+      }
+    }
+  }
+
+  #[test]
+  fn distances_match_astar() {
+    let grid = NavGrid::from_rows(&["..3..", ".#9#.", "....."]);
+    let diagonal = DiagonalMovement::Always;
+    let field = FlowField::new(&grid, (2, 2), diagonal);
+
+    let path = find_path(&grid, (0, 0), (2, 2), diagonal).unwrap();
+    let cost = grid.path_cost(&path);
+
+    assert!((field.distance((0, 0)).unwrap() - cost).abs() < 1e-4);
+    assert_eq!(field.direction((2, 2)), None);
+  }
+
+  #[test]
+  fn cut_off_cells_have_no_direction() {
+    let grid = NavGrid::from_rows(&["..#.."]);
+    let field = FlowField::new(&grid, (0, 0), DiagonalMovement::Always);
+
+    assert_eq!(field.direction((4, 0)), None);
+    assert_eq!(field.path((4, 0)), None);
+    assert_eq!(field.direction((1, 0)), Some((-1, 0)));
+  }
+}
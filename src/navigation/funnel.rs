@@ -0,0 +1,70 @@
+use super::geometry::cross;
+use crate::math::glm::Vec2;
+
+/// An edge crossed by a path, with its ends as seen when walking through it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) struct Portal {
+  pub left: Vec2,
+  pub right: Vec2,
+}
+
+/// The shortest path from `start` to `goal` through a corridor of portals, only turning
+/// at their corners. The funnel narrows portal by portal, and whenever one side crosses
+/// the other the corner it crossed becomes a turn in the path.
+pub(super) fn funnel(start: Vec2, goal: Vec2, portals: &[Portal]) -> Vec<Vec2> {
+  let mut corridor = Vec::with_capacity(portals.len() + 2);
+  corridor.push(Portal {
+    left: start,
+    right: start,
+  });
+  corridor.extend_from_slice(portals);
+  corridor.push(Portal {
+    left: goal,
+    right: goal,
+  });
+
+  let mut path = vec![start];
+  let (mut apex, mut left, mut right) = (start, start, start);
+  let (mut left_index, mut right_index) = (0, 0);
+
+  let mut i = 1;
+  while i < corridor.len() {
+    let portal = corridor[i];
+
+    // the right side moves in unless that would cross the left side
+    if cross(&apex, &right, &portal.right) >= 0.0 {
+      if apex == right || cross(&apex, &left, &portal.right) < 0.0 {
+        right = portal.right;
+        right_index = i;
+      } else {
+        apex = left;
+        path.push(apex);
+        right = apex;
+        right_index = left_index;
+        i = left_index + 1;
+        continue;
+      }
+    }
+
+    if cross(&apex, &left, &portal.left) <= 0.0 {
+      if apex == left || cross(&apex, &right, &portal.left) > 0.0 {
+        left = portal.left;
+        left_index = i;
+      } else {
+        apex = right;
+        path.push(apex);
+        left = apex;
+        left_index = right_index;
+        i = right_index + 1;
+        continue;
+      }
+    }
+
+    i += 1;
+  }
+
+  if path.last() != Some(&goal) {
+    path.push(goal);
+  }
+  path
+}
@@ -0,0 +1,177 @@
+use crate::math::glm::{self, Vec2};
+use geo::{algorithm::convex_hull::ConvexHull, LineString};
+
+/// Pieces smaller than this are dropped rather than becoming slivers in the mesh.
+const MIN_AREA: f32 = 1e-3;
+
+/// How far `c` is to the left of the line from `a` to `b`, twice the area of the triangle.
+pub(super) fn cross(a: &Vec2, b: &Vec2, c: &Vec2) -> f32 {
+  (b - a).perp(&(c - a))
+}
+
+pub(super) fn area(points: &[Vec2]) -> f32 {
+  points
+    .iter()
+    .zip(points.iter().cycle().skip(1))
+    .map(|(a, b)| a.perp(b))
+    .sum::<f32>()
+    / 2.0
+}
+
+/// The corners of a ring, without the repeated closing point or duplicates,
+/// wound so the area is positive.
+pub(super) fn ring(line: &LineString<f32>) -> Vec<Vec2> {
+  let mut points: Vec<Vec2> = Vec::new();
+  for coordinate in &line.0 {
+    let point = glm::vec2(coordinate.x, coordinate.y);
+    if points.last() != Some(&point) {
+      points.push(point);
+    }
+  }
+  if points.len() > 1 && points.first() == points.last() {
+    points.pop();
+  }
+
+  if area(&points) < 0.0 {
+    points.reverse();
+  }
+  points
+}
+
+fn in_triangle(point: &Vec2, a: &Vec2, b: &Vec2, c: &Vec2) -> bool {
+  cross(a, b, point) >= 0.0 && cross(b, c, point) >= 0.0 && cross(c, a, point) >= 0.0
+}
+
+/// Splits a simple polygon with positive area into triangles by clipping ears.
+pub(super) fn triangulate(points: &[Vec2]) -> Vec<[Vec2; 3]> {
+  let mut remaining = points.to_vec();
+  let mut triangles = Vec::new();
+
+  while remaining.len() > 3 {
+    let count = remaining.len();
+    let ear = (0..count).find(|i| {
+      let (a, b, c) = (
+        &remaining[(i + count - 1) % count],
+        &remaining[*i],
+        &remaining[(i + 1) % count],
+      );
+      cross(a, b, c) > 0.0
+        && remaining
+          .iter()
+          .filter(|p| *p != a && *p != b && *p != c)
+          .all(|p| !in_triangle(p, a, b, c))
+    });
+
+    // only degenerate polygons have no ear, whatever is left of them is dropped
+    let i = match ear {
+      Some(i) => i,
+      None => match (0..count).find(|i| {
+        let (a, b, c) = (
+          &remaining[(i + count - 1) % count],
+          &remaining[*i],
+          &remaining[(i + 1) % count],
+        );
+        cross(a, b, c).abs() <= f32::EPSILON
+      }) {
+        Some(collinear) => {
+          remaining.remove(collinear);
+          continue;
+        }
+        None => return triangles,
+      },
+    };
+
+    triangles.push([
+      remaining[(i + count - 1) % count],
+      remaining[i],
+      remaining[(i + 1) % count],
+    ]);
+    remaining.remove(i);
+  }
+
+  if remaining.len() == 3 && cross(&remaining[0], &remaining[1], &remaining[2]) > 0.0 {
+    triangles.push([remaining[0], remaining[1], remaining[2]]);
+  }
+  triangles
+}
+
+/// The convex hull of some points, each grown into an octagon around a circle of `radius`.
+pub(super) fn inflate(points: &[Vec2], radius: f32) -> Vec<Vec2> {
+  // the octagon corners sit further out than the radius, so its edges clear the circle
+  let reach = radius / (std::f32::consts::PI / 8.0).cos();
+  let mut grown = Vec::new();
+  for point in points {
+    if radius > 0.0 {
+      for i in 0..8 {
+        let angle = (i as f32 + 0.5) * std::f32::consts::FRAC_PI_4;
+        grown.push((point.x + reach * angle.cos(), point.y + reach * angle.sin()));
+      }
+    } else {
+      grown.push((point.x, point.y));
+    }
+  }
+
+  ring(LineString::from(grown).convex_hull().exterior())
+}
+
+/// The part of a convex polygon on the side of a line where `normal.dot(p) - offset`
+/// has the sign of `side`.
+fn clip(points: &[Vec2], normal: &Vec2, offset: f32, side: f32) -> Vec<Vec2> {
+  let distance = |p: &Vec2| (normal.dot(p) - offset) * side;
+  let mut clipped = Vec::new();
+
+  for (a, b) in points.iter().zip(points.iter().cycle().skip(1)) {
+    let (da, db) = (distance(a), distance(b));
+    if da >= 0.0 {
+      clipped.push(*a);
+    }
+    if (da >= 0.0) != (db >= 0.0) {
+      clipped.push(a + (b - a) * (da / (da - db)));
+    }
+  }
+
+  clipped
+}
+
+/// What is left of a convex polygon once a convex blocker is cut out of it, as convex pieces.
+pub(super) fn subtract(piece: &[Vec2], blocker: &[Vec2]) -> Vec<Vec<Vec2>> {
+  let center = blocker.iter().fold(glm::vec2(0.0, 0.0), |sum, p| sum + p) / blocker.len() as f32;
+  let mut pieces = Vec::new();
+  let mut remaining = piece.to_vec();
+
+  for (a, b) in blocker.iter().zip(blocker.iter().cycle().skip(1)) {
+    let edge = b - a;
+    let mut normal = match glm::vec2(edge.y, -edge.x).try_normalize(f32::EPSILON) {
+      Some(normal) => normal,
+      None => continue,
+    };
+    if normal.dot(&(a - center)) < 0.0 {
+      normal = -normal;
+    }
+    let offset = normal.dot(a);
+
+    let outside = clip(&remaining, &normal, offset, 1.0);
+    if area(&outside) > MIN_AREA {
+      pieces.push(outside);
+    }
+
+    remaining = clip(&remaining, &normal, offset, -1.0);
+    if area(&remaining) <= MIN_AREA {
+      return pieces;
+    }
+  }
+
+  // whatever is inside every edge is inside the blocker
+  pieces
+}
+
+pub(super) fn bounds(points: &[Vec2]) -> (Vec2, Vec2) {
+  points.iter().fold(
+    (glm::vec2(f32::MAX, f32::MAX), glm::vec2(f32::MIN, f32::MIN)),
+    |(min, max), point| (glm::min2(&min, point), glm::max2(&max, point)),
+  )
+}
+
+pub(super) fn overlaps((min_a, max_a): &(Vec2, Vec2), (min_b, max_b): &(Vec2, Vec2)) -> bool {
+  min_a.x <= max_b.x && min_b.x <= max_a.x && min_a.y <= max_b.y && min_b.y <= max_a.y
+}
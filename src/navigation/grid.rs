@@ -0,0 +1,327 @@
+use crate::map::{Map, EMPTY_TILE};
+use crate::math::glm::{self, Vec2};
+
+mod keys {
+  pub const SOLID: &str = "solid";
+  pub const COST: &str = "cost";
+}
+
+/// A tile position, x then y.
+pub type Cell = (i32, i32);
+
+/// Every single step, straight ones first.
+pub(super) const STEPS: [Cell; 8] = [
+  (1, 0),
+  (0, 1),
+  (-1, 0),
+  (0, -1),
+  (1, 1),
+  (-1, 1),
+  (-1, -1),
+  (1, -1),
+];
+
+pub(super) fn step_length((dx, dy): Cell) -> f32 {
+  if dx != 0 && dy != 0 {
+    std::f32::consts::SQRT_2
+  } else {
+    1.0
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagonalMovement {
+  Never,
+  /// Diagonal steps may squeeze between two blocked cells.
+  Always,
+  /// Diagonal steps need both cells beside them to be walkable.
+  NoCornerCutting,
+}
+
+/// What it costs to enter every cell of a map, `None` where a cell cannot be entered.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NavGrid {
+  pub width: u32,
+  pub height: u32,
+  /// The size of a cell in world units.
+  pub cell_size: Vec2,
+  costs: Vec<Option<f32>>,
+}
+
+impl NavGrid {
+  /// A grid where every cell costs 1 to enter.
+  pub fn new(width: u32, height: u32) -> Self {
+    Self {
+      width,
+      height,
+      cell_size: glm::vec2(1.0, 1.0),
+      costs: vec![Some(1.0); (width * height) as usize],
+    }
+  }
+
+  /// Builds a grid from tile layers, reading the `solid` and `cost` properties of their tiles.
+  /// Where layers overlap a cell is blocked if any tile blocks it, and costs the most any tile costs.
+  pub fn from_map(map: &Map, layers: &[&str]) -> Result<Self, String> {
+    let mut grid = Self::new(map.width, map.height);
+    grid.cell_size = glm::vec2(map.tile_width as f32, map.tile_height as f32);
+    let mut covered = vec![false; grid.costs.len()];
+
+    for name in layers {
+      let layer = map
+        .tile_layer(name)
+        .ok_or_else(|| format!("no tile layer named '{}'", name))?;
+
+      for (index, gid) in layer.tiles.iter().enumerate() {
+        if *gid == EMPTY_TILE || index >= grid.costs.len() {
+          continue;
+        }
+
+        let properties = map
+          .tileset_for(*gid)
+          .and_then(|tileset| tileset.tile(*gid))
+          .map(|tile| &tile.properties);
+        let property = |key: &str| properties.and_then(|properties| properties.get(key));
+
+        let solid = match property(keys::SOLID) {
+          Some(value) => value
+            .as_bool()
+            .ok_or_else(|| format!("tile {} '{}' must be a boolean", gid, keys::SOLID))?,
+          None => false,
+        };
+        let cost = match property(keys::COST) {
+          Some(value) => value
+            .as_float()
+            .filter(|cost| *cost > 0.0)
+            .ok_or_else(|| format!("tile {} '{}' must be a positive number", gid, keys::COST))?
+            as f32,
+          None => 1.0,
+        };
+
+        grid.costs[index] = match (grid.costs[index], solid) {
+          (_, true) | (None, _) => None,
+          // the first tile on a cell replaces the default rather than adding to it
+          (Some(_), false) if !covered[index] => Some(cost),
+          (Some(current), false) => Some(current.max(cost)),
+        };
+        covered[index] = true;
+      }
+    }
+
+    Ok(grid)
+  }
+
+  pub fn in_bounds(&self, (x, y): Cell) -> bool {
+    x >= 0 && y >= 0 && (x as u32) < self.width && (y as u32) < self.height
+  }
+
+  fn index(&self, (x, y): Cell) -> usize {
+    (y as u32 * self.width + x as u32) as usize
+  }
+
+  /// The cost of entering a cell, `None` if it is blocked or outside the grid.
+  pub fn cost(&self, cell: Cell) -> Option<f32> {
+    if self.in_bounds(cell) {
+      self.costs[self.index(cell)]
+    } else {
+      None
+    }
+  }
+
+  pub fn is_walkable(&self, cell: Cell) -> bool {
+    self.cost(cell).is_some()
+  }
+
+  pub fn set_cost(&mut self, cell: Cell, cost: Option<f32>) {
+    if self.in_bounds(cell) {
+      let index = self.index(cell);
+      self.costs[index] = cost;
+    }
+  }
+
+  /// The cheapest cell, which scales the heuristics so they never overestimate.
+  pub fn min_cost(&self) -> f32 {
+    self
+      .costs
+      .iter()
+      .flatten()
+      .copied()
+      .reduce(f32::min)
+      .unwrap_or(1.0)
+  }
+
+  /// Whether every walkable cell costs the same, which jump point search relies on.
+  pub fn is_uniform(&self) -> bool {
+    let mut costs = self.costs.iter().flatten();
+    match costs.next() {
+      Some(first) => costs.all(|cost| cost == first),
+      None => true,
+    }
+  }
+
+  pub fn cell_at(&self, position: &Vec2) -> Cell {
+    (
+      (position.x / self.cell_size.x).floor() as i32,
+      (position.y / self.cell_size.y).floor() as i32,
+    )
+  }
+
+  pub fn center(&self, (x, y): Cell) -> Vec2 {
+    glm::vec2(
+      (x as f32 + 0.5) * self.cell_size.x,
+      (y as f32 + 0.5) * self.cell_size.y,
+    )
+  }
+
+  /// Whether a single step from `from` by `(dx, dy)` is allowed.
+  pub fn can_step(&self, from: Cell, (dx, dy): Cell, diagonal: DiagonalMovement) -> bool {
+    let to = (from.0 + dx, from.1 + dy);
+    if !self.is_walkable(to) {
+      return false;
+    }
+    if dx == 0 || dy == 0 {
+      return true;
+    }
+
+    match diagonal {
+      DiagonalMovement::Never => false,
+      DiagonalMovement::Always => true,
+      DiagonalMovement::NoCornerCutting => {
+        self.is_walkable((from.0 + dx, from.1)) && self.is_walkable((from.0, from.1 + dy))
+      }
+    }
+  }
+
+  /// The cells reachable in one step, with what the step costs.
+  pub fn neighbors(&self, cell: Cell, diagonal: DiagonalMovement) -> Vec<(Cell, f32)> {
+    STEPS
+      .iter()
+      .filter(|step| self.can_step(cell, **step, diagonal))
+      .filter_map(|step| {
+        let to = (cell.0 + step.0, cell.1 + step.1);
+        self.cost(to).map(|cost| (to, cost * step_length(*step)))
+      })
+      .collect()
+  }
+}
+
+/// How far apart two cells are in steps, ignoring obstacles.
+pub fn distance(from: Cell, to: Cell, diagonal: DiagonalMovement) -> f32 {
+  let dx = (from.0 - to.0).abs() as f32;
+  let dy = (from.1 - to.1).abs() as f32;
+
+  match diagonal {
+    DiagonalMovement::Never => dx + dy,
+    _ => dx.max(dy) + (std::f32::consts::SQRT_2 - 1.0) * dx.min(dy),
+  }
+}
+
+#[cfg(test)]
+impl NavGrid {
+  /// Builds a grid from rows where `#` is blocked, `.` costs 1 and a digit costs that much.
+  pub(super) fn from_rows(rows: &[&str]) -> Self {
+    let mut grid = Self::new(rows[0].len() as u32, rows.len() as u32);
+    for (y, row) in rows.iter().enumerate() {
+      for (x, c) in row.chars().enumerate() {
+        let cost = match c {
+          '#' => None,
+          '.' => Some(1.0),
+          digit => digit.to_digit(10).map(|cost| cost as f32),
+        };
+        grid.set_cost((x as i32, y as i32), cost);
+      }
+    }
+    grid
+  }
+
+  /// What walking a path costs.
+  pub(super) fn path_cost(&self, path: &[Cell]) -> f32 {
+    path
+      .windows(2)
+      .map(|step| {
+        let (dx, dy) = (step[1].0 - step[0].0, step[1].1 - step[0].1);
+        self.cost(step[1]).unwrap_or(f32::MAX) * step_length((dx, dy))
+      })
+      .sum()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::map::{Layer, Properties, PropertyValue, TileInfo, TileLayer, Tileset};
+  use std::collections::BTreeMap;
+
+  fn tile(properties: &[(&str, PropertyValue)]) -> TileInfo {
+    TileInfo {
+      properties: properties
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.clone()))
+        .collect::<Properties>(),
+      animation: Vec::default(),
+    }
+  }
+
+  fn map() -> Map {
+    let mut map = Map::new(3, 1, 16, 16);
+    map.tilesets.push(Tileset {
+      name: String::from("terrain"),
+      texture: "exp.test.random.wall".parse().unwrap(),
+      first_gid: 1,
+      tile_width: 16,
+      tile_height: 16,
+      columns: 3,
+      tile_count: 3,
+      tiles: BTreeMap::from([
+        (1, tile(&[(keys::SOLID, PropertyValue::Bool(true))])),
+        (2, tile(&[(keys::COST, PropertyValue::Float(3.0))])),
+      ]),
+      terrains: Vec::default(),
+      properties: Properties::default(),
+    });
+
+    let mut ground = TileLayer::new("ground", 3, 1).unwrap();
+    ground.set(0, 0, 1);
+    ground.set(1, 0, 2);
+    ground.set(2, 0, 3);
+    let mut decoration = TileLayer::new("decoration", 3, 1).unwrap();
+    decoration.set(0, 0, 3);
+    decoration.set(2, 0, 1);
+    map.layers.push(Layer::Tiles(ground));
+    map.layers.push(Layer::Tiles(decoration));
+
+    map
+  }
+
+  #[test]
+  fn tile_properties_set_costs() {
+    let grid = NavGrid::from_map(&map(), &["ground"]).unwrap();
+
+    assert_eq!(grid.cost((0, 0)), Some(1.0));
+    assert_eq!(grid.cost((1, 0)), None);
+    assert_eq!(grid.cost((2, 0)), Some(3.0));
+    assert_eq!(grid.cell_size, glm::vec2(16.0, 16.0));
+  }
+
+  #[test]
+  fn overlapping_layers_take_the_worst_tile() {
+    let grid = NavGrid::from_map(&map(), &["ground", "decoration"]).unwrap();
+
+    assert_eq!(grid.cost((0, 0)), Some(3.0));
+    assert_eq!(grid.cost((1, 0)), None);
+    assert_eq!(grid.cost((2, 0)), Some(3.0));
+  }
+
+  #[test]
+  fn missing_layers_are_an_error() {
+    assert!(NavGrid::from_map(&map(), &["walls"]).is_err());
+  }
+
+  #[test]
+  fn corner_cutting_depends_on_the_diagonal_mode() {
+    let grid = NavGrid::from_rows(&[".#", ".."]);
+
+    assert!(grid.can_step((0, 0), (1, 1), DiagonalMovement::Always));
+    assert!(!grid.can_step((0, 0), (1, 1), DiagonalMovement::NoCornerCutting));
+    assert!(!grid.can_step((0, 0), (1, 1), DiagonalMovement::Never));
+  }
+}
@@ -0,0 +1,204 @@
+use super::astar::{find_path, search};
+use super::grid::{distance, Cell, DiagonalMovement, NavGrid};
+
+/// The directions worth exploring from `cell`, pruning those a path through the parent
+/// could reach at least as cheaply without passing through `cell`.
+fn pruned_directions(grid: &NavGrid, (x, y): Cell, parent: Option<Cell>) -> Vec<Cell> {
+  let walkable = |dx: i32, dy: i32| grid.is_walkable((x + dx, y + dy));
+
+  let (px, py) = match parent {
+    Some(parent) => parent,
+    None => {
+      return grid
+        .neighbors((x, y), DiagonalMovement::NoCornerCutting)
+        .into_iter()
+        .map(|((nx, ny), _)| (nx - x, ny - y))
+        .collect();
+    }
+  };
+  let (dx, dy) = ((x - px).signum(), (y - py).signum());
+
+  let mut directions = Vec::new();
+  if dx != 0 && dy != 0 {
+    if walkable(0, dy) {
+      directions.push((0, dy));
+    }
+    if walkable(dx, 0) {
+      directions.push((dx, 0));
+    }
+    if walkable(0, dy) && walkable(dx, 0) {
+      directions.push((dx, dy));
+    }
+  } else if dx != 0 {
+    let (ahead, below, above) = (walkable(dx, 0), walkable(0, 1), walkable(0, -1));
+    if ahead {
+      directions.push((dx, 0));
+      if below {
+        directions.push((dx, 1));
+      }
+      if above {
+        directions.push((dx, -1));
+      }
+    }
+    if below {
+      directions.push((0, 1));
+    }
+    if above {
+      directions.push((0, -1));
+    }
+  } else {
+    let (ahead, right, left) = (walkable(0, dy), walkable(1, 0), walkable(-1, 0));
+    if ahead {
+      directions.push((0, dy));
+      if right {
+        directions.push((1, dy));
+      }
+      if left {
+        directions.push((-1, dy));
+      }
+    }
+    if right {
+      directions.push((1, 0));
+    }
+    if left {
+      directions.push((-1, 0));
+    }
+  }
+
+  directions
+}
+
+/// Walks from `from` in one direction until reaching the goal or a cell with a forced
+/// neighbor, which becomes the next jump point.
+fn jump(grid: &NavGrid, from: Cell, (dx, dy): Cell, goal: Cell) -> Option<Cell> {
+  let walkable = |x: i32, y: i32| grid.is_walkable((x, y));
+  let (mut x, mut y) = (from.0 + dx, from.1 + dy);
+
+  loop {
+    if !walkable(x, y) {
+      return None;
+    }
+    if (x, y) == goal {
+      return Some(goal);
+    }
+
+    let forced = if dx != 0 && dy != 0 {
+      // a diagonal move stops wherever a straight jump from it would find something
+      jump(grid, (x, y), (dx, 0), goal).is_some() || jump(grid, (x, y), (0, dy), goal).is_some()
+    } else if dx != 0 {
+      (walkable(x, y - 1) && !walkable(x - dx, y - 1))
+        || (walkable(x, y + 1) && !walkable(x - dx, y + 1))
+    } else {
+      (walkable(x - 1, y) && !walkable(x - 1, y - dy))
+        || (walkable(x + 1, y) && !walkable(x + 1, y - dy))
+    };
+    if forced {
+      return Some((x, y));
+    }
+
+    if !walkable(x + dx, y) || !walkable(x, y + dy) {
+      return None;
+    }
+    x += dx;
+    y += dy;
+  }
+}
+
+/// Jump point search, which gives the same cost as `find_path` while expanding far fewer
+/// cells. It only applies to grids where every cell costs the same and diagonal moves
+/// cannot cut corners, other searches fall back to A*.
+pub fn jump_point_search(
+  grid: &NavGrid,
+  start: Cell,
+  goal: Cell,
+  diagonal: DiagonalMovement,
+) -> Option<Vec<Cell>> {
+  if diagonal != DiagonalMovement::NoCornerCutting || !grid.is_uniform() {
+    return find_path(grid, start, goal, diagonal);
+  }
+  if !grid.is_walkable(start) || !grid.is_walkable(goal) {
+    return None;
+  }
+
+  let cost = grid.min_cost();
+  let jump_points = search(
+    start,
+    goal,
+    |cell| distance(cell, goal, diagonal) * cost,
+    |cell, parent| {
+      pruned_directions(grid, cell, parent)
+        .into_iter()
+        .filter_map(|direction| jump(grid, cell, direction, goal))
+        .map(|next| (next, distance(cell, next, diagonal) * cost))
+        .collect()
+    },
+  )?;
+
+  // jump points are joined by straight or diagonal runs, which are filled back in
+  let mut path = vec![start];
+  for next in jump_points.iter().skip(1) {
+    let mut cell = *path.last().unwrap_or(&start);
+    let step = ((next.0 - cell.0).signum(), (next.1 - cell.1).signum());
+    while cell != *next {
+      cell = (cell.0 + step.0, cell.1 + step.1);
+      path.push(cell);
+    }
+  }
+
+  Some(path)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const MAZE: [&str; 8] = [
+    "..........",
+    ".####.###.",
+    ".#......#.",
+    ".#.####.#.",
+    ".#.#..#...",
+    "...#..###.",
+    "####......",
+    "..........",
+  ];
+
+  #[test]
+  fn matches_astar_cost() {
+    let grid = NavGrid::from_rows(&MAZE);
+    let diagonal = DiagonalMovement::NoCornerCutting;
+
+    for goal in [(9, 7), (4, 4), (0, 7), (5, 2)] {
+      let astar = find_path(&grid, (0, 0), goal, diagonal).unwrap();
+      let jps = jump_point_search(&grid, (0, 0), goal, diagonal).unwrap();
+
+      assert!((grid.path_cost(&astar) - grid.path_cost(&jps)).abs() < 1e-4);
+    }
+  }
+
+  #[test]
+  fn paths_are_filled_in_step_by_step() {
+    let grid = NavGrid::from_rows(&MAZE);
+
+    let path = jump_point_search(&grid, (0, 0), (9, 7), DiagonalMovement::NoCornerCutting).unwrap();
+
+    assert_eq!(path.first(), Some(&(0, 0)));
+    assert_eq!(path.last(), Some(&(9, 7)));
+    for step in path.windows(2) {
+      let (dx, dy) = (step[1].0 - step[0].0, step[1].1 - step[0].1);
+      assert!(grid.can_step(step[0], (dx, dy), DiagonalMovement::NoCornerCutting));
+    }
+  }
+
+  #[test]
+  fn weighted_grids_fall_back_to_astar() {
+    let grid = NavGrid::from_rows(&[".9.", "..."]);
+
+    let path = jump_point_search(&grid, (0, 0), (2, 0), DiagonalMovement::NoCornerCutting);
+
+    assert_eq!(
+      path,
+      find_path(&grid, (0, 0), (2, 0), DiagonalMovement::NoCornerCutting)
+    );
+  }
+}
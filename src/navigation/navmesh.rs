@@ -0,0 +1,384 @@
+use super::astar::search;
+use super::funnel::{funnel, Portal};
+use super::geometry::{bounds, cross, inflate, overlaps, ring, subtract, triangulate};
+use crate::map::{Map, OBSTACLE_AREA, WALKABLE_AREA};
+use crate::math::glm::{self, Vec2};
+use geo::Polygon;
+
+/// How far apart points can be and still count as the same, in world units.
+const TOLERANCE: f32 = 1e-2;
+
+/// A way from one triangle into a neighbor.
+#[derive(Debug, Clone, Copy)]
+struct Link {
+  to: usize,
+  portal: Portal,
+}
+
+impl Link {
+  fn midpoint(&self) -> Vec2 {
+    (self.portal.left + self.portal.right) / 2.0
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Node {
+  Start,
+  Link(usize),
+  Goal,
+}
+
+/// Where an agent can stand, as triangles that share edges with their neighbors.
+/// Paths are found through the triangles with A*, then pulled taut around corners.
+#[derive(Debug, Clone)]
+pub struct NavMesh {
+  walkable: Vec<Polygon<f32>>,
+  obstacles: Vec<Polygon<f32>>,
+  agent_radius: f32,
+  triangles: Vec<[Vec2; 3]>,
+  links: Vec<Link>,
+  /// The links leaving each triangle.
+  neighbors: Vec<Vec<usize>>,
+}
+
+impl NavMesh {
+  /// Builds a mesh over the walkable polygons, keeping agents `agent_radius` away
+  /// from their edges and holes.
+  pub fn new(walkable: Vec<Polygon<f32>>, agent_radius: f32) -> Result<Self, String> {
+    if walkable.is_empty() {
+      return Err(String::from("a navmesh needs at least one walkable area"));
+    }
+    if agent_radius < 0.0 {
+      return Err(String::from("the agent radius cannot be negative"));
+    }
+
+    let mut mesh = Self {
+      walkable,
+      obstacles: Vec::default(),
+      agent_radius,
+      triangles: Vec::default(),
+      links: Vec::default(),
+      neighbors: Vec::default(),
+    };
+    mesh.rebuild();
+    Ok(mesh)
+  }
+
+  /// Builds a mesh from the walkable and obstacle areas on a map's object layers.
+  pub fn from_map(map: &Map, agent_radius: f32) -> Result<Self, String> {
+    let walkable = map
+      .areas(WALKABLE_AREA)
+      .map(|area| area.polygon.clone())
+      .collect();
+    let mut mesh = Self::new(walkable, agent_radius)?;
+    mesh.set_obstacles(
+      map
+        .areas(OBSTACLE_AREA)
+        .map(|area| area.polygon.clone())
+        .collect(),
+    );
+    Ok(mesh)
+  }
+
+  pub fn agent_radius(&self) -> f32 {
+    self.agent_radius
+  }
+
+  pub fn obstacles(&self) -> &[Polygon<f32>] {
+    &self.obstacles
+  }
+
+  /// Replaces the obstacles cut out of the walkable areas and rebuilds the mesh.
+  /// Holes in obstacles are ignored.
+  pub fn set_obstacles(&mut self, obstacles: Vec<Polygon<f32>>) {
+    self.obstacles = obstacles;
+    self.rebuild();
+  }
+
+  pub fn add_obstacle(&mut self, obstacle: Polygon<f32>) {
+    self.obstacles.push(obstacle);
+    self.rebuild();
+  }
+
+  pub fn triangles(&self) -> &[[Vec2; 3]] {
+    &self.triangles
+  }
+
+  fn rebuild(&mut self) {
+    let blocker = |points: Vec<Vec2>| inflate(&points, self.agent_radius);
+    let obstacles: Vec<Vec<Vec2>> = self
+      .obstacles
+      .iter()
+      .flat_map(|obstacle| triangulate(&ring(obstacle.exterior())))
+      .map(|triangle| blocker(triangle.to_vec()))
+      .collect();
+
+    let mut triangles = Vec::new();
+    for polygon in &self.walkable {
+      let exterior = ring(polygon.exterior());
+
+      // agents keep their distance from the edges by cutting a strip along each of them
+      let mut blockers: Vec<Vec<Vec2>> = Vec::new();
+      if self.agent_radius > 0.0 {
+        blockers.extend(
+          exterior
+            .iter()
+            .zip(exterior.iter().cycle().skip(1))
+            .map(|(a, b)| blocker(vec![*a, *b])),
+        );
+      }
+      blockers.extend(
+        polygon
+          .interiors()
+          .iter()
+          .flat_map(|hole| triangulate(&ring(hole)))
+          .map(|triangle| blocker(triangle.to_vec())),
+      );
+      blockers.extend(obstacles.iter().cloned());
+
+      let mut pieces: Vec<Vec<Vec2>> = triangulate(&exterior)
+        .iter()
+        .map(|triangle| triangle.to_vec())
+        .collect();
+      for blocker in &blockers {
+        let blocker_bounds = bounds(blocker);
+        pieces = pieces
+          .into_iter()
+          .flat_map(|piece| {
+            if overlaps(&bounds(&piece), &blocker_bounds) {
+              subtract(&piece, blocker)
+            } else {
+              vec![piece]
+            }
+          })
+          .collect();
+      }
+
+      // the pieces are convex, so fanning out from a corner triangulates them
+      for piece in &pieces {
+        for i in 1..piece.len() - 1 {
+          if cross(&piece[0], &piece[i], &piece[i + 1]) > TOLERANCE * TOLERANCE {
+            triangles.push([piece[0], piece[i], piece[i + 1]]);
+          }
+        }
+      }
+    }
+
+    self.triangles = triangles;
+    self.connect();
+  }
+
+  /// Links triangles whose edges overlap, including where a corner of one touches
+  /// the middle of an edge of another.
+  fn connect(&mut self) {
+    self.links.clear();
+    self.neighbors = vec![Vec::new(); self.triangles.len()];
+    let triangle_bounds: Vec<_> = self
+      .triangles
+      .iter()
+      .map(|triangle| bounds(triangle))
+      .collect();
+
+    for i in 0..self.triangles.len() {
+      for j in i + 1..self.triangles.len() {
+        if !overlaps(&triangle_bounds[i], &triangle_bounds[j]) {
+          continue;
+        }
+
+        for edge in 0..3 {
+          for other in 0..3 {
+            let (p, q) = (self.triangles[i][edge], self.triangles[i][(edge + 1) % 3]);
+            let (a, b) = (self.triangles[j][other], self.triangles[j][(other + 1) % 3]);
+            if let Some((near, far)) = shared_segment(&p, &q, &a, &b) {
+              // triangles are wound the same way, so the far end is on the left leaving `i`
+              self.link(i, j, far, near);
+              self.link(j, i, near, far);
+            }
+          }
+        }
+      }
+    }
+  }
+
+  fn link(&mut self, from: usize, to: usize, left: Vec2, right: Vec2) {
+    self.neighbors[from].push(self.links.len());
+    self.links.push(Link {
+      to,
+      portal: Portal { left, right },
+    });
+  }
+
+  /// The triangle containing a point.
+  pub fn locate(&self, point: &Vec2) -> Option<usize> {
+    self.triangles.iter().position(|[a, b, c]| {
+      let edge_distance =
+        |from: &Vec2, to: &Vec2| cross(from, to, point) / glm::distance(from, to).max(f32::EPSILON);
+      edge_distance(a, b) >= -TOLERANCE
+        && edge_distance(b, c) >= -TOLERANCE
+        && edge_distance(c, a) >= -TOLERANCE
+    })
+  }
+
+  pub fn is_walkable(&self, point: &Vec2) -> bool {
+    self.locate(point).is_some()
+  }
+
+  /// The shortest way between two points, both ends included, `None` if either is
+  /// off the mesh or there is no way between them.
+  pub fn find_path(&self, from: Vec2, to: Vec2) -> Option<Vec<Vec2>> {
+    let (start, goal) = (self.locate(&from)?, self.locate(&to)?);
+    if start == goal {
+      return Some(vec![from, to]);
+    }
+
+    let position = |node: Node| match node {
+      Node::Start => from,
+      Node::Link(link) => self.links[link].midpoint(),
+      Node::Goal => to,
+    };
+    let nodes = search(
+      Node::Start,
+      Node::Goal,
+      |node| glm::distance(&position(node), &to),
+      |node, _| {
+        let triangle = match node {
+          Node::Start => start,
+          Node::Link(link) => self.links[link].to,
+          Node::Goal => return Vec::new(),
+        };
+
+        let mut next: Vec<(Node, f32)> = self.neighbors[triangle]
+          .iter()
+          .map(|link| {
+            let step = glm::distance(&position(node), &self.links[*link].midpoint());
+            (Node::Link(*link), step)
+          })
+          .collect();
+        if triangle == goal {
+          next.push((Node::Goal, glm::distance(&position(node), &to)));
+        }
+        next
+      },
+    )?;
+
+    let portals: Vec<Portal> = nodes
+      .iter()
+      .filter_map(|node| match node {
+        Node::Link(link) => Some(self.links[*link].portal),
+        _ => None,
+      })
+      .collect();
+    Some(funnel(from, to, &portals))
+  }
+}
+
+/// Where two edges running opposite ways along the same line overlap, as the ends
+/// nearest and furthest along the first edge.
+fn shared_segment(p: &Vec2, q: &Vec2, a: &Vec2, b: &Vec2) -> Option<(Vec2, Vec2)> {
+  let length = glm::distance(p, q);
+  if length <= TOLERANCE {
+    return None;
+  }
+  let direction = (q - p) / length;
+
+  let off_line = |point: &Vec2| direction.perp(&(point - p)).abs() > TOLERANCE;
+  if off_line(a) || off_line(b) || direction.dot(&(b - a)) >= 0.0 {
+    return None;
+  }
+
+  let (start, end) = (
+    direction.dot(&(b - p)).max(0.0),
+    direction.dot(&(a - p)).min(length),
+  );
+  if end - start <= TOLERANCE {
+    return None;
+  }
+  Some((p + direction * start, p + direction * end))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use geo::LineString;
+
+  fn polygon(points: &[(f32, f32)]) -> Polygon<f32> {
+    Polygon::new(LineString::from(points.to_vec()), Vec::default())
+  }
+
+  fn square(min: f32, max: f32) -> Polygon<f32> {
+    polygon(&[(min, min), (max, min), (max, max), (min, max)])
+  }
+
+  fn length(path: &[Vec2]) -> f32 {
+    path.windows(2).map(|w| glm::distance(&w[0], &w[1])).sum()
+  }
+
+  #[test]
+  fn open_areas_give_straight_paths() {
+    let mesh = NavMesh::new(vec![square(0.0, 100.0)], 0.0).unwrap();
+    let path = mesh
+      .find_path(glm::vec2(10.0, 10.0), glm::vec2(90.0, 80.0))
+      .unwrap();
+
+    assert_eq!(path, vec![glm::vec2(10.0, 10.0), glm::vec2(90.0, 80.0)]);
+  }
+
+  #[test]
+  fn paths_turn_at_the_corners_of_holes() {
+    let mut walkable = square(0.0, 100.0);
+    walkable.interiors_push(LineString::from(vec![
+      (40.0, 0.0),
+      (60.0, 0.0),
+      (60.0, 80.0),
+      (40.0, 80.0),
+    ]));
+    let mesh = NavMesh::new(vec![walkable], 0.0).unwrap();
+    let path = mesh
+      .find_path(glm::vec2(20.0, 20.0), glm::vec2(80.0, 20.0))
+      .unwrap();
+
+    assert_eq!(path.len(), 4);
+    assert!((path[1] - glm::vec2(40.0, 80.0)).norm() < 0.1);
+    assert!((path[2] - glm::vec2(60.0, 80.0)).norm() < 0.1);
+  }
+
+  #[test]
+  fn the_agent_radius_keeps_paths_off_edges() {
+    let mesh = NavMesh::new(vec![square(0.0, 100.0)], 10.0).unwrap();
+
+    assert!(!mesh.is_walkable(&glm::vec2(5.0, 50.0)));
+    assert!(mesh.is_walkable(&glm::vec2(15.0, 50.0)));
+  }
+
+  #[test]
+  fn adding_obstacles_rebuilds_the_mesh() {
+    let mut mesh = NavMesh::new(vec![square(0.0, 100.0)], 5.0).unwrap();
+    let (from, to) = (glm::vec2(10.0, 50.0), glm::vec2(90.0, 50.0));
+    assert!(mesh.is_walkable(&glm::vec2(50.0, 50.0)));
+    assert!(length(&mesh.find_path(from, to).unwrap()) < 82.0);
+
+    mesh.add_obstacle(square(40.0, 60.0));
+    let path = mesh.find_path(from, to).unwrap();
+
+    assert!(!mesh.is_walkable(&glm::vec2(50.0, 50.0)));
+    assert!(!mesh.is_walkable(&glm::vec2(50.0, 37.0)));
+    // the obstacle grown by the agent radius spans 35 to 65
+    assert!(length(&path) > 2.0 * 40f32.hypot(15.0));
+    assert!(path.iter().all(|point| mesh.is_walkable(point)));
+  }
+
+  #[test]
+  fn walled_off_goals_have_no_path() {
+    let mut mesh = NavMesh::new(vec![square(0.0, 100.0)], 0.0).unwrap();
+    mesh.add_obstacle(polygon(&[
+      (40.0, -10.0),
+      (60.0, -10.0),
+      (60.0, 110.0),
+      (40.0, 110.0),
+    ]));
+
+    assert!(mesh
+      .find_path(glm::vec2(10.0, 50.0), glm::vec2(90.0, 50.0))
+      .is_none());
+  }
+}
@@ -0,0 +1,124 @@
+use super::grid::{Cell, NavGrid};
+
+/// The cells a straight line between two cell centres passes through. Where the line
+/// crosses a corner exactly, both cells beside the corner are included.
+fn crossed_cells(from: Cell, to: Cell) -> Vec<Cell> {
+  let (dx, dy) = ((to.0 - from.0).abs(), (to.1 - from.1).abs());
+  let (step_x, step_y) = ((to.0 - from.0).signum(), (to.1 - from.1).signum());
+
+  let mut cells = vec![from];
+  let (mut x, mut y) = from;
+  // walks the grid lines the segment crosses, in units of 2 * dx * dy
+  let (mut ix, mut iy) = (0, 0);
+  while ix < dx || iy < dy {
+    let next_x = (1 + 2 * ix) * dy;
+    let next_y = (1 + 2 * iy) * dx;
+
+    if next_x == next_y {
+      cells.push((x + step_x, y));
+      cells.push((x, y + step_y));
+      x += step_x;
+      y += step_y;
+      ix += 1;
+      iy += 1;
+    } else if next_x < next_y {
+      x += step_x;
+      ix += 1;
+    } else {
+      y += step_y;
+      iy += 1;
+    }
+    cells.push((x, y));
+  }
+
+  cells
+}
+
+/// Whether an agent can walk straight from one cell centre to another.
+pub fn line_of_sight(grid: &NavGrid, from: Cell, to: Cell) -> bool {
+  crossed_cells(from, to)
+    .into_iter()
+    .all(|cell| grid.is_walkable(cell))
+}
+
+/// Drops the cells of a path that can be walked past in a straight line, leaving the corners.
+/// Shortcuts never cross a cell dearer than the part of the path they replace, so smoothing
+/// does not cut through terrain the search went around.
+pub fn smooth_path(grid: &NavGrid, path: &[Cell]) -> Vec<Cell> {
+  if path.len() <= 2 {
+    return path.to_vec();
+  }
+
+  let cost = |cell: Cell| grid.cost(cell).unwrap_or(f32::MAX);
+  let shortcut = |from: usize, to: usize| {
+    let limit = path[from..=to]
+      .iter()
+      .map(|cell| cost(*cell))
+      .fold(0.0, f32::max);
+    crossed_cells(path[from], path[to])
+      .into_iter()
+      .all(|cell| grid.is_walkable(cell) && cost(cell) <= limit)
+  };
+
+  let mut smoothed = vec![path[0]];
+  let mut anchor = 0;
+  while anchor < path.len() - 1 {
+    let mut next = anchor + 1;
+    for candidate in (anchor + 2..path.len()).rev() {
+      if shortcut(anchor, candidate) {
+        next = candidate;
+        break;
+      }
+    }
+
+    smoothed.push(path[next]);
+    anchor = next;
+  }
+
+  smoothed
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::navigation::{find_path, DiagonalMovement};
+
+  #[test]
+  fn open_areas_smooth_to_a_straight_line() {
+    let grid = NavGrid::from_rows(&["......", "......", "......"]);
+    let path = find_path(&grid, (0, 0), (5, 2), DiagonalMovement::Never).unwrap();
+
+    assert_eq!(smooth_path(&grid, &path), vec![(0, 0), (5, 2)]);
+  }
+
+  #[test]
+  fn corners_around_walls_are_kept() {
+    let grid = NavGrid::from_rows(&["....", "###.", "...."]);
+    let path = find_path(&grid, (0, 0), (0, 2), DiagonalMovement::NoCornerCutting).unwrap();
+
+    let smoothed = smooth_path(&grid, &path);
+
+    assert!(smoothed.len() > 2);
+    for leg in smoothed.windows(2) {
+      assert!(line_of_sight(&grid, leg[0], leg[1]));
+    }
+  }
+
+  #[test]
+  fn lines_through_corners_need_both_sides_open() {
+    let grid = NavGrid::from_rows(&[".#", ".."]);
+
+    assert!(!line_of_sight(&grid, (0, 0), (1, 1)));
+    assert!(line_of_sight(&grid, (0, 1), (1, 1)));
+  }
+
+  #[test]
+  fn shortcuts_do_not_cross_expensive_cells() {
+    let grid = NavGrid::from_rows(&["...", ".9.", "..."]);
+    let path = vec![(0, 0), (1, 0), (2, 0), (2, 1), (2, 2)];
+
+    let smoothed = smooth_path(&grid, &path);
+
+    assert!(!smoothed.windows(2).any(|leg| leg == [(0, 0), (2, 2)]));
+  }
+}
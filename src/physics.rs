@@ -0,0 +1,15 @@
+mod body;
+mod broad_phase;
+mod collider;
+mod collisions;
+mod dynamics;
+mod narrow_phase;
+mod queries;
+
+pub use body::{BodyKind, RigidBody};
+pub use broad_phase::SpatialHash;
+pub use collider::{layers, Collider, Shape, WorldShape};
+pub use collisions::{CollisionEvents, CollisionPhase, CollisionSystem};
+pub use dynamics::PhysicsWorld;
+pub use narrow_phase::Contact;
+pub use queries::QueryFilter;
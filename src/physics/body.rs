@@ -0,0 +1,230 @@
+use super::{Collider, Shape};
+use crate::math::glm::{self, Vec2};
+use serde_json::Value;
+
+mod keys {
+  pub const TYPE: &str = "type";
+  pub const MASS: &str = "mass";
+  pub const RESTITUTION: &str = "restitution";
+  pub const FRICTION: &str = "friction";
+  pub const GRAVITY_SCALE: &str = "gravity_scale";
+  pub const LINEAR_DAMPING: &str = "linear_damping";
+  pub const ANGULAR_DAMPING: &str = "angular_damping";
+  pub const FIXED_ROTATION: &str = "fixed_rotation";
+  pub const CAN_SLEEP: &str = "can_sleep";
+
+  pub const TYPE_STATIC: &str = "static";
+  pub const TYPE_KINEMATIC: &str = "kinematic";
+  pub const TYPE_DYNAMIC: &str = "dynamic";
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyKind {
+  /// Never moves.
+  Static,
+  /// Moves by its velocity alone, pushing dynamic bodies without being pushed back.
+  Kinematic,
+  /// Moved by gravity and collisions.
+  Dynamic,
+}
+
+impl TryFrom<&str> for BodyKind {
+  type Error = String;
+
+  fn try_from(value: &str) -> Result<Self, Self::Error> {
+    match value {
+      keys::TYPE_STATIC => Ok(BodyKind::Static),
+      keys::TYPE_KINEMATIC => Ok(BodyKind::Kinematic),
+      keys::TYPE_DYNAMIC => Ok(BodyKind::Dynamic),
+      invalid => Err(format!("unknown body type '{}'", invalid)),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RigidBody {
+  pub kind: BodyKind,
+  pub velocity: Vec2,
+  /// In radians per second.
+  pub angular_velocity: f32,
+  pub mass: f32,
+  pub restitution: f32,
+  pub friction: f32,
+  pub gravity_scale: f32,
+  pub linear_damping: f32,
+  pub angular_damping: f32,
+  pub fixed_rotation: bool,
+  pub can_sleep: bool,
+  pub(super) sleeping: bool,
+  pub(super) resting_time: f32,
+}
+
+impl RigidBody {
+  pub fn new(kind: BodyKind) -> Self {
+    Self {
+      kind,
+      velocity: glm::vec2(0.0, 0.0),
+      angular_velocity: 0.0,
+      mass: 1.0,
+      restitution: 0.0,
+      friction: 0.5,
+      gravity_scale: 1.0,
+      linear_damping: 0.0,
+      angular_damping: 0.0,
+      fixed_rotation: false,
+      can_sleep: true,
+      sleeping: false,
+      resting_time: 0.0,
+    }
+  }
+
+  pub fn wake(&mut self) {
+    self.sleeping = false;
+    self.resting_time = 0.0;
+  }
+
+  pub fn apply_impulse(&mut self, impulse: Vec2) {
+    if self.kind == BodyKind::Dynamic {
+      self.velocity += impulse * self.inverse_mass();
+      self.wake();
+    }
+  }
+
+  pub fn inverse_mass(&self) -> f32 {
+    match self.kind {
+      BodyKind::Dynamic if self.mass > 0.0 => 1.0 / self.mass,
+      _ => 0.0,
+    }
+  }
+
+  /// Boxes stay axis aligned, so they never rotate either.
+  pub fn inverse_inertia(&self, collider: Option<&Collider>, scale: &Vec2) -> f32 {
+    if self.kind != BodyKind::Dynamic || self.fixed_rotation || self.mass <= 0.0 {
+      return 0.0;
+    }
+
+    let inertia = match collider.map(|collider| &collider.shape) {
+      Some(Shape::Circle { radius }) => {
+        let radius = radius * scale.x.abs().max(scale.y.abs());
+        self.mass * radius * radius / 2.0
+      }
+      Some(Shape::Polygon { points }) => {
+        let points: Vec<Vec2> = points.iter().map(|p| p.component_mul(scale)).collect();
+        polygon_inertia(&points, self.mass)
+      }
+      Some(Shape::Aabb { .. }) | None => return 0.0,
+    };
+
+    if inertia > 0.0 {
+      1.0 / inertia
+    } else {
+      0.0
+    }
+  }
+}
+
+/// The moment of inertia of a convex polygon of uniform density about its centroid.
+fn polygon_inertia(points: &[Vec2], mass: f32) -> f32 {
+  let center = points.iter().fold(glm::vec2(0.0, 0.0), |sum, p| sum + p) / points.len() as f32;
+
+  let (mut numerator, mut denominator) = (0.0, 0.0);
+  for (a, b) in points.iter().zip(points.iter().cycle().skip(1)) {
+    let (a, b) = (a - center, b - center);
+    let cross = a.perp(&b).abs();
+    numerator += cross * (a.dot(&a) + a.dot(&b) + b.dot(&b));
+    denominator += cross;
+  }
+
+  if denominator > 0.0 {
+    mass * numerator / (6.0 * denominator)
+  } else {
+    0.0
+  }
+}
+
+impl TryFrom<&Value> for RigidBody {
+  type Error = String;
+
+  fn try_from(value: &Value) -> Result<Self, Self::Error> {
+    let table = value
+      .as_object()
+      .ok_or_else(|| String::from("body must be an object"))?;
+
+    let allowed = [
+      keys::TYPE,
+      keys::MASS,
+      keys::RESTITUTION,
+      keys::FRICTION,
+      keys::GRAVITY_SCALE,
+      keys::LINEAR_DAMPING,
+      keys::ANGULAR_DAMPING,
+      keys::FIXED_ROTATION,
+      keys::CAN_SLEEP,
+    ];
+    if let Some(key) = table.keys().find(|key| !allowed.contains(&key.as_str())) {
+      return Err(format!("unknown body key '{}'", key));
+    }
+
+    let kind = match value.get(keys::TYPE) {
+      Some(kind) => BodyKind::try_from(
+        kind
+          .as_str()
+          .ok_or_else(|| format!("body '{}' must be a string", keys::TYPE))?,
+      )?,
+      None => BodyKind::Dynamic,
+    };
+
+    let mut body = Self::new(kind);
+
+    let number = |key: &str, default: f32| match value.get(key) {
+      Some(v) => v
+        .as_f64()
+        .map(|v| v as f32)
+        .ok_or_else(|| format!("body '{}' must be a number", key)),
+      None => Ok(default),
+    };
+    let boolean = |key: &str, default: bool| match value.get(key) {
+      Some(v) => v
+        .as_bool()
+        .ok_or_else(|| format!("body '{}' must be a boolean", key)),
+      None => Ok(default),
+    };
+
+    body.mass = number(keys::MASS, body.mass)?;
+    body.restitution = number(keys::RESTITUTION, body.restitution)?;
+    body.friction = number(keys::FRICTION, body.friction)?;
+    body.gravity_scale = number(keys::GRAVITY_SCALE, body.gravity_scale)?;
+    body.linear_damping = number(keys::LINEAR_DAMPING, body.linear_damping)?;
+    body.angular_damping = number(keys::ANGULAR_DAMPING, body.angular_damping)?;
+    body.fixed_rotation = boolean(keys::FIXED_ROTATION, body.fixed_rotation)?;
+    body.can_sleep = boolean(keys::CAN_SLEEP, body.can_sleep)?;
+
+    if body.mass <= 0.0 && kind == BodyKind::Dynamic {
+      return Err(String::from("dynamic bodies need a positive mass"));
+    }
+
+    Ok(body)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn impulses_only_move_dynamic_bodies_with_mass() {
+    let mut body = RigidBody::new(BodyKind::Dynamic);
+    body.mass = 2.0;
+    body.apply_impulse(glm::vec2(4.0, 0.0));
+    assert_eq!(body.velocity, glm::vec2(2.0, 0.0));
+
+    body.mass = 0.0;
+    body.apply_impulse(glm::vec2(4.0, 0.0));
+    assert_eq!(body.velocity, glm::vec2(2.0, 0.0));
+
+    let mut body = RigidBody::new(BodyKind::Kinematic);
+    body.mass = 0.0;
+    body.apply_impulse(glm::vec2(4.0, 0.0));
+    assert_eq!(body.velocity, glm::vec2(0.0, 0.0));
+  }
+}
@@ -0,0 +1,178 @@
+use crate::game::Entity;
+use geo::Rect;
+use std::collections::{BTreeSet, HashMap};
+
+/// Entities whose bounds touch more cells than this are kept out of the grid and
+/// tested against everything instead, so one huge collider cannot stall a frame.
+const MAX_CELLS: i64 = 64;
+
+/// Buckets entities by the grid cells their bounds touch, so only entities
+/// sharing a cell are tested against each other.
+pub struct SpatialHash {
+  cell_size: f32,
+  cells: HashMap<(i32, i32), Vec<Entity>>,
+  /// Every entity in the grid.
+  bucketed: BTreeSet<Entity>,
+  /// The entities too large for the grid.
+  oversized: Vec<Entity>,
+}
+
+impl SpatialHash {
+  pub fn new(cell_size: f32) -> Self {
+    Self {
+      cell_size,
+      cells: HashMap::default(),
+      bucketed: BTreeSet::default(),
+      oversized: Vec::default(),
+    }
+  }
+
+  pub fn clear(&mut self) {
+    self.cells.clear();
+    self.bucketed.clear();
+    self.oversized.clear();
+  }
+
+  fn cell_range(&self, bounds: &Rect<f32>) -> (i32, i32, i32, i32) {
+    let cell = |v: f32| (v / self.cell_size).floor() as i32;
+    (
+      cell(bounds.min().x),
+      cell(bounds.min().y),
+      cell(bounds.max().x),
+      cell(bounds.max().y),
+    )
+  }
+
+  /// The number of cells in a range, `None` when there are too many to count.
+  fn span((x0, y0, x1, y1): (i32, i32, i32, i32)) -> Option<i64> {
+    (x1 as i64 - x0 as i64 + 1).checked_mul(y1 as i64 - y0 as i64 + 1)
+  }
+
+  pub fn insert(&mut self, entity: Entity, bounds: &Rect<f32>) {
+    let range = self.cell_range(bounds);
+    if Self::span(range).is_none_or(|span| span > MAX_CELLS) {
+      self.oversized.push(entity);
+      return;
+    }
+
+    let (x0, y0, x1, y1) = range;
+    self.bucketed.insert(entity);
+    for y in y0..=y1 {
+      for x in x0..=x1 {
+        self.cells.entry((x, y)).or_default().push(entity);
+      }
+    }
+  }
+
+  /// Every pair of entities that share a cell, smallest entity first. Oversized
+  /// entities pair with every other entity.
+  pub fn pairs(&self) -> BTreeSet<(Entity, Entity)> {
+    let mut pairs = BTreeSet::new();
+    for entities in self.cells.values() {
+      for (i, a) in entities.iter().enumerate() {
+        for b in &entities[i + 1..] {
+          if a != b {
+            pairs.insert((*a.min(b), *a.max(b)));
+          }
+        }
+      }
+    }
+
+    for (i, a) in self.oversized.iter().enumerate() {
+      for b in self.bucketed.iter().chain(&self.oversized[i + 1..]) {
+        if a != b {
+          pairs.insert((*a.min(b), *a.max(b)));
+        }
+      }
+    }
+    pairs
+  }
+
+  /// Every entity in a cell touched by the area, and every oversized entity.
+  pub fn query(&self, area: &Rect<f32>) -> BTreeSet<Entity> {
+    let range = self.cell_range(area);
+    let (x0, y0, x1, y1) = range;
+    let mut found: BTreeSet<Entity> = self.oversized.iter().copied().collect();
+
+    // a long cast can span far more cells than are occupied, so the fewer are walked
+    if Self::span(range).is_none_or(|spanned| spanned > self.cells.len() as i64) {
+      for ((x, y), entities) in &self.cells {
+        if (x0..=x1).contains(x) && (y0..=y1).contains(y) {
+          found.extend(entities);
+        }
+      }
+      return found;
+    }
+
+    for y in y0..=y1 {
+      for x in x0..=x1 {
+        if let Some(entities) = self.cells.get(&(x, y)) {
+          found.extend(entities);
+        }
+      }
+    }
+    found
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn rect(x0: f32, y0: f32, x1: f32, y1: f32) -> Rect<f32> {
+    Rect::new((x0, y0), (x1, y1))
+  }
+
+  /// Entities `0..count`, the way a world hands them out.
+  fn entities(count: usize) -> Vec<Entity> {
+    let mut world = crate::game::World::new();
+    (0..count).map(|_| world.spawn()).collect()
+  }
+
+  #[test]
+  fn only_entities_sharing_a_cell_pair() {
+    let e = entities(3);
+    let mut hash = SpatialHash::new(10.0);
+    hash.insert(e[0], &rect(0.0, 0.0, 5.0, 5.0));
+    hash.insert(e[1], &rect(4.0, 4.0, 8.0, 8.0));
+    hash.insert(e[2], &rect(50.0, 50.0, 55.0, 55.0));
+
+    assert_eq!(hash.pairs(), BTreeSet::from([(e[0], e[1])]));
+  }
+
+  #[test]
+  fn entities_spanning_cells_pair_once() {
+    let e = entities(2);
+    let mut hash = SpatialHash::new(10.0);
+    hash.insert(e[0], &rect(0.0, 0.0, 25.0, 25.0));
+    hash.insert(e[1], &rect(5.0, 5.0, 22.0, 22.0));
+
+    assert_eq!(hash.pairs(), BTreeSet::from([(e[0], e[1])]));
+  }
+
+  #[test]
+  fn oversized_entities_pair_with_everything() {
+    let e = entities(4);
+    let mut hash = SpatialHash::new(1.0);
+    hash.insert(e[0], &rect(0.0, 0.0, 0.5, 0.5));
+    hash.insert(e[1], &rect(-1e9, -1e9, 1e9, 1e9));
+    hash.insert(e[2], &rect(100.0, 100.0, 100.5, 100.5));
+    hash.insert(e[3], &rect(f32::NEG_INFINITY, 0.0, f32::INFINITY, 1.0));
+
+    assert!(hash.cells.len() <= 2);
+    assert_eq!(
+      hash.pairs(),
+      BTreeSet::from([
+        (e[0], e[1]),
+        (e[0], e[3]),
+        (e[1], e[2]),
+        (e[1], e[3]),
+        (e[2], e[3]),
+      ])
+    );
+    assert_eq!(
+      hash.query(&rect(100.0, 100.0, 101.0, 101.0)),
+      BTreeSet::from([e[1], e[2], e[3]])
+    );
+  }
+}
@@ -0,0 +1,353 @@
+use crate::game::components::Transform;
+use crate::math::glm::{self, Vec2};
+use geo::{algorithm::convex_hull::ConvexHull, Coordinate, Polygon, Rect};
+use serde_json::Value;
+
+mod keys {
+  pub const SHAPE: &str = "shape";
+  pub const WIDTH: &str = "width";
+  pub const HEIGHT: &str = "height";
+  pub const RADIUS: &str = "radius";
+  pub const POINTS: &str = "points";
+  pub const OFFSET: &str = "offset";
+  pub const LAYER: &str = "layer";
+  pub const MASK: &str = "mask";
+  pub const SENSOR: &str = "sensor";
+
+  pub const SHAPE_AABB: &str = "aabb";
+  pub const SHAPE_CIRCLE: &str = "circle";
+  pub const SHAPE_POLYGON: &str = "polygon";
+}
+
+/// Collision layer bits, a collider is on the layers in `layer` and hits those in `mask`.
+pub mod layers {
+  pub const DEFAULT: u32 = 1;
+  pub const ALL: u32 = u32::MAX;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Shape {
+  /// A box that stays axis aligned however its entity is rotated.
+  Aabb {
+    half_extents: Vec2,
+  },
+  Circle {
+    radius: f32,
+  },
+  /// A convex polygon in local space.
+  Polygon {
+    points: Vec<Vec2>,
+  },
+}
+
+impl Shape {
+  pub fn aabb(width: f32, height: f32) -> Self {
+    Shape::Aabb {
+      half_extents: glm::vec2(width / 2.0, height / 2.0),
+    }
+  }
+
+  pub fn circle(radius: f32) -> Self {
+    Shape::Circle { radius }
+  }
+
+  /// Uses the convex hull of the polygon, holes are ignored.
+  pub fn polygon(polygon: &Polygon<f32>) -> Result<Self, String> {
+    let hull = polygon.convex_hull();
+    let mut points: Vec<Vec2> = hull
+      .exterior()
+      .points_iter()
+      .map(|point| glm::vec2(point.x(), point.y()))
+      .collect();
+    // geo closes rings by repeating the first point
+    if points.len() > 1 && points.first() == points.last() {
+      points.pop();
+    }
+
+    if points.len() < 3 {
+      return Err(String::from(
+        "polygon colliders need at least three corners",
+      ));
+    }
+
+    Ok(Shape::Polygon { points })
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Collider {
+  pub shape: Shape,
+  pub offset: Vec2,
+  pub layer: u32,
+  pub mask: u32,
+  /// Sensors report collisions but are never pushed apart.
+  pub sensor: bool,
+}
+
+impl Collider {
+  pub fn new(shape: Shape) -> Self {
+    Self {
+      shape,
+      offset: glm::vec2(0.0, 0.0),
+      layer: layers::DEFAULT,
+      mask: layers::ALL,
+      sensor: false,
+    }
+  }
+
+  /// Both colliders have to be interested in each other for a collision to happen.
+  pub fn interacts(&self, other: &Collider) -> bool {
+    self.layer & other.mask != 0 && other.layer & self.mask != 0
+  }
+
+  pub fn world_shape(&self, transform: &Transform) -> WorldShape {
+    let rotation = transform.rotation.to_radians();
+    let place = |point: &Vec2| {
+      let scaled = (point + self.offset).component_mul(&transform.scale);
+      glm::rotate_vec2(&scaled, rotation) + transform.position
+    };
+
+    match &self.shape {
+      Shape::Aabb { half_extents } => {
+        let center = place(&glm::vec2(0.0, 0.0));
+        let half = half_extents.component_mul(&transform.scale).abs();
+        WorldShape::Polygon {
+          points: vec![
+            center + glm::vec2(-half.x, -half.y),
+            center + glm::vec2(half.x, -half.y),
+            center + glm::vec2(half.x, half.y),
+            center + glm::vec2(-half.x, half.y),
+          ],
+        }
+      }
+      Shape::Circle { radius } => WorldShape::Circle {
+        center: place(&glm::vec2(0.0, 0.0)),
+        radius: radius * transform.scale.x.abs().max(transform.scale.y.abs()),
+      },
+      Shape::Polygon { points } => WorldShape::Polygon {
+        points: points.iter().map(place).collect(),
+      },
+    }
+  }
+}
+
+fn f32_of(value: &Value, key: &str) -> Result<f32, String> {
+  value
+    .get(key)
+    .and_then(Value::as_f64)
+    .map(|v| v as f32)
+    .ok_or_else(|| format!("collider '{}' must be a number", key))
+}
+
+fn size_of(value: &Value, key: &str) -> Result<f32, String> {
+  let size = f32_of(value, key)?;
+  if size > 0.0 {
+    Ok(size)
+  } else {
+    Err(format!("collider '{}' must be positive", key))
+  }
+}
+
+fn vec2_of(value: &Value) -> Option<Vec2> {
+  match value.as_array()?.as_slice() {
+    [x, y] => Some(glm::vec2(x.as_f64()? as f32, y.as_f64()? as f32)),
+    _ => None,
+  }
+}
+
+fn bits_of(value: &Value, key: &str, default: u32) -> Result<u32, String> {
+  match value.get(key) {
+    Some(bits) => bits
+      .as_u64()
+      .and_then(|bits| u32::try_from(bits).ok())
+      .ok_or_else(|| format!("collider '{}' must be a 32 bit mask", key)),
+    None => Ok(default),
+  }
+}
+
+impl TryFrom<&Value> for Collider {
+  type Error = String;
+
+  fn try_from(value: &Value) -> Result<Self, Self::Error> {
+    let table = value
+      .as_object()
+      .ok_or_else(|| String::from("collider must be an object"))?;
+
+    let allowed = [
+      keys::SHAPE,
+      keys::WIDTH,
+      keys::HEIGHT,
+      keys::RADIUS,
+      keys::POINTS,
+      keys::OFFSET,
+      keys::LAYER,
+      keys::MASK,
+      keys::SENSOR,
+    ];
+    if let Some(key) = table.keys().find(|key| !allowed.contains(&key.as_str())) {
+      return Err(format!("unknown collider key '{}'", key));
+    }
+
+    let shape = match value.get(keys::SHAPE).and_then(Value::as_str) {
+      Some(keys::SHAPE_AABB) => {
+        Shape::aabb(size_of(value, keys::WIDTH)?, size_of(value, keys::HEIGHT)?)
+      }
+      Some(keys::SHAPE_CIRCLE) => Shape::circle(size_of(value, keys::RADIUS)?),
+      Some(keys::SHAPE_POLYGON) => {
+        let points = value
+          .get(keys::POINTS)
+          .and_then(Value::as_array)
+          .ok_or_else(|| format!("polygon collider is missing '{}'", keys::POINTS))?
+          .iter()
+          .map(|point| {
+            vec2_of(point)
+              .map(|point| Coordinate {
+                x: point.x,
+                y: point.y,
+              })
+              .ok_or_else(|| String::from("polygon points must be [x, y] pairs"))
+          })
+          .collect::<Result<Vec<_>, String>>()?;
+        Shape::polygon(&Polygon::new(points.into(), Vec::default()))?
+      }
+      Some(invalid) => return Err(format!("unknown collider shape '{}'", invalid)),
+      None => return Err(format!("collider is missing '{}'", keys::SHAPE)),
+    };
+
+    let offset = match value.get(keys::OFFSET) {
+      Some(offset) => vec2_of(offset)
+        .ok_or_else(|| format!("collider '{}' must be an [x, y] pair", keys::OFFSET))?,
+      None => glm::vec2(0.0, 0.0),
+    };
+
+    let sensor = match value.get(keys::SENSOR) {
+      Some(sensor) => sensor
+        .as_bool()
+        .ok_or_else(|| format!("collider '{}' must be a boolean", keys::SENSOR))?,
+      None => false,
+    };
+
+    Ok(Self {
+      shape,
+      offset,
+      layer: bits_of(value, keys::LAYER, layers::DEFAULT)?,
+      mask: bits_of(value, keys::MASK, layers::ALL)?,
+      sensor,
+    })
+  }
+}
+
+/// A collider placed in the world.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WorldShape {
+  Circle { center: Vec2, radius: f32 },
+  Polygon { points: Vec<Vec2> },
+}
+
+impl WorldShape {
+  pub fn center(&self) -> Vec2 {
+    match self {
+      WorldShape::Circle { center, .. } => *center,
+      WorldShape::Polygon { points } => {
+        points
+          .iter()
+          .fold(glm::vec2(0.0, 0.0), |sum, point| sum + point)
+          / points.len() as f32
+      }
+    }
+  }
+
+  pub fn bounds(&self) -> Rect<f32> {
+    match self {
+      WorldShape::Circle { center, radius } => Rect::new(
+        (center.x - radius, center.y - radius),
+        (center.x + radius, center.y + radius),
+      ),
+      WorldShape::Polygon { points } => {
+        let (min, max) = points.iter().fold(
+          (glm::vec2(f32::MAX, f32::MAX), glm::vec2(f32::MIN, f32::MIN)),
+          |(min, max), point| (glm::min2(&min, point), glm::max2(&max, point)),
+        );
+        Rect::new((min.x, min.y), (max.x, max.y))
+      }
+    }
+  }
+
+  /// The interval the shape covers along an axis.
+  pub fn project(&self, axis: &Vec2) -> (f32, f32) {
+    match self {
+      WorldShape::Circle { center, radius } => {
+        let at = center.dot(axis);
+        (at - radius, at + radius)
+      }
+      WorldShape::Polygon { points } => {
+        points
+          .iter()
+          .fold((f32::MAX, f32::MIN), |(min, max), point| {
+            let at = point.dot(axis);
+            (min.min(at), max.max(at))
+          })
+      }
+    }
+  }
+
+  pub fn contains_point(&self, point: &Vec2) -> bool {
+    match self {
+      WorldShape::Circle { center, radius } => glm::distance2(center, point) <= radius * radius,
+      WorldShape::Polygon { points } => {
+        // inside a convex polygon the point is on the same side of every edge
+        let mut sign = 0.0f32;
+        for (a, b) in points.iter().zip(points.iter().cycle().skip(1)) {
+          let cross = (b - a).perp(&(point - a));
+          if cross != 0.0 {
+            if sign != 0.0 && cross.signum() != sign {
+              return false;
+            }
+            sign = cross.signum();
+          }
+        }
+        true
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parse(source: &str) -> Result<Collider, String> {
+    Collider::try_from(&serde_json::from_str::<Value>(source).unwrap())
+  }
+
+  #[test]
+  fn shapes_are_read_with_their_defaults() {
+    let aabb = parse(r#"{ "shape": "aabb", "width": 4, "height": 2 }"#).unwrap();
+    assert_eq!(aabb, Collider::new(Shape::aabb(4.0, 2.0)));
+
+    let circle =
+      parse(r#"{ "shape": "circle", "radius": 3, "offset": [1, 2], "sensor": true }"#).unwrap();
+    assert_eq!(circle.shape, Shape::circle(3.0));
+    assert_eq!(circle.offset, glm::vec2(1.0, 2.0));
+    assert!(circle.sensor);
+  }
+
+  #[test]
+  fn sizes_must_be_positive() {
+    for source in [
+      r#"{ "shape": "aabb", "width": 0, "height": 2 }"#,
+      r#"{ "shape": "aabb", "width": 4, "height": -2 }"#,
+      r#"{ "shape": "circle", "radius": 0 }"#,
+      r#"{ "shape": "circle", "radius": -1 }"#,
+    ] {
+      let error = parse(source).unwrap_err();
+      assert!(error.contains("must be positive"), "{}", error);
+    }
+  }
+
+  #[test]
+  fn polygons_need_three_corners() {
+    assert!(parse(r#"{ "shape": "polygon", "points": [[0, 0], [1, 0]] }"#).is_err());
+    assert!(parse(r#"{ "shape": "polygon", "points": [[0, 0], [1, 0], [0, 1]] }"#).is_ok());
+  }
+}
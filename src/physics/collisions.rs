@@ -0,0 +1,267 @@
+use super::{narrow_phase, Collider, Contact, SpatialHash, WorldShape};
+use crate::game::{components::Transform, Entity, World};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CollisionPhase {
+  Enter,
+  Stay,
+  Exit,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CollisionEvent {
+  pub phase: CollisionPhase,
+  pub entity: Entity,
+  pub other: Entity,
+  /// Points from `entity` towards `other`, there is no contact once they exit.
+  pub contact: Option<Contact>,
+  /// Whether either collider is a sensor.
+  pub sensor: bool,
+}
+
+impl CollisionEvent {
+  /// The same event seen from the other entity.
+  pub fn flipped(&self) -> Self {
+    Self {
+      entity: self.other,
+      other: self.entity,
+      contact: self.contact.map(|contact| contact.flipped()),
+      ..self.clone()
+    }
+  }
+}
+
+/// The collision events of the last step that involve an entity, for its script to read.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CollisionEvents(pub Vec<CollisionEvent>);
+
+/// Finds overlapping colliders and reports when they start touching, keep touching, and separate.
+pub struct CollisionSystem {
+  broad_phase: SpatialHash,
+  shapes: BTreeMap<Entity, WorldShape>,
+  colliders: BTreeMap<Entity, Collider>,
+  touching: BTreeMap<(Entity, Entity), (Contact, bool)>,
+  events: Vec<CollisionEvent>,
+}
+
+impl CollisionSystem {
+  /// `cell_size` should be around the size of a typical collider.
+  pub fn new(cell_size: f32) -> Self {
+    Self {
+      broad_phase: SpatialHash::new(cell_size),
+      shapes: BTreeMap::default(),
+      colliders: BTreeMap::default(),
+      touching: BTreeMap::default(),
+      events: Vec::default(),
+    }
+  }
+
+  pub fn update(&mut self, world: &mut World) {
+    self.broad_phase.clear();
+    self.shapes.clear();
+    self.colliders.clear();
+
+    for (entity, collider) in world.query::<Collider>() {
+      if let Some(transform) = world.get::<Transform>(entity) {
+        let shape = collider.world_shape(transform);
+        self.broad_phase.insert(entity, &shape.bounds());
+        self.shapes.insert(entity, shape);
+        self.colliders.insert(entity, collider.clone());
+      }
+    }
+
+    let mut touching = BTreeMap::new();
+    for (a, b) in self.broad_phase.pairs() {
+      let (collider_a, collider_b) = (&self.colliders[&a], &self.colliders[&b]);
+      if !collider_a.interacts(collider_b) {
+        continue;
+      }
+
+      if let Some(contact) = narrow_phase::contact(&self.shapes[&a], &self.shapes[&b]) {
+        touching.insert((a, b), (contact, collider_a.sensor || collider_b.sensor));
+      }
+    }
+
+    self.events.clear();
+    for ((entity, other), (contact, sensor)) in &touching {
+      let phase = if self.touching.contains_key(&(*entity, *other)) {
+        CollisionPhase::Stay
+      } else {
+        CollisionPhase::Enter
+      };
+      self.events.push(CollisionEvent {
+        phase,
+        entity: *entity,
+        other: *other,
+        contact: Some(*contact),
+        sensor: *sensor,
+      });
+    }
+    for ((entity, other), (_, sensor)) in &self.touching {
+      if !touching.contains_key(&(*entity, *other)) {
+        self.events.push(CollisionEvent {
+          phase: CollisionPhase::Exit,
+          entity: *entity,
+          other: *other,
+          contact: None,
+          sensor: *sensor,
+        });
+      }
+    }
+    self.touching = touching;
+
+    for (_, events) in world.query_mut::<CollisionEvents>() {
+      events.0.clear();
+    }
+    for event in &self.events {
+      for event in [event.clone(), event.flipped()] {
+        if !world.contains(event.entity) {
+          continue;
+        }
+        match world.get_mut::<CollisionEvents>(event.entity) {
+          Some(events) => events.0.push(event),
+          None => world.insert(event.entity, CollisionEvents(vec![event])),
+        }
+      }
+    }
+  }
+
+  /// Every event of the last update, each pair reported once with the smaller entity first.
+  pub fn events(&self) -> &[CollisionEvent] {
+    &self.events
+  }
+
+  pub fn contacts(&self) -> impl Iterator<Item = (Entity, Entity, &Contact)> {
+    self
+      .touching
+      .iter()
+      .map(|((a, b), (contact, _))| (*a, *b, contact))
+  }
+
+  /// The contact between two entities, seen from `a`.
+  pub fn contact(&self, a: Entity, b: Entity) -> Option<Contact> {
+    match self.touching.get(&(a.min(b), a.max(b))) {
+      Some((contact, _)) if a < b => Some(*contact),
+      Some((contact, _)) => Some(contact.flipped()),
+      None => None,
+    }
+  }
+
+  /// Where each collider was placed during the last update.
+  pub fn shape(&self, entity: Entity) -> Option<&WorldShape> {
+    self.shapes.get(&entity)
+  }
+
+  /// The collider of an entity as it was during the last update.
+  pub fn collider(&self, entity: Entity) -> Option<&Collider> {
+    self.colliders.get(&entity)
+  }
+
+  pub fn broad_phase(&self) -> &SpatialHash {
+    &self.broad_phase
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{math::glm, physics::Shape};
+
+  fn spawn(world: &mut World, x: f32, collider: Collider) -> Entity {
+    let entity = world.spawn();
+    world.insert(
+      entity,
+      Transform {
+        position: glm::vec2(x, 0.0),
+        ..Transform::default()
+      },
+    );
+    world.insert(entity, collider);
+    entity
+  }
+
+  fn move_to(world: &mut World, entity: Entity, x: f32) {
+    world.get_mut::<Transform>(entity).unwrap().position.x = x;
+  }
+
+  fn phases(events: &[CollisionEvent]) -> Vec<CollisionPhase> {
+    events.iter().map(|event| event.phase).collect()
+  }
+
+  #[test]
+  fn touching_colliders_enter_stay_then_exit() {
+    let mut world = World::new();
+    let a = spawn(&mut world, 0.0, Collider::new(Shape::circle(1.0)));
+    let b = spawn(&mut world, 5.0, Collider::new(Shape::circle(1.0)));
+    let mut collisions = CollisionSystem::new(4.0);
+
+    collisions.update(&mut world);
+    assert!(collisions.events().is_empty());
+
+    move_to(&mut world, b, 1.5);
+    collisions.update(&mut world);
+    assert_eq!(phases(collisions.events()), [CollisionPhase::Enter]);
+
+    collisions.update(&mut world);
+    assert_eq!(phases(collisions.events()), [CollisionPhase::Stay]);
+    assert!(collisions.contact(a, b).is_some());
+
+    move_to(&mut world, b, 5.0);
+    collisions.update(&mut world);
+    assert_eq!(phases(collisions.events()), [CollisionPhase::Exit]);
+    assert_eq!(collisions.events()[0].contact, None);
+
+    collisions.update(&mut world);
+    assert!(collisions.events().is_empty());
+  }
+
+  #[test]
+  fn both_entities_see_the_event_from_their_side() {
+    let mut world = World::new();
+    let a = spawn(&mut world, 0.0, Collider::new(Shape::circle(1.0)));
+    let b = spawn(&mut world, 1.5, Collider::new(Shape::circle(1.0)));
+    let mut collisions = CollisionSystem::new(4.0);
+    collisions.update(&mut world);
+
+    let seen_by_a = &world.get::<CollisionEvents>(a).unwrap().0;
+    let seen_by_b = &world.get::<CollisionEvents>(b).unwrap().0;
+    assert_eq!(seen_by_a.len(), 1);
+    assert_eq!(seen_by_b, &[seen_by_a[0].flipped()]);
+    assert_eq!(seen_by_a[0].other, b);
+    assert!(seen_by_a[0].contact.unwrap().normal.x > 0.0);
+    assert!(seen_by_b[0].contact.unwrap().normal.x < 0.0);
+
+    // events only last for the update that produced them
+    move_to(&mut world, b, 5.0);
+    collisions.update(&mut world);
+    collisions.update(&mut world);
+    assert!(world.get::<CollisionEvents>(a).unwrap().0.is_empty());
+  }
+
+  #[test]
+  fn colliders_ignoring_each_other_never_collide() {
+    let mut world = World::new();
+    let mut ghost = Collider::new(Shape::circle(1.0));
+    ghost.mask = 0;
+    spawn(&mut world, 0.0, Collider::new(Shape::circle(1.0)));
+    spawn(&mut world, 1.5, ghost);
+    let mut collisions = CollisionSystem::new(4.0);
+
+    collisions.update(&mut world);
+    assert!(collisions.events().is_empty());
+  }
+
+  #[test]
+  fn sensor_collisions_are_marked() {
+    let mut world = World::new();
+    let mut sensor = Collider::new(Shape::circle(1.0));
+    sensor.sensor = true;
+    spawn(&mut world, 0.0, Collider::new(Shape::circle(1.0)));
+    spawn(&mut world, 1.5, sensor);
+    let mut collisions = CollisionSystem::new(4.0);
+
+    collisions.update(&mut world);
+    assert!(collisions.events()[0].sensor);
+  }
+}
@@ -0,0 +1,217 @@
+use super::WorldShape;
+use crate::math::glm::{self, Vec2};
+
+/// How two shapes overlap. The normal points from the first shape towards the second,
+/// and the point is the deepest point of the second shape inside the first.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Contact {
+  pub normal: Vec2,
+  pub depth: f32,
+  pub point: Vec2,
+}
+
+impl Contact {
+  /// The same contact seen from the second shape.
+  pub fn flipped(&self) -> Self {
+    Self {
+      normal: -self.normal,
+      ..*self
+    }
+  }
+}
+
+fn edge_normals(points: &[Vec2]) -> impl Iterator<Item = Vec2> + '_ {
+  points
+    .iter()
+    .zip(points.iter().cycle().skip(1))
+    .filter_map(|(a, b)| {
+      let edge = b - a;
+      (edge.norm_squared() > f32::EPSILON).then(|| glm::vec2(-edge.y, edge.x).normalize())
+    })
+}
+
+/// The axis from a circle to the nearest corner of a polygon, which the polygon edges alone miss.
+fn corner_axis(center: &Vec2, points: &[Vec2]) -> Option<Vec2> {
+  let closest = points
+    .iter()
+    .min_by(|a, b| glm::distance2(a, center).total_cmp(&glm::distance2(b, center)))?;
+  let axis = closest - center;
+  (axis.norm_squared() > f32::EPSILON).then(|| axis.normalize())
+}
+
+fn support(shape: &WorldShape, direction: &Vec2) -> Vec2 {
+  match shape {
+    WorldShape::Circle { center, radius } => center + direction * *radius,
+    WorldShape::Polygon { points } => *points
+      .iter()
+      .max_by(|a, b| a.dot(direction).total_cmp(&b.dot(direction)))
+      .unwrap_or(&glm::vec2(0.0, 0.0)),
+  }
+}
+
+/// Finds the axis of least overlap with the separating axis theorem.
+fn separating_axis(a: &WorldShape, b: &WorldShape, axes: &[Vec2]) -> Option<Contact> {
+  let mut best: Option<(Vec2, f32)> = None;
+
+  for axis in axes {
+    let (min_a, max_a) = a.project(axis);
+    let (min_b, max_b) = b.project(axis);
+    let overlap = max_a.min(max_b) - min_a.max(min_b);
+    if overlap <= 0.0 {
+      return None;
+    }
+
+    if best.is_none_or(|(_, depth)| overlap < depth) {
+      best = Some((*axis, overlap));
+    }
+  }
+
+  let (mut normal, depth) = best?;
+  if normal.dot(&(b.center() - a.center())) < 0.0 {
+    normal = -normal;
+  }
+
+  Some(Contact {
+    normal,
+    depth,
+    point: support(b, &-normal),
+  })
+}
+
+pub fn contact(a: &WorldShape, b: &WorldShape) -> Option<Contact> {
+  match (a, b) {
+    (
+      WorldShape::Circle {
+        center: center_a,
+        radius: radius_a,
+      },
+      WorldShape::Circle {
+        center: center_b,
+        radius: radius_b,
+      },
+    ) => {
+      let offset = center_b - center_a;
+      let distance = offset.norm();
+      let reach = radius_a + radius_b;
+      if distance >= reach {
+        return None;
+      }
+
+      let normal = if distance > f32::EPSILON {
+        offset / distance
+      } else {
+        glm::vec2(1.0, 0.0)
+      };
+
+      Some(Contact {
+        normal,
+        depth: reach - distance,
+        point: center_b - normal * *radius_b,
+      })
+    }
+    (WorldShape::Polygon { points: points_a }, WorldShape::Polygon { points: points_b }) => {
+      let axes: Vec<Vec2> = edge_normals(points_a)
+        .chain(edge_normals(points_b))
+        .collect();
+      separating_axis(a, b, &axes)
+    }
+    (WorldShape::Circle { center, .. }, WorldShape::Polygon { points })
+    | (WorldShape::Polygon { points }, WorldShape::Circle { center, .. }) => {
+      let axes: Vec<Vec2> = edge_normals(points)
+        .chain(corner_axis(center, points))
+        .collect();
+      separating_axis(a, b, &axes)
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn circle(x: f32, y: f32, radius: f32) -> WorldShape {
+    WorldShape::Circle {
+      center: glm::vec2(x, y),
+      radius,
+    }
+  }
+
+  /// An axis aligned box around `(x, y)`.
+  fn square(x: f32, y: f32, half: f32) -> WorldShape {
+    WorldShape::Polygon {
+      points: vec![
+        glm::vec2(x - half, y - half),
+        glm::vec2(x + half, y - half),
+        glm::vec2(x + half, y + half),
+        glm::vec2(x - half, y + half),
+      ],
+    }
+  }
+
+  fn assert_near(actual: Vec2, expected: Vec2) {
+    assert!(
+      glm::distance(&actual, &expected) < 1e-4,
+      "{:?} is not {:?}",
+      actual,
+      expected
+    );
+  }
+
+  #[test]
+  fn apart_shapes_do_not_touch() {
+    assert!(contact(&circle(0.0, 0.0, 5.0), &circle(10.0, 0.0, 5.0)).is_none());
+    assert!(contact(&square(0.0, 0.0, 5.0), &square(10.5, 0.0, 5.0)).is_none());
+    assert!(contact(&circle(0.0, 0.0, 5.0), &square(10.5, 0.0, 5.0)).is_none());
+    // inside the bounds of the box, but past its corner
+    assert!(contact(&circle(9.0, 9.0, 5.0), &square(0.0, 0.0, 5.0)).is_none());
+  }
+
+  #[test]
+  fn circles_touch_along_their_centers() {
+    let contact = contact(&circle(0.0, 0.0, 5.0), &circle(8.0, 0.0, 5.0)).unwrap();
+    assert_near(contact.normal, glm::vec2(1.0, 0.0));
+    assert!((contact.depth - 2.0).abs() < 1e-4);
+    assert_near(contact.point, glm::vec2(3.0, 0.0));
+  }
+
+  #[test]
+  fn boxes_touch_along_the_axis_of_least_overlap() {
+    let contact = contact(&square(0.0, 0.0, 5.0), &square(2.0, 8.0, 5.0)).unwrap();
+    assert_near(contact.normal, glm::vec2(0.0, 1.0));
+    assert!((contact.depth - 2.0).abs() < 1e-4);
+    assert!((contact.point.y - 3.0).abs() < 1e-4);
+  }
+
+  #[test]
+  fn circles_touch_boxes_from_either_side() {
+    let a = circle(0.0, 9.0, 5.0);
+    let b = square(0.0, 0.0, 5.0);
+
+    let contact_ab = contact(&a, &b).unwrap();
+    assert_near(contact_ab.normal, glm::vec2(0.0, -1.0));
+    assert!((contact_ab.depth - 1.0).abs() < 1e-4);
+
+    let contact_ba = contact(&b, &a).unwrap();
+    assert_near(contact_ba.normal, glm::vec2(0.0, 1.0));
+    assert!((contact_ba.depth - 1.0).abs() < 1e-4);
+    assert_near(contact_ba.point, glm::vec2(0.0, 4.0));
+  }
+
+  #[test]
+  fn the_point_is_the_deepest_point_of_the_second_shape() {
+    let pairs = [
+      (circle(0.0, 0.0, 5.0), circle(8.0, 1.0, 5.0)),
+      (square(0.0, 0.0, 5.0), square(8.0, 1.0, 5.0)),
+      (square(0.0, 0.0, 5.0), circle(8.0, 1.0, 5.0)),
+      (circle(0.0, 0.0, 5.0), square(8.0, 1.0, 5.0)),
+    ];
+    for (a, b) in &pairs {
+      let contact = contact(a, b).unwrap();
+      let along = contact.point.dot(&contact.normal);
+      // nothing of the second shape reaches further back, and the first reaches
+      // exactly `depth` past it
+      assert!((b.project(&contact.normal).0 - along).abs() < 1e-4);
+      assert!((a.project(&contact.normal).1 - along - contact.depth).abs() < 1e-4);
+    }
+  }
+}
@@ -0,0 +1,616 @@
+use super::{layers, narrow_phase, Collider, CollisionSystem, WorldShape};
+use crate::game::Entity;
+use crate::math::glm::{self, Vec2};
+use geo::Rect;
+
+/// Which colliders a query can hit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryFilter {
+  /// Only colliders on one of these layers are hit.
+  pub mask: u32,
+  pub sensors: bool,
+  pub exclude: Vec<Entity>,
+}
+
+impl Default for QueryFilter {
+  fn default() -> Self {
+    Self {
+      mask: layers::ALL,
+      sensors: false,
+      exclude: Vec::default(),
+    }
+  }
+}
+
+impl QueryFilter {
+  pub fn with_sensors(mut self) -> Self {
+    self.sensors = true;
+    self
+  }
+
+  /// Casting from inside an entity usually should not hit the entity itself.
+  pub fn excluding(mut self, entity: Entity) -> Self {
+    self.exclude.push(entity);
+    self
+  }
+
+  fn accepts(&self, entity: Entity, collider: &Collider) -> bool {
+    collider.layer & self.mask != 0
+      && (self.sensors || !collider.sensor)
+      && !self.exclude.contains(&entity)
+  }
+}
+
+/// Where a query met a collider.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hit {
+  pub entity: Entity,
+  pub point: Vec2,
+  /// The surface normal of the collider that was hit, facing the query.
+  pub normal: Vec2,
+  /// How far along the cast the hit is, from 0 at the start to 1 at the end.
+  pub fraction: f32,
+}
+
+/// Where a point moving by `translation` first reaches a circle, and the circle normal there.
+fn ray_circle(
+  origin: &Vec2,
+  translation: &Vec2,
+  center: &Vec2,
+  radius: f32,
+) -> Option<(f32, Vec2)> {
+  let offset = origin - center;
+  let a = translation.norm_squared();
+  let b = offset.dot(translation);
+  let c = offset.norm_squared() - radius * radius;
+  let discriminant = b * b - a * c;
+  if a <= f32::EPSILON || discriminant < 0.0 {
+    return None;
+  }
+
+  let t = (-b - discriminant.sqrt()) / a;
+  if !(0.0..=1.0).contains(&t) {
+    return None;
+  }
+
+  let normal = (origin + translation * t - center).try_normalize(f32::EPSILON)?;
+  Some((t, normal))
+}
+
+/// Like `ray_circle` for a convex polygon grown by `radius`, with rounded corners.
+/// A radius of zero is the polygon itself.
+fn ray_polygon(
+  origin: &Vec2,
+  translation: &Vec2,
+  points: &[Vec2],
+  radius: f32,
+) -> Option<(f32, Vec2)> {
+  let center = points.iter().fold(glm::vec2(0.0, 0.0), |sum, p| sum + p) / points.len() as f32;
+  let mut best: Option<(f32, Vec2)> = None;
+  let mut keep = |hit: Option<(f32, Vec2)>| {
+    if let Some(hit) = hit {
+      if best.is_none_or(|(t, _)| hit.0 < t) {
+        best = Some(hit);
+      }
+    }
+  };
+
+  for (a, b) in points.iter().zip(points.iter().cycle().skip(1)) {
+    let edge = b - a;
+    let mut normal = match glm::vec2(-edge.y, edge.x).try_normalize(f32::EPSILON) {
+      Some(normal) => normal,
+      None => continue,
+    };
+    if normal.dot(&(a - center)) < 0.0 {
+      normal = -normal;
+    }
+
+    // only edges facing the ray can be entered through
+    let denominator = translation.perp(&edge);
+    if normal.dot(translation) < 0.0 && denominator.abs() > f32::EPSILON {
+      let start = a + normal * radius - origin;
+      let t = start.perp(&edge) / denominator;
+      let u = start.perp(translation) / denominator;
+      if (0.0..=1.0).contains(&t) && (0.0..=1.0).contains(&u) {
+        keep(Some((t, normal)));
+      }
+    }
+
+    if radius > 0.0 {
+      keep(ray_circle(origin, translation, a, radius));
+    }
+  }
+
+  best
+}
+
+/// When a shape moving by `translation` first touches another, as (fraction, normal, point).
+fn cast(shape: &WorldShape, translation: &Vec2, target: &WorldShape) -> Option<(f32, Vec2, Vec2)> {
+  if let Some(contact) = narrow_phase::contact(shape, target) {
+    return Some((0.0, -contact.normal, contact.point));
+  }
+
+  match (shape, target) {
+    (
+      WorldShape::Circle { center, radius },
+      WorldShape::Circle {
+        center: target_center,
+        radius: target_radius,
+      },
+    ) => ray_circle(center, translation, target_center, radius + target_radius)
+      .map(|(t, normal)| (t, normal, target_center + normal * *target_radius)),
+    (WorldShape::Circle { center, radius }, WorldShape::Polygon { points }) => {
+      ray_polygon(center, translation, points, *radius)
+        .map(|(t, normal)| (t, normal, center + translation * t - normal * *radius))
+    }
+    // the same as the circle moving the other way into the polygon
+    (WorldShape::Polygon { points }, WorldShape::Circle { center, radius }) => {
+      ray_polygon(center, &-translation, points, *radius)
+        .map(|(t, normal)| (t, -normal, center - normal * *radius))
+    }
+    (
+      WorldShape::Polygon { points },
+      WorldShape::Polygon {
+        points: target_points,
+      },
+    ) => swept_polygons(points, translation, target_points),
+  }
+}
+
+/// The separating axis test with the first polygon moving, the time of impact being
+/// the latest time any axis starts to overlap.
+fn swept_polygons(
+  points: &[Vec2],
+  translation: &Vec2,
+  target: &[Vec2],
+) -> Option<(f32, Vec2, Vec2)> {
+  let (shape, target_shape) = (
+    WorldShape::Polygon {
+      points: points.to_vec(),
+    },
+    WorldShape::Polygon {
+      points: target.to_vec(),
+    },
+  );
+
+  let mut enter = (f32::MIN, glm::vec2(0.0, 0.0), false);
+  let mut exit = f32::MAX;
+
+  let axes = points
+    .iter()
+    .zip(points.iter().cycle().skip(1))
+    .map(|(a, b)| (b - a, false))
+    .chain(
+      target
+        .iter()
+        .zip(target.iter().cycle().skip(1))
+        .map(|(a, b)| (b - a, true)),
+    );
+
+  for (edge, on_target) in axes {
+    let axis = match glm::vec2(-edge.y, edge.x).try_normalize(f32::EPSILON) {
+      Some(axis) => axis,
+      None => continue,
+    };
+    let (min, max) = shape.project(&axis);
+    let (target_min, target_max) = target_shape.project(&axis);
+    let speed = translation.dot(&axis);
+
+    if speed.abs() <= f32::EPSILON {
+      if max <= target_min || min >= target_max {
+        return None;
+      }
+      continue;
+    }
+
+    let (start, end, normal) = if speed > 0.0 {
+      (
+        (target_min - max) / speed,
+        (target_max - min) / speed,
+        -axis,
+      )
+    } else {
+      ((target_max - min) / speed, (target_min - max) / speed, axis)
+    };
+    if start > enter.0 {
+      enter = (start, normal, on_target);
+    }
+    exit = exit.min(end);
+  }
+
+  let (t, normal, on_target) = enter;
+  if t > exit || !(0.0..=1.0).contains(&t) {
+    return None;
+  }
+
+  let support = |points: &[Vec2], direction: &Vec2| {
+    *points
+      .iter()
+      .max_by(|a, b| a.dot(direction).total_cmp(&b.dot(direction)))
+      .unwrap_or(&glm::vec2(0.0, 0.0))
+  };
+  // a corner of one polygon lands on a face of the other
+  let point = if on_target {
+    support(points, &-normal) + translation * t
+  } else {
+    support(target, &normal)
+  };
+
+  Some((t, normal, point))
+}
+
+fn swept_bounds(bounds: &Rect<f32>, translation: &Vec2) -> Rect<f32> {
+  let (min, max) = (bounds.min(), bounds.max());
+  Rect::new(
+    (
+      min.x.min(min.x + translation.x),
+      min.y.min(min.y + translation.y),
+    ),
+    (
+      max.x.max(max.x + translation.x),
+      max.y.max(max.y + translation.y),
+    ),
+  )
+}
+
+/// Queries against where the colliders were placed during the last update.
+impl CollisionSystem {
+  fn candidates<'a>(
+    &'a self,
+    area: &Rect<f32>,
+    filter: &'a QueryFilter,
+  ) -> impl Iterator<Item = (Entity, &'a WorldShape)> + 'a {
+    self
+      .broad_phase()
+      .query(area)
+      .into_iter()
+      .filter(move |entity| {
+        self
+          .collider(*entity)
+          .is_some_and(|collider| filter.accepts(*entity, collider))
+      })
+      .filter_map(move |entity| self.shape(entity).map(|shape| (entity, shape)))
+  }
+
+  /// Every hit along a ray, nearest first. Fails when `max_distance` is negative or not
+  /// finite.
+  pub fn raycast_all(
+    &self,
+    origin: Vec2,
+    direction: Vec2,
+    max_distance: f32,
+    filter: &QueryFilter,
+  ) -> Result<Vec<Hit>, String> {
+    if !max_distance.is_finite() {
+      return Err(format!("ray length {} is not finite", max_distance));
+    }
+    if max_distance < 0.0 {
+      return Err(format!("ray length {} is negative", max_distance));
+    }
+    let direction = match direction.try_normalize(f32::EPSILON) {
+      Some(direction) => direction,
+      None => return Ok(Vec::new()),
+    };
+    let translation = direction * max_distance;
+    let area = swept_bounds(
+      &Rect::new((origin.x, origin.y), (origin.x, origin.y)),
+      &translation,
+    );
+
+    let mut hits: Vec<Hit> = self
+      .candidates(&area, filter)
+      .filter_map(|(entity, shape)| {
+        let hit = if shape.contains_point(&origin) {
+          Some((0.0, -direction))
+        } else {
+          match shape {
+            WorldShape::Circle { center, radius } => {
+              ray_circle(&origin, &translation, center, *radius)
+            }
+            WorldShape::Polygon { points } => ray_polygon(&origin, &translation, points, 0.0),
+          }
+        };
+
+        hit.map(|(fraction, normal)| Hit {
+          entity,
+          point: origin + translation * fraction,
+          normal,
+          fraction,
+        })
+      })
+      .collect();
+
+    hits.sort_by(|a, b| {
+      a.fraction
+        .total_cmp(&b.fraction)
+        .then(a.entity.cmp(&b.entity))
+    });
+    Ok(hits)
+  }
+
+  /// The nearest hit along a ray.
+  pub fn raycast(
+    &self,
+    origin: Vec2,
+    direction: Vec2,
+    max_distance: f32,
+    filter: &QueryFilter,
+  ) -> Result<Option<Hit>, String> {
+    Ok(
+      self
+        .raycast_all(origin, direction, max_distance, filter)?
+        .into_iter()
+        .next(),
+    )
+  }
+
+  /// The first collider a shape touches while moving by `translation`.
+  pub fn shape_cast(
+    &self,
+    shape: &WorldShape,
+    translation: Vec2,
+    filter: &QueryFilter,
+  ) -> Option<Hit> {
+    let area = swept_bounds(&shape.bounds(), &translation);
+
+    self
+      .candidates(&area, filter)
+      .filter_map(|(entity, target)| {
+        cast(shape, &translation, target).map(|(fraction, normal, point)| Hit {
+          entity,
+          point,
+          normal,
+          fraction,
+        })
+      })
+      .min_by(|a, b| {
+        a.fraction
+          .total_cmp(&b.fraction)
+          .then(a.entity.cmp(&b.entity))
+      })
+  }
+
+  /// Every collider containing a point.
+  pub fn overlap_point(&self, point: Vec2, filter: &QueryFilter) -> Vec<Hit> {
+    let area = Rect::new((point.x, point.y), (point.x, point.y));
+
+    self
+      .candidates(&area, filter)
+      .filter(|(_, shape)| shape.contains_point(&point))
+      .map(|(entity, shape)| Hit {
+        entity,
+        point,
+        normal: (point - shape.center())
+          .try_normalize(f32::EPSILON)
+          .unwrap_or_else(|| glm::vec2(0.0, 0.0)),
+        fraction: 0.0,
+      })
+      .collect()
+  }
+
+  /// Every collider overlapping a shape, with the deepest point of each inside it.
+  pub fn overlap_shape(&self, shape: &WorldShape, filter: &QueryFilter) -> Vec<Hit> {
+    self
+      .candidates(&shape.bounds(), filter)
+      .filter_map(|(entity, target)| {
+        narrow_phase::contact(shape, target).map(|contact| Hit {
+          entity,
+          point: contact.point,
+          normal: -contact.normal,
+          fraction: 0.0,
+        })
+      })
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::game::{components::Transform, World};
+  use crate::physics::Shape;
+
+  const CELL_SIZE: f32 = 16.0;
+
+  struct Scene {
+    collisions: CollisionSystem,
+    wall: Entity,
+    ball: Entity,
+    sensor: Entity,
+  }
+
+  fn place(world: &mut World, x: f32, y: f32, collider: Collider) -> Entity {
+    let entity = world.spawn();
+    world.insert(
+      entity,
+      Transform {
+        position: glm::vec2(x, y),
+        ..Transform::default()
+      },
+    );
+    world.insert(entity, collider);
+    entity
+  }
+
+  /// A 20 unit wide box at x 50 and a ball at x 100 along the x axis, with a sensor
+  /// below them.
+  fn scene() -> Scene {
+    let mut world = World::new();
+    let wall = place(
+      &mut world,
+      50.0,
+      0.0,
+      Collider::new(Shape::aabb(20.0, 20.0)),
+    );
+    let ball = place(&mut world, 100.0, 0.0, Collider::new(Shape::circle(5.0)));
+    let mut sensor = Collider::new(Shape::aabb(10.0, 10.0));
+    sensor.sensor = true;
+    let sensor = place(&mut world, 0.0, 50.0, sensor);
+
+    let mut collisions = CollisionSystem::new(CELL_SIZE);
+    collisions.update(&mut world);
+    Scene {
+      collisions,
+      wall,
+      ball,
+      sensor,
+    }
+  }
+
+  fn approx(a: &Vec2, b: &Vec2) -> bool {
+    glm::distance(a, b) < 1e-3
+  }
+
+  #[test]
+  fn rays_hit_the_nearest_collider_first() {
+    let scene = scene();
+    let filter = QueryFilter::default();
+    let right = glm::vec2(1.0, 0.0);
+
+    let hit = scene
+      .collisions
+      .raycast(glm::vec2(0.0, 0.0), right, 200.0, &filter)
+      .unwrap()
+      .unwrap();
+    assert_eq!(hit.entity, scene.wall);
+    assert!((hit.fraction - 0.2).abs() < 1e-4);
+    assert!(approx(&hit.point, &glm::vec2(40.0, 0.0)));
+    assert!(approx(&hit.normal, &glm::vec2(-1.0, 0.0)));
+
+    let hits = scene
+      .collisions
+      .raycast_all(glm::vec2(0.0, 0.0), right, 200.0, &filter)
+      .unwrap();
+    let entities: Vec<_> = hits.iter().map(|hit| hit.entity).collect();
+    assert_eq!(entities, [scene.wall, scene.ball]);
+
+    let past_the_wall = filter.excluding(scene.wall);
+    let hit = scene
+      .collisions
+      .raycast(glm::vec2(0.0, 0.0), right, 200.0, &past_the_wall)
+      .unwrap()
+      .unwrap();
+    assert_eq!(hit.entity, scene.ball);
+    assert!(approx(&hit.point, &glm::vec2(95.0, 0.0)));
+  }
+
+  #[test]
+  fn short_rays_miss() {
+    let scene = scene();
+    let hit = scene
+      .collisions
+      .raycast(
+        glm::vec2(0.0, 0.0),
+        glm::vec2(1.0, 0.0),
+        30.0,
+        &QueryFilter::default(),
+      )
+      .unwrap();
+    assert_eq!(hit, None);
+  }
+
+  #[test]
+  fn rays_must_have_a_finite_non_negative_length() {
+    let scene = scene();
+    let filter = QueryFilter::default();
+    for length in [f32::INFINITY, f32::NEG_INFINITY, f32::NAN, -1.0] {
+      assert!(scene
+        .collisions
+        .raycast_all(glm::vec2(0.0, 0.0), glm::vec2(1.0, 0.0), length, &filter)
+        .is_err());
+    }
+  }
+
+  #[test]
+  fn very_long_rays_only_visit_occupied_cells() {
+    let scene = scene();
+    let hit = scene
+      .collisions
+      .raycast(
+        glm::vec2(0.0, 0.0),
+        glm::vec2(1.0, 0.1),
+        1e12,
+        &QueryFilter::default(),
+      )
+      .unwrap();
+    assert_eq!(hit.map(|hit| hit.entity), Some(scene.wall));
+  }
+
+  #[test]
+  fn shape_casts_stop_where_the_shape_first_touches() {
+    let scene = scene();
+    let ball = WorldShape::Circle {
+      center: glm::vec2(0.0, 0.0),
+      radius: 5.0,
+    };
+
+    let hit = scene
+      .collisions
+      .shape_cast(&ball, glm::vec2(100.0, 0.0), &QueryFilter::default())
+      .unwrap();
+    assert_eq!(hit.entity, scene.wall);
+    assert!((hit.fraction - 0.35).abs() < 1e-4);
+    assert!(approx(&hit.normal, &glm::vec2(-1.0, 0.0)));
+
+    let far = scene
+      .collisions
+      .shape_cast(&ball, glm::vec2(1e30, 0.0), &QueryFilter::default())
+      .unwrap();
+    assert_eq!(far.entity, scene.wall);
+
+    let upwards =
+      scene
+        .collisions
+        .shape_cast(&ball, glm::vec2(0.0, -100.0), &QueryFilter::default());
+    assert_eq!(upwards, None);
+  }
+
+  #[test]
+  fn overlaps_find_containing_colliders() {
+    let scene = scene();
+    let filter = QueryFilter::default();
+
+    let hits = scene
+      .collisions
+      .overlap_point(glm::vec2(55.0, 5.0), &filter);
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].entity, scene.wall);
+    assert!(scene
+      .collisions
+      .overlap_point(glm::vec2(75.0, 0.0), &filter)
+      .is_empty());
+
+    let area = WorldShape::Polygon {
+      points: vec![
+        glm::vec2(45.0, -2.0),
+        glm::vec2(98.0, -2.0),
+        glm::vec2(98.0, 2.0),
+        glm::vec2(45.0, 2.0),
+      ],
+    };
+    let mut entities: Vec<_> = scene
+      .collisions
+      .overlap_shape(&area, &filter)
+      .iter()
+      .map(|hit| hit.entity)
+      .collect();
+    entities.sort();
+    assert_eq!(entities, [scene.wall, scene.ball]);
+  }
+
+  #[test]
+  fn sensors_are_only_found_when_asked_for() {
+    let scene = scene();
+    let point = glm::vec2(0.0, 50.0);
+    assert!(scene
+      .collisions
+      .overlap_point(point, &QueryFilter::default())
+      .is_empty());
+
+    let hits = scene
+      .collisions
+      .overlap_point(point, &QueryFilter::default().with_sensors());
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].entity, scene.sensor);
+  }
+}
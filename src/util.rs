@@ -1,14 +1,17 @@
 mod fps;
+mod rng;
 mod settings;
 
+use crate::assets::AssetId;
 use fern::InitError;
-pub use fps::FpsManager;
+pub use fps::{FixedTimestep, FpsManager};
 use glium::debug::{MessageType, Severity, Source};
 use log::LevelFilter;
 use log::{error, info, warn};
+pub use rng::Rng;
 pub use settings::Settings;
 use std::{
-  ffi::{OsStr, OsString},
+  ffi::OsString,
   fs::{self, OpenOptions},
   path::{Path, PathBuf},
   time::SystemTime,
@@ -18,45 +21,6 @@ use walkdir::{DirEntry, WalkDir};
 const LOG_DIR: &str = "logs";
 const BASE_LOG_FILENAME: &str = "game";
 
-#[derive(Default, Debug, Clone, PartialEq, PartialOrd, Eq, Ord)]
-pub struct DirID {
-  id: OsString,
-}
-
-impl DirID {
-  pub fn id(&self) -> &OsString {
-    &self.id
-  }
-
-  pub fn extend<T: AsRef<OsStr>>(&self, s: T) -> Self {
-    let mut copy = self.clone();
-    copy.id.push(".");
-    copy.id.push(s);
-    copy
-  }
-}
-
-impl From<PathBuf> for DirID {
-  fn from(path: PathBuf) -> Self {
-    let mut v = Vec::default();
-
-    for part in path.iter() {
-      v.push(part);
-    }
-
-    let mut id = std::ffi::OsString::default();
-
-    for i in 0..v.len() {
-      id.push(v[i]);
-      if i != v.len() - 1 {
-        id.push(".");
-      }
-    }
-
-    Self { id }
-  }
-}
-
 pub fn read_log_dir() -> Vec<(OsString, SystemTime)> {
   let mut vec = Vec::new();
 
@@ -170,7 +134,11 @@ pub fn gl_error_handler(
   }
 }
 
-pub fn iterate_dir_with_id<F: FnMut(&Path, DirID)>(dir: &Path, mut f: F) {
+pub fn iterate_dir_with_id<F: FnMut(&Path, AssetId)>(dir: &Path, base: &AssetId, mut f: F) {
+  if !dir.exists() {
+    return;
+  }
+
   for result in WalkDir::new(dir) {
     let entry: DirEntry = result.unwrap();
     if entry.file_type().is_file() {
@@ -178,10 +146,34 @@ pub fn iterate_dir_with_id<F: FnMut(&Path, DirID)>(dir: &Path, mut f: F) {
       let mut entry_cpy = entry_suffix.to_path_buf();
       entry_cpy.pop();
       let last = entry.path().file_stem().unwrap();
-      let id = entry_cpy.join(last);
 
-      let id = DirID::from(id);
+      let mut id = base.clone();
+      for part in entry_cpy.iter() {
+        id = id.extend(part.to_string_lossy());
+      }
+      id = id.extend(last.to_string_lossy());
+
       f(entry.path(), id);
     }
   }
 }
+
+pub fn toml_to_json(value: &toml::Value) -> serde_json::Value {
+  match value {
+    toml::Value::String(s) => serde_json::Value::from(s.as_str()),
+    toml::Value::Integer(i) => serde_json::Value::from(*i),
+    toml::Value::Float(f) => serde_json::Value::from(*f),
+    toml::Value::Boolean(b) => serde_json::Value::from(*b),
+    toml::Value::Datetime(d) => serde_json::Value::from(d.to_string()),
+    toml::Value::Array(values) => {
+      serde_json::Value::Array(values.iter().map(toml_to_json).collect())
+    }
+    toml::Value::Table(table) => {
+      let mut object = serde_json::Map::new();
+      for (key, value) in table {
+        object.insert(key.clone(), toml_to_json(value));
+      }
+      serde_json::Value::Object(object)
+    }
+  }
+}
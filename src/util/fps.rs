@@ -4,11 +4,14 @@ use std::{
 };
 
 const NANOS_IN_SECS: u64 = 1_000_000_000;
+/// How much each frame moves the measured rate, smaller is steadier.
+const FPS_SMOOTHING: f32 = 0.1;
 
 pub struct FpsManager {
   base_sleep_time: Duration,
   start: Instant,
   target: u64,
+  fps: f32,
 }
 
 impl FpsManager {
@@ -19,11 +22,17 @@ impl FpsManager {
       base_sleep_time,
       start: Instant::now(),
       target,
+      fps: target as f32,
     }
   }
 
   pub fn begin(&mut self) {
-    self.start = Instant::now();
+    let now = Instant::now();
+    let frame = (now - self.start).as_secs_f32();
+    if frame > 0.0 {
+      self.fps += (1.0 / frame - self.fps) * FPS_SMOOTHING;
+    }
+    self.start = now;
   }
 
   pub fn end(&mut self) {
@@ -37,4 +46,84 @@ impl FpsManager {
   pub fn target(&self) -> u64 {
     self.target
   }
+
+  /// The frames actually drawn per second, averaged over the last few frames.
+  pub fn fps(&self) -> f32 {
+    self.fps
+  }
+}
+
+/// Turns variable frame times into a whole number of equal steps, carrying the remainder over.
+pub struct FixedTimestep {
+  step: Duration,
+  max_steps: u32,
+  accumulator: Duration,
+  last: Instant,
+}
+
+impl FixedTimestep {
+  /// `max_steps` caps how many steps one frame can run, so a long stall does not snowball.
+  pub fn new(rate: u32, max_steps: u32) -> Self {
+    Self {
+      step: Duration::from_nanos(NANOS_IN_SECS / rate as u64),
+      max_steps,
+      accumulator: Duration::ZERO,
+      last: Instant::now(),
+    }
+  }
+
+  /// How many steps to run this frame.
+  pub fn advance(&mut self) -> u32 {
+    let now = Instant::now();
+    let elapsed = now - self.last;
+    self.last = now;
+    self.accumulate(elapsed)
+  }
+
+  fn accumulate(&mut self, elapsed: Duration) -> u32 {
+    self.accumulator += elapsed;
+
+    let mut steps = 0;
+    while self.accumulator >= self.step && steps < self.max_steps {
+      self.accumulator -= self.step;
+      steps += 1;
+    }
+    // only time the cap left behind is dropped, a partial step carries over
+    if self.accumulator >= self.step {
+      self.accumulator = Duration::ZERO;
+    }
+
+    steps
+  }
+
+  /// The length of a step in seconds.
+  pub fn delta(&self) -> f32 {
+    self.step.as_secs_f32()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn partial_steps_carry_over() {
+    let mut timestep = FixedTimestep::new(100, 4);
+    assert_eq!(timestep.accumulate(Duration::from_millis(25)), 2);
+    assert_eq!(timestep.accumulate(Duration::from_millis(5)), 1);
+  }
+
+  #[test]
+  fn reaching_the_cap_keeps_a_partial_step() {
+    let mut timestep = FixedTimestep::new(100, 4);
+    assert_eq!(timestep.accumulate(Duration::from_millis(45)), 4);
+    assert_eq!(timestep.accumulate(Duration::from_millis(5)), 1);
+  }
+
+  #[test]
+  fn time_past_the_cap_is_dropped() {
+    let mut timestep = FixedTimestep::new(100, 4);
+    assert_eq!(timestep.accumulate(Duration::from_millis(500)), 4);
+    assert_eq!(timestep.accumulate(Duration::from_millis(5)), 0);
+  }
 }
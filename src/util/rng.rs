@@ -0,0 +1,48 @@
+/// A small seeded generator (splitmix64), so the same seed gives the same
+/// output on every platform and build.
+#[derive(Debug, Clone)]
+pub struct Rng {
+  state: u64,
+}
+
+impl Rng {
+  pub fn new(seed: u64) -> Self {
+    Self { state: seed }
+  }
+
+  pub fn next_u64(&mut self) -> u64 {
+    self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = self.state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+  }
+
+  /// A float in `[0, 1)`.
+  pub fn next_f64(&mut self) -> f64 {
+    (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+  }
+
+  pub fn next_f32(&mut self) -> f32 {
+    self.next_f64() as f32
+  }
+
+  /// An integer in `[min, max)`, or `min` when the range is empty.
+  pub fn range(&mut self, min: i32, max: i32) -> i32 {
+    if max <= min {
+      return min;
+    }
+
+    let span = (max as i64 - min as i64) as u64;
+    (min as i64 + (self.next_u64() % span) as i64) as i32
+  }
+
+  /// A float in `[min, max)`.
+  pub fn range_f32(&mut self, min: f32, max: f32) -> f32 {
+    min + (max - min) * self.next_f32()
+  }
+
+  pub fn chance(&mut self, probability: f64) -> bool {
+    self.next_f64() < probability
+  }
+}
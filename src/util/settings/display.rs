@@ -1,4 +1,5 @@
-use crate::view::window::WindowMode;
+use crate::view::{headless::HeadlessApi, window::WindowMode};
+use log::warn;
 use toml::{value::Table, Value};
 
 mod keys {
@@ -6,6 +7,7 @@ mod keys {
   pub const WIDTH: &str = "width";
   pub const HEIGHT: &str = "height";
   pub const MODE: &str = "video_mode";
+  pub const HEADLESS: &str = "headless";
 }
 
 pub struct DisplaySettings {
@@ -13,6 +15,8 @@ pub struct DisplaySettings {
   pub width: u32,
   pub height: u32,
   pub mode: WindowMode,
+  /// Renders off screen with this api instead of opening a window.
+  pub headless: Option<HeadlessApi>,
 }
 
 impl DisplaySettings {
@@ -28,6 +32,7 @@ impl Default for DisplaySettings {
       width: 720,
       height: 1280,
       mode: WindowMode::Windowed,
+      headless: None,
     }
   }
 }
@@ -52,6 +57,13 @@ impl From<&Table> for DisplaySettings {
       settings.mode = WindowMode::from(video_mode);
     }
 
+    if let Some(Value::String(headless)) = table.get(keys::HEADLESS) {
+      match HeadlessApi::try_from(headless.as_str()) {
+        Ok(api) => settings.headless = Some(api),
+        Err(msg) => warn!("opening a window instead: {}", msg),
+      }
+    }
+
     settings
   }
 }
@@ -83,6 +95,13 @@ impl Into<Table> for DisplaySettings {
       Value::String(self.mode.to_string()),
     );
 
+    if let Some(headless) = self.headless {
+      table.insert(
+        String::from(keys::HEADLESS),
+        Value::String(headless.to_string()),
+      );
+    }
+
     table
   }
 }
@@ -1 +1,5 @@
+pub mod camera;
+pub mod headless;
+pub mod inspector;
+pub mod overlay;
 pub mod window;
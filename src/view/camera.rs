@@ -0,0 +1,340 @@
+use crate::math::glm::{self, Mat4, Vec2};
+use crate::util::Rng;
+use geo::Rect;
+
+/// How much trauma wears off every second.
+const TRAUMA_DECAY: f32 = 1.0;
+
+/// Looks at the world from above. The view matrix takes world units to pixels with the
+/// camera position in the middle of the viewport, the projection takes pixels to clip
+/// space with y running down.
+#[derive(Debug, Clone)]
+pub struct Camera2D {
+  /// The point in the world at the middle of the viewport.
+  pub position: Vec2,
+  /// Pixels per world unit, always positive.
+  zoom: f32,
+  /// In degrees, turning the camera clockwise turns the world the other way on screen.
+  pub rotation: f32,
+  /// The size of the screen area the camera draws to, in pixels.
+  pub viewport: Vec2,
+  /// How far the target can move from the middle before the camera follows, as half
+  /// the size of the dead zone in world units.
+  pub dead_zone: Vec2,
+  /// How quickly the camera catches up with its target, zero snaps straight to it.
+  pub smoothing: f32,
+  /// The area the camera is kept inside of, usually the map.
+  pub bounds: Option<Rect<f32>>,
+  /// The furthest a full shake moves the camera, in world units.
+  pub max_shake_offset: Vec2,
+  /// The furthest a full shake turns the camera, in degrees.
+  pub max_shake_angle: f32,
+  trauma: f32,
+  shake_offset: Vec2,
+  shake_angle: f32,
+  rng: Rng,
+}
+
+impl Camera2D {
+  pub fn new(viewport: Vec2) -> Self {
+    Self {
+      position: viewport / 2.0,
+      zoom: 1.0,
+      rotation: 0.0,
+      viewport,
+      dead_zone: glm::vec2(0.0, 0.0),
+      smoothing: 0.0,
+      bounds: None,
+      max_shake_offset: glm::vec2(8.0, 8.0),
+      max_shake_angle: 2.0,
+      trauma: 0.0,
+      shake_offset: glm::vec2(0.0, 0.0),
+      shake_angle: 0.0,
+      rng: Rng::new(0),
+    }
+  }
+
+  pub fn zoom(&self) -> f32 {
+    self.zoom
+  }
+
+  /// Fails for zooms that are not positive and finite, which would leave nothing to see.
+  pub fn set_zoom(&mut self, zoom: f32) -> Result<(), String> {
+    if !(zoom > 0.0 && zoom.is_finite()) {
+      return Err(format!("camera zoom {} must be positive", zoom));
+    }
+
+    self.zoom = zoom;
+    Ok(())
+  }
+
+  pub fn view(&self) -> Mat4 {
+    let position = self.position + self.shake_offset;
+    let centered = glm::translate(
+      &Mat4::identity(),
+      &glm::vec3(self.viewport.x / 2.0, self.viewport.y / 2.0, 0.0),
+    );
+    let scaled = glm::scale(&centered, &glm::vec3(self.zoom, self.zoom, 1.0));
+    let rotated = glm::rotate_z(&scaled, -(self.rotation + self.shake_angle).to_radians());
+    glm::translate(&rotated, &glm::vec3(-position.x, -position.y, 0.0))
+  }
+
+  pub fn projection(&self) -> Mat4 {
+    glm::ortho(0.0, self.viewport.x, self.viewport.y, 0.0, -1.0, 1.0)
+  }
+
+  /// Where a point in the world is drawn, in pixels from the top left of the viewport.
+  pub fn world_to_screen(&self, world: &Vec2) -> Vec2 {
+    let screen = self.view() * glm::vec4(world.x, world.y, 0.0, 1.0);
+    glm::vec2(screen.x, screen.y)
+  }
+
+  /// The point in the world under a pixel of the viewport.
+  pub fn screen_to_world(&self, screen: &Vec2) -> Vec2 {
+    let world = glm::inverse(&self.view()) * glm::vec4(screen.x, screen.y, 0.0, 1.0);
+    glm::vec2(world.x, world.y)
+  }
+
+  /// The part of the world that can be seen, grown to fit when the camera is rotated.
+  pub fn visible_area(&self) -> Rect<f32> {
+    let corners = [
+      glm::vec2(0.0, 0.0),
+      glm::vec2(self.viewport.x, 0.0),
+      glm::vec2(0.0, self.viewport.y),
+      self.viewport,
+    ]
+    .map(|corner| self.screen_to_world(&corner));
+
+    let (min, max) = corners.iter().fold(
+      (glm::vec2(f32::MAX, f32::MAX), glm::vec2(f32::MIN, f32::MIN)),
+      |(min, max), corner| (glm::min2(&min, corner), glm::max2(&max, corner)),
+    );
+    Rect::new((min.x, min.y), (max.x, max.y))
+  }
+
+  /// Moves towards a target once it leaves the dead zone, then keeps the view in bounds.
+  pub fn follow(&mut self, target: &Vec2, dt: f32) {
+    let offset = target - self.position;
+    let outside = |offset: f32, dead_zone: f32| {
+      if offset.abs() > dead_zone {
+        offset - dead_zone * offset.signum()
+      } else {
+        0.0
+      }
+    };
+    let wanted = glm::vec2(
+      outside(offset.x, self.dead_zone.x),
+      outside(offset.y, self.dead_zone.y),
+    );
+
+    // frame rate independent easing, the same distance is covered however `dt` is split
+    let amount = if self.smoothing > 0.0 {
+      1.0 - (-self.smoothing * dt).exp()
+    } else {
+      1.0
+    };
+    self.position += wanted * amount;
+    self.clamp_to_bounds();
+  }
+
+  /// Keeps the viewport inside the bounds, centering on them when they are smaller.
+  /// Rotation is ignored.
+  pub fn clamp_to_bounds(&mut self) {
+    let bounds = match self.bounds {
+      Some(bounds) => bounds,
+      None => return,
+    };
+    let half = self.viewport / (2.0 * self.zoom);
+
+    let clamp = |position: f32, min: f32, max: f32, half: f32| {
+      if max - min <= half * 2.0 {
+        (min + max) / 2.0
+      } else {
+        position.clamp(min + half, max - half)
+      }
+    };
+    self.position = glm::vec2(
+      clamp(self.position.x, bounds.min().x, bounds.max().x, half.x),
+      clamp(self.position.y, bounds.min().y, bounds.max().y, half.y),
+    );
+  }
+
+  /// Shakes the camera, trauma adds up to at most 1 and wears off over time.
+  pub fn add_trauma(&mut self, amount: f32) {
+    self.trauma = (self.trauma + amount).clamp(0.0, 1.0);
+  }
+
+  /// Advances the shake. Shaking grows with the square of the trauma, so small hits
+  /// barely move the camera and big ones move it a lot.
+  pub fn update(&mut self, dt: f32) {
+    self.trauma = (self.trauma - TRAUMA_DECAY * dt).max(0.0);
+
+    let shake = self.trauma * self.trauma;
+    if shake == 0.0 {
+      self.shake_offset = glm::vec2(0.0, 0.0);
+      self.shake_angle = 0.0;
+      return;
+    }
+
+    self.shake_offset = glm::vec2(
+      self.max_shake_offset.x * shake * self.rng.range_f32(-1.0, 1.0),
+      self.max_shake_offset.y * shake * self.rng.range_f32(-1.0, 1.0),
+    );
+    self.shake_angle = self.max_shake_angle * shake * self.rng.range_f32(-1.0, 1.0);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn approx(a: &Vec2, b: &Vec2) -> bool {
+    glm::distance(a, b) < 1e-3
+  }
+
+  fn camera() -> Camera2D {
+    let mut camera = Camera2D::new(glm::vec2(320.0, 240.0));
+    camera.position = glm::vec2(100.0, 50.0);
+    camera
+  }
+
+  #[test]
+  fn the_position_is_drawn_in_the_middle_of_the_viewport() {
+    let camera = camera();
+    assert!(approx(
+      &camera.world_to_screen(&glm::vec2(100.0, 50.0)),
+      &glm::vec2(160.0, 120.0)
+    ));
+    assert!(approx(
+      &camera.world_to_screen(&glm::vec2(110.0, 40.0)),
+      &glm::vec2(170.0, 110.0)
+    ));
+  }
+
+  #[test]
+  fn screen_to_world_undoes_world_to_screen() {
+    let mut camera = camera();
+    camera.set_zoom(2.5).unwrap();
+    camera.rotation = 30.0;
+
+    for point in [
+      glm::vec2(0.0, 0.0),
+      glm::vec2(-40.0, 75.5),
+      glm::vec2(1e3, 3.0),
+    ] {
+      let screen = camera.world_to_screen(&point);
+      assert!(approx(&camera.screen_to_world(&screen), &point));
+    }
+  }
+
+  #[test]
+  fn zooming_in_scales_around_the_position() {
+    let mut camera = camera();
+    camera.set_zoom(2.0).unwrap();
+
+    assert!(approx(
+      &camera.world_to_screen(&glm::vec2(110.0, 60.0)),
+      &glm::vec2(180.0, 140.0)
+    ));
+    let visible = camera.visible_area();
+    assert!(approx(
+      &glm::vec2(visible.min().x, visible.min().y),
+      &glm::vec2(20.0, -10.0)
+    ));
+    assert!(approx(
+      &glm::vec2(visible.max().x, visible.max().y),
+      &glm::vec2(180.0, 110.0)
+    ));
+  }
+
+  #[test]
+  fn the_view_is_kept_inside_the_bounds() {
+    let mut camera = camera();
+    camera.bounds = Some(Rect::new((0.0, 0.0), (1000.0, 1000.0)));
+
+    camera.follow(&glm::vec2(-500.0, 2000.0), 0.1);
+    assert!(approx(&camera.position, &glm::vec2(160.0, 880.0)));
+
+    camera.set_zoom(2.0).unwrap();
+    camera.follow(&glm::vec2(-500.0, 2000.0), 0.1);
+    assert!(approx(&camera.position, &glm::vec2(80.0, 940.0)));
+  }
+
+  #[test]
+  fn bounds_smaller_than_the_viewport_are_centered() {
+    // like the map of the golden scene, which is smaller than the window
+    let mut camera = camera();
+    camera.bounds = Some(Rect::new((0.0, 0.0), (160.0, 200.0)));
+
+    for target in [glm::vec2(0.0, 0.0), glm::vec2(1000.0, 1000.0)] {
+      camera.follow(&target, 0.1);
+      assert!(approx(&camera.position, &glm::vec2(80.0, 100.0)));
+    }
+
+    // only the axis that fits is centered
+    camera.bounds = Some(Rect::new((0.0, 0.0), (160.0, 400.0)));
+    camera.follow(&glm::vec2(1000.0, 1000.0), 0.1);
+    assert!(approx(&camera.position, &glm::vec2(80.0, 280.0)));
+  }
+
+  #[test]
+  fn targets_inside_the_dead_zone_do_not_move_the_camera() {
+    let mut camera = camera();
+    camera.dead_zone = glm::vec2(20.0, 10.0);
+
+    camera.follow(&glm::vec2(115.0, 58.0), 0.1);
+    assert!(approx(&camera.position, &glm::vec2(100.0, 50.0)));
+
+    // leaving it drags the camera along until the target is back on its edge
+    camera.follow(&glm::vec2(150.0, 30.0), 0.1);
+    assert!(approx(&camera.position, &glm::vec2(130.0, 40.0)));
+  }
+
+  #[test]
+  fn smoothing_covers_the_same_distance_however_time_is_split() {
+    let mut once = camera();
+    once.smoothing = 4.0;
+    let mut split = once.clone();
+
+    once.follow(&glm::vec2(200.0, 50.0), 0.5);
+    for _ in 0..10 {
+      split.follow(&glm::vec2(200.0, 50.0), 0.05);
+    }
+    assert!(approx(&once.position, &split.position));
+    assert!(once.position.x > 100.0 && once.position.x < 200.0);
+  }
+
+  #[test]
+  fn trauma_shakes_the_camera_until_it_wears_off() {
+    let mut camera = camera();
+    let still = camera.world_to_screen(&glm::vec2(0.0, 0.0));
+
+    camera.add_trauma(0.7);
+    camera.add_trauma(0.7);
+    assert_eq!(camera.trauma, 1.0);
+    camera.update(0.1);
+    assert!(!approx(
+      &camera.world_to_screen(&glm::vec2(0.0, 0.0)),
+      &still
+    ));
+
+    camera.update(TRAUMA_DECAY);
+    assert_eq!(camera.trauma, 0.0);
+    assert!(approx(
+      &camera.world_to_screen(&glm::vec2(0.0, 0.0)),
+      &still
+    ));
+  }
+
+  #[test]
+  fn zoom_must_be_positive() {
+    let mut camera = camera();
+    for zoom in [0.0, -1.0, f32::NAN, f32::INFINITY] {
+      assert!(camera.set_zoom(zoom).is_err());
+    }
+    assert_eq!(camera.zoom(), 1.0);
+
+    camera.set_zoom(0.5).unwrap();
+    assert_eq!(camera.zoom(), 0.5);
+  }
+}
@@ -0,0 +1,428 @@
+use glium::{backend::Backend, SwapBuffersError};
+use libloading::Library;
+use std::cell::UnsafeCell;
+use std::ffi::CString;
+use std::fmt::{Display, Error, Formatter};
+use std::os::raw::{c_char, c_int, c_uchar, c_void};
+use std::ptr;
+
+/// Where frames are rendered when there is no window to show them in. Both are
+/// provided by Mesa, and fall back to its llvmpipe software renderer without a GPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeadlessApi {
+  /// An EGL pbuffer on the surfaceless platform, which needs no display server.
+  Egl,
+  /// Rendering straight into memory, slower but without any system dependencies.
+  OsMesa,
+}
+
+impl Display for HeadlessApi {
+  fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+    match self {
+      HeadlessApi::Egl => write!(f, "egl"),
+      HeadlessApi::OsMesa => write!(f, "osmesa"),
+    }
+  }
+}
+
+impl TryFrom<&str> for HeadlessApi {
+  type Error = String;
+
+  fn try_from(value: &str) -> Result<Self, Self::Error> {
+    match value {
+      "egl" => Ok(HeadlessApi::Egl),
+      "osmesa" => Ok(HeadlessApi::OsMesa),
+      invalid => Err(format!("unknown headless api '{}'", invalid)),
+    }
+  }
+}
+
+mod egl {
+  use std::os::raw::{c_char, c_void};
+
+  pub type Display = *mut c_void;
+  pub type Config = *mut c_void;
+  pub type Context = *mut c_void;
+  pub type Surface = *mut c_void;
+  pub type Int = i32;
+  pub type Boolean = u32;
+  pub type Enum = u32;
+  pub type Attrib = isize;
+
+  pub const LIBRARY: &str = "libEGL.so.1";
+
+  pub const FALSE: Boolean = 0;
+  pub const NONE: Int = 0x3038;
+  pub const PLATFORM_SURFACELESS_MESA: Enum = 0x31dd;
+  pub const OPENGL_API: Enum = 0x30a2;
+  pub const SURFACE_TYPE: Int = 0x3033;
+  pub const PBUFFER_BIT: Int = 0x0001;
+  pub const RENDERABLE_TYPE: Int = 0x3040;
+  pub const OPENGL_BIT: Int = 0x0008;
+  pub const RED_SIZE: Int = 0x3024;
+  pub const GREEN_SIZE: Int = 0x3023;
+  pub const BLUE_SIZE: Int = 0x3022;
+  pub const ALPHA_SIZE: Int = 0x3021;
+  pub const WIDTH: Int = 0x3057;
+  pub const HEIGHT: Int = 0x3056;
+  pub const CONTEXT_MAJOR_VERSION: Int = 0x3098;
+  pub const CONTEXT_MINOR_VERSION: Int = 0x30fb;
+  pub const CONTEXT_OPENGL_PROFILE_MASK: Int = 0x30fd;
+  pub const CONTEXT_OPENGL_CORE_PROFILE_BIT: Int = 0x0001;
+
+  /// The entry points used here, looked up once when the library is opened.
+  pub struct Functions {
+    pub get_proc_address: unsafe extern "C" fn(*const c_char) -> *const c_void,
+    pub get_platform_display: unsafe extern "C" fn(Enum, *mut c_void, *const Attrib) -> Display,
+    pub initialize: unsafe extern "C" fn(Display, *mut Int, *mut Int) -> Boolean,
+    pub bind_api: unsafe extern "C" fn(Enum) -> Boolean,
+    pub choose_config:
+      unsafe extern "C" fn(Display, *const Int, *mut Config, Int, *mut Int) -> Boolean,
+    pub create_context: unsafe extern "C" fn(Display, Config, Context, *const Int) -> Context,
+    pub destroy_context: unsafe extern "C" fn(Display, Context) -> Boolean,
+    pub create_pbuffer_surface: unsafe extern "C" fn(Display, Config, *const Int) -> Surface,
+    pub destroy_surface: unsafe extern "C" fn(Display, Surface) -> Boolean,
+    pub make_current: unsafe extern "C" fn(Display, Surface, Surface, Context) -> Boolean,
+    pub get_current_context: unsafe extern "C" fn() -> Context,
+    pub swap_buffers: unsafe extern "C" fn(Display, Surface) -> Boolean,
+    pub get_error: unsafe extern "C" fn() -> Int,
+  }
+}
+
+mod osmesa {
+  use std::os::raw::{c_char, c_int, c_uchar, c_uint, c_void};
+
+  pub type Context = *mut c_void;
+
+  pub const LIBRARY: &str = "libOSMesa.so.8";
+
+  pub const FORMAT: c_int = 0x22;
+  pub const RGBA: c_int = 0x1908;
+  pub const DEPTH_BITS: c_int = 0x30;
+  pub const PROFILE: c_int = 0x33;
+  pub const CORE_PROFILE: c_int = 0x34;
+  pub const CONTEXT_MAJOR_VERSION: c_int = 0x36;
+  pub const CONTEXT_MINOR_VERSION: c_int = 0x37;
+  pub const UNSIGNED_BYTE: c_uint = 0x1401;
+
+  pub struct Functions {
+    pub create_context_attribs: unsafe extern "C" fn(*const c_int, Context) -> Context,
+    pub destroy_context: unsafe extern "C" fn(Context),
+    pub make_current: unsafe extern "C" fn(Context, *mut c_void, c_uint, c_int, c_int) -> c_uchar,
+    pub get_current_context: unsafe extern "C" fn() -> Context,
+    pub get_proc_address: unsafe extern "C" fn(*const c_char) -> *const c_void,
+  }
+}
+
+/// Looks `name` up in `library` as a function of type `T`.
+unsafe fn symbol<T: Copy>(library: &Library, name: &str) -> Result<T, String> {
+  library
+    .get::<T>(name.as_bytes())
+    .map(|symbol| *symbol)
+    .map_err(|e| format!("cannot find {}: {}", name, e))
+}
+
+enum Api {
+  Egl {
+    functions: egl::Functions,
+    display: egl::Display,
+    context: egl::Context,
+    surface: egl::Surface,
+  },
+  OsMesa {
+    functions: osmesa::Functions,
+    context: osmesa::Context,
+    /// What OSMesa renders into, the default framebuffer of the context. It is never
+    /// resized, so OSMesa can hold on to a pointer into it.
+    buffer: UnsafeCell<Vec<u8>>,
+  },
+}
+
+/// Draws without a window, for tests and servers. The default framebuffer lives off
+/// screen and is read back with `glium::backend::Context::read_front_buffer`.
+pub struct HeadlessBackend {
+  api: Api,
+  size: (u32, u32),
+  /// Kept open for as long as the functions looked up in it are used.
+  _library: Library,
+}
+
+impl HeadlessBackend {
+  /// Creates an OpenGL 3.3 core context with a `width` by `height` framebuffer.
+  pub fn new(api: HeadlessApi, (width, height): (u32, u32)) -> Result<Self, String> {
+    if width == 0 || height == 0 {
+      return Err(format!("cannot render {}x{} frames", width, height));
+    }
+
+    let (library, api) = match api {
+      HeadlessApi::Egl => {
+        let library = unsafe { Library::new(egl::LIBRARY) }
+          .map_err(|e| format!("cannot open {}: {}", egl::LIBRARY, e))?;
+        let api = unsafe { Self::create_egl(&library, width, height) }?;
+        (library, api)
+      }
+      HeadlessApi::OsMesa => {
+        let library = unsafe { Library::new(osmesa::LIBRARY) }
+          .map_err(|e| format!("cannot open {}: {}", osmesa::LIBRARY, e))?;
+        let api = unsafe { Self::create_osmesa(&library, width, height) }?;
+        (library, api)
+      }
+    };
+
+    Ok(Self {
+      api,
+      size: (width, height),
+      _library: library,
+    })
+  }
+
+  /// The first api that works, for tests that need something to draw with.
+  #[cfg(test)]
+  pub fn any((width, height): (u32, u32)) -> Result<Self, String> {
+    let mut errors = Vec::new();
+    for api in [HeadlessApi::Egl, HeadlessApi::OsMesa] {
+      match Self::new(api, (width, height)) {
+        Ok(backend) => return Ok(backend),
+        Err(msg) => errors.push(format!("{}: {}", api, msg)),
+      }
+    }
+    Err(errors.join(", "))
+  }
+
+  unsafe fn create_egl(library: &Library, width: u32, height: u32) -> Result<Api, String> {
+    let functions = egl::Functions {
+      get_proc_address: symbol(library, "eglGetProcAddress")?,
+      get_platform_display: symbol(library, "eglGetPlatformDisplay")?,
+      initialize: symbol(library, "eglInitialize")?,
+      bind_api: symbol(library, "eglBindAPI")?,
+      choose_config: symbol(library, "eglChooseConfig")?,
+      create_context: symbol(library, "eglCreateContext")?,
+      destroy_context: symbol(library, "eglDestroyContext")?,
+      create_pbuffer_surface: symbol(library, "eglCreatePbufferSurface")?,
+      destroy_surface: symbol(library, "eglDestroySurface")?,
+      make_current: symbol(library, "eglMakeCurrent")?,
+      get_current_context: symbol(library, "eglGetCurrentContext")?,
+      swap_buffers: symbol(library, "eglSwapBuffers")?,
+      get_error: symbol(library, "eglGetError")?,
+    };
+    let failed = |call: &str| format!("{} failed with {:#x}", call, (functions.get_error)());
+
+    let display = (functions.get_platform_display)(
+      egl::PLATFORM_SURFACELESS_MESA,
+      ptr::null_mut(),
+      ptr::null(),
+    );
+    if display.is_null() {
+      return Err(failed("eglGetPlatformDisplay"));
+    }
+    // the display is shared by every context in the process, so it is never terminated
+    let (mut major, mut minor) = (0, 0);
+    if (functions.initialize)(display, &mut major, &mut minor) == egl::FALSE {
+      return Err(failed("eglInitialize"));
+    }
+
+    if (functions.bind_api)(egl::OPENGL_API) == egl::FALSE {
+      return Err(failed("eglBindAPI"));
+    }
+
+    let config_attributes = [
+      egl::SURFACE_TYPE,
+      egl::PBUFFER_BIT,
+      egl::RENDERABLE_TYPE,
+      egl::OPENGL_BIT,
+      egl::RED_SIZE,
+      8,
+      egl::GREEN_SIZE,
+      8,
+      egl::BLUE_SIZE,
+      8,
+      egl::ALPHA_SIZE,
+      8,
+      egl::NONE,
+    ];
+    let (mut config, mut count) = (ptr::null_mut(), 0);
+    if (functions.choose_config)(
+      display,
+      config_attributes.as_ptr(),
+      &mut config,
+      1,
+      &mut count,
+    ) == egl::FALSE
+    {
+      return Err(failed("eglChooseConfig"));
+    }
+    if count == 0 {
+      return Err(String::from("no EGL config can render OpenGL to a pbuffer"));
+    }
+
+    let context_attributes = [
+      egl::CONTEXT_MAJOR_VERSION,
+      3,
+      egl::CONTEXT_MINOR_VERSION,
+      3,
+      egl::CONTEXT_OPENGL_PROFILE_MASK,
+      egl::CONTEXT_OPENGL_CORE_PROFILE_BIT,
+      egl::NONE,
+    ];
+    let context = (functions.create_context)(
+      display,
+      config,
+      ptr::null_mut(),
+      context_attributes.as_ptr(),
+    );
+    if context.is_null() {
+      return Err(failed("eglCreateContext"));
+    }
+
+    let surface_attributes = [
+      egl::WIDTH,
+      width as i32,
+      egl::HEIGHT,
+      height as i32,
+      egl::NONE,
+    ];
+    let surface = (functions.create_pbuffer_surface)(display, config, surface_attributes.as_ptr());
+    if surface.is_null() {
+      let msg = failed("eglCreatePbufferSurface");
+      (functions.destroy_context)(display, context);
+      return Err(msg);
+    }
+    Ok(Api::Egl {
+      functions,
+      display,
+      context,
+      surface,
+    })
+  }
+
+  unsafe fn create_osmesa(library: &Library, width: u32, height: u32) -> Result<Api, String> {
+    let functions = osmesa::Functions {
+      create_context_attribs: symbol(library, "OSMesaCreateContextAttribs")?,
+      destroy_context: symbol(library, "OSMesaDestroyContext")?,
+      make_current: symbol(library, "OSMesaMakeCurrent")?,
+      get_current_context: symbol(library, "OSMesaGetCurrentContext")?,
+      get_proc_address: symbol(library, "OSMesaGetProcAddress")?,
+    };
+
+    let attributes = [
+      osmesa::FORMAT,
+      osmesa::RGBA,
+      osmesa::DEPTH_BITS,
+      24,
+      osmesa::PROFILE,
+      osmesa::CORE_PROFILE,
+      osmesa::CONTEXT_MAJOR_VERSION,
+      3,
+      osmesa::CONTEXT_MINOR_VERSION,
+      3,
+      0,
+    ];
+    let context = (functions.create_context_attribs)(attributes.as_ptr(), ptr::null_mut());
+    if context.is_null() {
+      return Err(String::from(
+        "OSMesaCreateContextAttribs cannot create an OpenGL 3.3 core context",
+      ));
+    }
+
+    Ok(Api::OsMesa {
+      functions,
+      context,
+      buffer: UnsafeCell::new(vec![0; width as usize * height as usize * 4]),
+    })
+  }
+}
+
+impl Drop for HeadlessBackend {
+  fn drop(&mut self) {
+    unsafe {
+      match &self.api {
+        Api::Egl {
+          functions,
+          display,
+          context,
+          surface,
+        } => {
+          (functions.make_current)(*display, ptr::null_mut(), ptr::null_mut(), ptr::null_mut());
+          (functions.destroy_surface)(*display, *surface);
+          (functions.destroy_context)(*display, *context);
+        }
+        Api::OsMesa {
+          functions, context, ..
+        } => (functions.destroy_context)(*context),
+      }
+    }
+  }
+}
+
+unsafe impl Backend for HeadlessBackend {
+  fn swap_buffers(&self) -> Result<(), SwapBuffersError> {
+    // pbuffers have a single buffer, this only flushes what was drawn to it
+    if let Api::Egl {
+      functions,
+      display,
+      surface,
+      ..
+    } = &self.api
+    {
+      unsafe { (functions.swap_buffers)(*display, *surface) };
+    }
+    Ok(())
+  }
+
+  unsafe fn get_proc_address(&self, proc_name: &str) -> *const c_void {
+    let name = match CString::new(proc_name) {
+      Ok(name) => name,
+      Err(_) => return ptr::null(),
+    };
+    let name: *const c_char = name.as_ptr();
+    match &self.api {
+      Api::Egl { functions, .. } => (functions.get_proc_address)(name),
+      Api::OsMesa { functions, .. } => (functions.get_proc_address)(name),
+    }
+  }
+
+  fn get_framebuffer_dimensions(&self) -> (u32, u32) {
+    self.size
+  }
+
+  fn is_current(&self) -> bool {
+    unsafe {
+      match &self.api {
+        Api::Egl {
+          functions, context, ..
+        } => (functions.get_current_context)() == *context,
+        Api::OsMesa {
+          functions, context, ..
+        } => (functions.get_current_context)() == *context,
+      }
+    }
+  }
+
+  unsafe fn make_current(&self) {
+    let (width, height) = self.size;
+    let current = match &self.api {
+      Api::Egl {
+        functions,
+        display,
+        context,
+        surface,
+      } => (functions.make_current)(*display, *surface, *surface, *context) != egl::FALSE,
+      Api::OsMesa {
+        functions,
+        context,
+        buffer,
+      } => {
+        let data = (*buffer.get()).as_mut_ptr() as *mut c_void;
+        (functions.make_current)(
+          *context,
+          data,
+          osmesa::UNSIGNED_BYTE,
+          width as c_int,
+          height as c_int,
+        ) != 0 as c_uchar
+      }
+    };
+    // glium has no way to hear about this, and draws into nothing if it goes on
+    assert!(current, "cannot make the headless context current");
+  }
+}
@@ -0,0 +1,740 @@
+use super::camera::Camera2D;
+use crate::assets::{AssetId, Handle};
+use crate::game::{
+  components::{Name, Properties, Renderable, Transform},
+  Entity, World,
+};
+use crate::gfx::{BlendMode, Light, LightKind, ParticleEmitter, Shadows, TilemapRenderer};
+use crate::map::{Gid, Map, PropertyValue};
+use crate::math::glm::{self, Vec2};
+use crate::navigation::{
+  find_path, jump_point_search, line_of_sight, smooth_path, DiagonalMovement, FlowField, NavGrid,
+  NavMesh,
+};
+use crate::physics::{Collider, CollisionSystem, QueryFilter, RigidBody, WorldShape};
+use geo::{LineString, Polygon};
+use imgui::{ChildWindow, ColorEdit, Condition, Drag, MouseButton, Selectable, TreeNodeFlags, Ui};
+
+/// How far from its position, in world units, a click still picks an entity without
+/// a collider.
+const PICK_RADIUS: f32 = 8.0;
+const OUTLINE_COLOR: [f32; 4] = [1.0, 0.8, 0.0, 1.0];
+const OUTLINE_THICKNESS: f32 = 2.0;
+/// The color of the line of sight from the selection to the cursor, and of the path
+/// the selection is about to move along.
+const TRACE_COLOR: [f32; 4] = [0.3, 0.8, 1.0, 1.0];
+/// How far ahead, in seconds, the path of a moving selection is traced.
+const LOOKAHEAD: f32 = 1.0;
+/// The radius in pixels of the marks where traces hit something.
+const HIT_MARK_RADIUS: f32 = 4.0;
+const PATH_COLOR: [f32; 4] = [0.4, 1.0, 0.4, 1.0];
+const PATH_THICKNESS: f32 = 2.0;
+const MESH_COLOR: [f32; 4] = [0.4, 1.0, 0.4, 0.25];
+const PATH_METHODS: [&str; 4] = ["A*", "jump point search", "flow field", "navmesh"];
+/// The index of the navmesh in `PATH_METHODS`, the others search the grid.
+const NAVMESH_METHOD: usize = 3;
+/// How far navmesh paths keep from walls and obstacles, in world units.
+const AGENT_RADIUS: f32 = 6.0;
+const DIAGONALS: [(&str, DiagonalMovement); 3] = [
+  ("never", DiagonalMovement::Never),
+  ("always", DiagonalMovement::Always),
+  ("no corner cutting", DiagonalMovement::NoCornerCutting),
+];
+
+/// The map as the path finders see it, rebuilt whenever a tile is painted.
+#[derive(Debug)]
+struct Navigation {
+  grid: Result<NavGrid, String>,
+  /// The field towards the cell last pointed at, kept until the cursor leaves it.
+  field: Option<FlowField>,
+  mesh: Result<NavMesh, String>,
+}
+const BLEND_MODES: [(&str, BlendMode); 5] = [
+  ("none", BlendMode::None),
+  ("alpha", BlendMode::Alpha),
+  ("additive", BlendMode::Additive),
+  ("multiply", BlendMode::Multiply),
+  ("premultiplied", BlendMode::Premultiplied),
+];
+const SHADOWS: [&str; 3] = ["none", "hard", "soft"];
+/// How soft shadows start out when they are turned soft in the panel, in world units.
+const SOFTNESS: f32 = 4.0;
+
+/// A debug panel listing the entities of the world, with the components of the
+/// selected one laid out to be edited while the game runs.
+#[derive(Debug, Default)]
+pub struct Inspector {
+  pub selected: Option<Entity>,
+  /// Why the last animation typed in could not be used.
+  animation_error: Option<String>,
+  /// The tile painted by right clicking the viewport, the empty tile erases.
+  brush: Gid,
+  /// The index of the tile layer painted on.
+  brush_layer: usize,
+  /// The index into `PATH_METHODS` of how paths are found.
+  path_method: usize,
+  /// The index into `DIAGONALS` of how grid paths move.
+  diagonal: usize,
+  smooth_paths: bool,
+  navigation: Option<Navigation>,
+}
+
+impl Inspector {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// The entity under `point` in the world. Colliders are tried first, the smallest
+  /// one winning, then the entity placed closest to the point.
+  pub fn pick(world: &World, collisions: &CollisionSystem, point: &Vec2) -> Option<Entity> {
+    let hit = collisions
+      .overlap_point(*point, &QueryFilter::default().with_sensors())
+      .into_iter()
+      .filter_map(|hit| {
+        let bounds = collisions.shape(hit.entity)?.bounds();
+        Some((hit.entity, bounds.width() * bounds.height()))
+      })
+      .min_by(|(_, a), (_, b)| a.total_cmp(b));
+    if let Some((entity, _)) = hit {
+      return Some(entity);
+    }
+
+    world
+      .query::<Transform>()
+      .map(|(entity, transform)| (entity, glm::distance(&transform.position, point)))
+      .filter(|(_, distance)| *distance <= PICK_RADIUS)
+      .min_by(|(_, a), (_, b)| a.total_cmp(b))
+      .map(|(entity, _)| entity)
+  }
+
+  /// Builds the panel, selects the entity clicked in the viewport unless imgui keeps
+  /// the click, and outlines the selection along with what it sees and is about to hit.
+  pub fn build(
+    &mut self,
+    ui: &Ui,
+    world: &mut World,
+    collisions: &CollisionSystem,
+    camera: &Camera2D,
+  ) {
+    if self.selected.is_some_and(|entity| !world.contains(entity)) {
+      self.selected = None;
+    }
+    let [x, y] = ui.io().mouse_pos;
+    let cursor = camera.screen_to_world(&glm::vec2(x, y));
+    if ui.is_mouse_clicked(MouseButton::Left) && !ui.io().want_capture_mouse {
+      self.select(Self::pick(world, collisions, &cursor));
+    }
+
+    imgui::Window::new("Inspector")
+      .position([8.0, 96.0], Condition::FirstUseEver)
+      .size([320.0, 480.0], Condition::FirstUseEver)
+      .build(ui, || {
+        self.build_entities(ui, world);
+        ui.separator();
+        match self.selected {
+          Some(entity) => self.build_components(ui, world, collisions, entity),
+          None => ui.text_disabled("click an entity to inspect it"),
+        }
+      });
+
+    if let Some(entity) = self.selected {
+      Self::outline(ui, world, camera, entity);
+      if !ui.io().want_capture_mouse {
+        Self::trace_sight(ui, world, collisions, camera, entity, &cursor);
+      }
+      Self::trace_path(ui, world, collisions, camera, entity);
+    }
+  }
+
+  /// Builds the tile brush, painting the tile under the cursor when the viewport is
+  /// right clicked.
+  pub fn build_tiles(
+    &mut self,
+    ui: &Ui,
+    map: &Handle<Map>,
+    tilemap: &mut TilemapRenderer,
+    camera: &Camera2D,
+  ) {
+    // not held past here, an edit would copy a map that is still in use
+    let (layers, tile_size) = match map.get() {
+      Some(map) => (
+        map
+          .tile_layers()
+          .map(|layer| layer.name.clone())
+          .collect::<Vec<_>>(),
+        glm::vec2(map.tile_width as f32, map.tile_height as f32),
+      ),
+      None => return,
+    };
+    if layers.is_empty() {
+      return;
+    }
+    self.brush_layer = self.brush_layer.min(layers.len() - 1);
+
+    imgui::Window::new("Tiles")
+      .position([336.0, 32.0], Condition::FirstUseEver)
+      .always_auto_resize(true)
+      .build(ui, || {
+        ui.combo_simple_string("layer", &mut self.brush_layer, &layers);
+        Drag::new("tile").build(ui, &mut self.brush);
+        ui.text_disabled("right click to paint, tile 0 erases");
+      });
+
+    if ui.is_mouse_clicked(MouseButton::Right) && !ui.io().want_capture_mouse {
+      let [x, y] = ui.io().mouse_pos;
+      let cell = camera
+        .screen_to_world(&glm::vec2(x, y))
+        .component_div(&tile_size);
+      let painted = tilemap.set_tile(
+        map,
+        &layers[self.brush_layer],
+        cell.x.floor() as i32,
+        cell.y.floor() as i32,
+        self.brush,
+      );
+      if painted {
+        self.navigation = None;
+      }
+    }
+  }
+
+  /// Builds the path finding panel, drawing the way from the selection to the cursor.
+  /// Middle clicking the viewport blocks or clears a cell for the path finders only, or
+  /// drops an obstacle the size of a cell into the navmesh.
+  pub fn build_paths(&mut self, ui: &Ui, world: &World, map: &Map, camera: &Camera2D) {
+    let navigation = self.navigation.get_or_insert_with(|| {
+      let layers: Vec<&str> = map.tile_layers().map(|layer| layer.name.as_str()).collect();
+      Navigation {
+        grid: NavGrid::from_map(map, &layers),
+        field: None,
+        mesh: NavMesh::from_map(map, AGENT_RADIUS),
+      }
+    });
+
+    let [x, y] = ui.io().mouse_pos;
+    let goal = camera.screen_to_world(&glm::vec2(x, y));
+    let start = self
+      .selected
+      .and_then(|entity| world.get::<Transform>(entity))
+      .map(|transform| transform.position);
+
+    let mut changed = false;
+    if ui.is_mouse_clicked(MouseButton::Middle) && !ui.io().want_capture_mouse {
+      let size = glm::vec2(map.tile_width as f32, map.tile_height as f32);
+      if self.path_method == NAVMESH_METHOD {
+        if let Ok(mesh) = &mut navigation.mesh {
+          let min = goal - size / 2.0;
+          mesh.add_obstacle(Polygon::new(
+            LineString::from(vec![
+              (min.x, min.y),
+              (min.x + size.x, min.y),
+              (min.x + size.x, min.y + size.y),
+              (min.x, min.y + size.y),
+            ]),
+            Vec::new(),
+          ));
+        }
+      } else if let Ok(grid) = &mut navigation.grid {
+        let cell = grid.cell_at(&goal);
+        let cost = if grid.is_walkable(cell) {
+          None
+        } else {
+          Some(1.0)
+        };
+        grid.set_cost(cell, cost);
+        changed = true;
+      }
+    }
+
+    let path = imgui::Window::new("Paths")
+      .position([336.0, 128.0], Condition::FirstUseEver)
+      .always_auto_resize(true)
+      .build(ui, || {
+        changed |= ui.combo_simple_string("method", &mut self.path_method, &PATH_METHODS);
+        let names = DIAGONALS.map(|(name, _)| name);
+        changed |= ui.combo_simple_string("diagonals", &mut self.diagonal, &names);
+        ui.checkbox("smooth", &mut self.smooth_paths);
+
+        let start = match start {
+          Some(start) => start,
+          None => {
+            ui.text_disabled("select an entity to find paths from it");
+            return None;
+          }
+        };
+
+        if self.path_method == NAVMESH_METHOD {
+          let mesh = match &navigation.mesh {
+            Ok(mesh) => mesh,
+            Err(msg) => {
+              ui.text_colored([1.0, 0.4, 0.4, 1.0], msg);
+              return None;
+            }
+          };
+          ui.text(format!(
+            "{} triangles around {} obstacles, {} from walls",
+            mesh.triangles().len(),
+            mesh.obstacles().len(),
+            mesh.agent_radius()
+          ));
+          Self::draw_mesh(ui, mesh, camera);
+
+          let path = mesh.find_path(start, goal);
+          match &path {
+            Some(path) => ui.text(format!("{} waypoints", path.len())),
+            None if !mesh.is_walkable(&goal) => ui.text_disabled("the cursor is off the mesh"),
+            None => ui.text_disabled("no path to the cursor"),
+          }
+          return path;
+        }
+
+        let grid = match &navigation.grid {
+          Ok(grid) => grid,
+          Err(msg) => {
+            ui.text_colored([1.0, 0.4, 0.4, 1.0], msg);
+            return None;
+          }
+        };
+
+        let (from, to) = (grid.cell_at(&start), grid.cell_at(&goal));
+        let diagonal = DIAGONALS[self.diagonal].1;
+        let cells = match self.path_method {
+          0 => find_path(grid, from, to, diagonal),
+          1 => jump_point_search(grid, from, to, diagonal),
+          _ => {
+            if changed
+              || navigation
+                .field
+                .as_ref()
+                .is_none_or(|field| field.goal() != to)
+            {
+              navigation.field = Some(FlowField::new(grid, to, diagonal));
+            }
+            navigation.field.as_ref().and_then(|field| field.path(from))
+          }
+        };
+        let cells = match cells {
+          Some(cells) if self.smooth_paths => smooth_path(grid, &cells),
+          Some(cells) => cells,
+          None => {
+            ui.text_disabled("no path to the cursor");
+            return None;
+          }
+        };
+
+        ui.text(format!("{} waypoints", cells.len()));
+        if line_of_sight(grid, from, to) {
+          ui.text_disabled("the cursor is in sight");
+        }
+        Some(
+          cells
+            .iter()
+            .map(|cell| grid.center(*cell))
+            .collect::<Vec<_>>(),
+        )
+      })
+      .flatten();
+
+    if let Some(path) = path {
+      let draw_list = ui.get_background_draw_list();
+      for pair in path.windows(2) {
+        let from = camera.world_to_screen(&pair[0]);
+        let to = camera.world_to_screen(&pair[1]);
+        draw_list
+          .add_line(from.into(), to.into(), PATH_COLOR)
+          .thickness(PATH_THICKNESS)
+          .build();
+      }
+    }
+  }
+
+  /// Traces the triangles of a navmesh over the viewport.
+  fn draw_mesh(ui: &Ui, mesh: &NavMesh, camera: &Camera2D) {
+    let draw_list = ui.get_background_draw_list();
+    for triangle in mesh.triangles() {
+      let [a, b, c] = triangle.map(|point| -> [f32; 2] { camera.world_to_screen(&point).into() });
+      draw_list.add_triangle(a, b, c, MESH_COLOR).build();
+    }
+  }
+
+  fn select(&mut self, entity: Option<Entity>) {
+    if self.selected != entity {
+      self.selected = entity;
+      self.animation_error = None;
+    }
+  }
+
+  fn build_entities(&mut self, ui: &Ui, world: &World) {
+    ChildWindow::new("entities")
+      .size([0.0, 160.0])
+      .border(true)
+      .build(ui, || {
+        for entity in world.entities() {
+          let label = format!("{}##{}", label_of(world, entity), entity.index());
+          let selected = self.selected == Some(entity);
+          if Selectable::new(&label).selected(selected).build(ui) {
+            self.select(Some(entity));
+          }
+        }
+      });
+  }
+
+  fn build_components(
+    &mut self,
+    ui: &Ui,
+    world: &mut World,
+    collisions: &CollisionSystem,
+    entity: Entity,
+  ) {
+    ui.text(label_of(world, entity));
+
+    let touching: Vec<String> = collisions
+      .shape(entity)
+      .map(|shape| {
+        let filter = QueryFilter::default().with_sensors().excluding(entity);
+        collisions.overlap_shape(shape, &filter)
+      })
+      .unwrap_or_default()
+      .iter()
+      .map(|hit| label_of(world, hit.entity))
+      .collect();
+
+    if let Some(transform) = world.get_mut::<Transform>(entity) {
+      if ui.collapsing_header("Transform", TreeNodeFlags::DEFAULT_OPEN) {
+        edit_vec2(ui, "position", &mut transform.position, 0.5);
+        Drag::new("rotation")
+          .speed(0.5)
+          .build(ui, &mut transform.rotation);
+        edit_vec2(ui, "scale", &mut transform.scale, 0.01);
+      }
+    }
+
+    if let Some(renderable) = world.get_mut::<Renderable>(entity) {
+      if ui.collapsing_header("Renderable", TreeNodeFlags::DEFAULT_OPEN) {
+        let prototype = &renderable.prototype;
+        ui.text(format!(
+          "prototype {} ({} users)",
+          prototype.id(),
+          prototype.ref_count()
+        ));
+        if !prototype.is_loaded() {
+          ui.text_colored([1.0, 0.4, 0.4, 1.0], "failed to reload");
+        }
+
+        let mut animation = renderable
+          .animation
+          .as_ref()
+          .map(|id| id.to_string())
+          .unwrap_or_default();
+        let inherited = prototype
+          .get()
+          .and_then(|prototype| prototype.animation.as_ref().map(|id| id.to_string()))
+          .unwrap_or_default();
+        if ui
+          .input_text("animation", &mut animation)
+          .hint(inherited)
+          .enter_returns_true(true)
+          .build()
+        {
+          // nothing typed in goes back to the animation of the prototype
+          match animation.trim() {
+            "" => {
+              renderable.animation = None;
+              self.animation_error = None;
+            }
+            id => match id.parse::<AssetId>() {
+              Ok(id) => {
+                renderable.animation = Some(id);
+                self.animation_error = None;
+              }
+              Err(msg) => self.animation_error = Some(msg.to_string()),
+            },
+          }
+        }
+        if let Some(msg) = &self.animation_error {
+          ui.text_colored([1.0, 0.4, 0.4, 1.0], msg);
+        }
+        ui.input_text("layer", &mut renderable.layer).build();
+
+        let state = &mut renderable.render_state;
+        let mut blend = BLEND_MODES
+          .iter()
+          .position(|(_, mode)| *mode == state.blend)
+          .unwrap_or_default();
+        let names = BLEND_MODES.map(|(name, _)| name);
+        if ui.combo_simple_string("blend", &mut blend, &names) {
+          state.blend = BLEND_MODES[blend].1;
+        }
+        ui.checkbox("wireframe", &mut state.wireframe);
+        ui.checkbox("depth test", &mut state.depth_test);
+        ui.checkbox("depth write", &mut state.depth_write);
+        ui.checkbox("cull backfaces", &mut state.cull_backfaces);
+      }
+    }
+
+    if let Some(properties) = world.get_mut::<Properties>(entity) {
+      if !properties.0.is_empty() && ui.collapsing_header("Properties", TreeNodeFlags::DEFAULT_OPEN)
+      {
+        let _id = ui.push_id("properties");
+        for (name, value) in properties.0.iter_mut() {
+          match value {
+            PropertyValue::Bool(value) => {
+              ui.checkbox(name, value);
+            }
+            PropertyValue::Int(value) => {
+              Drag::new(name).build(ui, value);
+            }
+            PropertyValue::Float(value) => {
+              Drag::new(name).speed(0.1).build(ui, value);
+            }
+            PropertyValue::String(value) => {
+              ui.input_text(name, value).build();
+            }
+          }
+        }
+      }
+    }
+
+    if let Some(body) = world.get_mut::<RigidBody>(entity) {
+      if ui.collapsing_header("Rigid body", TreeNodeFlags::empty()) {
+        ui.text(format!("{:?}", body.kind));
+        edit_vec2(ui, "velocity", &mut body.velocity, 1.0);
+        Drag::new("mass")
+          .speed(0.1)
+          .range(0.0, f32::MAX)
+          .build(ui, &mut body.mass);
+        Drag::new("restitution")
+          .speed(0.01)
+          .range(0.0, 1.0)
+          .build(ui, &mut body.restitution);
+        Drag::new("friction")
+          .speed(0.01)
+          .range(0.0, f32::MAX)
+          .build(ui, &mut body.friction);
+        Drag::new("linear damping")
+          .speed(0.01)
+          .range(0.0, f32::MAX)
+          .build(ui, &mut body.linear_damping);
+      }
+    }
+
+    if let Some(collider) = world.get_mut::<Collider>(entity) {
+      if ui.collapsing_header("Collider", TreeNodeFlags::empty()) {
+        edit_vec2(ui, "offset", &mut collider.offset, 0.5);
+        ui.checkbox("sensor", &mut collider.sensor);
+        ui.text(format!(
+          "layer {:#x}, mask {:#x}",
+          collider.layer, collider.mask
+        ));
+        if touching.is_empty() {
+          ui.text_disabled("touching nothing");
+        } else {
+          ui.text(format!("touching {}", touching.join(", ")));
+        }
+      }
+    }
+
+    if let Some(light) = world.get_mut::<Light>(entity) {
+      if ui.collapsing_header("Light", TreeNodeFlags::empty()) {
+        let mut color: [f32; 3] = light.color.into();
+        if ColorEdit::new("color", &mut color).build(ui) {
+          light.color = color.into();
+        }
+        Drag::new("intensity")
+          .speed(0.01)
+          .range(0.0, f32::MAX)
+          .build(ui, &mut light.intensity);
+        Drag::new("radius")
+          .speed(0.5)
+          .range(0.0, f32::MAX)
+          .build(ui, &mut light.radius);
+        Drag::new("height").speed(0.5).build(ui, &mut light.height);
+
+        match &mut light.kind {
+          LightKind::Ambient => ui.text("ambient"),
+          LightKind::Point => ui.text("point"),
+          LightKind::Spot { direction, angle } => {
+            ui.text("spot");
+            Drag::new("direction").speed(0.5).build(ui, direction);
+            Drag::new("angle")
+              .speed(0.5)
+              .range(0.0, 360.0)
+              .build(ui, angle);
+          }
+        }
+
+        let mut shadows = match light.shadows {
+          Shadows::None => 0,
+          Shadows::Hard => 1,
+          Shadows::Soft { .. } => 2,
+        };
+        if ui.combo_simple_string("shadows", &mut shadows, &SHADOWS) {
+          light.shadows = match shadows {
+            0 => Shadows::None,
+            1 => Shadows::Hard,
+            _ => Shadows::Soft { softness: SOFTNESS },
+          };
+        }
+        if let Shadows::Soft { softness } = &mut light.shadows {
+          Drag::new("softness")
+            .speed(0.1)
+            .range(0.0, f32::MAX)
+            .build(ui, softness);
+        }
+      }
+    }
+
+    if let Some(emitter) = world.get_mut::<ParticleEmitter>(entity) {
+      if ui.collapsing_header("Particles", TreeNodeFlags::empty()) {
+        ui.text(format!(
+          "{} from {}",
+          emitter.particle_count(),
+          emitter.effect.id()
+        ));
+        Drag::new("rate scale")
+          .speed(0.01)
+          .range(0.0, f32::MAX)
+          .build(ui, &mut emitter.rate_scale);
+        if emitter.is_emitting() {
+          if ui.button("stop") {
+            emitter.stop();
+          }
+        } else if ui.button("play") {
+          emitter.play();
+        }
+        ui.same_line();
+        if ui.button("clear") {
+          emitter.clear();
+        }
+      }
+    }
+  }
+
+  /// Traces the collider of `entity` over the viewport, or marks its position when it
+  /// has none.
+  fn outline(ui: &Ui, world: &World, camera: &Camera2D, entity: Entity) {
+    let transform = match world.get::<Transform>(entity) {
+      Some(transform) => transform,
+      None => return,
+    };
+    let shape = match world.get::<Collider>(entity) {
+      Some(collider) => collider.world_shape(transform),
+      None => WorldShape::Circle {
+        center: transform.position,
+        radius: PICK_RADIUS,
+      },
+    };
+
+    let draw_list = ui.get_background_draw_list();
+    let screen = |point: &Vec2| -> [f32; 2] { camera.world_to_screen(point).into() };
+    match &shape {
+      WorldShape::Circle { center, radius } => {
+        let edge = camera.world_to_screen(&(center + glm::vec2(*radius, 0.0)));
+        let center = camera.world_to_screen(center);
+        draw_list
+          .add_circle(center.into(), glm::distance(&center, &edge), OUTLINE_COLOR)
+          .thickness(OUTLINE_THICKNESS)
+          .build();
+      }
+      WorldShape::Polygon { points } => {
+        for (index, point) in points.iter().enumerate() {
+          let next = &points[(index + 1) % points.len()];
+          draw_list
+            .add_line(screen(point), screen(next), OUTLINE_COLOR)
+            .thickness(OUTLINE_THICKNESS)
+            .build();
+        }
+      }
+    }
+  }
+
+  /// Draws a line from `entity` towards the cursor, cut short by the first collider
+  /// in the way.
+  fn trace_sight(
+    ui: &Ui,
+    world: &World,
+    collisions: &CollisionSystem,
+    camera: &Camera2D,
+    entity: Entity,
+    cursor: &Vec2,
+  ) {
+    let origin = match world.get::<Transform>(entity) {
+      Some(transform) => transform.position,
+      None => return,
+    };
+    let filter = QueryFilter::default().excluding(entity);
+    let hit = collisions
+      .raycast(
+        origin,
+        cursor - origin,
+        glm::distance(&origin, cursor),
+        &filter,
+      )
+      .ok()
+      .flatten();
+    let end = hit.map_or(*cursor, |hit| hit.point);
+
+    let draw_list = ui.get_background_draw_list();
+    let (start, end) = (
+      camera.world_to_screen(&origin),
+      camera.world_to_screen(&end),
+    );
+    draw_list
+      .add_line(start.into(), end.into(), TRACE_COLOR)
+      .build();
+    if hit.is_some() {
+      draw_list
+        .add_circle(end.into(), HIT_MARK_RADIUS, TRACE_COLOR)
+        .filled(true)
+        .build();
+    }
+  }
+
+  /// Draws where the collider of a moving `entity` would first hit something if it
+  /// kept its velocity.
+  fn trace_path(
+    ui: &Ui,
+    world: &World,
+    collisions: &CollisionSystem,
+    camera: &Camera2D,
+    entity: Entity,
+  ) {
+    let (body, shape) = match (world.get::<RigidBody>(entity), collisions.shape(entity)) {
+      (Some(body), Some(shape)) => (body, shape),
+      _ => return,
+    };
+    let translation = body.velocity * LOOKAHEAD;
+    let filter = QueryFilter::default().excluding(entity);
+    let hit = match collisions.shape_cast(shape, translation, &filter) {
+      Some(hit) => hit,
+      None => return,
+    };
+
+    let draw_list = ui.get_background_draw_list();
+    let start = camera.world_to_screen(&shape.center());
+    let center = camera.world_to_screen(&(shape.center() + translation * hit.fraction));
+    let point = camera.world_to_screen(&hit.point);
+    draw_list
+      .add_line(start.into(), center.into(), TRACE_COLOR)
+      .build();
+    draw_list
+      .add_circle(point.into(), HIT_MARK_RADIUS, TRACE_COLOR)
+      .filled(true)
+      .build();
+  }
+}
+
+/// The name of `entity`, or its index when it has none.
+fn label_of(world: &World, entity: Entity) -> String {
+  match world.get::<Name>(entity) {
+    Some(name) => name.0.clone(),
+    None => format!("entity {}", entity.index()),
+  }
+}
+
+fn edit_vec2(ui: &Ui, label: &str, value: &mut Vec2, speed: f32) {
+  let mut array: [f32; 2] = (*value).into();
+  if Drag::new(label).speed(speed).build_array(ui, &mut array) {
+    *value = array.into();
+  }
+}
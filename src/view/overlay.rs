@@ -0,0 +1,138 @@
+use crate::assets::{AssetId, AssetServer};
+use crate::gfx::ImguiRenderer;
+use glfw::{Action, Modifiers, WindowEvent};
+use glium::Surface;
+use imgui::{Key, Ui};
+use std::rc::Rc;
+
+/// Debug panels drawn with imgui on top of the frame. While it is visible it sees
+/// window events before the game does, and keeps those it wants for itself.
+pub struct DebugOverlay {
+  imgui: imgui::Context,
+  renderer: ImguiRenderer,
+  pub visible: bool,
+}
+
+impl DebugOverlay {
+  /// Starts out hidden.
+  pub fn new(
+    ctx: Rc<glium::backend::Context>,
+    asset_server: &mut AssetServer,
+    shader: &AssetId,
+  ) -> Result<Self, String> {
+    let mut imgui = imgui::Context::create();
+    // panel layouts are not worth a file next to the game
+    imgui.set_ini_filename(None);
+
+    let io = imgui.io_mut();
+    for (key, glfw_key) in [
+      (Key::Tab, glfw::Key::Tab),
+      (Key::LeftArrow, glfw::Key::Left),
+      (Key::RightArrow, glfw::Key::Right),
+      (Key::UpArrow, glfw::Key::Up),
+      (Key::DownArrow, glfw::Key::Down),
+      (Key::PageUp, glfw::Key::PageUp),
+      (Key::PageDown, glfw::Key::PageDown),
+      (Key::Home, glfw::Key::Home),
+      (Key::End, glfw::Key::End),
+      (Key::Insert, glfw::Key::Insert),
+      (Key::Delete, glfw::Key::Delete),
+      (Key::Backspace, glfw::Key::Backspace),
+      (Key::Space, glfw::Key::Space),
+      (Key::Enter, glfw::Key::Enter),
+      (Key::Escape, glfw::Key::Escape),
+      (Key::KeyPadEnter, glfw::Key::KpEnter),
+      (Key::A, glfw::Key::A),
+      (Key::C, glfw::Key::C),
+      (Key::V, glfw::Key::V),
+      (Key::X, glfw::Key::X),
+      (Key::Y, glfw::Key::Y),
+      (Key::Z, glfw::Key::Z),
+    ] {
+      io[key] = glfw_key as u32;
+    }
+
+    let renderer = ImguiRenderer::new(ctx, asset_server, shader, &mut imgui)?;
+    Ok(Self {
+      imgui,
+      renderer,
+      visible: false,
+    })
+  }
+
+  /// Feeds `event` to imgui, returning whether imgui keeps it from the game. Nothing
+  /// is kept while the overlay is hidden, nor are key releases, so a key held when a
+  /// panel takes focus is not stuck down for the game.
+  pub fn handle_event(&mut self, event: &WindowEvent) -> bool {
+    if !self.visible {
+      return false;
+    }
+
+    let io = self.imgui.io_mut();
+    match *event {
+      WindowEvent::CursorPos(x, y) => {
+        io.mouse_pos = [x as f32, y as f32];
+        io.want_capture_mouse
+      }
+      WindowEvent::MouseButton(button, action, modifiers) => {
+        Self::set_modifiers(io, modifiers);
+        if let Some(down) = io.mouse_down.get_mut(button as usize) {
+          *down = action != Action::Release;
+        }
+        io.want_capture_mouse && action != Action::Release
+      }
+      WindowEvent::Scroll(x, y) => {
+        io.mouse_wheel_h += x as f32;
+        io.mouse_wheel += y as f32;
+        io.want_capture_mouse
+      }
+      WindowEvent::Char(character) => {
+        io.add_input_character(character);
+        io.want_capture_keyboard
+      }
+      WindowEvent::Key(key, _scancode, action, modifiers) => {
+        Self::set_modifiers(io, modifiers);
+        // unknown keys come as -1
+        if let Some(down) = usize::try_from(key as i32)
+          .ok()
+          .and_then(|index| io.keys_down.get_mut(index))
+        {
+          *down = action != Action::Release;
+        }
+        io.want_capture_keyboard && action != Action::Release
+      }
+      _ => false,
+    }
+  }
+
+  /// Builds this frame's panels with `build` and draws them over `surface`, unless
+  /// the overlay is hidden. `dt` is in seconds.
+  pub fn draw<S: Surface>(
+    &mut self,
+    surface: &mut S,
+    dt: f32,
+    build: impl FnOnce(&Ui),
+  ) -> Result<(), String> {
+    if !self.visible {
+      return Ok(());
+    }
+
+    let (width, height) = surface.get_dimensions();
+    let io = self.imgui.io_mut();
+    io.display_size = [width as f32, height as f32];
+    // imgui refuses to start a frame that took no time
+    io.delta_time = dt.max(f32::EPSILON);
+
+    let ui = self.imgui.frame();
+    build(&ui);
+    let draw_data = ui.render();
+    self.renderer.draw(surface, draw_data)
+  }
+
+  fn set_modifiers(io: &mut imgui::Io, modifiers: Modifiers) {
+    io.key_ctrl = modifiers.contains(Modifiers::Control);
+    io.key_shift = modifiers.contains(Modifiers::Shift);
+    io.key_alt = modifiers.contains(Modifiers::Alt);
+    io.key_super = modifiers.contains(Modifiers::Super);
+  }
+}
@@ -1,3 +1,4 @@
+use super::overlay::DebugOverlay;
 use crate::input::{
   keyboard::{Key, KeyAction, KeyEvent},
   InputDevices, InputProcessor,
@@ -109,9 +110,19 @@ impl Window {
     self.window_handle.borrow_mut().show();
   }
 
-  pub fn poll_events(&self, input_devices: &mut InputDevices) {
+  /// Hands events to `overlay` first, the ones it keeps never reach `input_devices`.
+  pub fn poll_events(
+    &self,
+    input_devices: &mut InputDevices,
+    mut overlay: Option<&mut DebugOverlay>,
+  ) {
     self.glfw_handle.borrow_mut().poll_events();
     for (_, event) in glfw::flush_messages(&self.event_stream) {
+      if let Some(overlay) = overlay.as_deref_mut() {
+        if overlay.handle_event(&event) {
+          continue;
+        }
+      }
       match event {
         WindowEvent::Key(key, _scancode, action, _modifiers) => {
           input_devices.process(Self::convert_key_event(key, action))
@@ -153,6 +164,7 @@ impl Window {
 
       glfw::Key::Escape => Key::Esc,
       glfw::Key::Tab => Key::Tab,
+      glfw::Key::F1 => Key::F1,
 
       _ => Key::Unsupported,
     };
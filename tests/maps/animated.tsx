@@ -0,0 +1,17 @@
+<?xml version="1.0" encoding="UTF-8"?>
+<tileset version="1.10" name="water" tilewidth="16" tileheight="16" tilecount="4" columns="2">
+ <properties>
+  <property name="texture" value="exp.test.water"/>
+ </properties>
+ <image source="water.png" width="32" height="32"/>
+ <tile id="0">
+  <properties>
+   <property name="solid" type="bool" value="true"/>
+  </properties>
+  <animation>
+   <frame tileid="0" duration="100"/>
+   <frame tileid="1" duration="150"/>
+   <frame tileid="2" duration="200"/>
+  </animation>
+ </tile>
+</tileset>